@@ -0,0 +1,351 @@
+//! Deciding whether to allow the request a proxy asks about.
+
+use hawk::{AuthSource, Challenge, CredentialsProvider, Header, MethodOverride, PublicEndpoint,
+           RejectionReason, RequestBuilder, ValidationOptions, METHOD_OVERRIDE_HEADER};
+use http::{HttpRequest, HttpResponse};
+use std::str::FromStr;
+
+/// The header carrying the authenticated credentials id, for the proxy to pass upstream.
+pub const ID_HEADER: &str = "X-Hawk-Id";
+
+/// The header carrying the authenticated request's `ext` data, if any.
+pub const EXT_HEADER: &str = "X-Hawk-Ext";
+
+/// The proxy protocol to speak.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    /// Envoy's HTTP `ext_authz` service: the original method and path (after the configured
+    /// `path_prefix`) arrive in the request line, and the original headers, including `Host`,
+    /// are passed along.  Envoy returns any non-200 response, with its `WWW-Authenticate`
+    /// header, to the client.
+    Envoy,
+    /// nginx's `auth_request` subrequest: the original method and URI arrive in the
+    /// `X-Original-Method` and `X-Original-URI` headers, and the original host in
+    /// `X-Forwarded-Host` or `Host`.  nginx only passes 401 and 403 responses on to the
+    /// client, treating any other status as a server error, so malformed requests are given a
+    /// 401 as well.
+    Nginx,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Mode, String> {
+        match s {
+            "envoy" => Ok(Mode::Envoy),
+            "nginx" => Ok(Mode::Nginx),
+            _ => Err(format!("unknown mode `{}`; expected `envoy` or `nginx`", s)),
+        }
+    }
+}
+
+/// Answers authorization requests by validating the original request's Hawk header against
+/// the keys of a `CredentialsProvider`.
+pub struct Authorizer<P> {
+    provider: P,
+    options: ValidationOptions,
+    mode: Mode,
+    path_prefix: String,
+    default_scheme: String,
+    public_endpoint: Option<PublicEndpoint>,
+    method_override: MethodOverride,
+}
+
+impl<P: CredentialsProvider> Authorizer<P> {
+    /// Create an authorizer for the given mode.  The path prefix is empty and the default
+    /// scheme, used when a request has no `X-Forwarded-Proto` header, is `http`.
+    pub fn new(provider: P, options: ValidationOptions, mode: Mode) -> Authorizer<P> {
+        Authorizer {
+            provider,
+            options,
+            mode,
+            path_prefix: String::new(),
+            default_scheme: "http".to_string(),
+            public_endpoint: None,
+            method_override: MethodOverride::Transmitted,
+        }
+    }
+
+    /// Set the prefix Envoy adds to the original path (its `path_prefix` setting).
+    pub fn with_path_prefix<S: Into<String>>(mut self, path_prefix: S) -> Self {
+        self.path_prefix = path_prefix.into();
+        self
+    }
+
+    /// Set the scheme assumed for requests without an `X-Forwarded-Proto` header.
+    pub fn with_default_scheme<S: Into<String>>(mut self, default_scheme: S) -> Self {
+        self.default_scheme = default_scheme.into();
+        self
+    }
+
+    /// Validate requests as made to the given public endpoint, rather than to the host in the
+    /// forwarded headers and the scheme's default port.  This is needed when the proxy listens
+    /// on a different port from the one clients connect to, such as behind a load balancer.
+    pub fn with_public_endpoint(mut self, public_endpoint: PublicEndpoint) -> Self {
+        self.public_endpoint = Some(public_endpoint);
+        self
+    }
+
+    /// Set which method is validated for `POST` requests with an `X-HTTP-Method-Override`
+    /// header.  By default, the override is ignored, and `POST` is validated.
+    pub fn with_method_override(mut self, method_override: MethodOverride) -> Self {
+        self.method_override = method_override;
+        self
+    }
+
+    /// Decide whether to allow the original request described by the given request.
+    pub fn authorize(&self, request: &HttpRequest) -> HttpResponse {
+        match self.check(request) {
+            Ok(response) => response,
+            Err(error) => {
+                let status = match self.mode {
+                    Mode::Envoy => 400,
+                    Mode::Nginx => 401,
+                };
+                let challenge = Challenge::new().with_error(Some(error)).unwrap();
+                HttpResponse::new(status).with_header("WWW-Authenticate", challenge.to_string())
+            }
+        }
+    }
+
+    /// Check the request, returning a response for requests which were understood, and an
+    /// error message for those which were not.
+    fn check(&self, request: &HttpRequest) -> Result<HttpResponse, &'static str> {
+        let (method, target) = match self.mode {
+            Mode::Envoy => {
+                let target = request.target.get(self.path_prefix.len()..)
+                    .filter(|_| request.target.starts_with(&self.path_prefix[..]))
+                    .ok_or("Request path does not start with the path prefix")?;
+                (&request.method[..], target)
+            }
+            Mode::Nginx => {
+                (request.header("x-original-method").ok_or("Missing X-Original-Method")?,
+                 request.header("x-original-uri").ok_or("Missing X-Original-URI")?)
+            }
+        };
+        let mut authorizations = request.headers("authorization");
+        let authorization = authorizations.next();
+        if authorizations.next().is_some() {
+            return Err("Multiple Authorization headers");
+        }
+        let header = match AuthSource::from_request(authorization, target) {
+            Ok(Some(AuthSource::Header(header))) => header,
+            Ok(_) => return Ok(unauthorized(Challenge::new())),
+            Err(_) => return Err("Multiple authentications"),
+        };
+        let header = Header::from_str(header).map_err(|_| "Bad header format")?;
+        let original = match self.public_endpoint {
+            Some(ref endpoint) => endpoint.request_builder(method, target),
+            None => {
+                let host = match self.mode {
+                    Mode::Envoy => request.header("host"),
+                    Mode::Nginx => {
+                        request.header("x-forwarded-host").or_else(|| request.header("host"))
+                    }
+                };
+                let host = host.ok_or("Missing Host")?;
+                let scheme = request.header("x-forwarded-proto")
+                    .unwrap_or(&self.default_scheme[..]);
+                RequestBuilder::from_host_header(method, scheme, host, target)
+                    .map_err(|_| "Invalid Host")?
+            }
+        };
+        let original = original.method_override(request.header(METHOD_OVERRIDE_HEADER))
+            .method_override_policy(self.method_override)
+            .request();
+
+        match original.check_with_provider(&header, &self.provider, &self.options) {
+            Ok(artifacts) => {
+                // check_with_provider fails for headers without an id
+                let mut response = HttpResponse::new(200)
+                    .with_header(ID_HEADER, artifacts.id().unwrap_or(""));
+                if let Some(ext) = artifacts.ext() {
+                    response = response.with_header(EXT_HEADER, ext);
+                }
+                Ok(response)
+            }
+            Err(RejectionReason::Stale) => {
+                let key = header.id().and_then(|id| self.provider.keys(id).into_iter().next());
+                Ok(unauthorized(match key {
+                    Some(key) => Challenge::stale(&key),
+                    None => Challenge::rejected(RejectionReason::Stale),
+                }))
+            }
+            Err(reason) => Ok(unauthorized(Challenge::rejected(reason))),
+        }
+    }
+}
+
+fn unauthorized(challenge: Challenge) -> HttpResponse {
+    HttpResponse::new(401).with_header("WWW-Authenticate", challenge.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hawk::keyset::KeySet;
+    use hawk::{Credentials, Key, SHA256};
+    use time::Duration;
+
+    const KEYS: &str = r#"{"keys": [{"id": "me", "algorithm": "sha256",
+                                     "key": "Y2NjY2NjY2NjY2NjY2NjY2NjY2NjY2NjY2NjY2NjY2M="}]}"#;
+
+    fn authorization(method: &str, port: u16, path: &str, ext: Option<&str>) -> String {
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &SHA256),
+        };
+        RequestBuilder::new(method, "example.com", port, path)
+            .ext(ext)
+            .request()
+            .sign_to_string(&credentials)
+            .unwrap()
+    }
+
+    fn authorizer(mode: Mode) -> Authorizer<KeySet> {
+        let options = ValidationOptions::default().with_ts_skew(Duration::minutes(1));
+        Authorizer::new(KeySet::from_json(KEYS).unwrap(), options, mode)
+    }
+
+    fn challenge(response: &HttpResponse) -> Challenge {
+        response.header("www-authenticate").unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn envoy() {
+        let authorizer = authorizer(Mode::Envoy).with_path_prefix("/authz");
+        let authorization = authorization("POST", 80, "/a?b=c", Some("tenant"));
+        let request = HttpRequest::new("POST",
+                                       "/authz/a?b=c",
+                                       &[("Host", "example.com"),
+                                         ("Authorization", &authorization)]);
+        let response = authorizer.authorize(&request);
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header(ID_HEADER), Some("me"));
+        assert_eq!(response.header(EXT_HEADER), Some("tenant"));
+
+        let request = HttpRequest::new("POST",
+                                       "/authz/a?b=d",
+                                       &[("Host", "example.com"),
+                                         ("Authorization", &authorization)]);
+        let response = authorizer.authorize(&request);
+        assert_eq!(response.status, 401);
+        assert_eq!(challenge(&response).error(), Some("Bad mac"));
+
+        let request = HttpRequest::new("POST", "/a?b=c", &[("Host", "example.com")]);
+        assert_eq!(authorizer.authorize(&request).status, 400);
+    }
+
+    #[test]
+    fn envoy_forwarded_proto() {
+        let authorizer = authorizer(Mode::Envoy);
+        let authorization = authorization("GET", 443, "/", None);
+        let request = HttpRequest::new("GET",
+                                       "/",
+                                       &[("Host", "example.com"),
+                                         ("X-Forwarded-Proto", "https"),
+                                         ("Authorization", &authorization)]);
+        assert_eq!(authorizer.authorize(&request).status, 200);
+        assert_eq!(authorizer.with_default_scheme("https")
+                       .authorize(&HttpRequest::new("GET",
+                                                    "/",
+                                                    &[("Host", "example.com"),
+                                                      ("Authorization", &authorization)]))
+                       .status,
+                   200);
+    }
+
+    #[test]
+    fn public_endpoint() {
+        let authorizer = authorizer(Mode::Envoy)
+            .with_public_endpoint(PublicEndpoint::new("https", "example.com", 443));
+        let authorization = authorization("GET", 443, "/", None);
+        let request = HttpRequest::new("GET",
+                                       "/",
+                                       &[("Host", "example.com:8443"),
+                                         ("Authorization", &authorization)]);
+        assert_eq!(authorizer.authorize(&request).status, 200);
+        let request = HttpRequest::new("GET", "/", &[("Authorization", &authorization)]);
+        assert_eq!(authorizer.authorize(&request).status, 200);
+    }
+
+    #[test]
+    fn method_override() {
+        let authorization = authorization("DELETE", 80, "/x", None);
+        let request = HttpRequest::new("POST",
+                                       "/x",
+                                       &[("Host", "example.com"),
+                                         ("X-HTTP-Method-Override", "DELETE"),
+                                         ("Authorization", &authorization)]);
+        assert_eq!(authorizer(Mode::Envoy).authorize(&request).status, 401);
+        let authorizer = authorizer(Mode::Envoy).with_method_override(MethodOverride::Overridden);
+        assert_eq!(authorizer.authorize(&request).status, 200);
+    }
+
+    #[test]
+    fn nginx() {
+        let authorizer = authorizer(Mode::Nginx);
+        let authorization = authorization("DELETE", 8080, "/x", None);
+        let request = HttpRequest::new("GET",
+                                       "/auth",
+                                       &[("Host", "authz.internal"),
+                                         ("X-Forwarded-Host", "example.com:8080"),
+                                         ("X-Original-Method", "DELETE"),
+                                         ("X-Original-URI", "/x"),
+                                         ("Authorization", &authorization)]);
+        assert_eq!(authorizer.authorize(&request).status, 200);
+
+        // nginx only forwards 401 and 403 to the client
+        let request = HttpRequest::new("GET", "/auth", &[("Authorization", &authorization)]);
+        let response = authorizer.authorize(&request);
+        assert_eq!(response.status, 401);
+        assert_eq!(challenge(&response).error(), Some("Missing X-Original-Method"));
+    }
+
+    #[test]
+    fn rejections() {
+        let authorizer = authorizer(Mode::Envoy);
+        let request = |authorization: &[&str]| {
+            let mut headers = vec![("Host", "example.com")];
+            headers.extend(authorization.iter().map(|&a| ("Authorization", a)));
+            authorizer.authorize(&HttpRequest::new("GET", "/", &headers))
+        };
+
+        // no Hawk authentication: a bare challenge
+        for response in &[request(&[]), request(&["Basic dXNlcjpwYXNz"])] {
+            assert_eq!(response.status, 401);
+            assert_eq!(challenge(response), Challenge::new());
+        }
+
+        let authorization = authorization("GET", 80, "/", None);
+        assert_eq!(request(&[&authorization, &authorization]).status, 400);
+        assert_eq!(request(&["Hawk id=\"me"]).status, 400);
+
+        let unknown = authorization.replace("id=\"me\"", "id=\"you\"");
+        assert_eq!(challenge(&request(&[&unknown])).error(), Some("Unknown credentials"));
+    }
+
+    #[test]
+    fn stale() {
+        let authorization = {
+            let _deterministic = hawk::testing::deterministic_for_tests();
+            authorization("GET", 80, "/", None)
+        };
+        let authorizer = authorizer(Mode::Envoy);
+        let response = authorizer.authorize(&HttpRequest::new("GET",
+                                                              "/",
+                                                              &[("Host", "example.com"),
+                                                                ("Authorization",
+                                                                 &authorization)]));
+        assert_eq!(response.status, 401);
+        let challenge = challenge(&response);
+        assert_eq!(challenge.error(), Some("Stale timestamp"));
+        assert!(challenge.verified_ts(&Key::new(vec![99u8; 32], &SHA256)).is_some());
+    }
+
+    #[test]
+    fn mode_from_str() {
+        assert_eq!("envoy".parse(), Ok(Mode::Envoy));
+        assert_eq!("nginx".parse(), Ok(Mode::Nginx));
+        assert!("apache".parse::<Mode>().is_err());
+    }
+}
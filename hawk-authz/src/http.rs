@@ -0,0 +1,214 @@
+//! Just enough HTTP/1.1 to answer authorization requests from a proxy.
+//!
+//! Envoy and nginx send small, well-formed requests, so this reads only the request line and
+//! headers, discards any `Content-Length` body, and writes bodiless responses.  Anything more
+//! unusual, such as a chunked body, is an error and closes the connection.
+
+use std::io::{self, BufRead, Read, Write};
+
+/// The longest request line or header line accepted.
+const MAX_LINE: u64 = 8192;
+
+/// The most headers accepted in one request.
+const MAX_HEADERS: usize = 100;
+
+/// An HTTP request, without its body.
+#[derive(Clone, PartialEq, Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    pub target: String,
+    headers: Vec<(String, String)>,
+}
+
+impl HttpRequest {
+    /// Create a request with the given method, target, and headers.
+    #[cfg(test)]
+    pub fn new(method: &str, target: &str, headers: &[(&str, &str)]) -> HttpRequest {
+        HttpRequest {
+            method: method.to_string(),
+            target: target.to_string(),
+            headers: headers.iter()
+                .map(|&(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Get the values of the headers with the given name, which is case-insensitive.
+    pub fn headers<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers.iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| &v[..])
+    }
+
+    /// Get the value of the first header with the given name, which is case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| &v[..])
+    }
+
+    /// Determine whether the client asked to close the connection after this request.
+    pub fn wants_close(&self) -> bool {
+        self.headers("connection").any(|v| v.eq_ignore_ascii_case("close"))
+    }
+}
+
+/// Read a request from the given reader, discarding its body.  Returns `Ok(None)` if the
+/// connection was closed before a request began.
+pub fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Option<HttpRequest>> {
+    let line = match read_line(reader)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    let mut parts = line.split(' ');
+    let (method, target) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version), None) if version.starts_with("HTTP/1.") => {
+            (method, target)
+        }
+        _ => return Err(invalid("malformed request line")),
+    };
+
+    let mut headers = vec![];
+    loop {
+        let line = read_line(reader)?.ok_or_else(|| invalid("connection closed in headers"))?;
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(invalid("too many headers"));
+        }
+        let (name, value) = line.split_once(':').ok_or_else(|| invalid("malformed header"))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let request = HttpRequest {
+        method: method.to_string(),
+        target: target.to_string(),
+        headers,
+    };
+    if request.header("transfer-encoding").is_some() {
+        return Err(invalid("request bodies must have a Content-Length"));
+    }
+    if let Some(len) = request.header("content-length") {
+        let len = len.parse::<u64>().map_err(|_| invalid("invalid Content-Length"))?;
+        io::copy(&mut reader.take(len), &mut io::sink())?;
+    }
+    Ok(Some(request))
+}
+
+/// Read a line, without its line ending.  Returns `Ok(None)` at the end of the input.
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut line = vec![];
+    reader.take(MAX_LINE).read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(invalid("line too long"));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map(Some).map_err(|_| invalid("line is not UTF-8"))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// An HTTP response, without a body.
+#[derive(Clone, PartialEq, Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+}
+
+impl HttpResponse {
+    /// Create a response with the given status and no headers.
+    pub fn new(status: u16) -> HttpResponse {
+        HttpResponse {
+            status,
+            headers: vec![],
+        }
+    }
+
+    /// Add a header to the response.
+    pub fn with_header<S: Into<String>>(mut self, name: &'static str, value: S) -> HttpResponse {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// Get the value of the first header with the given name, which is case-insensitive.
+    #[cfg(test)]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|&&(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| &v[..])
+    }
+
+    /// Write the response.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            _ => "Internal Server Error",
+        };
+        write!(writer, "HTTP/1.1 {} {}\r\n", self.status, reason)?;
+        for &(name, ref value) in &self.headers {
+            write!(writer, "{}: {}\r\n", name, value)?;
+        }
+        writer.write_all(b"Content-Length: 0\r\n\r\n")?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn read() {
+        let mut input = Cursor::new(&b"GET /auth/a?b=c HTTP/1.1\r\n\
+                                       Host: example.com\r\n\
+                                       authorization: Hawk id=\"me\"\r\n\
+                                       Content-Length: 4\r\n\
+                                       \r\n\
+                                       bodyPOST / HTTP/1.0\n\n"[..]);
+        let request = read_request(&mut input).unwrap().unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.target, "/auth/a?b=c");
+        assert_eq!(request.header("HOST"), Some("example.com"));
+        assert_eq!(request.header("Authorization"), Some("Hawk id=\"me\""));
+        assert_eq!(request.header("x-missing"), None);
+
+        let request = read_request(&mut input).unwrap().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(read_request(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn read_invalid() {
+        let read = |input: &[u8]| read_request(&mut Cursor::new(input)).unwrap_err().kind();
+        assert_eq!(read(b"GET /\r\n\r\n"), io::ErrorKind::InvalidData);
+        assert_eq!(read(b"GET / SPDY/3\r\n\r\n"), io::ErrorKind::InvalidData);
+        assert_eq!(read(b"GET / HTTP/1.1\r\nHost\r\n\r\n"), io::ErrorKind::InvalidData);
+        assert_eq!(read(b"GET / HTTP/1.1\r\nHost: a\r\n"), io::ErrorKind::InvalidData);
+        assert_eq!(read(b"GET / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n"),
+                   io::ErrorKind::InvalidData);
+        assert_eq!(read(&vec![b'a'; 10000]), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn write() {
+        let mut output = vec![];
+        HttpResponse::new(401)
+            .with_header("WWW-Authenticate", "Hawk")
+            .write_to(&mut output)
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Hawk\r\nContent-Length: 0\r\n\r\n");
+    }
+}
@@ -0,0 +1,149 @@
+//! An external authorization service validating Hawk headers, so that a proxy can require Hawk
+//! authentication in front of services which know nothing about it.
+//!
+//! The service speaks Envoy's HTTP `ext_authz` protocol (`--mode envoy`) or answers nginx
+//! `auth_request` subrequests (`--mode nginx`).  It allows requests with a valid Hawk
+//! `Authorization` header, responding `200` with the credentials id in `X-Hawk-Id` (and any
+//! `ext` data in `X-Hawk-Ext`) for the proxy to pass upstream, and rejects others with a `401`
+//! and a Hawk `WWW-Authenticate` challenge for the proxy to return to the client.
+//!
+//! Credentials are read from a key set file, in the format described in `hawk::keyset`, which
+//! is re-read periodically so that keys can be rotated without a restart.
+//!
+//! ```text
+//! hawk-authz --credentials keys.json [--listen 127.0.0.1:9191] [--mode envoy|nginx]
+//!            [--path-prefix PREFIX] [--default-scheme http|https] [--ts-skew SECONDS]
+//!            [--refresh SECONDS] [--public-url URL] [--method-override]
+//! ```
+//!
+//! By default, the request is validated for the host in the forwarded `Host` header and the
+//! default port of the forwarded scheme.  If clients connect to another port, such as when TLS
+//! is terminated in front of the proxy, give the address they use as `--public-url`, for
+//! example `--public-url https://api.example.com`.
+//!
+//! With `--method-override`, a `POST` request with an `X-HTTP-Method-Override` header is
+//! validated as a request with the overriding method, for clients which sign that method.
+//!
+//! Payload hashes are not verified, since the proxy does not pass request bodies.
+
+extern crate hawk;
+extern crate time;
+
+mod authz;
+mod http;
+
+use authz::{Authorizer, Mode};
+use hawk::keyset::RemoteCredentialsProvider;
+use hawk::{CredentialsProvider, MethodOverride, PublicEndpoint, ValidationOptions};
+use std::env;
+use std::fs;
+use std::io::{BufReader, BufWriter};
+use std::net::{TcpListener, TcpStream};
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use time::Duration;
+
+struct Args {
+    listen: String,
+    credentials: String,
+    mode: Mode,
+    path_prefix: String,
+    default_scheme: String,
+    ts_skew: i64,
+    refresh: i64,
+    public_endpoint: Option<PublicEndpoint>,
+    method_override: MethodOverride,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        listen: "127.0.0.1:9191".to_string(),
+        credentials: String::new(),
+        mode: Mode::Envoy,
+        path_prefix: String::new(),
+        default_scheme: "http".to_string(),
+        ts_skew: 60,
+        refresh: 60,
+        public_endpoint: None,
+        method_override: MethodOverride::Transmitted,
+    };
+    let mut argv = env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        let mut value = || argv.next().ok_or_else(|| format!("{} requires a value", arg));
+        match &arg[..] {
+            "--listen" => args.listen = value()?,
+            "--credentials" => args.credentials = value()?,
+            "--mode" => args.mode = value()?.parse()?,
+            "--path-prefix" => args.path_prefix = value()?,
+            "--default-scheme" => args.default_scheme = value()?,
+            "--ts-skew" => {
+                args.ts_skew = value()?.parse().map_err(|_| "invalid --ts-skew".to_string())?
+            }
+            "--refresh" => {
+                args.refresh = value()?.parse().map_err(|_| "invalid --refresh".to_string())?
+            }
+            "--public-url" => {
+                let url = value()?;
+                let endpoint = url.parse().map_err(|e| format!("invalid --public-url: {}", e))?;
+                args.public_endpoint = Some(endpoint);
+            }
+            "--method-override" => args.method_override = MethodOverride::Overridden,
+            _ => return Err(format!("unknown argument `{}`", arg)),
+        }
+    }
+    if args.credentials.is_empty() {
+        return Err("--credentials is required".to_string());
+    }
+    Ok(args)
+}
+
+fn serve<P: CredentialsProvider>(stream: TcpStream, authorizer: &Authorizer<P>) {
+    let mut writer = match stream.try_clone() {
+        Ok(stream) => BufWriter::new(stream),
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    while let Ok(Some(request)) = http::read_request(&mut reader) {
+        let response = authorizer.authorize(&request);
+        if response.write_to(&mut writer).is_err() || request.wants_close() {
+            break;
+        }
+    }
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|e| {
+        eprintln!("hawk-authz: {}", e);
+        process::exit(2);
+    });
+
+    let path = args.credentials.clone();
+    let fetch = move || {
+        fs::read_to_string(&path).map_err(|e| format!("cannot read {}: {}", path, e).into())
+    };
+    let provider = RemoteCredentialsProvider::new(fetch, Duration::seconds(args.refresh))
+        .unwrap_or_else(|e| {
+            eprintln!("hawk-authz: cannot load credentials: {}", e);
+            process::exit(1);
+        });
+    let options = ValidationOptions::default().with_ts_skew(Duration::seconds(args.ts_skew));
+    let mut authorizer = Authorizer::new(provider, options, args.mode)
+        .with_path_prefix(args.path_prefix)
+        .with_default_scheme(args.default_scheme)
+        .with_method_override(args.method_override);
+    if let Some(endpoint) = args.public_endpoint {
+        authorizer = authorizer.with_public_endpoint(endpoint);
+    }
+    let authorizer = Arc::new(authorizer);
+
+    let listen = args.listen;
+    let listener = TcpListener::bind(&listen[..]).unwrap_or_else(|e| {
+        eprintln!("hawk-authz: cannot listen on {}: {}", listen, e);
+        process::exit(1);
+    });
+    for stream in listener.incoming().flatten() {
+        let authorizer = authorizer.clone();
+        thread::spawn(move || serve(stream, &authorizer));
+    }
+}
@@ -0,0 +1,411 @@
+use base64;
+use header::{base64_len, decimal_len};
+use mac_bytes::MacBytes;
+use stage::Stage;
+use std::str;
+use std::str::FromStr;
+use time::Timespec;
+use std::borrow::Cow;
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::fmt::Write;
+
+/// A Bewit is a piece of data attached to a GET request that functions in place of a Hawk
+/// Authentication header.  It contains an id, a timestamp, a MAC, and an optional `ext` value.
+/// These are available using accessor functions.
+#[derive(Clone, Debug)]
+pub struct Bewit<'a> {
+    id: Cow<'a, str>,
+    exp: Timespec,
+    mac: MacBytes,
+    ext: Option<Cow<'a, str>>,
+}
+
+impl<'a> Bewit<'a> {
+    /// Create a new Bewit with the given values.
+    ///
+    /// See Request.make_bewit, in the `hawk` crate, for an easier way to make a Bewit
+    pub fn new(id: &'a str, exp: Timespec, mac: MacBytes, ext: Option<&'a str>) -> Bewit<'a> {
+        Bewit {
+            id: Cow::Borrowed(id),
+            exp: exp,
+            mac,
+            ext: match ext {
+                Some(s) => Some(Cow::Borrowed(s)),
+                None => None,
+            },
+        }
+    }
+
+    /// Generate the fully-encoded string for this Bewit
+    pub fn to_str(&self) -> String {
+        let mut encoded = String::with_capacity(self.encoded_len() - "bewit=".len());
+        self.encode_into(&mut encoded);
+        encoded
+    }
+
+    /// Append the fully-encoded string for this Bewit to the given buffer.  This allows a
+    /// caller building a URL to encode the bewit in place.  The pieces of the bewit are encoded
+    /// straight into `buf`, so nothing else is allocated, and `buf` is not reallocated if it has
+    /// room for `encoded_len()` more bytes, less the `bewit=` prefix.
+    pub fn encode_into(&self, buf: &mut String) {
+        buf.reserve(self.encoded_len() - "bewit=".len());
+        let mut encoder = ChunkedEncoder::new(buf);
+        encoder.push(self.id.as_bytes());
+        // writing to a ChunkedEncoder cannot fail
+        let _ = write!(encoder, "\\{}\\", self.exp.sec);
+        encoder.push_base64(&self.mac);
+        encoder.push(b"\\");
+        encoder.push(self.ext().unwrap_or("").as_bytes());
+        encoder.finish();
+    }
+
+    /// Get the exact length, in bytes, of the `bewit=..` query parameter for this Bewit,
+    /// including the `bewit=` prefix but not any `?` or `&` separating it from the rest of the
+    /// query.
+    pub fn encoded_len(&self) -> usize {
+        let raw = self.id.len() + decimal_len(self.exp.sec) + base64_len(self.mac.len(), true) +
+                  self.ext().map_or(0, |ext| ext.len()) + 3;
+        "bewit=".len() + base64_len(raw, false)
+    }
+
+    /// Get the Bewit's client identifier
+    pub fn id(&self) -> &str {
+        self.id.as_ref()
+    }
+
+    /// Get the expiration time of the bewit
+    pub fn exp(&self) -> Timespec {
+        self.exp
+    }
+
+    /// Get the MAC included in the Bewit
+    pub fn mac(&self) -> &MacBytes {
+        &self.mac
+    }
+
+    /// Get the Bewit's `ext` field.
+    pub fn ext(&self) -> Option<&str> {
+        match self.ext {
+            Some(ref cow) => Some(cow.as_ref()),
+            None => None,
+        }
+    }
+
+    /// Convert this Bewit into one which owns all of its data, and thus does not borrow from
+    /// the credentials or request used to create it.
+    pub fn into_owned(self) -> Bewit<'static> {
+        Bewit {
+            id: Cow::Owned(self.id.into_owned()),
+            exp: self.exp,
+            mac: self.mac,
+            ext: self.ext.map(|ext| Cow::Owned(ext.into_owned())),
+        }
+    }
+}
+
+const BACKSLASH: u8 = b'\\';
+
+/// The standard base64 alphabet, used for the MAC within a bewit.
+const STANDARD_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// URL-safe, unpadded base64 encoding of input supplied in pieces, appended to a `String`.
+///
+/// Input is gathered in a stack buffer and encoded a whole number of 3-byte groups at a time, so
+/// the output is the same as encoding the concatenated input in one go.
+struct ChunkedEncoder<'b> {
+    buf: &'b mut String,
+    pending: [u8; 48],
+    len: usize,
+}
+
+impl<'b> ChunkedEncoder<'b> {
+    fn new(buf: &'b mut String) -> ChunkedEncoder<'b> {
+        ChunkedEncoder {
+            buf,
+            pending: [0; 48],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let n = cmp::min(bytes.len(), self.pending.len() - self.len);
+            self.pending[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+            self.len += n;
+            bytes = &bytes[n..];
+            if self.len == self.pending.len() {
+                base64::encode_config_buf(&self.pending[..], base64::URL_SAFE_NO_PAD, self.buf);
+                self.len = 0;
+            }
+        }
+    }
+
+    /// Push the standard, padded base64 encoding of `bytes`.
+    fn push_base64(&mut self, bytes: &[u8]) {
+        for group in bytes.chunks(3) {
+            let bits = group.iter()
+                .enumerate()
+                .fold(0u32, |bits, (i, &b)| bits | u32::from(b) << (16 - 8 * i));
+            let mut quad = [b'='; 4];
+            for (i, c) in quad.iter_mut().take(group.len() + 1).enumerate() {
+                *c = STANDARD_ALPHABET[(bits >> (18 - 6 * i)) as usize & 0x3f];
+            }
+            self.push(&quad);
+        }
+    }
+
+    fn finish(self) {
+        base64::encode_config_buf(&self.pending[..self.len], base64::URL_SAFE_NO_PAD, self.buf);
+    }
+}
+
+impl<'b> Write for ChunkedEncoder<'b> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// The ways in which parsing or validating a bewit can fail.  Validation, which returns the
+/// `MacMismatch`, `OutOfScope`, and `Expired` variants, is in the `hawk` crate.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BewitError {
+    /// The bewit is not valid base64.
+    Base64,
+    /// The decoded bewit does not have four `\`-separated parts; contains the number found.
+    WrongPartCount(usize),
+    /// The id is not valid UTF-8.
+    BadId,
+    /// The expiration time is not an integer.
+    BadExp,
+    /// The MAC is not valid base64.
+    BadMac,
+    /// The ext is not valid UTF-8.
+    BadExt,
+    /// The bewit's MAC does not match the request.
+    MacMismatch,
+    /// The bewit is a genuine scoped bewit, but the request's path is not within its scope.
+    OutOfScope,
+    /// The bewit is genuine, but expired at the given time.
+    Expired { at: Timespec },
+}
+
+impl BewitError {
+    /// Get the stage of authentication at which this error occurred.
+    pub fn stage(&self) -> Stage {
+        match *self {
+            BewitError::Base64 |
+            BewitError::WrongPartCount(_) |
+            BewitError::BadId |
+            BewitError::BadExp |
+            BewitError::BadMac |
+            BewitError::BadExt => Stage::Parse,
+            BewitError::MacMismatch => Stage::Crypto,
+            BewitError::OutOfScope => Stage::Policy,
+            BewitError::Expired { .. } => Stage::Clock,
+        }
+    }
+}
+
+impl fmt::Display for BewitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BewitError::Base64 => f.write_str("Error decoding bewit base64"),
+            BewitError::WrongPartCount(n) => {
+                write!(f, "Invalid bewit format: expected 4 parts, found {}", n)
+            }
+            BewitError::BadId => f.write_str("Invalid bewit id"),
+            BewitError::BadExp => f.write_str("Invalid bewit exp"),
+            BewitError::BadMac => f.write_str("Invalid bewit mac"),
+            BewitError::BadExt => f.write_str("Invalid bewit ext"),
+            BewitError::MacMismatch => f.write_str("Bewit MAC does not match request"),
+            BewitError::OutOfScope => f.write_str("Request path is outside the bewit's scope"),
+            BewitError::Expired { at } => write!(f, "Bewit expired at {}", at.sec),
+        }
+    }
+}
+
+impl error::Error for BewitError {}
+
+impl<'a> FromStr for Bewit<'a> {
+    type Err = BewitError;
+    fn from_str(bewit: &str) -> ::std::result::Result<Bewit<'a>, BewitError> {
+        let bewit = base64::decode(bewit).map_err(|_| BewitError::Base64)?;
+
+        let mut parts = bewit.split(|c| *c == BACKSLASH);
+        let (id, exp, mac, ext) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(id), Some(exp), Some(mac), Some(ext)) if parts.next().is_none() => {
+                (id, exp, mac, ext)
+            }
+            _ => {
+                return Err(BewitError::WrongPartCount(bewit.split(|c| *c == BACKSLASH).count()))
+            }
+        };
+
+        let id = String::from_utf8(id.to_vec()).map_err(|_| BewitError::BadId)?;
+
+        let exp = str::from_utf8(exp).map_err(|_| BewitError::BadExp)?;
+        let exp = i64::from_str(exp).map_err(|_| BewitError::BadExp)?;
+        let exp = Timespec::new(exp, 0);
+
+        let mac = str::from_utf8(mac).map_err(|_| BewitError::BadMac)?;
+        let mac = MacBytes::from(base64::decode(mac).map_err(|_| BewitError::BadMac)?);
+
+        let ext = if ext.is_empty() {
+            None
+        } else {
+            Some(Cow::Owned(String::from_utf8(ext.to_vec()).map_err(|_| BewitError::BadExt)?))
+        };
+
+        Ok(Bewit {
+            id: Cow::Owned(id),
+            exp,
+            mac,
+            ext,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn make_mac() -> MacBytes {
+        MacBytes::from(base64::decode("fiy4e5wBdapDNxHHeA18NrSrU1U3iS66gm0XjTJpYyU=").unwrap())
+    }
+
+    #[test]
+    fn test_to_str() {
+        let bewit = Bewit::new("me", Timespec::new(1353832834, 0), make_mac(), None);
+        assert_eq!(bewit.to_str(),
+                   "bWVcMTM1MzgzMjgzNFxmaXk0ZTV3QmRhcEROeEhIZUExOE5yU3JVMVUzaVM2NmdtMFhqVEpwWXlVPVw");
+        let bewit = Bewit::new("me", Timespec::new(1353832834, 0), make_mac(), Some("abcd"));
+        assert_eq!(bewit.to_str(),
+                   "bWVcMTM1MzgzMjgzNFxmaXk0ZTV3QmRhcEROeEhIZUExOE5yU3JVMVUzaVM2NmdtMFhqVEpwWXlVPVxhYmNk");
+    }
+
+    #[test]
+    fn test_encoded_len() {
+        let bewit = Bewit::new("me", Timespec::new(1353832834, 0), make_mac(), None);
+        assert_eq!(bewit.encoded_len(), format!("bewit={}", bewit.to_str()).len());
+        let bewit = Bewit::new("me", Timespec::new(1353832834, 0), make_mac(), Some("abcd"));
+        assert_eq!(bewit.encoded_len(), format!("bewit={}", bewit.to_str()).len());
+        let bewit = Bewit::new("someone", Timespec::new(0, 0), make_mac(), Some("a"));
+        assert_eq!(bewit.encoded_len(), format!("bewit={}", bewit.to_str()).len());
+    }
+
+    #[test]
+    fn test_encode_into() {
+        let bewit = Bewit::new("me", Timespec::new(1353832834, 0), make_mac(), Some("abcd"));
+        let mut url = "/resource?bewit=".to_string();
+        bewit.encode_into(&mut url);
+        assert_eq!(url, format!("/resource?bewit={}", bewit.to_str()));
+        assert_eq!(Bewit::from_str(&url[16..]).unwrap().ext(), Some("abcd"));
+    }
+
+    #[test]
+    fn test_encode_into_does_not_reallocate() {
+        let bewit = Bewit::new("someone", Timespec::new(1353832834, 0), make_mac(),
+                               Some("an ext value long enough to span several chunks"));
+        let mut buf = String::with_capacity(bewit.encoded_len());
+        buf.push_str("bewit=");
+        let (ptr, capacity) = (buf.as_ptr(), buf.capacity());
+        bewit.encode_into(&mut buf);
+        assert_eq!(buf.len(), bewit.encoded_len());
+        assert_eq!((buf.as_ptr(), buf.capacity()), (ptr, capacity));
+    }
+
+    #[test]
+    fn test_encode_into_chunk_boundaries() {
+        // every alignment of the pieces against the encoder's chunks gives the same result as
+        // encoding the whole bewit at once
+        let ext = "x".repeat(100);
+        for len in 0..ext.len() {
+            let bewit = Bewit::new("me", Timespec::new(1353832834, 0), make_mac(),
+                                   Some(&ext[..len]));
+            let raw = format!("me\\1353832834\\{}\\{}", base64::encode(&bewit.mac()[..]),
+                              &ext[..len]);
+            assert_eq!(bewit.to_str(), base64::encode_config(&raw, base64::URL_SAFE_NO_PAD));
+        }
+        let mac = MacBytes::from(&[1u8, 2, 3, 4, 5][..]);
+        for len in 1..mac.len() + 1 {
+            let bewit = Bewit::new("me", Timespec::new(-5, 0), MacBytes::from(&mac[..len]), None);
+            let raw = format!("me\\-5\\{}\\", base64::encode(&mac[..len]));
+            assert_eq!(bewit.to_str(), base64::encode_config(&raw, base64::URL_SAFE_NO_PAD));
+        }
+    }
+
+    #[test]
+    fn test_accessors() {
+        let bewit = Bewit::from_str("bWVcMTM1MzgzMjgzNFxmaXk0ZTV3QmRhcEROeEhIZUExOE5yU3JVMVUzaVM2NmdtMFhqVEpwWXlVPVw").unwrap();
+        assert_eq!(bewit.id(), "me");
+        assert_eq!(bewit.exp(), Timespec::new(1353832834, 0));
+        assert_eq!(bewit.mac(), &make_mac());
+        assert_eq!(bewit.ext(), None);
+    }
+
+    #[test]
+    fn test_from_str_invalid_base64() {
+        assert_eq!(Bewit::from_str("!/==").unwrap_err(), BewitError::Base64);
+    }
+
+    #[test]
+    fn test_from_str_invalid_too_many_parts() {
+        let bewit = base64::encode(&"a\\123\\abc\\ext\\WHUT?".as_bytes());
+        assert_eq!(Bewit::from_str(&bewit).unwrap_err(), BewitError::WrongPartCount(5));
+    }
+
+    #[test]
+    fn test_from_str_invalid_too_few_parts() {
+        let bewit = base64::encode(&"a\\123\\abc".as_bytes());
+        assert_eq!(Bewit::from_str(&bewit).unwrap_err(), BewitError::WrongPartCount(3));
+    }
+
+    #[test]
+    fn test_from_str_invalid_exp() {
+        let bewit = base64::encode(&"a\\soon\\AAAA\\".as_bytes());
+        assert_eq!(Bewit::from_str(&bewit).unwrap_err(), BewitError::BadExp);
+    }
+
+    #[test]
+    fn test_from_str_invalid_mac() {
+        let bewit = base64::encode(&"a\\123\\!!!!\\".as_bytes());
+        assert_eq!(Bewit::from_str(&bewit).unwrap_err(), BewitError::BadMac);
+    }
+
+    #[test]
+    fn test_from_str_invalid_parts() {
+        let valid_mac = b"AAAA";
+        let mut bewit = vec![159u8, b'\\'];
+        bewit.extend_from_slice(b"123\\");
+        bewit.extend_from_slice(valid_mac);
+        bewit.push(b'\\');
+        assert_eq!(Bewit::from_str(&base64::encode(&bewit)).unwrap_err(), BewitError::BadId);
+
+        let mut bewit = b"a\\123\\".to_vec();
+        bewit.extend_from_slice(valid_mac);
+        bewit.extend_from_slice(&[b'\\', 159u8]);
+        assert_eq!(Bewit::from_str(&base64::encode(&bewit)).unwrap_err(), BewitError::BadExt);
+    }
+
+    #[test]
+    fn test_from_str_invalid_not_utf8() {
+        let a = 'a' as u8;
+        let one = '1' as u8;
+        let slash = '\\' as u8;
+        let invalid1 = 0u8;
+        let invalid2 = 159u8;
+        let bewit = base64::encode(&[invalid1, invalid2, slash, one, slash, a, slash, a]);
+        assert!(Bewit::from_str(&bewit).is_err());
+        let bewit = base64::encode(&[a, slash, invalid1, invalid2, slash, a, slash, a]);
+        assert!(Bewit::from_str(&bewit).is_err());
+        let bewit = base64::encode(&[a, slash, one, slash, invalid1, invalid2, slash, a]);
+        assert!(Bewit::from_str(&bewit).is_err());
+        let bewit = base64::encode(&[a, slash, one, slash, a, slash, invalid1, invalid2]);
+        assert!(Bewit::from_str(&bewit).is_err());
+    }
+}
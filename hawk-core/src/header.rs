@@ -0,0 +1,1077 @@
+use base64;
+use std::cell::RefCell;
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+use header_field::HeaderField;
+use header_parser::{Fields, HeaderSyntaxError, ParseMode};
+use mac_bytes::{InlineBytes, MacBytes};
+use stage::Stage;
+use time::Timespec;
+
+/// Representation of a Hawk `Authorization` header value (the part following "Hawk ").
+///
+/// Headers can be derived froms trings using the `FromStr` trait, and formatted into a
+/// string using the `fmt_header` method.
+///
+/// All fields are optional, although for specific purposes some fields must be present.  Fields
+/// are available using accessor functions, and can be replaced with the `with_*` methods, which
+/// enforce the same checks as `Header::new`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Header {
+    id: Option<String>,
+    ts: Option<Timespec>,
+    nonce: Option<String>,
+    mac: Option<MacBytes>,
+    ext: Option<String>,
+    hash: Option<InlineBytes>,
+    app: Option<String>,
+    dlg: Option<String>,
+}
+
+/// The ways in which making or parsing a header can fail.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HeaderError {
+    /// The header value is malformed, or has an unknown field or an invalid field value.
+    Syntax(HeaderSyntaxError),
+    /// A field value contains `"`.
+    Quote,
+    /// A field value contains a control character.
+    ControlCharacter,
+    /// The header does not survive a round trip through its wire format; see
+    /// `Header::canonicalize`.
+    NotCanonical,
+}
+
+impl HeaderError {
+    /// Get the stage of authentication at which this error occurred.
+    pub fn stage(&self) -> Stage {
+        match *self {
+            HeaderError::Syntax(_) => Stage::Parse,
+            HeaderError::Quote | HeaderError::ControlCharacter | HeaderError::NotCanonical => {
+                Stage::Other
+            }
+        }
+    }
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HeaderError::Syntax(_) => f.write_str("Unparseable Hawk header"),
+            HeaderError::Quote => f.write_str("Hawk headers cannot contain `\"`"),
+            HeaderError::ControlCharacter => {
+                f.write_str("Hawk headers cannot contain control characters")
+            }
+            HeaderError::NotCanonical => f.write_str("Header does not survive a round trip"),
+        }
+    }
+}
+
+impl error::Error for HeaderError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            HeaderError::Syntax(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+thread_local! {
+    /// A buffer for decoding hashes and MACs during parsing, reused to avoid allocating for
+    /// each header.
+    static DECODE_BUF: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Decode a base64-encoded hash or MAC without allocating.
+fn decode_inline(value: &str) -> Result<InlineBytes, base64::DecodeError> {
+    DECODE_BUF.with(|buf| {
+        let mut buf = buf.borrow_mut();
+        buf.clear();
+        base64::decode_config_buf(value, base64::STANDARD, &mut buf)?;
+        Ok(InlineBytes::from_slice(&buf))
+    })
+}
+
+/// The number of characters in the base64 encoding of `len` bytes, with or without padding.
+pub(crate) fn base64_len(len: usize, pad: bool) -> usize {
+    if pad {
+        len.div_ceil(3) * 4
+    } else {
+        len / 3 * 4 + [0, 2, 3][len % 3]
+    }
+}
+
+/// The number of characters in the decimal representation of `value`.
+pub(crate) fn decimal_len(value: i64) -> usize {
+    let mut len = if value < 0 { 2 } else { 1 };
+    let mut value = value.unsigned_abs();
+    while value >= 10 {
+        value /= 10;
+        len += 1;
+    }
+    len
+}
+
+impl Header {
+    /// Create a new Header with the full set of Hawk fields.
+    ///
+    /// This is a low-level funtion. Headers are more often created from Request or Responses, in
+    /// the `hawk` crate.
+    ///
+    /// Note that none of the string-formatted header components can contain the character `\"`.
+    pub fn new<S>(id: Option<S>,
+                  ts: Option<Timespec>,
+                  nonce: Option<S>,
+                  mac: Option<MacBytes>,
+                  ext: Option<S>,
+                  hash: Option<Vec<u8>>,
+                  app: Option<S>,
+                  dlg: Option<S>)
+                  -> Result<Header, HeaderError>
+        where S: Into<String>
+    {
+        Ok(Header {
+            id: Header::check_component(id)?,
+            ts: ts,
+            nonce: Header::check_component(nonce)?,
+            mac: mac,
+            ext: Header::check_component(ext)?,
+            hash: hash.map(|hash| InlineBytes::from_slice(&hash)),
+            app: Header::check_component(app)?,
+            dlg: Header::check_component(dlg)?,
+        })
+    }
+
+    /// Check a header component for validity.
+    fn check_component<S>(value: Option<S>) -> Result<Option<String>, HeaderError>
+        where S: Into<String>
+    {
+        if let Some(value) = value {
+            let value = value.into();
+            Header::check_str(&value)?;
+            Ok(Some(value))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Check a borrowed header component for validity.  Components cannot contain `"`, which
+    /// would end the quoted value, or control characters such as CR and LF, which would allow
+    /// a value to inject other HTTP headers.
+    pub fn check_str(value: &str) -> Result<(), HeaderError> {
+        if value.contains('\"') {
+            return Err(HeaderError::Quote);
+        }
+        if value.contains(char::is_control) {
+            return Err(HeaderError::ControlCharacter);
+        }
+        Ok(())
+    }
+
+    /// Get the Hawk `id` field.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_ref().map(|id| &id[..])
+    }
+
+    /// Get the Hawk `ts` field.
+    pub fn ts(&self) -> Option<Timespec> {
+        self.ts
+    }
+
+    /// Get the Hawk `nonce` field.
+    pub fn nonce(&self) -> Option<&str> {
+        self.nonce.as_ref().map(|nonce| &nonce[..])
+    }
+
+    /// Get the Hawk `mac` field.
+    pub fn mac(&self) -> Option<&MacBytes> {
+        self.mac.as_ref()
+    }
+
+    /// Get the Hawk `ext` field.
+    pub fn ext(&self) -> Option<&str> {
+        self.ext.as_ref().map(|ext| &ext[..])
+    }
+
+    /// Get the Hawk `hash` field.
+    pub fn hash(&self) -> Option<&[u8]> {
+        self.hash.as_ref().map(|hash| &hash[..])
+    }
+
+    /// Get the Hawk `app` field.
+    pub fn app(&self) -> Option<&str> {
+        self.app.as_ref().map(|app| &app[..])
+    }
+
+    /// Get the Hawk `dlg` field.
+    pub fn dlg(&self) -> Option<&str> {
+        self.dlg.as_ref().map(|dlg| &dlg[..])
+    }
+
+    /// Get the fields required to authenticate a request (`id`, `ts`, `nonce`, and `mac`) which
+    /// are missing from this header.
+    ///
+    /// This is a cheap check that a server can make before looking up credentials, in order to
+    /// reject obviously incomplete headers early.
+    pub fn missing_request_fields(&self) -> Vec<HeaderField> {
+        let mut missing = vec![];
+        if self.id.is_none() {
+            missing.push(HeaderField::Id);
+        }
+        if self.ts.is_none() {
+            missing.push(HeaderField::Ts);
+        }
+        if self.nonce.is_none() {
+            missing.push(HeaderField::Nonce);
+        }
+        if self.mac.is_none() {
+            missing.push(HeaderField::Mac);
+        }
+        missing
+    }
+
+    /// Get the fields required to authenticate a response (just `mac`) which are missing from
+    /// this header.
+    pub fn missing_response_fields(&self) -> Vec<HeaderField> {
+        let mut missing = vec![];
+        if self.mac.is_none() {
+            missing.push(HeaderField::Mac);
+        }
+        missing
+    }
+
+    /// Determine whether this header has all of the fields required to authenticate a request.
+    pub fn is_complete_for_request(&self) -> bool {
+        self.missing_request_fields().is_empty()
+    }
+
+    /// Determine whether this header has all of the fields required to authenticate a response.
+    pub fn is_complete_for_response(&self) -> bool {
+        self.missing_response_fields().is_empty()
+    }
+
+    /// Replace the Hawk `id` field, subject to the same checks as `Header::new`.
+    pub fn with_id<S>(mut self, id: Option<S>) -> Result<Header, HeaderError>
+        where S: Into<String>
+    {
+        self.id = Header::check_component(id)?;
+        Ok(self)
+    }
+
+    /// Replace the Hawk `ts` field.
+    pub fn with_ts(mut self, ts: Option<Timespec>) -> Header {
+        self.ts = ts;
+        self
+    }
+
+    /// Replace the Hawk `nonce` field, subject to the same checks as `Header::new`.
+    pub fn with_nonce<S>(mut self, nonce: Option<S>) -> Result<Header, HeaderError>
+        where S: Into<String>
+    {
+        self.nonce = Header::check_component(nonce)?;
+        Ok(self)
+    }
+
+    /// Replace the Hawk `mac` field.
+    pub fn with_mac(mut self, mac: Option<MacBytes>) -> Header {
+        self.mac = mac;
+        self
+    }
+
+    /// Replace the Hawk `ext` field, subject to the same checks as `Header::new`.
+    pub fn with_ext<S>(mut self, ext: Option<S>) -> Result<Header, HeaderError>
+        where S: Into<String>
+    {
+        self.ext = Header::check_component(ext)?;
+        Ok(self)
+    }
+
+    /// Replace the Hawk `hash` field.
+    pub fn with_hash(mut self, hash: Option<Vec<u8>>) -> Header {
+        self.hash = hash.map(|hash| InlineBytes::from_slice(&hash));
+        self
+    }
+
+    /// Replace the Hawk `app` field, subject to the same checks as `Header::new`.
+    pub fn with_app<S>(mut self, app: Option<S>) -> Result<Header, HeaderError>
+        where S: Into<String>
+    {
+        self.app = Header::check_component(app)?;
+        Ok(self)
+    }
+
+    /// Replace the Hawk `dlg` field, subject to the same checks as `Header::new`.
+    pub fn with_dlg<S>(mut self, dlg: Option<S>) -> Result<Header, HeaderError>
+        where S: Into<String>
+    {
+        self.dlg = Header::check_component(dlg)?;
+        Ok(self)
+    }
+
+    /// Get the exact length, in bytes, of the complete `Authorization` header value for this
+    /// header, including the `"Hawk "` prefix.
+    ///
+    /// This is useful for checking, before sending a request, that a header with a long `ext`
+    /// or `app` will fit within the header size limits imposed by proxies.
+    pub fn size(&self) -> usize {
+        // each field is formatted as `name="value"`, with fields separated by `, `
+        fn field(name: &str, len: usize) -> usize {
+            name.len() + len + 3
+        }
+
+        let fields = [self.id.as_ref().map(|id| field("id", id.len())),
+                          self.ts.map(|ts| field("ts", decimal_len(ts.sec))),
+                          self.nonce.as_ref().map(|nonce| field("nonce", nonce.len())),
+                          self.mac.as_ref().map(|mac| field("mac", base64_len(mac.len(), true))),
+                          self.ext.as_ref().map(|ext| field("ext", ext.len())),
+                          self.hash
+                              .as_ref()
+                              .map(|hash| field("hash", base64_len(hash.len(), true))),
+                          self.app.as_ref().map(|app| field("app", app.len())),
+                          self.dlg.as_ref().map(|dlg| field("dlg", dlg.len()))];
+        let present = fields.iter().flatten().count();
+        let separators = present.saturating_sub(1) * 2;
+        "Hawk ".len() + separators + fields.iter().flatten().sum::<usize>()
+    }
+
+    /// Check that this header survives a round trip through its wire format: that formatting it,
+    /// parsing the result, and formatting that again produces the same bytes, and the same
+    /// fields.  Returns the parsed header, which differs from this one only in discarding any
+    /// sub-second part of `ts`, which is not transmitted.
+    ///
+    /// Headers made by the `hawk` crate are checked this way in debug builds.
+    pub fn canonicalize(&self) -> Result<Header, HeaderError> {
+        let formatted = self.to_string();
+        let parsed = Header::from_str(&formatted).map_err(|_| HeaderError::NotCanonical)?;
+        let expected = self.clone().with_ts(self.ts.map(|ts| Timespec::new(ts.sec, 0)));
+        if parsed != expected || parsed.to_string() != formatted {
+            return Err(HeaderError::NotCanonical);
+        }
+        Ok(parsed)
+    }
+
+    /// Format the header for transmission in an Authorization header, omitting the `"Hawk "`
+    /// prefix.
+    ///
+    /// Components are checked when the header is created or parsed, but are checked again here,
+    /// so that a header can never be formatted with a `"` or control character in a value.
+    pub fn fmt_header(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let components = [&self.id, &self.nonce, &self.ext, &self.app, &self.dlg];
+        if components.iter().filter_map(|c| c.as_ref()).any(|c| Header::check_str(c).is_err()) {
+            return Err(fmt::Error);
+        }
+        let mut sep = "";
+        if let Some(ref id) = self.id {
+            write!(f, "{}id=\"{}\"", sep, id)?;
+            sep = ", ";
+        }
+        if let Some(ref ts) = self.ts {
+            write!(f, "{}ts=\"{}\"", sep, ts.sec)?;
+            sep = ", ";
+        }
+        if let Some(ref nonce) = self.nonce {
+            write!(f, "{}nonce=\"{}\"", sep, nonce)?;
+            sep = ", ";
+        }
+        if let Some(ref mac) = self.mac {
+            write!(f, "{}mac=\"{}\"", sep, base64::encode(mac))?;
+            sep = ", ";
+        }
+        if let Some(ref ext) = self.ext {
+            write!(f, "{}ext=\"{}\"", sep, ext)?;
+            sep = ", ";
+        }
+        if let Some(ref hash) = self.hash {
+            write!(f, "{}hash=\"{}\"", sep, base64::encode(hash))?;
+            sep = ", ";
+        }
+        if let Some(ref app) = self.app {
+            write!(f, "{}app=\"{}\"", sep, app)?;
+            sep = ", ";
+        }
+        if let Some(ref dlg) = self.dlg {
+            write!(f, "{}dlg=\"{}\"", sep, dlg)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_header(f)
+    }
+}
+
+impl Header {
+    /// Parse a header value (the part following "Hawk ") in the given mode.  `from_str` parses
+    /// in `ParseMode::Lenient`.
+    pub fn parse_with_mode(s: &str, mode: ParseMode) -> Result<Header, HeaderError> {
+        Ok(HeaderRef::parse_with_mode(s, mode)?.to_header())
+    }
+}
+
+impl FromStr for Header {
+    type Err = HeaderError;
+    fn from_str(s: &str) -> Result<Header, HeaderError> {
+        Ok(HeaderRef::parse(s)?.to_header())
+    }
+}
+
+/// The names of the fields of a Hawk header.
+const FIELD_NAMES: &[&str] = &["id", "ts", "nonce", "mac", "ext", "hash", "app", "dlg"];
+
+/// A parsed Hawk header which borrows its string fields from the input.
+///
+/// Parsing a `HeaderRef` makes no allocations: string fields refer to the parsed input, and the
+/// MAC and hash are stored inline.  This suits proxies which parse many headers, examine a few
+/// fields, and discard them.  Use `to_header` to convert to an owned `Header`, for example to
+/// validate it with the `hawk` crate.
+#[derive(Clone, PartialEq, Debug)]
+pub struct HeaderRef<'a> {
+    id: Option<&'a str>,
+    ts: Option<Timespec>,
+    nonce: Option<&'a str>,
+    mac: Option<InlineBytes>,
+    ext: Option<&'a str>,
+    hash: Option<InlineBytes>,
+    app: Option<&'a str>,
+    dlg: Option<&'a str>,
+}
+
+impl<'a> HeaderRef<'a> {
+    /// Parse a header value (the part following "Hawk "), as for `Header::from_str`.
+    pub fn parse(s: &'a str) -> Result<HeaderRef<'a>, HeaderError> {
+        HeaderRef::parse_with_mode(s, ParseMode::Lenient)
+    }
+
+    /// Parse a header value (the part following "Hawk ") in the given mode.
+    ///
+    /// Syntax errors, unknown fields, and invalid field values fail with `HeaderError::Syntax`,
+    /// giving the location of the problem.  The `hawk` crate reports these as an
+    /// `ErrorKind::HeaderParseError`, caused by the `HeaderSyntaxError`.
+    pub fn parse_with_mode(s: &'a str, mode: ParseMode) -> Result<HeaderRef<'a>, HeaderError> {
+        // Required attributes
+        let mut id: Option<&str> = None;
+        let mut ts: Option<Timespec> = None;
+        let mut nonce: Option<&str> = None;
+        let mut mac: Option<InlineBytes> = None;
+        // Optional attributes
+        let mut hash: Option<InlineBytes> = None;
+        let mut ext: Option<&str> = None;
+        let mut app: Option<&str> = None;
+        let mut dlg: Option<&str> = None;
+
+        // errors are static, so that rejecting a malformed header does not allocate beyond the
+        // error itself
+        let syntax_error = HeaderError::Syntax;
+        let invalid = |offset, problem| syntax_error(HeaderSyntaxError::new(offset, problem));
+        for field in Fields::new(s, mode) {
+            let field = field.map_err(syntax_error)?;
+            let mut val = field.value;
+            let name = if mode == ParseMode::Tolerant {
+                let name = FIELD_NAMES.iter()
+                    .find(|name| name.eq_ignore_ascii_case(field.name))
+                    .map_or(field.name, |name| *name);
+                if matches!(name, "ts" | "mac" | "hash") {
+                    val = val.trim();
+                }
+                name
+            } else {
+                field.name
+            };
+            if val.contains(char::is_control) {
+                return Err(invalid(field.offset, "control character in field value"));
+            }
+            let repeated = match name {
+                "id" => id.replace(val).is_some(),
+                "ts" => {
+                    let epoch = i64::from_str(val)
+                        .map_err(|_| invalid(field.offset, "invalid `ts` value"))?;
+                    ts.replace(Timespec::new(epoch, 0)).is_some()
+                }
+                "mac" => {
+                    mac.replace(decode_inline(val)
+                            .map_err(|_| invalid(field.offset, "invalid `mac` value"))?)
+                        .is_some()
+                }
+                "nonce" => nonce.replace(val).is_some(),
+                "ext" => ext.replace(val).is_some(),
+                "hash" => {
+                    hash.replace(decode_inline(val)
+                            .map_err(|_| invalid(field.offset, "invalid `hash` value"))?)
+                        .is_some()
+                }
+                "app" => app.replace(val).is_some(),
+                "dlg" => dlg.replace(val).is_some(),
+                _ => return Err(invalid(field.offset, "unknown field")),
+            };
+            if repeated && mode == ParseMode::Strict {
+                return Err(invalid(field.offset, "repeated field"));
+            }
+        }
+
+        Ok(HeaderRef {
+            id,
+            ts,
+            nonce,
+            mac,
+            ext,
+            hash,
+            app,
+            dlg,
+        })
+    }
+
+    /// Get the Hawk `id` field.
+    pub fn id(&self) -> Option<&'a str> {
+        self.id
+    }
+
+    /// Get the Hawk `ts` field.
+    pub fn ts(&self) -> Option<Timespec> {
+        self.ts
+    }
+
+    /// Get the Hawk `nonce` field.
+    pub fn nonce(&self) -> Option<&'a str> {
+        self.nonce
+    }
+
+    /// Get the Hawk `mac` field.
+    pub fn mac(&self) -> Option<&[u8]> {
+        self.mac.as_ref().map(|mac| &mac[..])
+    }
+
+    /// Get the Hawk `ext` field.
+    pub fn ext(&self) -> Option<&'a str> {
+        self.ext
+    }
+
+    /// Get the Hawk `hash` field.
+    pub fn hash(&self) -> Option<&[u8]> {
+        self.hash.as_ref().map(|hash| &hash[..])
+    }
+
+    /// Get the Hawk `app` field.
+    pub fn app(&self) -> Option<&'a str> {
+        self.app
+    }
+
+    /// Get the Hawk `dlg` field.
+    pub fn dlg(&self) -> Option<&'a str> {
+        self.dlg
+    }
+
+    /// Convert to an owned `Header`.
+    pub fn to_header(&self) -> Header {
+        Header {
+            id: self.id.map(|id| id.to_string()),
+            ts: self.ts,
+            nonce: self.nonce.map(|nonce| nonce.to_string()),
+            mac: self.mac.as_ref().map(|mac| MacBytes(mac.clone())),
+            ext: self.ext.map(|ext| ext.to_string()),
+            hash: self.hash.clone(),
+            app: self.app.map(|app| app.to_string()),
+            dlg: self.dlg.map(|dlg| dlg.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{base64_len, decimal_len, Header, HeaderError, HeaderRef};
+    use header_field::HeaderField;
+    use header_parser::ParseMode;
+    use base64;
+    use time::Timespec;
+    use std::fmt;
+    use std::str::FromStr;
+    use mac_bytes::MacBytes;
+
+    #[test]
+    fn illegal_id() {
+        assert!(Header::new(Some("ab\"cdef"),
+                            Some(Timespec::new(1234, 0)),
+                            Some("nonce"),
+                            Some(MacBytes::from(vec![])),
+                            Some("ext"),
+                            None,
+                            None,
+                            None)
+            .is_err());
+    }
+
+    #[test]
+    fn illegal_nonce() {
+        assert!(Header::new(Some("abcdef"),
+                            Some(Timespec::new(1234, 0)),
+                            Some("no\"nce"),
+                            Some(MacBytes::from(vec![])),
+                            Some("ext"),
+                            None,
+                            None,
+                            None)
+            .is_err());
+    }
+
+    #[test]
+    fn illegal_ext() {
+        assert!(Header::new(Some("abcdef"),
+                            Some(Timespec::new(1234, 0)),
+                            Some("nonce"),
+                            Some(MacBytes::from(vec![])),
+                            Some("ex\"t"),
+                            None,
+                            None,
+                            None)
+            .is_err());
+    }
+
+    #[test]
+    fn illegal_app() {
+        assert!(Header::new(Some("abcdef"),
+                            Some(Timespec::new(1234, 0)),
+                            Some("nonce"),
+                            Some(MacBytes::from(vec![])),
+                            None,
+                            None,
+                            Some("a\"pp"),
+                            None)
+            .is_err());
+    }
+
+    #[test]
+    fn illegal_dlg() {
+        assert!(Header::new(Some("abcdef"),
+                            Some(Timespec::new(1234, 0)),
+                            Some("nonce"),
+                            Some(MacBytes::from(vec![])),
+                            None,
+                            None,
+                            None,
+                            Some("d\"lg"))
+            .is_err());
+    }
+
+    #[test]
+    fn accessors() {
+        let s = Header::new(Some("dh37fgj492je"),
+                            Some(Timespec::new(1353832234, 0)),
+                            Some("j4h3g2"),
+                            Some(MacBytes::from(vec![8, 35, 182])),
+                            Some("my-ext-value"),
+                            Some(vec![1, 2, 3, 4]),
+                            Some("my-app"),
+                            Some("my-dlg"))
+            .unwrap();
+        assert_eq!(s.id(), Some("dh37fgj492je"));
+        assert_eq!(s.ts(), Some(Timespec::new(1353832234, 0)));
+        assert_eq!(s.nonce(), Some("j4h3g2"));
+        assert_eq!(s.mac(), Some(&MacBytes::from(vec![8, 35, 182])));
+        assert_eq!(s.ext(), Some("my-ext-value"));
+        assert_eq!(s.hash(), Some(&[1u8, 2, 3, 4][..]));
+        assert_eq!(s.app(), Some("my-app"));
+        assert_eq!(s.dlg(), Some("my-dlg"));
+    }
+
+    #[test]
+    fn hash_inline() {
+        let hash = vec![7u8; 64];
+        let s = Header::from_str(&format!("hash=\"{}\"", base64::encode(&hash))).unwrap();
+        assert_eq!(s.hash(), Some(&hash[..]));
+        assert!(!s.hash.as_ref().unwrap().spilled());
+
+        // longer hashes are not produced by any supported algorithm, but still work
+        let hash = vec![7u8; 100];
+        let s = Header::from_str(&format!("hash=\"{}\"", base64::encode(&hash))).unwrap();
+        assert_eq!(s.hash(), Some(&hash[..]));
+        let s = s.with_hash(Some(vec![1, 2, 3]));
+        assert_eq!(s.hash(), Some(&[1u8, 2, 3][..]));
+        assert!(!s.hash.as_ref().unwrap().spilled());
+    }
+
+    #[test]
+    fn with_fields() {
+        let s = Header::new::<String>(None, None, None, None, None, None, None, None)
+            .unwrap()
+            .with_id(Some("abcdef"))
+            .unwrap()
+            .with_ts(Some(Timespec::new(1234, 0)))
+            .with_mac(Some(MacBytes::from(vec![1, 2, 3])))
+            .with_ext(Some("ext"))
+            .unwrap()
+            .with_hash(Some(vec![4, 5, 6]));
+        assert_eq!(s.id(), Some("abcdef"));
+        assert_eq!(s.ts(), Some(Timespec::new(1234, 0)));
+        assert_eq!(s.mac(), Some(&MacBytes::from(vec![1, 2, 3])));
+        assert_eq!(s.ext(), Some("ext"));
+        assert_eq!(s.hash(), Some(&[4u8, 5, 6][..]));
+
+        let s = s.with_ext::<String>(None).unwrap();
+        assert_eq!(s.ext(), None);
+    }
+
+    #[test]
+    fn with_fields_illegal() {
+        let s = Header::new::<String>(None, None, None, None, None, None, None, None).unwrap();
+        assert!(s.clone().with_id(Some("ab\"cdef")).is_err());
+        assert!(s.clone().with_nonce(Some("no\"nce")).is_err());
+        assert!(s.clone().with_ext(Some("ex\"t")).is_err());
+        assert!(s.clone().with_app(Some("a\"pp")).is_err());
+        assert!(s.with_dlg(Some("d\"lg")).is_err());
+    }
+
+    #[test]
+    fn control_characters() {
+        let s = Header::new::<String>(None, None, None, None, None, None, None, None).unwrap();
+        assert!(s.clone().with_id(Some("me\r\nX-Injected: yes")).is_err());
+        assert!(s.clone().with_nonce(Some("no\nnce")).is_err());
+        assert!(s.clone().with_ext(Some("ext\r\nX-Injected: yes")).is_err());
+        assert!(s.clone().with_app(Some("a\tpp")).is_err());
+        assert!(s.clone().with_dlg(Some("d\u{7f}lg")).is_err());
+        assert!(s.clone().with_ext(Some("caf\u{e9}")).is_ok());
+        assert_eq!(Header::check_str("x\0").unwrap_err().to_string(),
+                   "Hawk headers cannot contain control characters");
+
+        for mode in &[ParseMode::Strict, ParseMode::Lenient, ParseMode::Tolerant] {
+            assert!(Header::parse_with_mode("id=\"me\", ext=\"a\r\nX-Injected: yes\"", *mode)
+                .is_err());
+            assert!(Header::parse_with_mode("id=\"m\te\"", *mode).is_err());
+        }
+
+        // formatting refuses a component which slipped past the checks
+        let mut s = s.with_id(Some("me")).unwrap();
+        assert_eq!(s.to_string(), "id=\"me\"");
+        s.ext = Some("a\r\nX-Injected: yes".to_string());
+        let mut formatted = String::new();
+        assert!(fmt::write(&mut formatted, format_args!("{}", s)).is_err());
+    }
+
+    #[test]
+    fn complete_for_request() {
+        let s = Header::new(Some("dh37fgj492je"),
+                            Some(Timespec::new(1353832234, 0)),
+                            Some("j4h3g2"),
+                            Some(MacBytes::from(vec![8, 35, 182])),
+                            None,
+                            None,
+                            None,
+                            None)
+            .unwrap();
+        assert!(s.missing_request_fields().is_empty());
+        assert!(s.is_complete_for_request());
+        assert!(s.is_complete_for_response());
+    }
+
+    #[test]
+    fn incomplete_for_request() {
+        let s = Header::new(Some("dh37fgj492je"),
+                            None,
+                            None,
+                            Some(MacBytes::from(vec![8, 35, 182])),
+                            Some("ext"),
+                            None,
+                            None,
+                            None)
+            .unwrap();
+        assert_eq!(s.missing_request_fields(),
+                   vec![HeaderField::Ts, HeaderField::Nonce]);
+        assert!(!s.is_complete_for_request());
+        assert!(s.is_complete_for_response());
+    }
+
+    #[test]
+    fn incomplete_for_response() {
+        let s = Header::new::<String>(None, None, None, None, None, None, None, None).unwrap();
+        assert_eq!(s.missing_request_fields(),
+                   vec![HeaderField::Id, HeaderField::Ts, HeaderField::Nonce, HeaderField::Mac]);
+        assert_eq!(s.missing_response_fields(), vec![HeaderField::Mac]);
+        assert!(!s.is_complete_for_response());
+    }
+
+    #[test]
+    fn header_field_display() {
+        assert_eq!(format!("{}", HeaderField::Nonce), "nonce");
+    }
+
+    #[test]
+    fn from_str() {
+        let s = Header::from_str("id=\"dh37fgj492je\", ts=\"1353832234\", \
+                                      nonce=\"j4h3g2\", ext=\"some-app-ext-data\", \
+                                      mac=\"6R4rV5iE+NPoym+WwjeHzjAGXUtLNIxmo1vpMofpLAE=\", \
+                                      hash=\"6R4rV5iE+NPoym+WwjeHzjAGXUtLNIxmo1vpMofpLAE=\", \
+                                      app=\"my-app\", dlg=\"my-authority\"")
+            .unwrap();
+        assert!(s.id == Some("dh37fgj492je".to_string()));
+        assert!(s.ts == Some(Timespec::new(1353832234, 0)));
+        assert!(s.nonce == Some("j4h3g2".to_string()));
+        assert!(s.mac ==
+                Some(MacBytes::from(vec![233, 30, 43, 87, 152, 132, 248, 211, 232, 202, 111, 150,
+                                         194, 55, 135, 206, 48, 6, 93, 75, 75, 52, 140, 102, 163,
+                                         91, 233, 50, 135, 233, 44, 1])));
+        assert!(s.ext == Some("some-app-ext-data".to_string()));
+        assert!(s.app == Some("my-app".to_string()));
+        assert!(s.dlg == Some("my-authority".to_string()));
+    }
+
+    #[test]
+    fn from_str_invalid_mac() {
+        let r = Header::from_str("id=\"dh37fgj492je\", ts=\"1353832234\", \
+                                      nonce=\"j4h3g2\", ext=\"some-app-ext-data\", \
+                                      mac=\"6!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!AE=\", \
+                                      app=\"my-app\", dlg=\"my-authority\"");
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn from_str_no_field() {
+        let s = Header::from_str("").unwrap();
+        assert!(s.id == None);
+        assert!(s.ts == None);
+        assert!(s.nonce == None);
+        assert!(s.mac == None);
+        assert!(s.ext == None);
+        assert!(s.app == None);
+        assert!(s.dlg == None);
+    }
+
+    #[test]
+    fn from_str_few_field() {
+        let s = Header::from_str("id=\"xyz\", ts=\"1353832234\", \
+                                      nonce=\"abc\", \
+                                      mac=\"6R4rV5iE+NPoym+WwjeHzjAGXUtLNIxmo1vpMofpLAE=\"")
+            .unwrap();
+        assert!(s.id == Some("xyz".to_string()));
+        assert!(s.ts == Some(Timespec::new(1353832234, 0)));
+        assert!(s.nonce == Some("abc".to_string()));
+        assert!(s.mac ==
+                Some(MacBytes::from(vec![233, 30, 43, 87, 152, 132, 248, 211, 232, 202, 111, 150,
+                                         194, 55, 135, 206, 48, 6, 93, 75, 75, 52, 140, 102, 163,
+                                         91, 233, 50, 135, 233, 44, 1])));
+        assert!(s.ext == None);
+        assert!(s.app == None);
+        assert!(s.dlg == None);
+    }
+
+    #[test]
+    fn from_str_messy() {
+        let s = Header::from_str(", id  =  \"dh37fgj492je\", ts=\"1353832234\", \
+                                      nonce=\"j4h3g2\"  , , ext=\"some-app-ext-data\", \
+                                      mac=\"6R4rV5iE+NPoym+WwjeHzjAGXUtLNIxmo1vpMofpLAE=\"")
+            .unwrap();
+        assert!(s.id == Some("dh37fgj492je".to_string()));
+        assert!(s.ts == Some(Timespec::new(1353832234, 0)));
+        assert!(s.nonce == Some("j4h3g2".to_string()));
+        assert!(s.mac ==
+                Some(MacBytes::from(vec![233, 30, 43, 87, 152, 132, 248, 211, 232, 202, 111, 150,
+                                         194, 55, 135, 206, 48, 6, 93, 75, 75, 52, 140, 102, 163,
+                                         91, 233, 50, 135, 233, 44, 1])));
+        assert!(s.ext == Some("some-app-ext-data".to_string()));
+        assert!(s.app == None);
+        assert!(s.dlg == None);
+    }
+
+    #[test]
+    fn parse_strict() {
+        let s = "id=\"dh37fgj492je\", ts=\"1353832234\",nonce=\"j4h3g2\", \
+                 mac=\"6R4rV5iE+NPoym+WwjeHzjAGXUtLNIxmo1vpMofpLAE=\"";
+        assert_eq!(Header::parse_with_mode(s, ParseMode::Strict).unwrap(),
+                   Header::from_str(s).unwrap());
+    }
+
+    #[test]
+    fn parse_tolerant() {
+        let s = "ID=\"dh37fgj492je\", Ts = \" 1353832234 \",NONCE=\"j4h3g2\"\t\
+                 Mac=\" 6R4rV5iE+NPoym+WwjeHzjAGXUtLNIxmo1vpMofpLAE= \", ext=\" x \"";
+        let header = Header::parse_with_mode(s, ParseMode::Tolerant).unwrap();
+        assert_eq!(header.id(), Some("dh37fgj492je"));
+        assert_eq!(header.ts(), Some(Timespec::new(1353832234, 0)));
+        assert_eq!(header.nonce(), Some("j4h3g2"));
+        assert!(header.mac().is_some());
+        // values covered by the MAC are not trimmed
+        assert_eq!(header.ext(), Some(" x "));
+
+        // the default remains case-sensitive
+        assert!(Header::from_str(s).is_err());
+        assert!(Header::parse_with_mode(s, ParseMode::Strict).is_err());
+    }
+
+    #[test]
+    fn parse_strict_errors() {
+        fn diagnostic(s: &str) -> String {
+            match Header::parse_with_mode(s, ParseMode::Strict).unwrap_err() {
+                HeaderError::Syntax(e) => e.to_string(),
+                e => panic!("unexpected error {:?}", e),
+            }
+        }
+
+        // repeated fields are accepted leniently, with the last value winning
+        let repeated = "id=\"a\", id=\"b\"";
+        assert_eq!(Header::from_str(repeated).unwrap().id(), Some("b"));
+        assert_eq!(diagnostic(repeated), "repeated field at byte 8");
+
+        // as is the messy syntax some clients produce
+        let messy = ", id  =  \"dh37fgj492je\"  , , ext=\"some-app-ext-data\"";
+        assert!(Header::from_str(messy).is_ok());
+        assert_eq!(diagnostic(messy), "expected field name at byte 0");
+        assert_eq!(diagnostic("id=\"a\" ts=\"1\""), "expected `,` between fields at byte 6");
+        assert_eq!(diagnostic("id=\"caf\u{e9}\""), "expected `\"` to close field value at byte 7");
+
+        // invalid values and unknown fields are reported in the same way
+        assert_eq!(diagnostic("id=\"a\", ts=\"soon\""), "invalid `ts` value at byte 8");
+        assert_eq!(diagnostic("mac=\"!!\""), "invalid `mac` value at byte 0");
+        assert_eq!(diagnostic("id=\"a\", realm=\"x\""), "unknown field at byte 8");
+    }
+
+    #[test]
+    fn to_str_no_fields() {
+        // must supply a type for S, since it is otherwise unused
+        let s = Header::new::<String>(None, None, None, None, None, None, None, None).unwrap();
+        let formatted = format!("{}", s);
+        println!("got: {}", formatted);
+        assert!(formatted == "")
+    }
+
+    #[test]
+    fn to_str_few_fields() {
+        let s = Header::new(Some("dh37fgj492je"),
+                            Some(Timespec::new(1353832234, 0)),
+                            Some("j4h3g2"),
+                            Some(MacBytes::from(vec![8, 35, 182, 149, 42, 111, 33, 192, 19, 22, 94,
+                                                     43, 118, 176, 65, 69, 86, 4, 156, 184, 85, 107,
+                                                     249, 242, 172, 200, 66, 209, 57, 63, 38, 83])),
+                            None,
+                            None,
+                            None,
+                            None)
+            .unwrap();
+        let formatted = format!("{}", s);
+        println!("got: {}", formatted);
+        assert!(formatted ==
+                "id=\"dh37fgj492je\", ts=\"1353832234\", nonce=\"j4h3g2\", \
+                 mac=\"CCO2lSpvIcATFl4rdrBBRVYEnLhVa/nyrMhC0Tk/JlM=\"")
+    }
+
+    #[test]
+    fn to_str_maximal() {
+        let s = Header::new(Some("dh37fgj492je"),
+                            Some(Timespec::new(1353832234, 0)),
+                            Some("j4h3g2"),
+                            Some(MacBytes::from(vec![8, 35, 182, 149, 42, 111, 33, 192, 19, 22, 94,
+                                                     43, 118, 176, 65, 69, 86, 4, 156, 184, 85, 107,
+                                                     249, 242, 172, 200, 66, 209, 57, 63, 38, 83])),
+                            Some("my-ext-value"),
+                            Some(vec![1, 2, 3, 4]),
+                            Some("my-app"),
+                            Some("my-dlg"))
+            .unwrap();
+        let formatted = format!("{}", s);
+        println!("got: {}", formatted);
+        assert!(formatted ==
+                "id=\"dh37fgj492je\", ts=\"1353832234\", nonce=\"j4h3g2\", \
+                 mac=\"CCO2lSpvIcATFl4rdrBBRVYEnLhVa/nyrMhC0Tk/JlM=\", ext=\"my-ext-value\", \
+                 hash=\"AQIDBA==\", app=\"my-app\", dlg=\"my-dlg\"")
+    }
+
+    #[test]
+    fn round_trip() {
+        let s = Header::new(Some("dh37fgj492je"),
+                            Some(Timespec::new(1353832234, 0)),
+                            Some("j4h3g2"),
+                            Some(MacBytes::from(vec![8, 35, 182, 149, 42, 111, 33, 192, 19, 22, 94,
+                                                     43, 118, 176, 65, 69, 86, 4, 156, 184, 85, 107,
+                                                     249, 242, 172, 200, 66, 209, 57, 63, 38, 83])),
+                            Some("my-ext-value"),
+                            Some(vec![1, 2, 3, 4]),
+                            Some("my-app"),
+                            Some("my-dlg"))
+            .unwrap();
+        let formatted = format!("{}", s);
+        println!("got: {}", s);
+        let s2 = Header::from_str(&formatted).unwrap();
+        assert!(s2 == s);
+    }
+
+    #[test]
+    fn canonicalize() {
+        let s = "id=\"dh37fgj492je\", ts=\"1353832234\", nonce=\"j4h3g2\", \
+                 mac=\"6R4rV5iE+NPoym+WwjeHzjAGXUtLNIxmo1vpMofpLAE=\", ext=\"a b=c\", \
+                 hash=\"6R4rV5iE+NPoym+WwjeHzjAGXUtLNIxmo1vpMofpLAE=\", app=\"my-app\", \
+                 dlg=\"my-dlg\"";
+        let header = Header::from_str(s).unwrap();
+        assert_eq!(header.canonicalize().unwrap(), header);
+
+        // sub-second timestamps are not transmitted
+        let precise = header.clone().with_ts(Some(Timespec::new(1353832234, 500)));
+        assert_eq!(precise.canonicalize().unwrap(), header);
+
+        // an empty header round-trips too
+        let empty = Header::new::<String>(None, None, None, None, None, None, None, None).unwrap();
+        assert_eq!(empty.canonicalize().unwrap(), empty);
+    }
+
+    #[test]
+    fn header_ref() {
+        let input = "id=\"dh37fgj492je\", ts=\"1353832234\", nonce=\"j4h3g2\", \
+                     mac=\"6R4rV5iE+NPoym+WwjeHzjAGXUtLNIxmo1vpMofpLAE=\", ext=\"some-app-ext-data\", \
+                     hash=\"AQIDBA==\", app=\"my-app\", dlg=\"my-dlg\"";
+        let r = HeaderRef::parse(input).unwrap();
+        assert_eq!(r.id(), Some("dh37fgj492je"));
+        assert_eq!(r.ts(), Some(Timespec::new(1353832234, 0)));
+        assert_eq!(r.nonce(), Some("j4h3g2"));
+        assert_eq!(r.mac().map(|mac| mac.len()), Some(32));
+        assert_eq!(r.ext(), Some("some-app-ext-data"));
+        assert_eq!(r.hash(), Some(&[1u8, 2, 3, 4][..]));
+        assert_eq!(r.app(), Some("my-app"));
+        assert_eq!(r.dlg(), Some("my-dlg"));
+
+        // fields borrow from the input
+        let start = input.as_ptr() as usize;
+        assert!((start..start + input.len()).contains(&(r.id().unwrap().as_ptr() as usize)));
+
+        assert_eq!(r.to_header(), Header::from_str(input).unwrap());
+    }
+
+    #[test]
+    fn header_ref_invalid() {
+        assert!(HeaderRef::parse("id=\"abc").is_err());
+        assert!(HeaderRef::parse("mac=\"!!!\"").is_err());
+        assert!(HeaderRef::parse("bogus=\"x\"").is_err());
+    }
+
+    #[test]
+    fn size_matches_formatted() {
+        let empty = Header::new::<String>(None, None, None, None, None, None, None, None).unwrap();
+        assert_eq!(empty.size(), "Hawk ".len());
+
+        let s = "id=\"dh37fgj492je\", ts=\"1353832234\", nonce=\"j4h3g2\", \
+                 mac=\"6R4rV5iE+NPoym+WwjeHzjAGXUtLNIxmo1vpMofpLAE=\", ext=\"some-app-ext-data\"";
+        let header = Header::from_str(s).unwrap();
+        assert_eq!(header.size(), format!("Hawk {}", header).len());
+
+        let header = header.with_hash(Some(vec![1, 2, 3, 4, 5]))
+            .with_app(Some("my-app"))
+            .unwrap()
+            .with_dlg(Some("my-dlg"))
+            .unwrap()
+            .with_ts(Some(Timespec::new(-5, 0)));
+        assert_eq!(header.size(), format!("Hawk {}", header).len());
+
+        let header = header.with_id(None::<String>).unwrap();
+        assert_eq!(header.size(), format!("Hawk {}", header).len());
+    }
+
+    #[test]
+    fn length_helpers() {
+        for len in 0..10 {
+            let bytes = vec![0u8; len];
+            assert_eq!(base64_len(len, true), base64::encode(&bytes).len());
+            assert_eq!(base64_len(len, false),
+                       base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD).len());
+        }
+        for &value in &[0i64, 9, 10, 99, 1353832234, -1, -10, i64::MAX, i64::MIN] {
+            assert_eq!(decimal_len(value), value.to_string().len());
+        }
+    }
+}
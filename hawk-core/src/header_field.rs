@@ -0,0 +1,36 @@
+use std::fmt;
+
+/// The fields of a Hawk header, used to report which fields are missing from a header.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HeaderField {
+    Id,
+    Ts,
+    Nonce,
+    Mac,
+    Ext,
+    Hash,
+    App,
+    Dlg,
+}
+
+impl HeaderField {
+    /// Get the name of this field, as it appears in a header.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            HeaderField::Id => "id",
+            HeaderField::Ts => "ts",
+            HeaderField::Nonce => "nonce",
+            HeaderField::Mac => "mac",
+            HeaderField::Ext => "ext",
+            HeaderField::Hash => "hash",
+            HeaderField::App => "app",
+            HeaderField::Dlg => "dlg",
+        }
+    }
+}
+
+impl fmt::Display for HeaderField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
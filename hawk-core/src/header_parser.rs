@@ -0,0 +1,256 @@
+//! The tokenizer for Hawk header values.
+//!
+//! A header value is a sequence of `name="value"` fields.  `Fields` splits a value into its
+//! fields, leaving the interpretation of names and values to the caller (in the `hawk` crate,
+//! `HeaderRef`), and reports syntax errors with the byte offset at which they occur.
+
+use std::error;
+use std::fmt;
+
+/// How strictly to parse a Hawk header.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ParseMode {
+    /// Accept any mixture of commas and whitespace between fields (including none), whitespace
+    /// around `=`, and repeated fields (the last value wins).  Field values may contain any
+    /// character but `"`.  This is the behavior of `hawk::Header::from_str`.
+    #[default]
+    Lenient,
+    /// Accept everything `Lenient` does, and also field names in any case (`ID="x"`), and
+    /// whitespace inside the quotes of the `ts`, `mac`, and `hash` fields, whose values are not
+    /// otherwise affected by it.  Some clients in the wild produce such headers.  Values of
+    /// other fields are kept exactly, since they are covered by the MAC.
+    Tolerant,
+    /// Accept only the syntax produced by Hawk implementations: fields separated by a comma
+    /// and optional spaces or tabs, no whitespace around `=`, no repeated fields, and values
+    /// containing only the printable ASCII characters the Node Hawk implementation allows.
+    Strict,
+}
+
+/// A syntax error in a Hawk header.
+///
+/// In the `hawk` crate, this is the cause of an `ErrorKind::HeaderParseError`, and can be found
+/// by iterating the error's causes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HeaderSyntaxError {
+    offset: usize,
+    problem: &'static str,
+}
+
+impl HeaderSyntaxError {
+    /// Create an error describing the given problem at the given byte offset.
+    pub fn new(offset: usize, problem: &'static str) -> HeaderSyntaxError {
+        HeaderSyntaxError { offset, problem }
+    }
+
+    /// Get the byte offset in the header value at which the error was found.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Get a description of the problem.
+    pub fn problem(&self) -> &'static str {
+        self.problem
+    }
+}
+
+impl fmt::Display for HeaderSyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.problem, self.offset)
+    }
+}
+
+impl error::Error for HeaderSyntaxError {}
+
+/// A single `name="value"` field.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Field<'a> {
+    /// The field's name.  In `ParseMode::Tolerant`, this may be in any case.
+    pub name: &'a str,
+    /// The field's value, without the quotes.
+    pub value: &'a str,
+    /// The byte offset of the field's name in the header value.
+    pub offset: usize,
+}
+
+/// An iterator over the fields of a header value.  After the first error, it yields nothing.
+pub struct Fields<'a> {
+    input: &'a str,
+    pos: usize,
+    mode: ParseMode,
+    done: bool,
+}
+
+impl<'a> Fields<'a> {
+    /// Iterate over the fields of the given header value (the part following `Hawk `).
+    pub fn new(input: &'a str, mode: ParseMode) -> Fields<'a> {
+        Fields {
+            input,
+            pos: 0,
+            mode,
+            done: false,
+        }
+    }
+
+    /// Get the unparsed remainder of the input.
+    fn rest(&self) -> &'a str {
+        self.input.get(self.pos..).unwrap_or("")
+    }
+
+    /// Advance past the longest prefix of the remainder whose characters match `pred`,
+    /// returning it.
+    fn take_while<P>(&mut self, pred: P) -> &'a str
+        where P: Fn(char) -> bool
+    {
+        let rest = self.rest();
+        let len = rest.find(|c| !pred(c)).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    /// Advance past the given character, failing if the remainder does not start with it.
+    fn expect(&mut self, c: char, problem: &'static str) -> Result<(), HeaderSyntaxError> {
+        if self.rest().starts_with(c) {
+            self.pos += c.len_utf8();
+            Ok(())
+        } else {
+            Err(self.error(problem))
+        }
+    }
+
+    fn error(&self, problem: &'static str) -> HeaderSyntaxError {
+        HeaderSyntaxError::new(self.pos, problem)
+    }
+
+    /// Skip the separator before a field, returning false at the end of the input.
+    fn separator(&mut self) -> Result<bool, HeaderSyntaxError> {
+        match self.mode {
+            ParseMode::Lenient | ParseMode::Tolerant => {
+                let skipped = self.take_while(|c| c == ',' || c.is_whitespace());
+                if self.rest().is_empty() {
+                    // trailing whitespace is allowed, but trailing commas are not
+                    return if skipped.contains(',') {
+                        Err(self.error("expected field after `,`"))
+                    } else {
+                        Ok(false)
+                    };
+                }
+            }
+            ParseMode::Strict => {
+                if self.rest().is_empty() {
+                    return Ok(false);
+                }
+                if self.pos > 0 {
+                    self.expect(',', "expected `,` between fields")?;
+                    self.take_while(|c| c == ' ' || c == '\t');
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn field(&mut self) -> Result<Field<'a>, HeaderSyntaxError> {
+        let lenient = self.mode != ParseMode::Strict;
+        let offset = self.pos;
+        let name = if lenient {
+            self.take_while(|c| c != '=').trim()
+        } else {
+            self.take_while(|c| c.is_ascii_alphanumeric())
+        };
+        if name.is_empty() {
+            return Err(self.error("expected field name"));
+        }
+        self.expect('=', "expected `=` after field name")?;
+        if lenient {
+            self.take_while(char::is_whitespace);
+        }
+        self.expect('"', "expected `\"` to open field value")?;
+        // Hawk does not allow `"` in field values, so we do not support RFC 7235 backslash
+        // escapes: the value is everything up to the next `"`
+        let value = if lenient {
+            self.take_while(|c| c != '"')
+        } else {
+            self.take_while(|c| matches!(c, ' '..='~') && c != '"' && c != '\\')
+        };
+        self.expect('"', "expected `\"` to close field value")?;
+        Ok(Field {
+            name,
+            value,
+            offset,
+        })
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<Field<'a>, HeaderSyntaxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = match self.separator() {
+            Ok(false) => {
+                self.done = true;
+                return None;
+            }
+            Ok(true) => self.field(),
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            self.done = true;
+        }
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Fields, ParseMode};
+
+    fn fields(input: &str, mode: ParseMode) -> Result<Vec<(&str, &str)>, (usize, &'static str)> {
+        Fields::new(input, mode)
+            .map(|f| f.map(|f| (f.name, f.value)).map_err(|e| (e.offset(), e.problem())))
+            .collect()
+    }
+
+    #[test]
+    fn lenient() {
+        assert_eq!(fields(" , a = \"1\"b=\"x y\",,c=\"\"  ", ParseMode::Lenient),
+                   Ok(vec![("a", "1"), ("b", "x y"), ("c", "")]));
+        assert_eq!(fields("", ParseMode::Lenient), Ok(vec![]));
+        assert_eq!(fields("a=\"\\\"", ParseMode::Lenient), Ok(vec![("a", "\\")]));
+    }
+
+    #[test]
+    fn lenient_errors() {
+        assert_eq!(fields("a=\"1\", ", ParseMode::Lenient),
+                   Err((7, "expected field after `,`")));
+        assert_eq!(fields("a=1", ParseMode::Lenient),
+                   Err((2, "expected `\"` to open field value")));
+        assert_eq!(fields("a=\"1", ParseMode::Lenient),
+                   Err((4, "expected `\"` to close field value")));
+        assert_eq!(fields("a", ParseMode::Lenient),
+                   Err((1, "expected `=` after field name")));
+        assert_eq!(fields("=\"1\"", ParseMode::Lenient), Err((0, "expected field name")));
+    }
+
+    #[test]
+    fn strict() {
+        assert_eq!(fields("a=\"1\", b=\"x y\",c=\"\"", ParseMode::Strict),
+                   Ok(vec![("a", "1"), ("b", "x y"), ("c", "")]));
+        assert_eq!(fields("", ParseMode::Strict), Ok(vec![]));
+    }
+
+    #[test]
+    fn strict_errors() {
+        assert_eq!(fields(" a=\"1\"", ParseMode::Strict), Err((0, "expected field name")));
+        assert_eq!(fields("a = \"1\"", ParseMode::Strict),
+                   Err((1, "expected `=` after field name")));
+        assert_eq!(fields("a=\"1\" b=\"2\"", ParseMode::Strict),
+                   Err((5, "expected `,` between fields")));
+        assert_eq!(fields("a=\"1\", ", ParseMode::Strict), Err((7, "expected field name")));
+        assert_eq!(fields("a=\"caf\u{e9}\"", ParseMode::Strict),
+                   Err((6, "expected `\"` to close field value")));
+        assert_eq!(fields("a=\"x\\y\"", ParseMode::Strict),
+                   Err((4, "expected `\"` to close field value")));
+    }
+}
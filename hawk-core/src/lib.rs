@@ -0,0 +1,74 @@
+//! Parsing and formatting Hawk headers and bewits.
+//!
+//! This crate holds the types which parse and format Hawk `Authorization` headers and bewits,
+//! without any cryptographic dependencies.  It suits tools such as log scrubbers and routers,
+//! which examine or rewrite Hawk headers without validating them.  Calculating and checking
+//! MACs is left to the `hawk` crate, which builds on this one and re-exports everything here.
+//! Most users should depend on `hawk`.
+//!
+//! MACs are carried as `MacBytes`, which compare with `==` in variable time; this crate never
+//! compares MACs itself.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate hawk_core;
+//!
+//! use hawk_core::Header;
+//! use std::str::FromStr;
+//!
+//! fn main() {
+//!     let value = "id=\"dh37fgj492je\", ts=\"1353832234\", nonce=\"j4h3g2\", mac=\"c2VjcmV0\"";
+//!     let header = Header::from_str(value).unwrap();
+//!     assert_eq!(header.id(), Some("dh37fgj492je"));
+//!
+//!     // scrub the MAC before logging the header
+//!     let scrubbed = header.with_mac(None);
+//!     assert_eq!(scrubbed.to_string(),
+//!                "id=\"dh37fgj492je\", ts=\"1353832234\", nonce=\"j4h3g2\"");
+//! }
+//! ```
+//!
+//! Headers can also be split into their `name="value"` fields without interpreting them:
+//!
+//! ```
+//! use hawk_core::{Fields, ParseMode};
+//!
+//! let value = "id=\"dh37fgj492je\", ts=\"1353832234\", nonce=\"j4h3g2\", mac=\"c2VjcmV0\"";
+//! let scrubbed: Vec<String> = Fields::new(value, ParseMode::Strict)
+//!     .map(|field| {
+//!         let field = field.unwrap();
+//!         match field.name {
+//!             "mac" => "mac=\"..\"".to_string(),
+//!             name => format!("{}=\"{}\"", name, field.value),
+//!         }
+//!     })
+//!     .collect();
+//! assert_eq!(scrubbed.join(", "),
+//!            "id=\"dh37fgj492je\", ts=\"1353832234\", nonce=\"j4h3g2\", mac=\"..\"");
+//! ```
+
+extern crate base64;
+extern crate smallvec;
+extern crate time;
+
+mod header_field;
+pub use header_field::HeaderField;
+
+mod header_parser;
+pub use header_parser::{Field, Fields, HeaderSyntaxError, ParseMode};
+
+mod header;
+pub use header::{Header, HeaderError, HeaderRef};
+
+mod bewit;
+pub use bewit::{Bewit, BewitError};
+
+mod redact;
+pub use redact::{RedactedBewit, RedactedHeader};
+
+mod mac_bytes;
+pub use mac_bytes::MacBytes;
+
+mod stage;
+pub use stage::Stage;
@@ -0,0 +1,53 @@
+use smallvec::SmallVec;
+use std::ops::Deref;
+
+/// Storage for a payload hash or MAC.  These are at most 64 bytes for the supported digest
+/// algorithms, so they are stored inline rather than in a separate heap allocation.
+pub(crate) type InlineBytes = SmallVec<[u8; 64]>;
+
+/// The bytes of a MAC, as carried in a Hawk header or bewit.
+///
+/// This holds a received or formatted MAC without any means of calculating one, which is left
+/// to `hawk::mac::Mac`.  Comparing `MacBytes` with `==` is not constant-time, so use
+/// `hawk::crypto::ct_eq` to check a received MAC against a calculated one.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MacBytes(pub(crate) InlineBytes);
+
+impl<'a> From<&'a [u8]> for MacBytes {
+    fn from(mac: &'a [u8]) -> Self {
+        MacBytes(InlineBytes::from_slice(mac))
+    }
+}
+
+impl From<Vec<u8>> for MacBytes {
+    fn from(mac: Vec<u8>) -> Self {
+        MacBytes::from(&mac[..])
+    }
+}
+
+impl AsRef<[u8]> for MacBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0[..]
+    }
+}
+
+impl Deref for MacBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0[..]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MacBytes;
+
+    #[test]
+    fn inline() {
+        let mac = MacBytes::from(vec![7u8; 64]);
+        assert_eq!(&mac[..], &[7u8; 64][..]);
+        assert!(!mac.0.spilled());
+        assert_eq!(MacBytes::from(&[1u8, 2, 3][..]).as_ref(), &[1u8, 2, 3][..]);
+    }
+}
@@ -0,0 +1,155 @@
+use base64;
+use bewit::Bewit;
+use header::Header;
+use std::fmt;
+
+/// The number of base64 characters of a MAC or hash retained when redacting.
+const REDACTED_PREFIX_LEN: usize = 6;
+
+/// The value shown in place of a redacted field.
+const BLANK: &str = "...";
+
+/// Encode the given bytes as base64, truncated to a short prefix.
+fn prefix(bytes: &[u8]) -> String {
+    let mut encoded = base64::encode(bytes);
+    if encoded.len() > REDACTED_PREFIX_LEN {
+        encoded.truncate(REDACTED_PREFIX_LEN);
+        encoded.push_str(BLANK);
+    }
+    encoded
+}
+
+/// Write `name="value"` pairs for the fields which are present, separated by commas.
+fn write_fields(f: &mut fmt::Formatter, fields: &[(&str, Option<&str>)]) -> fmt::Result {
+    let mut first = true;
+    for &(name, value) in fields {
+        if let Some(value) = value {
+            if !first {
+                f.write_str(", ")?;
+            }
+            first = false;
+            write!(f, "{}=\"{}\"", name, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// A view of a `Header` for logging, created with `Header::redacted`.
+///
+/// This formats the `id`, `ts`, `nonce`, `app`, and `dlg` fields as they are, and the `mac`
+/// truncated to a short prefix, which is enough to correlate log entries but not to replay the
+/// request.  The `hash` and `ext` fields, which may be derived from or contain sensitive data,
+/// are blanked.  `Debug` formats the same redacted fields.
+#[derive(Clone, Copy)]
+pub struct RedactedHeader<'a>(&'a Header);
+
+impl Header {
+    /// Get a view of this header which is safe to log; see `RedactedHeader`.
+    pub fn redacted(&self) -> RedactedHeader<'_> {
+        RedactedHeader(self)
+    }
+}
+
+impl<'a> fmt::Display for RedactedHeader<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let header = self.0;
+        let ts = header.ts().map(|ts| ts.sec.to_string());
+        let mac = header.mac().map(|mac| prefix(mac));
+        write_fields(f,
+                     &[("id", header.id()),
+                       ("ts", ts.as_ref().map(|v| &v[..])),
+                       ("nonce", header.nonce()),
+                       ("mac", mac.as_ref().map(|v| &v[..])),
+                       ("ext", header.ext().map(|_| BLANK)),
+                       ("hash", header.hash().map(|_| BLANK)),
+                       ("app", header.app()),
+                       ("dlg", header.dlg())])
+    }
+}
+
+/// A view of a `Bewit` for logging, created with `Bewit::redacted`.
+///
+/// This formats the `id` and `exp` fields as they are, the `mac` truncated to a short prefix,
+/// and the `ext` field, if any, blanked.  Unlike the bewit itself, this cannot be used to
+/// access the resource.  `Debug` formats the same redacted fields.
+#[derive(Clone, Copy)]
+pub struct RedactedBewit<'a, 'b: 'a>(&'a Bewit<'b>);
+
+impl<'b> Bewit<'b> {
+    /// Get a view of this bewit which is safe to log; see `RedactedBewit`.
+    pub fn redacted(&self) -> RedactedBewit<'_, 'b> {
+        RedactedBewit(self)
+    }
+}
+
+impl<'a> fmt::Debug for RedactedHeader<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RedactedHeader({})", self)
+    }
+}
+
+impl<'a, 'b> fmt::Display for RedactedBewit<'a, 'b> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bewit = self.0;
+        let exp = bewit.exp().sec.to_string();
+        let mac = prefix(bewit.mac());
+        write_fields(f,
+                     &[("id", Some(bewit.id())),
+                       ("exp", Some(&exp)),
+                       ("mac", Some(&mac)),
+                       ("ext", bewit.ext().map(|_| BLANK))])
+    }
+}
+
+impl<'a, 'b> fmt::Debug for RedactedBewit<'a, 'b> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RedactedBewit({})", self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bewit::Bewit;
+    use header::Header;
+    use mac_bytes::MacBytes;
+    use time::Timespec;
+
+    #[test]
+    fn header() {
+        let header = Header::new(Some("me"),
+                                 Some(Timespec::new(1353832234, 0)),
+                                 Some("j4h3g2"),
+                                 Some(MacBytes::from(vec![7u8; 32])),
+                                 Some("secret-ext"),
+                                 Some(vec![1u8; 32]),
+                                 None,
+                                 None)
+            .unwrap();
+        assert_eq!(header.redacted().to_string(),
+                   "id=\"me\", ts=\"1353832234\", nonce=\"j4h3g2\", mac=\"BwcHBw...\", \
+                    ext=\"...\", hash=\"...\"");
+
+        let header = Header::new(Some("me"), None, None, None, None, None, None, None).unwrap();
+        assert_eq!(header.redacted().to_string(), "id=\"me\"");
+    }
+
+    #[test]
+    fn bewit() {
+        let bewit = Bewit::new("me",
+                               Timespec::new(1353832834, 0),
+                               MacBytes::from(vec![7u8; 32]),
+                               Some("secret-ext"));
+        let redacted = bewit.redacted().to_string();
+        assert_eq!(redacted,
+                   "id=\"me\", exp=\"1353832834\", mac=\"BwcHBw...\", ext=\"...\"");
+        assert!(!redacted.contains("secret"));
+        assert!(!format!("{:?}", bewit.redacted()).contains("secret"));
+
+        let bewit = Bewit::new("me",
+                               Timespec::new(1353832834, 0),
+                               MacBytes::from(vec![7u8; 32]),
+                               None);
+        assert_eq!(bewit.redacted().to_string(),
+                   "id=\"me\", exp=\"1353832834\", mac=\"BwcHBw...\"");
+    }
+}
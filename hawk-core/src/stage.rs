@@ -0,0 +1,44 @@
+use std::fmt;
+
+/// The stage of authentication at which a failure occurred.
+///
+/// This distinguishes failures which call for different responses, or which should be counted
+/// separately: a malformed header (`Parse`) is a client bug, while a MAC mismatch (`Crypto`)
+/// may be an attack.  It is available from `HeaderError::stage` and `BewitError::stage`, and in
+/// the `hawk` crate from `Error::stage` and `RejectionReason::stage`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum Stage {
+    /// Parsing the header or bewit.
+    Parse,
+    /// Looking up the credentials for the request's id.
+    Lookup,
+    /// Verifying the MAC or payload hash.
+    Crypto,
+    /// Applying a policy, such as a required payload hash, `ext` limits, or replay protection.
+    Policy,
+    /// Checking the timestamp or expiration time against the clock.
+    Clock,
+    /// Anything else, such as an I/O error or an invalid argument.
+    Other,
+}
+
+impl Stage {
+    /// Get the name of this stage, in lower case, suitable for a metrics label.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Stage::Parse => "parse",
+            Stage::Lookup => "lookup",
+            Stage::Crypto => "crypto",
+            Stage::Policy => "policy",
+            Stage::Clock => "clock",
+            Stage::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
@@ -0,0 +1,57 @@
+//! Compare signing a request from scratch with signing from a precomputed fingerprint, and
+//! with a Signer reusing scratch buffers.
+//!
+//! Run with `cargo bench`.  This uses a simple timing loop, so that it runs on stable Rust.
+
+extern crate hawk;
+extern crate time;
+
+use hawk::{Credentials, Key, RequestBuilder, Scratch, Signer, SHA256};
+use std::time::Instant;
+
+const ITERATIONS: u32 = 100_000;
+
+fn bench<F>(name: &str, mut f: F)
+    where F: FnMut()
+{
+    // warm up
+    for _ in 0..ITERATIONS / 10 {
+        f();
+    }
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let elapsed = start.elapsed();
+    println!("{:<24} {:>8} ns/iter", name, elapsed.as_nanos() / u128::from(ITERATIONS));
+}
+
+fn main() {
+    let credentials = Credentials {
+        id: "me".to_string(),
+        key: Key::new(vec![99u8; 32], &SHA256),
+    };
+    let hash = [7u8; 32];
+    let request = RequestBuilder::new("POST", "example.com", 443, "/v1/some/long/api/path")
+        .hash(&hash[..])
+        .ext("some-ext-data")
+        .request();
+    let fingerprint = request.fingerprint().unwrap();
+    let ts = time::Timespec::new(1353832234, 0);
+
+    bench("make_header_full", || {
+        request.make_header_full(&credentials, ts, "nonny").unwrap();
+    });
+    bench("fingerprint header", || {
+        fingerprint.make_header_full(&credentials, ts, "nonny").unwrap();
+    });
+    bench("make_header_full + fmt", || {
+        request.make_header_full(&credentials, ts, "nonny").unwrap().to_string();
+    });
+    let mut scratch = Scratch::new();
+    let mut signer = Signer::with_scratch(&mut scratch);
+    bench("signer with scratch", || {
+        signer.make_header_full(&request, &credentials, ts, "nonny").unwrap();
+    });
+}
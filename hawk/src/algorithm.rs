@@ -0,0 +1,88 @@
+use error::*;
+use ring::digest::{Algorithm, SHA256, SHA384, SHA512};
+use std::ptr;
+
+const ALGORITHMS: &[(&str, &Algorithm)] = &[("sha256", &SHA256),
+                                            ("sha384", &SHA384),
+                                            ("sha512", &SHA512)];
+
+/// Look up a digest algorithm by name, as might appear in a configuration file.
+///
+/// The names are those used by the Node Hawk implementation: `sha256`, `sha384`, and `sha512`.
+/// Names are matched case-insensitively, and may also be given with a hyphen, as in `SHA-256`.
+///
+/// # Examples
+///
+/// ```
+/// use hawk::{digest_algorithm, digest_algorithm_name};
+///
+/// let algorithm = digest_algorithm("SHA-256").unwrap();
+/// assert_eq!(digest_algorithm_name(algorithm), Some("sha256"));
+/// ```
+pub fn digest_algorithm(name: &str) -> Result<&'static Algorithm> {
+    let normalized = name.to_lowercase().replace('-', "");
+    for &(alg_name, algorithm) in ALGORITHMS {
+        if alg_name == normalized {
+            return Ok(algorithm);
+        }
+    }
+    bail!("Unknown digest algorithm {}", name)
+}
+
+/// Get the name of the given digest algorithm, as accepted by `digest_algorithm`.  Returns
+/// `None` for algorithms not supported by Hawk.
+pub fn digest_algorithm_name(algorithm: &Algorithm) -> Option<&'static str> {
+    ALGORITHMS.iter()
+        .find(|&&(_, alg)| ptr::eq(alg, algorithm))
+        .map(|&(name, _)| name)
+}
+
+/// Get the length, in bytes, of the output of the given digest algorithm.  This is the length
+/// of a valid `mac` or `hash` made with that algorithm, once decoded from base64.
+///
+/// # Examples
+///
+/// ```
+/// use hawk::{digest_algorithm, digest_output_len};
+///
+/// assert_eq!(digest_output_len(digest_algorithm("sha256").unwrap()), 32);
+/// ```
+pub fn digest_output_len(algorithm: &Algorithm) -> usize {
+    algorithm.output_len
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ring::digest;
+
+    #[test]
+    fn round_trip() {
+        for &(name, _) in ALGORITHMS {
+            let algorithm = digest_algorithm(name).unwrap();
+            assert_eq!(digest_algorithm_name(algorithm), Some(name));
+        }
+    }
+
+    #[test]
+    fn output_len() {
+        assert_eq!(digest_output_len(&digest::SHA256), 32);
+        assert_eq!(digest_output_len(&digest::SHA384), 48);
+        assert_eq!(digest_output_len(&digest::SHA512), 64);
+    }
+
+    #[test]
+    fn spellings() {
+        assert!(ptr::eq(digest_algorithm("sha256").unwrap(), &digest::SHA256));
+        assert!(ptr::eq(digest_algorithm("SHA256").unwrap(), &digest::SHA256));
+        assert!(ptr::eq(digest_algorithm("SHA-384").unwrap(), &digest::SHA384));
+        assert!(ptr::eq(digest_algorithm("sha-512").unwrap(), &digest::SHA512));
+    }
+
+    #[test]
+    fn unknown() {
+        assert!(digest_algorithm("md5").is_err());
+        assert!(digest_algorithm("").is_err());
+        assert_eq!(digest_algorithm_name(&digest::SHA1), None);
+    }
+}
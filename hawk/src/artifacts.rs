@@ -0,0 +1,468 @@
+use error::*;
+use ext::ExtFields;
+use hawk_core::Header;
+use response::ResponseBuilder;
+use time::Timespec;
+
+/// The principal on whose behalf a request is made, as determined by its validated header and
+/// the credentials it was validated with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Principal<'a> {
+    /// A user, identified by the header's `id`, with no application involved.
+    User(&'a str),
+    /// An application acting on its own behalf, identified by the header's `id`, which the
+    /// credentials provider reports as belonging to application credentials (see
+    /// `CredentialsProvider::is_app` and `AppCredentials`).  The header's fields cannot make a
+    /// request an application's, since any client can sign an `app` equal to its own id.
+    App(&'a str),
+    /// A user, identified by `id`, accessing the service through the application `app`,
+    /// possibly by delegation from another application `dlg`.
+    UserViaApp {
+        id: &'a str,
+        app: &'a str,
+        dlg: Option<&'a str>,
+    },
+}
+
+/// Artifacts describe a request whose Hawk header has been successfully validated.
+///
+/// They are returned from `Request::authenticate_header`, and combine the request information
+/// with the fields of the validated header.  A server uses them to respond to the request:
+/// Hawk responses are signed with the same timestamp and nonce as the request, and
+/// `make_response_builder` takes care of carrying those over.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Artifacts<'a> {
+    method: &'a str,
+    host: &'a str,
+    port: u16,
+    path: &'a str,
+    header: &'a Header,
+    ts: Timespec,
+    nonce: &'a str,
+    without_query: bool,
+    normalized_hash: Option<String>,
+    empty_ext_as_absent: bool,
+    app_principal: bool,
+}
+
+impl<'a> Artifacts<'a> {
+    pub(crate) fn new(method: &'a str,
+                      host: &'a str,
+                      port: u16,
+                      path: &'a str,
+                      header: &'a Header,
+                      ts: Timespec,
+                      nonce: &'a str)
+                      -> Self {
+        Artifacts {
+            method,
+            host,
+            port,
+            path,
+            header,
+            ts,
+            nonce,
+            without_query: false,
+            normalized_hash: None,
+            empty_ext_as_absent: false,
+            app_principal: false,
+        }
+    }
+
+    /// Record that the MAC was calculated over the request path without its query, and that
+    /// `path` is that path.
+    pub(crate) fn signed_without_query(mut self) -> Self {
+        self.without_query = true;
+        self
+    }
+
+    /// Record the hash of the normalized string which was authenticated.
+    pub(crate) fn with_normalized_hash(mut self, normalized_hash: Option<String>) -> Self {
+        self.normalized_hash = normalized_hash;
+        self
+    }
+
+    /// Record whether an empty `ext` is reported as absent.
+    pub(crate) fn with_empty_ext_as_absent(mut self, empty_ext_as_absent: bool) -> Self {
+        self.empty_ext_as_absent = empty_ext_as_absent;
+        self
+    }
+
+    /// Record whether the header was validated with application credentials.
+    pub(crate) fn with_app_principal(mut self, app_principal: bool) -> Self {
+        self.app_principal = app_principal;
+        self
+    }
+
+    /// Determine whether the header was validated with application credentials.
+    pub(crate) fn is_app_principal(&self) -> bool {
+        self.app_principal
+    }
+
+    /// Get the request method.
+    pub fn method(&self) -> &'a str {
+        self.method
+    }
+
+    /// Get the request host.
+    pub fn host(&self) -> &'a str {
+        self.host
+    }
+
+    /// Get the request port.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Get the request path.
+    pub fn path(&self) -> &'a str {
+        self.path
+    }
+
+    /// Determine whether the request's MAC was calculated over its path without the query,
+    /// which is only accepted with `ValidationOptions::accept_mac_without_query`.  In that case,
+    /// `path` omits the query too, so that responses are signed as the client expects.
+    pub fn is_signed_without_query(&self) -> bool {
+        self.without_query
+    }
+
+    /// Get the validated request header.
+    pub fn header(&self) -> &'a Header {
+        self.header
+    }
+
+    /// Get the client identifier from the request header, if any.
+    pub fn id(&self) -> Option<&'a str> {
+        self.header.id()
+    }
+
+    /// Get the request's `app` field.  This is covered by the MAC, so it can be trusted.
+    pub fn app(&self) -> Option<&'a str> {
+        self.header.app()
+    }
+
+    /// Get the request's `dlg` field.  This is covered by the MAC, so it can be trusted.
+    pub fn dlg(&self) -> Option<&'a str> {
+        self.header.dlg()
+    }
+
+    /// Get the principal on whose behalf the request was made: a user, or an application
+    /// acting on its own behalf, or a user acting through an application.
+    ///
+    /// A request is only made by an application if it was validated with
+    /// `Request::check_with_provider` (or an `Authenticator`) and a provider reporting its id
+    /// as an application's.  Otherwise, a header with an `app` is made by a user through that
+    /// application, even if the `app` is the same as the `id`.
+    pub fn principal(&self) -> Principal<'a> {
+        // validation guarantees that the header has an id
+        let id = self.header.id().unwrap_or("");
+        if self.app_principal {
+            return Principal::App(id);
+        }
+        match (self.header.app(), self.header.dlg()) {
+            (None, _) => Principal::User(id),
+            (Some(app), dlg) => Principal::UserViaApp { id, app, dlg },
+        }
+    }
+
+    /// Get the timestamp of the request.
+    pub fn ts(&self) -> Timespec {
+        self.ts
+    }
+
+    /// Get the nonce of the request.
+    pub fn nonce(&self) -> &'a str {
+        self.nonce
+    }
+
+    /// Get the request's `ext` field.  If `ValidationOptions::empty_ext_as_absent` was set, an
+    /// empty `ext` is reported as `None`.
+    pub fn ext(&self) -> Option<&'a str> {
+        self.header.ext().filter(|ext| !(self.empty_ext_as_absent && ext.is_empty()))
+    }
+
+    /// Parse the request's `ext` field as `ExtFields`.  A request without an `ext` field has no
+    /// fields.
+    pub fn ext_fields(&self) -> Result<ExtFields> {
+        ExtFields::parse(self.ext().unwrap_or(""))
+    }
+
+    /// Get the request's payload hash, as given in the header.
+    pub fn hash(&self) -> Option<&'a [u8]> {
+        self.header.hash()
+    }
+
+    /// Get the lower-case hex SHA-256 hash of the normalized string which the header's MAC
+    /// authenticated, if `ValidationOptions::record_normalized_hash` was set.  If the header
+    /// was signed without the query, this is the hash of the string without it.
+    pub fn normalized_hash(&self) -> Option<&str> {
+        self.normalized_hash.as_ref().map(|h| &h[..])
+    }
+
+    /// Get a ResponseBuilder for a response to this request.  The response will be signed with
+    /// the request's timestamp and nonce, as the Hawk protocol requires.
+    pub fn make_response_builder(&self) -> ResponseBuilder<'a> {
+        ResponseBuilder::from_request_header(self.header,
+                                             self.method,
+                                             self.host,
+                                             self.port,
+                                             self.path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Principal;
+    use credentials::{AppCredentials, Key};
+    use std::collections::HashMap;
+    use payload::PayloadHasher;
+    use request::RequestBuilder;
+    use testing::test_credentials;
+    use validation::ValidationOptions;
+    use ring::digest;
+
+    /// Run a full request/response exchange: the client signs a request, the server validates it
+    /// and signs a response, and the client validates the response.
+    fn round_trip(client_sends_hash: bool,
+                  server_requires_hash: bool,
+                  server_sends_hash: bool,
+                  client_requires_hash: bool,
+                  server_ext: Option<&str>) {
+        let credentials = test_credentials();
+        let req_hash = PayloadHasher::hash("text/plain", &digest::SHA256, "request-body");
+        let resp_hash = PayloadHasher::hash("text/plain", &digest::SHA256, "response-body");
+
+        // client
+        let mut bldr = RequestBuilder::new("POST", "example.com", 443, "/v1/things");
+        if client_sends_hash {
+            bldr = bldr.hash(&req_hash[..]);
+        }
+        let client_req = bldr.request();
+        let req_header = client_req.make_header(&credentials).unwrap();
+
+        // server
+        let mut bldr = RequestBuilder::new("POST", "example.com", 443, "/v1/things");
+        if server_requires_hash {
+            bldr = bldr.hash(&req_hash[..]);
+        }
+        let server_req = bldr.request();
+        let artifacts = server_req.authenticate_header(&req_header,
+                                                       &credentials.key,
+                                                       &ValidationOptions::default())
+            .unwrap();
+        assert_eq!(artifacts.id(), Some("me"));
+        assert_eq!(artifacts.ts(), req_header.ts().unwrap());
+        assert_eq!(artifacts.nonce(), req_header.nonce().unwrap());
+        assert_eq!(artifacts.path(), "/v1/things");
+
+        let mut resp_bldr = artifacts.make_response_builder().ext(server_ext);
+        if server_sends_hash {
+            resp_bldr = resp_bldr.hash(&resp_hash[..]);
+        }
+        let resp_header = resp_bldr.response().make_header(&credentials.key).unwrap();
+        assert_eq!(resp_header.ext(), server_ext);
+
+        // client
+        let mut resp_bldr = client_req.make_response_builder(&req_header);
+        if client_requires_hash {
+            resp_bldr = resp_bldr.hash(&resp_hash[..]);
+        }
+        assert!(resp_bldr.response().validate_header(&resp_header, &credentials.key));
+    }
+
+    #[test]
+    fn round_trip_no_hashes() {
+        round_trip(false, false, false, false, None);
+    }
+
+    #[test]
+    fn round_trip_client_sends() {
+        round_trip(true, false, false, false, None);
+    }
+
+    #[test]
+    fn round_trip_server_requires() {
+        round_trip(true, true, false, false, None);
+    }
+
+    #[test]
+    fn round_trip_server_sends() {
+        round_trip(true, true, true, false, None);
+    }
+
+    #[test]
+    fn round_trip_client_requires() {
+        round_trip(true, true, true, true, None);
+    }
+
+    #[test]
+    fn round_trip_response_hash_only() {
+        round_trip(false, false, true, true, None);
+    }
+
+    #[test]
+    fn round_trip_server_ext() {
+        round_trip(true, true, true, true, Some("server-ext"));
+    }
+
+    #[test]
+    fn response_uses_request_nonce() {
+        // a response signed for one request does not validate for another request, even with
+        // the same timestamp, because the nonce differs
+        let credentials = test_credentials();
+        let client_req = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let ts = ::time::Timespec::new(1353832234, 0);
+        let req_header1 = client_req.make_header_full(&credentials, ts, "nonce1").unwrap();
+        let req_header2 = client_req.make_header_full(&credentials, ts, "nonce2").unwrap();
+
+        let options = ValidationOptions {
+            ts_skew: ::time::Duration::weeks(52000),
+            ..ValidationOptions::default()
+        };
+        let artifacts = client_req.authenticate_header(&req_header1, &credentials.key, &options)
+            .unwrap();
+        let resp_header = artifacts.make_response_builder()
+            .response()
+            .make_header(&credentials.key)
+            .unwrap();
+
+        assert!(client_req.make_response_builder(&req_header1)
+            .response()
+            .validate_header(&resp_header, &credentials.key));
+        assert!(!client_req.make_response_builder(&req_header2)
+            .response()
+            .validate_header(&resp_header, &credentials.key));
+    }
+
+    #[test]
+    fn response_hash_mismatch() {
+        let credentials = test_credentials();
+        let resp_hash = PayloadHasher::hash("text/plain", &digest::SHA256, "response-body");
+        let other_hash = PayloadHasher::hash("text/plain", &digest::SHA256, "other-body");
+        let client_req = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let req_header = client_req.make_header(&credentials).unwrap();
+        let artifacts = client_req.authenticate_header(&req_header,
+                                                       &credentials.key,
+                                                       &ValidationOptions::default())
+            .unwrap();
+        let resp_header = artifacts.make_response_builder()
+            .hash(&resp_hash[..])
+            .response()
+            .make_header(&credentials.key)
+            .unwrap();
+
+        assert!(!client_req.make_response_builder(&req_header)
+            .hash(&other_hash[..])
+            .response()
+            .validate_header(&resp_header, &credentials.key));
+    }
+
+    #[test]
+    fn principal_user() {
+        let credentials = test_credentials();
+        let req = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let header = req.make_header(&credentials).unwrap();
+        let artifacts = req.authenticate_header(&header,
+                                                &credentials.key,
+                                                &ValidationOptions::default())
+            .unwrap();
+        assert_eq!(artifacts.principal(), Principal::User("me"));
+    }
+
+    #[test]
+    fn principal_app() {
+        let app = AppCredentials::new("my-app", Key::new(vec![99u8; 32], &digest::SHA256));
+        // any app or dlg on the request is replaced
+        let req = RequestBuilder::new("GET", "example.com", 443, "/")
+            .dlg("other-app")
+            .request();
+        let header = req.make_app_header(&app).unwrap();
+        assert_eq!(header.id(), Some("my-app"));
+        assert_eq!(header.app(), Some("my-app"));
+        assert_eq!(header.dlg(), None);
+
+        let server_req = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let artifacts = server_req.check_with_provider(&header, &app, &ValidationOptions::default())
+            .unwrap();
+        assert_eq!(artifacts.principal(), Principal::App("my-app"));
+        assert_eq!(artifacts.app(), Some("my-app"));
+
+        // without the provider's word, the same header is a user's
+        let artifacts = server_req.authenticate_header(&header,
+                                                       &app.credentials().key,
+                                                       &ValidationOptions::default())
+            .unwrap();
+        assert_eq!(artifacts.principal(),
+                   Principal::UserViaApp {
+                       id: "my-app",
+                       app: "my-app",
+                       dlg: None,
+                   });
+
+        // the response covers the app as well
+        let resp_header = artifacts.make_response_builder()
+            .response()
+            .make_header(&app.credentials().key)
+            .unwrap();
+        assert!(req.make_response_builder(&header)
+            .response()
+            .validate_header(&resp_header, &app.credentials().key));
+    }
+
+    #[test]
+    fn principal_app_claimed_by_user() {
+        // a user signing `app` equal to its own id is not an application
+        let credentials = test_credentials();
+        let req = RequestBuilder::new("GET", "example.com", 443, "/").app("me").request();
+        let header = req.make_header(&credentials).unwrap();
+        let mut provider = HashMap::new();
+        provider.insert("me".to_string(), credentials.key.clone());
+        let artifacts = req.check_with_provider(&header, &provider, &ValidationOptions::default())
+            .unwrap();
+        assert_eq!(artifacts.principal(),
+                   Principal::UserViaApp {
+                       id: "me",
+                       app: "me",
+                       dlg: None,
+                   });
+    }
+
+    #[test]
+    fn principal_user_via_app() {
+        let credentials = test_credentials();
+        let req = RequestBuilder::new("GET", "example.com", 443, "/")
+            .app("my-app")
+            .dlg("other-app")
+            .request();
+        let header = req.make_header(&credentials).unwrap();
+        let artifacts = req.authenticate_header(&header,
+                                                &credentials.key,
+                                                &ValidationOptions::default())
+            .unwrap();
+        assert_eq!(artifacts.principal(),
+                   Principal::UserViaApp {
+                       id: "me",
+                       app: "my-app",
+                       dlg: Some("other-app"),
+                   });
+    }
+
+    #[test]
+    fn app_and_dlg_are_covered_by_mac() {
+        let credentials = test_credentials();
+        let req = RequestBuilder::new("GET", "example.com", 443, "/")
+            .app("my-app")
+            .request();
+        let header = req.make_header(&credentials).unwrap();
+        let options = ValidationOptions::default();
+        assert!(req.authenticate_header(&header, &credentials.key, &options).is_some());
+
+        let forged = header.clone().with_app(Some("my-app")).unwrap().with_dlg(Some("x")).unwrap();
+        assert!(req.authenticate_header(&forged, &credentials.key, &options).is_none());
+        let forged = header.clone().with_app(Some("other-app")).unwrap();
+        assert!(req.authenticate_header(&forged, &credentials.key, &options).is_none());
+        let forged = header.with_app(None::<String>).unwrap();
+        assert!(req.authenticate_header(&forged, &credentials.key, &options).is_none());
+    }
+}
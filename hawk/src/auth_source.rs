@@ -0,0 +1,117 @@
+use error::*;
+
+/// The Hawk authentication presented by a request: either an `Authorization` header or a
+/// `bewit` query parameter.
+///
+/// The Hawk specification forbids a request from presenting both, since a server validating
+/// only one of them might act on the other.  `AuthSource::from_request` rejects such requests
+/// with `ErrorKind::MultipleAuthentications`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AuthSource<'a> {
+    /// A Hawk `Authorization` header; this contains the value following the `Hawk ` scheme,
+    /// suitable for parsing as a `Header`.
+    Header(&'a str),
+    /// A `bewit` query parameter; this contains the parameter's value, suitable for parsing as a
+    /// `Bewit`.
+    Bewit(&'a str),
+}
+
+impl<'a> AuthSource<'a> {
+    /// Determine how a request presents Hawk authentication, given the value of its
+    /// `Authorization` header, if any, and its path including the query.
+    ///
+    /// An `Authorization` header with a scheme other than Hawk is ignored.  This returns
+    /// `Ok(None)` for a request with no Hawk authentication, and fails if the request presents
+    /// both a Hawk header and a bewit.
+    pub fn from_request(authorization: Option<&'a str>,
+                        path: &'a str)
+                        -> Result<Option<AuthSource<'a>>> {
+        let header = authorization.and_then(hawk_header_value);
+        let bewit = bewit_param(path);
+        match (header, bewit) {
+            (Some(_), Some(_)) => bail!(ErrorKind::MultipleAuthentications),
+            (Some(header), None) => Ok(Some(AuthSource::Header(header))),
+            (None, Some(bewit)) => Ok(Some(AuthSource::Bewit(bewit))),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+/// Get the part of an Authorization header value following the `Hawk` scheme, if the header
+/// uses that scheme.  Scheme names are case-insensitive.
+pub(crate) fn hawk_header_value(authorization: &str) -> Option<&str> {
+    let authorization = authorization.trim_start();
+    let (scheme, rest) = match authorization.find(char::is_whitespace) {
+        Some(i) => (&authorization[..i], &authorization[i..]),
+        None => (authorization, ""),
+    };
+    if scheme.eq_ignore_ascii_case("hawk") {
+        Some(rest.trim_start())
+    } else {
+        None
+    }
+}
+
+/// Get the value of the `bewit` query parameter in the given path, if any.
+fn bewit_param(path: &str) -> Option<&str> {
+    let query = path.split_once('?')?.1;
+    let query = query.split('#').next().unwrap_or("");
+    query.split('&').find_map(|param| match param.split_once('=') {
+        Some(("bewit", value)) => Some(value),
+        None if param == "bewit" => Some(""),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::AuthSource;
+    use error::ErrorKind;
+
+    #[test]
+    fn none() {
+        assert_eq!(AuthSource::from_request(None, "/resource").unwrap(), None);
+        assert_eq!(AuthSource::from_request(None, "/resource?a=1&bewitx=2").unwrap(), None);
+        assert_eq!(AuthSource::from_request(Some("Basic dXNlcjpwYXNz"), "/resource").unwrap(),
+                   None);
+    }
+
+    #[test]
+    fn header() {
+        assert_eq!(AuthSource::from_request(Some("Hawk id=\"me\", mac=\"abc\""), "/?a=1")
+                       .unwrap(),
+                   Some(AuthSource::Header("id=\"me\", mac=\"abc\"")));
+        assert_eq!(AuthSource::from_request(Some("hawk  id=\"me\""), "/").unwrap(),
+                   Some(AuthSource::Header("id=\"me\"")));
+        // a different scheme which begins with `hawk` is not Hawk
+        assert_eq!(AuthSource::from_request(Some("Hawkish id=\"me\""), "/").unwrap(), None);
+    }
+
+    #[test]
+    fn bewit() {
+        assert_eq!(AuthSource::from_request(None, "/resource?bewit=abcd").unwrap(),
+                   Some(AuthSource::Bewit("abcd")));
+        assert_eq!(AuthSource::from_request(None, "/resource?a=1&bewit=abcd&b=2").unwrap(),
+                   Some(AuthSource::Bewit("abcd")));
+        assert_eq!(AuthSource::from_request(None, "/resource?bewit=abcd#frag").unwrap(),
+                   Some(AuthSource::Bewit("abcd")));
+        // a non-Hawk Authorization header does not conflict with a bewit
+        assert_eq!(AuthSource::from_request(Some("Bearer xyz"), "/resource?bewit=abcd").unwrap(),
+                   Some(AuthSource::Bewit("abcd")));
+    }
+
+    #[test]
+    fn both() {
+        match AuthSource::from_request(Some("Hawk id=\"me\""), "/resource?bewit=abcd") {
+            Err(e) => {
+                match *e.kind() {
+                    ErrorKind::MultipleAuthentications => {}
+                    _ => panic!("unexpected error {}", e),
+                }
+            }
+            Ok(v) => panic!("unexpected success {:?}", v),
+        }
+        // even an empty bewit counts
+        assert!(AuthSource::from_request(Some("Hawk id=\"me\""), "/resource?bewit").is_err());
+    }
+}
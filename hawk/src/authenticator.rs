@@ -0,0 +1,589 @@
+use artifacts::Artifacts;
+use credentials::{Credentials, CredentialsProvider, Key};
+use hawk_core::{Header, HeaderField};
+use header_cache::{CachedHeader, CachedVerdict};
+use nonce::{NonceStore, NonceStoreUnavailable};
+use payload::PayloadHasher;
+use rand::{self, Rng};
+use rejected::RejectionReason;
+use request::{random_string, Request, RequestBuilder};
+use ring::digest;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use validation::{NonceOutagePolicy, ValidationOptions};
+
+/// A reusable server-side authenticator, combining a credentials provider, validation options,
+/// and optionally a nonce store.
+///
+/// A gateway typically creates one Authenticator at startup and shares it between request
+/// handlers.  Before taking traffic, `self_test` can confirm that each part of the
+/// authentication stack works, for example in a readiness probe.
+///
+/// If the nonce store is unavailable, requests are rejected or accepted according to the
+/// `nonce_outage` validation option.  Either way, the outage is counted in `nonce_outages` and
+/// reported to any function registered with `on_nonce_outage`.
+pub struct Authenticator<P> {
+    provider: P,
+    options: ValidationOptions,
+    nonces: Option<Arc<dyn NonceStore + Send + Sync>>,
+    nonce_outages: AtomicU64,
+    on_nonce_outage: Option<Box<OutageCallback>>,
+}
+
+type OutageCallback = dyn Fn(&NonceStoreUnavailable, NonceOutagePolicy) + Send + Sync;
+
+/// The result of testing one component of an `Authenticator`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ComponentStatus {
+    /// The component works.
+    Passed,
+    /// The component is not configured, or could not be tested.
+    Skipped,
+    /// The component failed, for the given reason.
+    Failed(String),
+}
+
+impl ComponentStatus {
+    /// Determine whether this status represents a failure.
+    pub fn is_failed(&self) -> bool {
+        matches!(*self, ComponentStatus::Failed(_))
+    }
+}
+
+/// The results of `Authenticator::self_test`, per component.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SelfTestReport {
+    /// Signing and validating a synthetic request with a throwaway key, for each supported
+    /// digest algorithm.
+    pub crypto: ComponentStatus,
+    /// Recording a synthetic nonce, and detecting its replay.
+    pub nonce_store: ComponentStatus,
+    /// Looking up the probe id's keys, and validating a synthetic request signed with them.
+    pub credentials: ComponentStatus,
+}
+
+impl SelfTestReport {
+    /// Determine whether no component failed.
+    pub fn is_healthy(&self) -> bool {
+        !(self.crypto.is_failed() || self.nonce_store.is_failed() || self.credentials.is_failed())
+    }
+}
+
+/// The id under which the self-test records nonces.
+const SELF_TEST_ID: &str = "hawk-self-test";
+
+impl<P> Authenticator<P>
+    where P: CredentialsProvider
+{
+    /// Create a new Authenticator looking up keys with the given provider, with the default
+    /// validation options and no nonce store.
+    pub fn new(provider: P) -> Self {
+        Authenticator {
+            provider,
+            options: ValidationOptions::default(),
+            nonces: None,
+            nonce_outages: AtomicU64::new(0),
+            on_nonce_outage: None,
+        }
+    }
+
+    /// Set the validation options.
+    pub fn options(mut self, options: ValidationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set the nonce store used to reject replayed requests.
+    pub fn nonce_store(mut self, nonces: Arc<dyn NonceStore + Send + Sync>) -> Self {
+        self.nonces = Some(nonces);
+        self
+    }
+
+    /// Call the given function whenever the nonce store is unavailable, with the error and the
+    /// policy applied to the request, for example to log the outage.
+    pub fn on_nonce_outage<F>(mut self, on_nonce_outage: F) -> Self
+        where F: Fn(&NonceStoreUnavailable, NonceOutagePolicy) + Send + Sync + 'static
+    {
+        self.on_nonce_outage = Some(Box::new(on_nonce_outage));
+        self
+    }
+
+    /// Get the number of requests for which the nonce store was unavailable, whether they were
+    /// rejected or accepted.
+    pub fn nonce_outages(&self) -> u64 {
+        self.nonce_outages.load(Ordering::Relaxed)
+    }
+
+    /// Get the credentials provider.
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    /// Get the validation options.
+    pub fn validation_options(&self) -> &ValidationOptions {
+        &self.options
+    }
+
+    /// Authenticate a request with the given header, as for
+    /// `Request::authenticate_with_provider`.  If a nonce store is configured, the header's
+    /// nonce is then recorded, and a replayed nonce is rejected.
+    pub fn authenticate<'b>(&self,
+                            request: &'b Request,
+                            header: &'b Header)
+                            -> Option<Artifacts<'b>> {
+        self.check(request, header).ok()
+    }
+
+    /// Authenticate a request with the given header, as for `authenticate`, but returning the
+    /// reason for any failure.  See `Request::check_with_provider`.
+    pub fn check<'b>(&self,
+                     request: &'b Request,
+                     header: &'b Header)
+                     -> ::std::result::Result<Artifacts<'b>, RejectionReason> {
+        let artifacts = request.check_with_provider(header, &self.provider, &self.options)?;
+        self.record_nonce(artifacts)
+    }
+
+    /// Authenticate a request with a header from a `HeaderCache`, as for `check`, but reusing
+    /// the verdict of an earlier validation of the same header for the same request, if any.
+    ///
+    /// The timestamp, the validity of the matching key, and the nonce are checked as usual; see
+    /// `HeaderCache` for when cached verdicts are safe to use.
+    pub fn check_cached<'b>(&self,
+                            request: &'b Request,
+                            cached: &'b CachedHeader)
+                            -> ::std::result::Result<Artifacts<'b>, RejectionReason> {
+        let header = cached.header();
+        let artifacts = match cached.verdict(request) {
+            Some(CachedVerdict::Rejected(reason)) => return Err(reason),
+            Some(CachedVerdict::Valid { without_query,
+                                        app_principal,
+                                        validity,
+                                        normalized_hash }) => {
+                // a valid verdict is only recorded for headers with these fields
+                let ts = header.ts().ok_or(RejectionReason::MissingField(HeaderField::Ts))?;
+                let nonce = header.nonce()
+                    .ok_or(RejectionReason::MissingField(HeaderField::Nonce))?;
+                let now = self.options.clock.now();
+                let skew = if now > ts { now - ts } else { ts - now };
+                if skew > self.options.ts_skew {
+                    return Err(RejectionReason::Stale);
+                }
+                if validity.not_before.is_some_and(|nbf| now < nbf) {
+                    return Err(RejectionReason::CredentialsNotYetValid);
+                }
+                if !validity.is_valid_at(now) {
+                    return Err(RejectionReason::CredentialsExpired);
+                }
+                let path = match request.path().split_once('?') {
+                    Some((path, _)) if without_query => path,
+                    _ => request.path(),
+                };
+                let artifacts = Artifacts::new(request.method(),
+                                               request.host(),
+                                               request.port(),
+                                               path,
+                                               header,
+                                               ts,
+                                               nonce);
+                let artifacts = artifacts.with_normalized_hash(normalized_hash)
+                    .with_empty_ext_as_absent(self.options.empty_ext_as_absent)
+                    .with_app_principal(app_principal);
+                if without_query {
+                    artifacts.signed_without_query()
+                } else {
+                    artifacts
+                }
+            }
+            None => {
+                let result =
+                    request.check_with_provider_validity(header, &self.provider, &self.options);
+                cached.record(request,
+                              match result {
+                                  Ok((ref artifacts, validity)) => Ok((artifacts, validity)),
+                                  Err(reason) => Err(reason),
+                              });
+                result?.0
+            }
+        };
+        self.record_nonce(artifacts)
+    }
+
+    /// Record the nonce of a request whose header has been validated, rejecting a replay.
+    fn record_nonce<'b>(&self,
+                        artifacts: Artifacts<'b>)
+                        -> ::std::result::Result<Artifacts<'b>, RejectionReason> {
+        if let Some(ref nonces) = self.nonces {
+            let id = artifacts.id().ok_or(RejectionReason::MissingField(HeaderField::Id))?;
+            match nonces.try_check_and_record(id, artifacts.nonce(), artifacts.ts()) {
+                Ok(true) => {}
+                Ok(false) => return Err(RejectionReason::Replayed),
+                Err(e) => {
+                    self.nonce_outages.fetch_add(1, Ordering::Relaxed);
+                    let policy = self.options.nonce_outage;
+                    if let Some(ref on_nonce_outage) = self.on_nonce_outage {
+                        on_nonce_outage(&e, policy);
+                    }
+                    if policy == NonceOutagePolicy::FailClosed {
+                        return Err(RejectionReason::NonceStoreUnavailable);
+                    }
+                }
+            }
+        }
+        Ok(artifacts)
+    }
+
+    /// Test each component of this authenticator, by signing and validating synthetic
+    /// requests.
+    ///
+    /// The credentials provider is tested only if `probe_id` is given; this should be an id for
+    /// which the provider has keys.  The nonce store, if any, is left with a synthetic nonce
+    /// recorded under the id `hawk-self-test`.
+    pub fn self_test(&self, probe_id: Option<&str>) -> SelfTestReport {
+        SelfTestReport {
+            crypto: self.test_crypto(),
+            nonce_store: self.test_nonce_store(),
+            credentials: match probe_id {
+                Some(id) => self.test_credentials(id),
+                None => ComponentStatus::Skipped,
+            },
+        }
+    }
+
+    fn test_crypto(&self) -> ComponentStatus {
+        for algorithm in &[&digest::SHA256, &digest::SHA384, &digest::SHA512] {
+            let mut key = vec![0u8; algorithm.output_len];
+            rand::thread_rng().fill_bytes(&mut key);
+            let credentials = Credentials {
+                id: SELF_TEST_ID.to_string(),
+                key: Key::new(key, algorithm),
+            };
+            if let Err(e) = self.round_trip(&credentials, |request, header| {
+                request.authenticate_header(header, &credentials.key, &self.options).is_some()
+            }) {
+                return ComponentStatus::Failed(format!("{:?}: {}", algorithm, e));
+            }
+        }
+        ComponentStatus::Passed
+    }
+
+    fn test_nonce_store(&self) -> ComponentStatus {
+        let nonces = match self.nonces {
+            Some(ref nonces) => nonces,
+            None => return ComponentStatus::Skipped,
+        };
+        let nonce = random_string(10);
+        let now = self.options.clock.now();
+        match nonces.try_check_and_record(SELF_TEST_ID, &nonce, now) {
+            Ok(true) => {}
+            Ok(false) => {
+                return ComponentStatus::Failed("a new nonce was reported as replayed".to_string())
+            }
+            Err(e) => return ComponentStatus::Failed(e.to_string()),
+        }
+        match nonces.try_check_and_record(SELF_TEST_ID, &nonce, now) {
+            Ok(false) => ComponentStatus::Passed,
+            Ok(true) => ComponentStatus::Failed("a replayed nonce was not detected".to_string()),
+            Err(e) => ComponentStatus::Failed(e.to_string()),
+        }
+    }
+
+    fn test_credentials(&self, id: &str) -> ComponentStatus {
+        let keys = self.provider.keys(id);
+        if keys.is_empty() {
+            return ComponentStatus::Failed(format!("no keys for probe id {}", id));
+        }
+        for key in keys {
+            let credentials = Credentials {
+                id: id.to_string(),
+                key,
+            };
+            if let Err(e) = self.round_trip(&credentials, |request, header| {
+                request.authenticate_with_provider(header, &self.provider, &self.options)
+                    .is_some()
+            }) {
+                return ComponentStatus::Failed(e);
+            }
+        }
+        ComponentStatus::Passed
+    }
+
+    /// Sign a synthetic request with the given credentials, and check that `validate` accepts
+    /// it.  The request carries a payload hash, so that it satisfies any `PayloadPolicy`.
+    fn round_trip<F>(&self, credentials: &Credentials, validate: F) -> Result<(), String>
+        where F: Fn(&Request, &Header) -> bool
+    {
+        let hash = PayloadHasher::hash("text/plain", credentials.key.algorithm(), SELF_TEST_ID);
+        let request = RequestBuilder::new("POST", "self-test.invalid", 443, "/hawk-self-test")
+            .hash(&hash[..])
+            .request();
+        let header = request
+            .make_header_full(credentials, self.options.clock.now(), random_string(10))
+            .map_err(|e| format!("signing failed: {}", e))?;
+        if !validate(&request, &header) {
+            return Err("validation of a freshly signed request failed".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use credentials::{SharedCredentials, Validity};
+    use nonce::MemoryNonceStore;
+    use time::{Duration, Timespec};
+
+    fn provider() -> SharedCredentials {
+        let provider = SharedCredentials::new();
+        provider.insert("me", Key::new(vec![99u8; 32], &digest::SHA256));
+        provider
+    }
+
+    #[test]
+    fn authenticate_rejects_replay() {
+        let authenticator = Authenticator::new(provider())
+            .nonce_store(Arc::new(MemoryNonceStore::new(Duration::minutes(1))));
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        let request = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let header = request.make_header(&credentials).unwrap();
+        assert!(authenticator.authenticate(&request, &header).is_some());
+        assert_eq!(authenticator.check(&request, &header).unwrap_err(),
+                   RejectionReason::Replayed);
+    }
+
+    #[test]
+    fn check_cached() {
+        use header_cache::HeaderCache;
+        use std::sync::atomic::AtomicUsize;
+        use testing::Deterministic;
+
+        struct Counting(SharedCredentials, AtomicUsize);
+        impl CredentialsProvider for Counting {
+            fn keys(&self, id: &str) -> Vec<Key> {
+                self.1.fetch_add(1, Ordering::Relaxed);
+                self.0.keys(id)
+            }
+        }
+
+        let _deterministic = Deterministic::at(Timespec::new(1353832234, 0));
+        let authenticator = Authenticator::new(Counting(provider(), AtomicUsize::new(0)));
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        let request = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let raw = request.make_header(&credentials).unwrap().to_string();
+        let cache = HeaderCache::new(10, Duration::seconds(30));
+
+        // the retry reuses the verdict, without looking up the keys
+        for _ in 0..2 {
+            let cached = cache.get(&raw).unwrap();
+            let artifacts = authenticator.check_cached(&request, &cached).unwrap();
+            assert_eq!(artifacts.id(), Some("me"));
+        }
+        assert_eq!(authenticator.provider().1.load(Ordering::Relaxed), 1);
+
+        // a different request is validated afresh, and its rejection cached
+        let other = RequestBuilder::new("GET", "example.com", 443, "/x").request();
+        for _ in 0..2 {
+            assert_eq!(authenticator.check_cached(&other, &cache.get(&raw).unwrap()).unwrap_err(),
+                       RejectionReason::MacMismatch);
+        }
+        assert_eq!(authenticator.provider().1.load(Ordering::Relaxed), 2);
+        drop(_deterministic);
+
+        // a cached header still becomes stale
+        let _deterministic = Deterministic::at(Timespec::new(1353832234 + 120, 0));
+        let cached = cache.get(&raw).unwrap();
+        assert_eq!(authenticator.check_cached(&request, &cached).unwrap_err(),
+                   RejectionReason::Stale);
+    }
+
+    #[test]
+    fn check_cached_rejects_replay() {
+        use header_cache::HeaderCache;
+
+        let authenticator = Authenticator::new(provider())
+            .nonce_store(Arc::new(MemoryNonceStore::new(Duration::minutes(1))));
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        let request = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let raw = request.make_header(&credentials).unwrap().to_string();
+        let cache = HeaderCache::new(10, Duration::seconds(30));
+        assert!(authenticator.check_cached(&request, &cache.get(&raw).unwrap()).is_ok());
+        assert_eq!(authenticator.check_cached(&request, &cache.get(&raw).unwrap()).unwrap_err(),
+                   RejectionReason::Replayed);
+    }
+
+    #[test]
+    fn check_credentials_validity() {
+        struct Limited(Validity);
+        impl CredentialsProvider for Limited {
+            fn keys(&self, _id: &str) -> Vec<Key> {
+                unreachable!()
+            }
+            fn keys_with_validity(&self, id: &str) -> Vec<(Key, Validity)> {
+                match id {
+                    "me" => vec![(Key::new(vec![99u8; 32], &digest::SHA256), self.0)],
+                    _ => vec![],
+                }
+            }
+        }
+
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        let request = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let header = request.make_header(&credentials).unwrap();
+        let now = ::clock::now();
+        let check = |validity| Authenticator::new(Limited(validity)).check(&request, &header);
+
+        assert!(check(Validity::default()).is_ok());
+        assert_eq!(check(Validity {
+                             not_before: Some(now + Duration::hours(1)),
+                             expires: None,
+                         })
+                       .unwrap_err(),
+                   RejectionReason::CredentialsNotYetValid);
+        assert_eq!(check(Validity {
+                             not_before: None,
+                             expires: Some(now - Duration::hours(1)),
+                         })
+                       .unwrap_err(),
+                   RejectionReason::CredentialsExpired);
+
+        // a forged header is rejected for its MAC, regardless of validity
+        let forged = request.make_header(&Credentials {
+                id: "me".to_string(),
+                key: Key::new(vec![1u8; 32], &digest::SHA256),
+            })
+            .unwrap();
+        assert_eq!(Authenticator::new(Limited(Validity {
+                           not_before: None,
+                           expires: Some(now - Duration::hours(1)),
+                       }))
+                       .check(&request, &forged)
+                       .unwrap_err(),
+                   RejectionReason::MacMismatch);
+
+        let unknown = request.make_header(&Credentials {
+                id: "you".to_string(),
+                key: Key::new(vec![99u8; 32], &digest::SHA256),
+            })
+            .unwrap();
+        assert_eq!(Authenticator::new(Limited(Validity::default()))
+                       .check(&request, &unknown)
+                       .unwrap_err(),
+                   RejectionReason::UnknownId);
+    }
+
+    #[test]
+    fn self_test_healthy() {
+        let authenticator = Authenticator::new(provider())
+            .nonce_store(Arc::new(MemoryNonceStore::new(Duration::minutes(1))));
+        let report = authenticator.self_test(Some("me"));
+        assert_eq!(report,
+                   SelfTestReport {
+                       crypto: ComponentStatus::Passed,
+                       nonce_store: ComponentStatus::Passed,
+                       credentials: ComponentStatus::Passed,
+                   });
+        assert!(report.is_healthy());
+    }
+
+    #[test]
+    fn self_test_unconfigured() {
+        let report = Authenticator::new(provider()).self_test(None);
+        assert_eq!(report.crypto, ComponentStatus::Passed);
+        assert_eq!(report.nonce_store, ComponentStatus::Skipped);
+        assert_eq!(report.credentials, ComponentStatus::Skipped);
+        assert!(report.is_healthy());
+    }
+
+    #[test]
+    fn self_test_unknown_probe_id() {
+        let report = Authenticator::new(provider()).self_test(Some("nobody"));
+        assert!(report.credentials.is_failed());
+        assert!(!report.is_healthy());
+    }
+
+    /// A nonce store which never detects replays.
+    struct ForgetfulStore;
+
+    impl NonceStore for ForgetfulStore {
+        fn check_and_record(&self, _id: &str, _nonce: &str, _ts: Timespec) -> bool {
+            true
+        }
+    }
+
+    /// A nonce store which is always unavailable.
+    struct UnavailableStore;
+
+    impl NonceStore for UnavailableStore {
+        fn check_and_record(&self, _id: &str, _nonce: &str, _ts: Timespec) -> bool {
+            false
+        }
+
+        fn try_check_and_record(&self,
+                                _id: &str,
+                                _nonce: &str,
+                                _ts: Timespec)
+                                -> ::std::result::Result<bool, NonceStoreUnavailable> {
+            Err(NonceStoreUnavailable::new("timed out"))
+        }
+    }
+
+    #[test]
+    fn nonce_outage_policy() {
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        let request = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let header = request.make_header(&credentials).unwrap();
+
+        let authenticator = Authenticator::new(provider())
+            .nonce_store(Arc::new(UnavailableStore));
+        assert_eq!(authenticator.check(&request, &header).unwrap_err(),
+                   RejectionReason::NonceStoreUnavailable);
+        assert_eq!(authenticator.nonce_outages(), 1);
+
+        let reported = Arc::new(AtomicU64::new(0));
+        let authenticator = {
+            let reported = reported.clone();
+            Authenticator::new(provider())
+                .options(ValidationOptions::default()
+                    .with_nonce_outage(NonceOutagePolicy::FailOpen))
+                .nonce_store(Arc::new(UnavailableStore))
+                .on_nonce_outage(move |e, policy| {
+                    assert_eq!(e.reason(), "timed out");
+                    assert_eq!(policy, NonceOutagePolicy::FailOpen);
+                    reported.fetch_add(1, Ordering::Relaxed);
+                })
+        };
+        assert!(authenticator.check(&request, &header).is_ok());
+        assert!(authenticator.check(&request, &header).is_ok());
+        assert_eq!(authenticator.nonce_outages(), 2);
+        assert_eq!(reported.load(Ordering::Relaxed), 2);
+
+        assert_eq!(authenticator.self_test(None).nonce_store,
+                   ComponentStatus::Failed("nonce store unavailable: timed out".to_string()));
+    }
+
+    #[test]
+    fn self_test_broken_nonce_store() {
+        let report = Authenticator::new(provider())
+            .nonce_store(Arc::new(ForgetfulStore))
+            .self_test(Some("me"));
+        assert_eq!(report.nonce_store,
+                   ComponentStatus::Failed("a replayed nonce was not detected".to_string()));
+        assert!(!report.is_healthy());
+    }
+}
@@ -0,0 +1,238 @@
+use base64;
+use clock;
+use credentials::Key;
+use crypto;
+use error::*;
+use hawk_core::{Fields, Header, HeaderField, ParseMode};
+use mac::Mac;
+use rejected::RejectionReason;
+use std::fmt;
+use std::str::FromStr;
+use time::Timespec;
+
+/// A Hawk `WWW-Authenticate` challenge, sent with a `401 Unauthorized` response.
+///
+/// A bare challenge, `Hawk`, tells the client that the resource requires Hawk authentication.
+/// A challenge may also carry an `error` message and, for requests rejected for a stale
+/// timestamp, the server's current time `ts` with its MAC `tsm`, which the client can verify
+/// with `verified_ts` and use to correct its clock.
+///
+/// Challenges are formatted, with the `Hawk` scheme, using `Display`, and parsed with `FromStr`.
+///
+/// # Examples
+///
+/// ```
+/// use hawk::{Challenge, Key, SHA256};
+///
+/// let key = Key::new(vec![99u8; 32], &SHA256);
+///
+/// // server
+/// let challenge = Challenge::stale(&key);
+/// let www_authenticate = challenge.to_string();
+///
+/// // client
+/// let challenge: Challenge = www_authenticate.parse().unwrap();
+/// assert_eq!(challenge.error(), Some("Stale timestamp"));
+/// let server_ts = challenge.verified_ts(&key).unwrap();
+/// ```
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Challenge {
+    ts: Option<Timespec>,
+    tsm: Option<Mac>,
+    error: Option<String>,
+}
+
+impl Challenge {
+    /// Create a bare challenge, with no error or timestamp.
+    pub fn new() -> Challenge {
+        Challenge::default()
+    }
+
+    /// Create a challenge for a request rejected for a stale timestamp, carrying the current
+    /// time and its MAC calculated with the given key.  This should be the key the request was
+    /// signed with.
+    pub fn stale(key: &Key) -> Challenge {
+        Challenge {
+            error: Some("Stale timestamp".to_string()),
+            ..Challenge::default()
+        }
+        .with_ts(clock::now(), key)
+    }
+
+    /// Create a challenge for a request rejected for the given reason, with the error message
+    /// the Node Hawk server uses.  This does not include a timestamp, even for
+    /// `RejectionReason::Stale`; use `stale` for that.
+    pub fn rejected(reason: RejectionReason) -> Challenge {
+        let error = match reason {
+            RejectionReason::MissingField(_) => "Missing attributes",
+            RejectionReason::WrongLength(HeaderField::Hash) => "Bad payload hash",
+            RejectionReason::WrongLength(_) => "Bad mac",
+            RejectionReason::ExtNotPermitted => "Invalid ext",
+            RejectionReason::NonceNotPermitted => "Invalid nonce",
+            RejectionReason::MacMismatch => "Bad mac",
+            RejectionReason::MissingHash => "Missing required payload hash",
+            RejectionReason::HashMismatch => "Bad payload hash",
+            RejectionReason::Stale => "Stale timestamp",
+            RejectionReason::UnknownId => "Unknown credentials",
+            RejectionReason::CredentialsNotYetValid => "Credentials not yet valid",
+            RejectionReason::CredentialsExpired => "Expired credentials",
+            RejectionReason::Replayed => "Invalid nonce",
+            RejectionReason::NonceStoreUnavailable => "Unable to verify nonce",
+        };
+        Challenge {
+            error: Some(error.to_string()),
+            ..Challenge::default()
+        }
+    }
+
+    /// Set the error message.  The message cannot contain `"`.
+    pub fn with_error<S>(mut self, error: Option<S>) -> Result<Challenge>
+        where S: Into<String>
+    {
+        self.error = match error {
+            Some(error) => {
+                let error = error.into();
+                Header::check_str(&error)?;
+                Some(error)
+            }
+            None => None,
+        };
+        Ok(self)
+    }
+
+    /// Set the timestamp, and its MAC calculated with the given key.
+    pub fn with_ts(mut self, ts: Timespec, key: &Key) -> Challenge {
+        self.ts = Some(Timespec::new(ts.sec, 0));
+        self.tsm = Some(crypto::calculate_ts_mac(ts, key));
+        self
+    }
+
+    /// Get the `error` message, if any.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_ref().map(|e| &e[..])
+    }
+
+    /// Get the timestamp, if any.  This is not verified; see `verified_ts`.
+    pub fn ts(&self) -> Option<Timespec> {
+        self.ts
+    }
+
+    /// Get the timestamp MAC, if any.
+    pub fn tsm(&self) -> Option<&Mac> {
+        self.tsm.as_ref()
+    }
+
+    /// Get the timestamp, if it is present and its MAC is valid for the given key.
+    pub fn verified_ts(&self, key: &Key) -> Option<Timespec> {
+        match (self.ts, self.tsm.as_ref()) {
+            (Some(ts), Some(tsm)) if crypto::verify_ts_mac(ts, tsm, key) => Some(ts),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Challenge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Hawk")?;
+        let mut sep = " ";
+        if let (Some(ts), Some(tsm)) = (self.ts, self.tsm.as_ref()) {
+            write!(f, "{}ts=\"{}\", tsm=\"{}\"", sep, ts.sec, base64::encode(tsm))?;
+            sep = ", ";
+        }
+        if let Some(ref error) = self.error {
+            write!(f, "{}error=\"{}\"", sep, error)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Challenge {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Challenge> {
+        let s = s.trim_start();
+        let (scheme, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        if !scheme.eq_ignore_ascii_case("hawk") {
+            bail!("Not a Hawk challenge");
+        }
+
+        let mut challenge = Challenge::default();
+        for field in Fields::new(rest, ParseMode::Lenient) {
+            let field = field.map_err(|e| Error::with_chain(e, ErrorKind::HeaderParseError))?;
+            match field.name {
+                "ts" => {
+                    let ts = i64::from_str(field.value).chain_err(|| "Error parsing `ts` field")?;
+                    challenge.ts = Some(Timespec::new(ts, 0));
+                }
+                "tsm" => {
+                    let tsm = base64::decode(field.value)
+                        .chain_err(|| "Error parsing `tsm` field")?;
+                    challenge.tsm = Some(Mac::from(tsm));
+                }
+                "error" => challenge.error = Some(field.value.to_string()),
+                attr => bail!("Invalid Hawk challenge field {}", attr),
+            }
+        }
+        Ok(challenge)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Challenge;
+    use credentials::Key;
+    use rejected::RejectionReason;
+    use ring::digest;
+    use std::str::FromStr;
+    use time::Timespec;
+
+    fn key() -> Key {
+        Key::new(&b"werxhqb98rpaxn39848xrunpaw3489ruxnpa98w4rxn"[..], &digest::SHA256)
+    }
+
+    #[test]
+    fn bare() {
+        assert_eq!(Challenge::new().to_string(), "Hawk");
+        assert_eq!(Challenge::from_str("Hawk").unwrap(), Challenge::new());
+    }
+
+    #[test]
+    fn error() {
+        let challenge = Challenge::rejected(RejectionReason::MacMismatch);
+        assert_eq!(challenge.to_string(), "Hawk error=\"Bad mac\"");
+        assert_eq!(Challenge::from_str("hawk error=\"Bad mac\"").unwrap(), challenge);
+        assert!(Challenge::new().with_error(Some("a \"quote\"")).is_err());
+    }
+
+    #[test]
+    fn ts() {
+        let challenge = Challenge::new()
+            .with_error(Some("Stale timestamp"))
+            .unwrap()
+            .with_ts(Timespec::new(1353832234, 0), &key());
+        // as produced by the Node Hawk server
+        let formatted = "Hawk ts=\"1353832234\", tsm=\"2mw1eh/qXzl0wJZ/E6XvBhRMEJN7L3j8AyMA8eItEb0=\", \
+                         error=\"Stale timestamp\"";
+        assert_eq!(challenge.to_string(), formatted);
+
+        let parsed = Challenge::from_str(formatted).unwrap();
+        assert_eq!(parsed, challenge);
+        assert_eq!(parsed.verified_ts(&key()), Some(Timespec::new(1353832234, 0)));
+        let other = Key::new(vec![1u8; 32], &digest::SHA256);
+        assert_eq!(parsed.verified_ts(&other), None);
+    }
+
+    #[test]
+    fn stale() {
+        let challenge = Challenge::stale(&key());
+        assert_eq!(challenge.error(), Some("Stale timestamp"));
+        assert!(challenge.verified_ts(&key()).is_some());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(Challenge::from_str("Basic realm=\"x\"").is_err());
+        assert!(Challenge::from_str("Hawk ts=\"soon\"").is_err());
+        assert!(Challenge::from_str("Hawk realm=\"x\"").is_err());
+        assert!(Challenge::from_str("Hawk error=\"x").is_err());
+    }
+}
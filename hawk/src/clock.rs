@@ -0,0 +1,358 @@
+use challenge::Challenge;
+use credentials::Key;
+use std::error;
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicI64, AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{self as std_time, SystemTime, UNIX_EPOCH};
+use time::{self, Duration, Timespec};
+
+/// A source of the current time, used when validating timestamps and expiration times.
+///
+/// The default is `SystemClock`.  Tests, and services which take their time from elsewhere, can
+/// supply their own implementation.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Get the current time.
+    fn now(&self) -> Timespec;
+}
+
+/// A Clock reading the system time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timespec {
+        time::now().to_timespec()
+    }
+}
+
+/// The clock registered with `set_default_clock`, if any.
+static DEFAULT_CLOCK: RwLock<Option<Arc<dyn Clock>>> = RwLock::new(None);
+
+thread_local! {
+    /// A clock overriding the default on this thread; see `testing::deterministic_for_tests`.
+    static THREAD_CLOCK: RefCell<Option<Arc<dyn Clock>>> = const { RefCell::new(None) };
+}
+
+/// Register the clock used by default throughout the crate, in place of the system time.
+///
+/// Once registered, this clock provides the current time wherever no other clock is given:
+/// for the timestamps of headers made by `make_header`, the expiration times of bewits made by
+/// `make_bewit`, and in validation with the default `ValidationOptions`.  This suits embedded
+/// environments without a reliable real-time clock, which can register a network-time source
+/// once at startup.
+pub fn set_default_clock(clock: Arc<dyn Clock>) {
+    *DEFAULT_CLOCK.write().unwrap_or_else(|e| e.into_inner()) = Some(clock);
+}
+
+/// Remove any clock registered with `set_default_clock`, reverting to the system time.
+pub fn reset_default_clock() {
+    *DEFAULT_CLOCK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// A Clock which reads the clock registered with `set_default_clock`, or the system time if
+/// none is registered.  The registered clock is consulted on every call, so this reflects
+/// registrations made after it was created.
+///
+/// This is the clock in the default `ValidationOptions`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultClock;
+
+impl Clock for DefaultClock {
+    fn now(&self) -> Timespec {
+        now()
+    }
+}
+
+/// Replace the clock overriding the default on this thread, returning the previous one.
+pub(crate) fn replace_thread_clock(clock: Option<Arc<dyn Clock>>) -> Option<Arc<dyn Clock>> {
+    THREAD_CLOCK.with(|c| c.replace(clock))
+}
+
+/// Get the current time from the default clock.
+pub(crate) fn now() -> Timespec {
+    if let Some(now) = THREAD_CLOCK.with(|c| c.borrow().as_ref().map(|c| c.now())) {
+        return now;
+    }
+    let registered = DEFAULT_CLOCK.read().unwrap_or_else(|e| e.into_inner());
+    match *registered {
+        Some(ref clock) => clock.now(),
+        None => time::now().to_timespec(),
+    }
+}
+
+/// A Clock which always returns the same time.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedClock(pub Timespec);
+
+impl Clock for FixedClock {
+    fn now(&self) -> Timespec {
+        self.0
+    }
+}
+
+/// A Clock which corrects another clock by an offset learned from the server.
+///
+/// When a server rejects a request because its timestamp is outside the allowed skew, it
+/// tells the client its current time (see `Challenge::verified_ts`).  Passing that time to
+/// `adjust_to` records the difference from the local clock, and `now` thereafter returns the
+/// local time corrected by that difference, suitable for signing further requests with
+/// `make_header_full`.  A `ClockCorrector` can manage these corrections.
+///
+/// Although Hawk timestamps are whole seconds, the offset is kept to the millisecond: the
+/// local time is measured to the millisecond when the offset is calculated, and applied with
+/// the same precision.  Truncating both to seconds could leave the corrected clock almost two
+/// seconds off, too much for tight skew windows.
+#[derive(Debug)]
+pub struct SkewCorrectedClock {
+    inner: Arc<dyn Clock>,
+    offset_ms: AtomicI64,
+}
+
+impl SkewCorrectedClock {
+    /// Create a new clock correcting the given clock, initially with no offset.
+    pub fn new(inner: Arc<dyn Clock>) -> SkewCorrectedClock {
+        SkewCorrectedClock {
+            inner,
+            offset_ms: AtomicI64::new(0),
+        }
+    }
+
+    /// Record the server's current time, replacing any previous offset.
+    pub fn adjust_to(&self, server_now: Timespec) {
+        let offset = to_millis(server_now) - to_millis(self.inner.now());
+        self.offset_ms.store(offset, Ordering::Relaxed);
+    }
+
+    /// Get the offset currently applied to the local clock.
+    pub fn offset(&self) -> Duration {
+        Duration::milliseconds(self.offset_ms.load(Ordering::Relaxed))
+    }
+}
+
+impl Clock for SkewCorrectedClock {
+    fn now(&self) -> Timespec {
+        self.inner.now() + self.offset()
+    }
+}
+
+/// A client-side policy for correcting the clock in response to stale-timestamp challenges,
+/// giving up when corrections do not help.
+///
+/// When a request is rejected with a `Challenge` carrying the server's time, pass it to
+/// `correct`, which adjusts the `SkewCorrectedClock` and indicates whether to retry the request
+/// (after waiting for any `Retry-After` the server sent).  After `max_corrections` consecutive
+/// corrections without a successful request, which suggests the local clock is unstable or the
+/// server's is wrong, `correct` fails with `ClockUnsynchronized` instead, so the client does not
+/// retry indefinitely.  Call `succeeded` after each successful request to reset the count.
+#[derive(Debug)]
+pub struct ClockCorrector {
+    clock: Arc<SkewCorrectedClock>,
+    max_corrections: u32,
+    corrections: AtomicU32,
+}
+
+impl ClockCorrector {
+    /// Create a new corrector for the given clock, allowing `max_corrections` consecutive
+    /// corrections.
+    pub fn new(clock: Arc<SkewCorrectedClock>, max_corrections: u32) -> ClockCorrector {
+        ClockCorrector {
+            clock,
+            max_corrections,
+            corrections: AtomicU32::new(0),
+        }
+    }
+
+    /// Get the clock being corrected.
+    pub fn clock(&self) -> &Arc<SkewCorrectedClock> {
+        &self.clock
+    }
+
+    /// Handle a challenge received in response to a request signed with the given key.
+    ///
+    /// Returns true if the challenge carried a verified server time and the clock was corrected,
+    /// in which case the request should be re-signed and retried; or false if the challenge
+    /// carried no verified time, in which case retrying will not help.
+    pub fn correct(&self,
+                   challenge: &Challenge,
+                   key: &Key)
+                   -> ::std::result::Result<bool, ClockUnsynchronized> {
+        let server_now = match challenge.verified_ts(key) {
+            Some(ts) => ts,
+            None => return Ok(false),
+        };
+        let corrections = self.corrections.fetch_add(1, Ordering::Relaxed);
+        if corrections >= self.max_corrections {
+            let local = self.clock.inner.now();
+            return Err(ClockUnsynchronized {
+                offset: server_now - local,
+                corrections,
+            });
+        }
+        self.clock.adjust_to(server_now);
+        Ok(true)
+    }
+
+    /// Record that a request succeeded, resetting the count of consecutive corrections.
+    pub fn succeeded(&self) {
+        self.corrections.store(0, Ordering::Relaxed);
+    }
+}
+
+/// The error returned when repeated clock corrections have not resolved stale-timestamp
+/// rejections.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ClockUnsynchronized {
+    offset: Duration,
+    corrections: u32,
+}
+
+impl ClockUnsynchronized {
+    /// Get the most recently measured offset of the server's clock from the local clock.
+    pub fn offset(&self) -> Duration {
+        self.offset
+    }
+
+    /// Get the number of corrections made before giving up.
+    pub fn corrections(&self) -> u32 {
+        self.corrections
+    }
+}
+
+impl fmt::Display for ClockUnsynchronized {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "Clock unsynchronized with server after {} corrections; server is {}s ahead",
+               self.corrections,
+               self.offset.num_seconds())
+    }
+}
+
+impl error::Error for ClockUnsynchronized {}
+
+/// Convert a SystemTime to a Timespec.
+pub(crate) fn timespec_from_system_time(t: SystemTime) -> Timespec {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => Timespec::new(d.as_secs() as i64, d.subsec_nanos() as i32),
+        Err(e) => Timespec::new(0, 0) - duration_from_std(e.duration()),
+    }
+}
+
+/// Convert a std Duration to a time Duration, saturating at the maximum.
+pub(crate) fn duration_from_std(d: std_time::Duration) -> Duration {
+    Duration::from_std(d).unwrap_or_else(|_| Duration::max_value())
+}
+
+/// Convert a Timespec to milliseconds since the epoch, truncating any finer precision.
+fn to_millis(ts: Timespec) -> i64 {
+    ts.sec * 1000 + i64::from(ts.nsec / 1_000_000)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{duration_from_std, reset_default_clock, set_default_clock,
+                timespec_from_system_time, Clock, ClockCorrector, DefaultClock, FixedClock,
+                SkewCorrectedClock, SystemClock};
+    use challenge::Challenge;
+    use credentials::Key;
+    use ring::digest;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{self as std_time, UNIX_EPOCH};
+    use time::{self, Duration, Timespec};
+
+    #[test]
+    fn system_clock() {
+        let diff = SystemClock.now() - time::now().to_timespec();
+        assert!(diff < Duration::seconds(1) && diff > Duration::seconds(-1));
+    }
+
+    #[test]
+    fn fixed_clock() {
+        assert_eq!(FixedClock(Timespec::new(1000, 0)).now(), Timespec::new(1000, 0));
+    }
+
+    #[test]
+    fn clock_corrector() {
+        let key = Key::new(vec![1u8; 32], &digest::SHA256);
+        let clock = Arc::new(SkewCorrectedClock::new(Arc::new(FixedClock(Timespec::new(1000, 0)))));
+        let corrector = ClockCorrector::new(clock.clone(), 2);
+        let stale = |server_ts| {
+            Challenge::new().with_error(Some("Stale timestamp")).unwrap().with_ts(server_ts, &key)
+        };
+
+        // challenges without a verified time are not corrections
+        assert_eq!(corrector.correct(&Challenge::new(), &key), Ok(false));
+        let other = Key::new(vec![2u8; 32], &digest::SHA256);
+        assert_eq!(corrector.correct(&stale(Timespec::new(2000, 0)), &other), Ok(false));
+        assert_eq!(clock.offset(), Duration::zero());
+
+        assert_eq!(corrector.correct(&stale(Timespec::new(1100, 0)), &key), Ok(true));
+        assert_eq!(clock.offset(), Duration::seconds(100));
+
+        // a success resets the count
+        corrector.succeeded();
+        assert_eq!(corrector.correct(&stale(Timespec::new(1200, 0)), &key), Ok(true));
+        assert_eq!(corrector.correct(&stale(Timespec::new(1300, 0)), &key), Ok(true));
+        let err = corrector.correct(&stale(Timespec::new(1400, 0)), &key).unwrap_err();
+        assert_eq!(err.offset(), Duration::seconds(400));
+        assert_eq!(err.corrections(), 2);
+        assert_eq!(clock.offset(), Duration::seconds(300));
+    }
+
+    #[test]
+    fn skew_corrected_clock() {
+        let local = Timespec::new(1000, 750_000_000);
+        let clock = SkewCorrectedClock::new(Arc::new(FixedClock(local)));
+        assert_eq!(clock.now(), local);
+
+        // the server is 29.25s ahead; only whole seconds are known, but the local time is
+        // measured to the millisecond
+        clock.adjust_to(Timespec::new(1030, 0));
+        assert_eq!(clock.offset(), Duration::milliseconds(29250));
+        assert_eq!(clock.now(), Timespec::new(1030, 0));
+        assert_eq!(clock.now().sec, 1030);
+
+        clock.adjust_to(Timespec::new(990, 0));
+        assert_eq!(clock.offset(), Duration::milliseconds(-10750));
+        assert_eq!(clock.now(), Timespec::new(990, 0));
+    }
+
+    #[test]
+    fn system_time_conversion() {
+        let t = UNIX_EPOCH + std_time::Duration::new(1353832234, 500);
+        assert_eq!(timespec_from_system_time(t), Timespec::new(1353832234, 500));
+        let t = UNIX_EPOCH - std_time::Duration::from_millis(1500);
+        assert_eq!(timespec_from_system_time(t), Timespec::new(-2, 500_000_000));
+        assert_eq!(duration_from_std(std_time::Duration::from_secs(90)),
+                   Duration::seconds(90));
+        assert_eq!(duration_from_std(std_time::Duration::from_secs(u64::MAX)),
+                   Duration::max_value());
+    }
+
+    /// A clock reading the system time, counting how often it is read.
+    #[derive(Debug, Default)]
+    struct CountingClock(AtomicUsize);
+
+    impl Clock for CountingClock {
+        fn now(&self) -> Timespec {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            time::now().to_timespec()
+        }
+    }
+
+    #[test]
+    fn default_clock() {
+        // the registration is global, so this registers a clock which still reads the system
+        // time, so as not to disturb tests running concurrently
+        let clock = Arc::new(CountingClock::default());
+        set_default_clock(clock.clone());
+        DefaultClock.now();
+        assert!(clock.0.load(Ordering::SeqCst) >= 1);
+        reset_default_clock();
+        let count = clock.0.load(Ordering::SeqCst);
+        DefaultClock.now();
+        assert_eq!(clock.0.load(Ordering::SeqCst), count);
+    }
+}
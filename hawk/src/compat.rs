@@ -0,0 +1,229 @@
+//! Wire-format compatibility tests.
+//!
+//! Every artifact this crate puts on the wire -- normalized strings, request headers, bewits,
+//! and response headers -- is generated here for a fixed set of requests, keys, timestamps, and
+//! nonces under each supported digest algorithm, and compared byte-for-byte against the golden
+//! files in `golden/`.  A change which alters any of these files breaks compatibility with
+//! deployed peers, and must not be made casually.
+//!
+//! To regenerate the golden files after an intentional format change, run the tests with
+//! `HAWK_BLESS_GOLDEN=1` and review the resulting diff.
+
+use credentials::{Credentials, Key};
+use mac::{self, MacType};
+use request::{Request, RequestBuilder};
+use response::ResponseBuilder;
+use ring::digest;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use testing::Deterministic;
+use time::{Duration, Timespec};
+
+const TS: i64 = 1353832234;
+const NONCE: &str = "j4h3g2";
+
+fn algorithms() -> Vec<(&'static str, &'static digest::Algorithm)> {
+    vec![("sha256", &digest::SHA256), ("sha384", &digest::SHA384), ("sha512", &digest::SHA512)]
+}
+
+fn credentials(algorithm: &'static digest::Algorithm) -> Credentials {
+    Credentials {
+        id: "dh37fgj492je".to_string(),
+        key: Key::new(&b"werxhqb98rpaxn39848xrunpaw3489ruxnpa98w4rxn"[..], algorithm),
+    }
+}
+
+const HASH: &[u8] = &[1, 2, 3, 4, 5, 6, 7, 8];
+
+/// The requests for which artifacts are generated, by name.
+fn requests() -> Vec<(&'static str, Request<'static>)> {
+    vec![("get", RequestBuilder::new("GET", "example.com", 8000, "/resource/1?b=1&a=2").request()),
+         ("post-hash-ext",
+          RequestBuilder::new("POST", "example.com", 443, "/v1/users")
+              .hash(HASH)
+              .ext("some-app-data")
+              .request()),
+         ("delegated",
+          RequestBuilder::new("PUT", "api.example.com", 80, "/v1/items/%7E")
+              .ext("x")
+              .app("my-app")
+              .dlg("my-dlg")
+              .request())]
+}
+
+/// Escape newlines so that each entry occupies a single line of a golden file.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// The normalized string for the given request, including the `app` and `dlg` lines, as they
+/// are signed.
+fn normalized(mac_type: MacType,
+              ts: Timespec,
+              nonce: &str,
+              request: &Request,
+              hash: Option<&[u8]>,
+              ext: Option<&str>,
+              (app, dlg): (Option<&str>, Option<&str>))
+              -> String {
+    let mut buffer = vec![];
+    mac::write_normalized_prefix(&mut buffer, mac_type, ts, nonce).unwrap();
+    buffer.extend(mac::normalized_suffix(request.method(),
+                                         request.host(),
+                                         request.port(),
+                                         request.path(),
+                                         hash,
+                                         ext)
+        .unwrap());
+    mac::write_normalized_app(&mut buffer, app, dlg).unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+fn normalized_golden() -> String {
+    let mut out = String::new();
+    for (name, request) in requests() {
+        let hash = if name == "post-hash-ext" { Some(HASH) } else { None };
+        let ext = match name {
+            "get" => None,
+            "post-hash-ext" => Some("some-app-data"),
+            _ => Some("x"),
+        };
+        let (app, dlg) = if name == "delegated" {
+            (Some("my-app"), Some("my-dlg"))
+        } else {
+            (None, None)
+        };
+        for mac_type in [MacType::Header, MacType::Response, MacType::Bewit] {
+            // bewits have no nonce, and do not cover app and dlg
+            let (nonce, app, dlg) = if mac_type == MacType::Bewit {
+                ("", None, None)
+            } else {
+                (NONCE, app, dlg)
+            };
+            let ts = Timespec::new(TS, 0);
+            let s = normalized(mac_type, ts, nonce, &request, hash, ext, (app, dlg));
+            writeln!(out, "{} {}: {}", name, mac_type, escape(&s)).unwrap();
+        }
+    }
+    out
+}
+
+fn headers_golden() -> String {
+    let mut out = String::new();
+    for (alg, algorithm) in algorithms() {
+        let credentials = credentials(algorithm);
+        for (name, request) in requests() {
+            let header = request.make_header_full(&credentials, Timespec::new(TS, 0), NONCE)
+                .unwrap();
+            writeln!(out, "{} {}: {}", alg, name, header).unwrap();
+        }
+    }
+    out
+}
+
+fn bewits_golden() -> String {
+    let mut out = String::new();
+    let _deterministic = Deterministic::at(Timespec::new(TS, 0));
+    for (alg, algorithm) in algorithms() {
+        let credentials = credentials(algorithm);
+        for ext in [None, Some("some-app-data")] {
+            let bewit = RequestBuilder::new("GET", "example.com", 443, "/download/file.txt")
+                .ext(ext)
+                .request()
+                .make_bewit(&credentials, Duration::seconds(60))
+                .unwrap();
+            writeln!(out,
+                     "{} {}: {}",
+                     alg,
+                     if ext.is_some() { "ext" } else { "no-ext" },
+                     bewit.to_str())
+                .unwrap();
+        }
+    }
+    out
+}
+
+fn responses_golden() -> String {
+    let mut out = String::new();
+    for (alg, algorithm) in algorithms() {
+        let credentials = credentials(algorithm);
+        for (name, request) in requests() {
+            let req_header = request.make_header_full(&credentials, Timespec::new(TS, 0), NONCE)
+                .unwrap();
+            let response = ResponseBuilder::from_request_header(&req_header,
+                                                                request.method(),
+                                                                request.host(),
+                                                                request.port(),
+                                                                request.path())
+                .hash(HASH)
+                .ext("response-ext")
+                .response();
+            writeln!(out, "{} {}: {}", alg, name, response.make_header(&credentials.key).unwrap())
+                .unwrap();
+        }
+    }
+    out
+}
+
+/// Compare `actual` against the named golden file, or overwrite the golden file if
+/// `HAWK_BLESS_GOLDEN` is set.
+fn check_golden(file: &str, expected: &str, actual: &str) {
+    if env::var_os("HAWK_BLESS_GOLDEN").is_some() {
+        let path = format!("{}/golden/{}", env!("CARGO_MANIFEST_DIR"), file);
+        fs::write(path, actual).unwrap();
+        return;
+    }
+    assert_eq!(actual, expected, "golden/{} has changed", file);
+}
+
+#[test]
+fn normalized_strings() {
+    check_golden("normalized.txt",
+                 include_str!("../golden/normalized.txt"),
+                 &normalized_golden());
+}
+
+#[test]
+fn request_headers() {
+    check_golden("headers.txt", include_str!("../golden/headers.txt"), &headers_golden());
+}
+
+#[test]
+fn bewits() {
+    check_golden("bewits.txt", include_str!("../golden/bewits.txt"), &bewits_golden());
+}
+
+#[test]
+fn response_headers() {
+    check_golden("responses.txt",
+                 include_str!("../golden/responses.txt"),
+                 &responses_golden());
+}
+
+/// The golden files must themselves validate, so that a bad bless is caught.
+#[test]
+fn golden_headers_validate() {
+    use hawk_core::Header;
+    use std::str::FromStr;
+
+    let golden = include_str!("../golden/headers.txt");
+    let normalized = include_str!("../golden/normalized.txt");
+    for (alg, algorithm) in algorithms() {
+        let key = credentials(algorithm).key;
+        for (name, request) in requests() {
+            let prefix = format!("{} {}: ", alg, name);
+            let line = golden.lines().find(|l| l.starts_with(&prefix)).unwrap();
+            let header = Header::from_str(&line[prefix.len()..]).unwrap();
+            assert!(request.validate_header(&header, &key, Duration::weeks(100000)),
+                    "{}",
+                    line);
+
+            // the golden normalized string is the one signed
+            let prefix = format!("{} {}: ", name, MacType::Header);
+            let line = normalized.lines().find(|l| l.starts_with(&prefix)).unwrap();
+            let s = line[prefix.len()..].replace("\\n", "\n").replace("\\\\", "\\");
+            assert_eq!(&key.sign(s.as_bytes())[..], &header.mac().unwrap()[..], "{}", line);
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::borrow::Cow;
+
+/// Whether to replicate the quirks of the Node Hawk implementation.
+///
+/// Hawk is defined by its Node reference implementation, and this crate matches it wherever the
+/// two must agree for a MAC to validate, including the trailing newline after the payload in
+/// payload hashes and the decimal formatting of the port in the normalized string.  Those
+/// behaviors are the same in both modes.  The Node implementation also normalizes some inputs
+/// which this crate, by default, signs exactly as given; `NodeHawk` replicates that
+/// normalization, for interoperating with clients and servers that rely on it.
+///
+/// Both client and server should use the same mode.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Conformance {
+    /// Sign and validate requests exactly as given.  This is the default.
+    #[default]
+    Strict,
+    /// Replicate the Node implementation:
+    ///
+    ///  * the request method is converted to upper case before signing, so `get` signs as `GET`;
+    ///  * an empty `ext` is omitted from headers, rather than sent as `ext=""` (the MAC is the
+    ///    same either way);
+    ///  * payload content types are normalized with `content_type`, so that
+    ///    `Text/Plain; charset=utf-8` hashes as `text/plain`.
+    NodeHawk,
+}
+
+impl Conformance {
+    /// Apply this mode to a request method.
+    pub fn method<'a>(&self, method: Cow<'a, str>) -> Cow<'a, str> {
+        match *self {
+            Conformance::NodeHawk if method.bytes().any(|b| b.is_ascii_lowercase()) => {
+                Cow::Owned(method.to_ascii_uppercase())
+            }
+            _ => method,
+        }
+    }
+
+    /// Apply this mode to an `ext` value to be included in a header.
+    pub fn ext<'a>(&self, ext: Option<&'a str>) -> Option<&'a str> {
+        match *self {
+            Conformance::NodeHawk => ext.filter(|ext| !ext.is_empty()),
+            Conformance::Strict => ext,
+        }
+    }
+
+    /// Apply this mode to the content type of a payload, before passing it to `PayloadHasher`.
+    /// In `NodeHawk` mode, any parameters are removed, and the remainder is trimmed and
+    /// converted to lower case.
+    pub fn content_type<'a>(&self, content_type: &'a str) -> Cow<'a, str> {
+        match *self {
+            Conformance::Strict => Cow::Borrowed(content_type),
+            Conformance::NodeHawk => {
+                let content_type = content_type.split(';').next().unwrap_or("").trim();
+                if content_type.bytes().any(|b| b.is_ascii_uppercase()) {
+                    Cow::Owned(content_type.to_ascii_lowercase())
+                } else {
+                    Cow::Borrowed(content_type)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Conformance;
+    use payload::PayloadHasher;
+    use ring::digest::SHA256;
+    use std::borrow::Cow;
+
+    #[test]
+    fn method() {
+        assert_eq!(Conformance::Strict.method(Cow::Borrowed("get")), "get");
+        assert_eq!(Conformance::NodeHawk.method(Cow::Borrowed("get")), "GET");
+        assert!(matches!(Conformance::NodeHawk.method(Cow::Borrowed("GET")), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn ext() {
+        assert_eq!(Conformance::Strict.ext(Some("")), Some(""));
+        assert_eq!(Conformance::NodeHawk.ext(Some("")), None);
+        assert_eq!(Conformance::NodeHawk.ext(Some("x")), Some("x"));
+    }
+
+    #[test]
+    fn payload_vector() {
+        // the payload hash from the Hawk specification
+        let expected = "Yi9LfIIFRtBEPt74PVmbTF/xVAwPn7ub15ePICfgnuY=";
+        let hash = |conformance: Conformance, content_type| {
+            let content_type = conformance.content_type(content_type);
+            ::base64::encode(&PayloadHasher::hash(&content_type[..],
+                                                  &SHA256,
+                                                  "Thank you for flying Hawk"))
+        };
+        assert_eq!(hash(Conformance::Strict, "text/plain"), expected);
+        assert_eq!(hash(Conformance::NodeHawk, "text/plain"), expected);
+        assert_eq!(hash(Conformance::NodeHawk, " Text/Plain ; charset=utf-8"), expected);
+        assert_ne!(hash(Conformance::Strict, "text/plain; charset=utf-8"), expected);
+    }
+}
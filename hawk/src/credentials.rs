@@ -1,17 +1,26 @@
-use ring::{digest, hmac};
+use ring::{digest, hkdf, hmac};
+use signed_writer::SignedWriter;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::{Arc, RwLock};
+use time::Timespec;
 
 /// Hawk key.
 ///
 /// While any sequence of bytes can be specified as a key, note that each digest algorithm has
 /// a suggested key length, and that passwords should *not* be used as keys.  Keys of incorrect
 /// length are handled according to the digest's implementation.
-pub struct Key(hmac::SigningKey);
+///
+/// Keys are `Send` and `Sync`, so a single key can be shared between threads.  Cloning a key is
+/// cheap, as clones share the same underlying key material.
+#[derive(Clone)]
+pub struct Key(Arc<hmac::SigningKey>);
 
 impl Key {
     pub fn new<B>(key: B, algorithm: &'static digest::Algorithm) -> Key
         where B: Into<Vec<u8>>
     {
-        Key(hmac::SigningKey::new(algorithm, key.into().as_ref()))
+        Key(Arc::new(hmac::SigningKey::new(algorithm, key.into().as_ref())))
     }
 
     pub fn sign(&self, data: &[u8]) -> Vec<u8> {
@@ -20,16 +29,244 @@ impl Key {
         mac.clone_from_slice(digest.as_ref());
         mac
     }
+
+    /// Sign the concatenation of the given parts, without first copying them together.
+    pub(crate) fn sign_parts(&self, parts: &[&[u8]]) -> Vec<u8> {
+        let digest = self.sign_raw(parts);
+        let mut mac = vec![0; self.0.digest_algorithm().output_len];
+        mac.clone_from_slice(digest.as_ref());
+        mac
+    }
+
+    /// Sign the concatenation of the given parts, returning the signature without copying it to
+    /// the heap.
+    pub(crate) fn sign_raw(&self, parts: &[&[u8]]) -> hmac::Signature {
+        let mut writer = SignedWriter::new(self);
+        for part in parts {
+            writer.update(part);
+        }
+        writer.sign()
+    }
+
+    pub(crate) fn signing_key(&self) -> &hmac::SigningKey {
+        &self.0
+    }
+
+    /// Get the digest algorithm used with this key.
+    pub fn algorithm(&self) -> &'static digest::Algorithm {
+        self.0.digest_algorithm()
+    }
+
+    /// Derive a subkey for the given context, such as a date or a service name, from this
+    /// master key.  The subkey uses the same digest algorithm, and a client holding only the
+    /// subkey cannot recover the master key or derive subkeys for other contexts.
+    ///
+    /// The subkey is the HKDF-Expand (RFC 5869) of the master key, used as the pseudorandom
+    /// key, with the context as the `info` and the digest's output length as the length.  Any
+    /// HKDF implementation can therefore derive the same subkey, provided the master key is
+    /// itself uniformly random, as Hawk keys should be.  See `subkey::SubkeyProvider` for
+    /// validating requests signed with subkeys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hawk::{Key, SHA256};
+    ///
+    /// let master = Key::new(vec![99u8; 32], &SHA256);
+    /// let today = master.derive_subkey(b"2026-10-15");
+    /// assert_eq!(today.sign(b"data"), master.derive_subkey(b"2026-10-15").sign(b"data"));
+    /// assert_ne!(today.sign(b"data"), master.derive_subkey(b"2026-10-16").sign(b"data"));
+    /// ```
+    pub fn derive_subkey(&self, context: &[u8]) -> Key {
+        let mut subkey = vec![0; self.algorithm().output_len];
+        hkdf::expand(&self.0, context, &mut subkey);
+        Key::new(subkey, self.algorithm())
+    }
 }
 
 /// Hawk credentials: an ID and a key associated with that ID.  The digest algorithm
 /// must be agreed between the server and the client, and the length of the key is
 /// specific to that algorithm.
+#[derive(Clone)]
 pub struct Credentials {
     pub id: String,
     pub key: Key,
 }
 
+/// Credentials for an application acting on its own behalf, rather than for a user.
+///
+/// Requests signed with app credentials (see `Request::make_app_header`) carry the application
+/// id as both the Hawk `id` and the `app` field, with no `dlg`.  App credentials are also a
+/// `CredentialsProvider` for their own id, reporting it as an application's, so that servers
+/// validating with them can recognize such requests with `Artifacts::principal`.
+#[derive(Clone)]
+pub struct AppCredentials(Credentials);
+
+impl AppCredentials {
+    /// Create credentials for the given application id and key.
+    pub fn new<S>(app: S, key: Key) -> AppCredentials
+        where S: Into<String>
+    {
+        AppCredentials(Credentials {
+            id: app.into(),
+            key,
+        })
+    }
+
+    /// Get the application id.
+    pub fn app(&self) -> &str {
+        &self.0.id
+    }
+
+    /// Get the underlying credentials, whose `id` is the application id.
+    pub fn credentials(&self) -> &Credentials {
+        &self.0
+    }
+}
+
+/// The period during which a key may be used, for time-limited credentials.
+///
+/// The default validity has no limits.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Validity {
+    /// The time from which the key is valid, inclusive.
+    pub not_before: Option<Timespec>,
+
+    /// The time at which the key stops being valid, exclusive.
+    pub expires: Option<Timespec>,
+}
+
+impl Validity {
+    /// Determine whether the key is valid at the given time.
+    pub fn is_valid_at(&self, now: Timespec) -> bool {
+        self.not_before.is_none_or(|nbf| now >= nbf) && self.expires.is_none_or(|exp| now < exp)
+    }
+}
+
+/// A source of keys for validating requests, looked up by the Hawk `id`.
+///
+/// More than one key may be returned for an id, for example while keys are being rotated.  A
+/// request is valid if it validates with any of the keys.
+///
+/// Lookups are synchronous, as is validation, so there is no adapter for closures returning
+/// futures.  A service whose keys live in an asynchronous store can fetch the keys for a
+/// header's `id` before validating, and pass them in a `HashMap` (see below), or keep a
+/// `SharedCredentials` or `keyset::RemoteCredentialsProvider` up to date in the background.
+pub trait CredentialsProvider {
+    /// Get the keys for the given id.  An unknown id has no keys.
+    fn keys(&self, id: &str) -> Vec<Key>;
+
+    /// Get the keys for the given id, each with the period during which it is valid.
+    ///
+    /// Providers of time-limited credentials should implement this to return keys outside their
+    /// validity period as well, so that requests signed with them are rejected as
+    /// `RejectionReason::CredentialsExpired` or `CredentialsNotYetValid` rather than as an
+    /// unknown id.  The default returns the result of `keys`, with no limits.
+    fn keys_with_validity(&self, id: &str) -> Vec<(Key, Validity)> {
+        self.keys(id).into_iter().map(|key| (key, Validity::default())).collect()
+    }
+
+    /// Determine whether the given id belongs to application credentials, rather than a user,
+    /// so that requests validated with its keys are made by `Principal::App`.  The default
+    /// returns false.
+    fn is_app(&self, _id: &str) -> bool {
+        false
+    }
+}
+
+impl CredentialsProvider for AppCredentials {
+    fn keys(&self, id: &str) -> Vec<Key> {
+        if id == self.app() {
+            vec![self.0.key.clone()]
+        } else {
+            vec![]
+        }
+    }
+
+    fn is_app(&self, id: &str) -> bool {
+        id == self.app()
+    }
+}
+
+/// A set of keys, keyed by id, which can be shared between threads and replaced at runtime.
+///
+/// Clones of a SharedCredentials share the same set of keys, so a service can hand clones to
+/// its request handlers and rotate keys (for example, from a configuration watcher) by calling
+/// `replace` on any clone, without restarting.
+#[derive(Clone, Default)]
+pub struct SharedCredentials(Arc<RwLock<HashMap<String, Vec<Key>>>>);
+
+impl SharedCredentials {
+    /// Create a new, empty SharedCredentials.
+    pub fn new() -> Self {
+        SharedCredentials::default()
+    }
+
+    /// Atomically replace the entire set of keys.  Lookups in progress see either the old or
+    /// the new set, never a mixture.
+    pub fn replace(&self, keys: HashMap<String, Vec<Key>>) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = keys;
+    }
+
+    /// Add a key for the given id, in addition to any existing keys for that id.
+    pub fn insert<S>(&self, id: S, key: Key)
+        where S: Into<String>
+    {
+        self.0
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(id.into())
+            .or_default()
+            .push(key);
+    }
+
+    /// Remove all keys for the given id.
+    pub fn remove(&self, id: &str) {
+        self.0.write().unwrap_or_else(|e| e.into_inner()).remove(id);
+    }
+}
+
+impl CredentialsProvider for SharedCredentials {
+    fn keys(&self, id: &str) -> Vec<Key> {
+        match self.0.read().unwrap_or_else(|e| e.into_inner()).get(id) {
+            Some(keys) => keys.clone(),
+            None => vec![],
+        }
+    }
+}
+
+/// A closure looking up credentials by id can be used as a provider.  The id of the returned
+/// credentials is not checked against the id looked up.
+///
+/// ```
+/// use hawk::{Credentials, CredentialsProvider, Key, SHA256};
+///
+/// let provider = |id: &str| if id == "me" {
+///     Some(Credentials { id: id.to_string(), key: Key::new(vec![99u8; 32], &SHA256) })
+/// } else {
+///     None
+/// };
+/// assert_eq!(provider.keys("me").len(), 1);
+/// assert_eq!(provider.keys("you").len(), 0);
+/// ```
+impl<F> CredentialsProvider for F
+    where F: Fn(&str) -> Option<Credentials>
+{
+    fn keys(&self, id: &str) -> Vec<Key> {
+        self(id).into_iter().map(|credentials| credentials.key).collect()
+    }
+}
+
+/// A map from ids to keys can be used as a provider.  Since `HashMap` has its own `keys` method,
+/// call this one as `CredentialsProvider::keys(&map, id)`.
+impl<S> CredentialsProvider for HashMap<String, Key, S>
+    where S: BuildHasher
+{
+    fn keys(&self, id: &str) -> Vec<Key> {
+        self.get(id).cloned().into_iter().collect()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -48,4 +285,109 @@ mod test {
         let key = vec![0u8; 99];
         Key::new(key, &digest::SHA256);
     }
+
+    #[test]
+    fn test_derive_subkey() {
+        // RFC 5869, test case 1, truncated to the SHA-256 output length
+        let prk = vec![0x07, 0x77, 0x09, 0x36, 0x2c, 0x2e, 0x32, 0xdf, 0x0d, 0xdc, 0x3f, 0x0d,
+                       0xc4, 0x7b, 0xba, 0x63, 0x90, 0xb6, 0xc7, 0x3b, 0xb5, 0x0f, 0x9c, 0x31,
+                       0x22, 0xec, 0x84, 0x4a, 0xd7, 0xc2, 0xb3, 0xe5];
+        let info = [0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9];
+        let okm = vec![0x3c, 0xb2, 0x5f, 0x25, 0xfa, 0xac, 0xd5, 0x7a, 0x90, 0x43, 0x4f, 0x64,
+                       0xd0, 0x36, 0x2f, 0x2a, 0x2d, 0x2d, 0x0a, 0x90, 0xcf, 0x1a, 0x5a, 0x4c,
+                       0x5d, 0xb0, 0x2d, 0x56, 0xec, 0xc4, 0xc5, 0xbf];
+        let subkey = Key::new(prk, &digest::SHA256).derive_subkey(&info);
+        assert!(::std::ptr::eq(subkey.algorithm(), &digest::SHA256));
+        assert_eq!(subkey.sign(b"data"), Key::new(okm, &digest::SHA256).sign(b"data"));
+
+        let master = Key::new(vec![77u8; 64], &digest::SHA512);
+        let subkey = master.derive_subkey(b"a");
+        assert!(::std::ptr::eq(subkey.algorithm(), &digest::SHA512));
+        assert_ne!(subkey.sign(b"data"), master.derive_subkey(b"b").sign(b"data"));
+        assert_ne!(subkey.sign(b"data"), master.sign(b"data"));
+    }
+
+    #[test]
+    fn test_clone() {
+        let key = Key::new(vec![77u8; 32], &digest::SHA256);
+        let key2 = key.clone();
+        assert_eq!(key.sign(b"data"), key2.sign(b"data"));
+    }
+
+    #[test]
+    fn test_closure_and_map_providers() {
+        use request::RequestBuilder;
+        use validation::ValidationOptions;
+
+        let key = Key::new(vec![77u8; 32], &digest::SHA256);
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: key.clone(),
+        };
+        let request = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let header = request.make_header(&credentials).unwrap();
+        let options = ValidationOptions::default();
+
+        let lookup = |id: &str| if id == "me" { Some(credentials.clone()) } else { None };
+        assert!(request.authenticate_with_provider(&header, &lookup, &options).is_some());
+        assert!(lookup.keys("you").is_empty());
+
+        let mut map = HashMap::new();
+        map.insert("me".to_string(), key);
+        assert!(request.authenticate_with_provider(&header, &map, &options).is_some());
+        assert!(CredentialsProvider::keys(&map, "you").is_empty());
+    }
+
+    #[test]
+    fn test_shared_credentials() {
+        let creds = SharedCredentials::new();
+        assert_eq!(creds.keys("me").len(), 0);
+
+        creds.insert("me", Key::new(vec![1u8; 32], &digest::SHA256));
+        creds.insert("me", Key::new(vec![2u8; 32], &digest::SHA256));
+        assert_eq!(creds.keys("me").len(), 2);
+        assert_eq!(creds.keys("you").len(), 0);
+
+        // clones share the same keys
+        let clone = creds.clone();
+        clone.remove("me");
+        assert_eq!(creds.keys("me").len(), 0);
+    }
+
+    #[test]
+    fn test_shared_credentials_replace() {
+        let creds = SharedCredentials::new();
+        creds.insert("me", Key::new(vec![1u8; 32], &digest::SHA256));
+
+        let mut keys = HashMap::new();
+        keys.insert("you".to_string(), vec![Key::new(vec![2u8; 32], &digest::SHA256)]);
+        creds.replace(keys);
+
+        assert_eq!(creds.keys("me").len(), 0);
+        let keys = creds.keys("you");
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].sign(b"data"),
+                   Key::new(vec![2u8; 32], &digest::SHA256).sign(b"data"));
+    }
+
+    #[test]
+    fn test_validity() {
+        let validity = Validity {
+            not_before: Some(Timespec::new(100, 0)),
+            expires: Some(Timespec::new(200, 0)),
+        };
+        assert!(!validity.is_valid_at(Timespec::new(99, 0)));
+        assert!(validity.is_valid_at(Timespec::new(100, 0)));
+        assert!(validity.is_valid_at(Timespec::new(199, 999999999)));
+        assert!(!validity.is_valid_at(Timespec::new(200, 0)));
+        assert!(Validity::default().is_valid_at(Timespec::new(0, 0)));
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Key>();
+        assert_send_sync::<Credentials>();
+        assert_send_sync::<SharedCredentials>();
+    }
 }
@@ -0,0 +1,82 @@
+//! Cryptographic utilities for use alongside Hawk.
+
+use credentials::Key;
+use mac::Mac;
+use ring::{constant_time, digest};
+use std::fmt::Write;
+use time::Timespec;
+
+/// Compare two byte slices for equality in constant time.
+///
+/// Use this instead of `==` when comparing secret-derived values such as MACs, hashes, or
+/// tokens, to avoid leaking information about their contents through timing side-channels.
+/// Note that the comparison returns early if the lengths differ, so the lengths of the values
+/// are not protected.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    constant_time::verify_slices_are_equal(a, b).is_ok()
+}
+
+/// Calculate the timestamp MAC (`tsm`) for the given timestamp: the MAC of
+/// `hawk.1.ts\n<ts>\n`, where `<ts>` is in whole seconds.
+///
+/// A server rejecting a request for a stale timestamp sends its current time, with this MAC, in
+/// the `WWW-Authenticate` challenge, so that the client can verify the time came from the server
+/// and correct its clock.
+pub fn calculate_ts_mac(ts: Timespec, key: &Key) -> Mac {
+    let ts = ts.sec.to_string();
+    Mac::from(key.sign_parts(&[b"hawk.1.ts\n", ts.as_bytes(), b"\n"]))
+}
+
+/// Verify a timestamp MAC (`tsm`), as calculated by `calculate_ts_mac`, in constant time.
+pub fn verify_ts_mac(ts: Timespec, tsm: &[u8], key: &Key) -> bool {
+    ct_eq(&calculate_ts_mac(ts, key), tsm)
+}
+
+/// Calculate the lower-case hex SHA-256 hash of the given bytes, as reported for normalized
+/// strings.
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    let digest = digest::digest(&digest::SHA256, bytes);
+    let mut hex = String::with_capacity(64);
+    for b in digest.as_ref() {
+        let _ = write!(hex, "{:02x}", b);
+    }
+    hex
+}
+
+#[cfg(test)]
+mod test {
+    use super::{calculate_ts_mac, ct_eq, verify_ts_mac};
+    use base64;
+    use credentials::Key;
+    use ring::digest;
+    use time::Timespec;
+
+    #[test]
+    fn equal() {
+        assert!(ct_eq(b"", b""));
+        assert!(ct_eq(&[1, 2, 3], &[1, 2, 3]));
+    }
+
+    #[test]
+    fn not_equal() {
+        assert!(!ct_eq(&[1, 2, 3], &[1, 2, 4]));
+        assert!(!ct_eq(&[1, 2, 3], &[1, 2]));
+        assert!(!ct_eq(&[], &[1]));
+    }
+
+    #[test]
+    fn ts_mac() {
+        // as calculated by the Node Hawk implementation's `calculateTsMac`
+        let key = Key::new(&b"werxhqb98rpaxn39848xrunpaw3489ruxnpa98w4rxn"[..], &digest::SHA256);
+        let ts = Timespec::new(1353832234, 0);
+        let tsm = calculate_ts_mac(ts, &key);
+        assert_eq!(base64::encode(&tsm), "2mw1eh/qXzl0wJZ/E6XvBhRMEJN7L3j8AyMA8eItEb0=");
+
+        // sub-second precision is ignored
+        assert_eq!(calculate_ts_mac(Timespec::new(1353832234, 500), &key), tsm);
+
+        assert!(verify_ts_mac(ts, &tsm, &key));
+        assert!(!verify_ts_mac(Timespec::new(1353832235, 0), &tsm, &key));
+        assert!(!verify_ts_mac(ts, &tsm[1..], &key));
+    }
+}
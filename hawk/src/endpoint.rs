@@ -0,0 +1,130 @@
+use error::*;
+use request::{default_port, RequestBuilder};
+use std::fmt;
+use std::str::FromStr;
+use url::Url;
+
+/// The scheme, host, and port at which clients reach a server, as configured for a deployment.
+///
+/// Clients sign the host and port they connect to, which is not always the address the server
+/// is bound to or sees in its socket: when TLS terminates in front of the server or in a
+/// sidecar, clients sign port 443 while the server listens on, say, 8443.  Server integrations
+/// given a `PublicEndpoint` reconstruct the request to validate from it, rather than from
+/// connection information or the `Host` header.
+///
+/// # Examples
+///
+/// ```
+/// use hawk::PublicEndpoint;
+///
+/// let endpoint: PublicEndpoint = "https://api.example.com".parse().unwrap();
+/// assert_eq!(endpoint.port(), 443);
+/// let request = endpoint.request_builder("GET", "/v1/status").request();
+/// assert_eq!(request.host(), "api.example.com");
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PublicEndpoint {
+    scheme: String,
+    host: String,
+    port: u16,
+}
+
+impl PublicEndpoint {
+    /// Create an endpoint with the given scheme, host, and port.
+    pub fn new<S, H>(scheme: S, host: H, port: u16) -> PublicEndpoint
+        where S: Into<String>,
+              H: Into<String>
+    {
+        PublicEndpoint {
+            scheme: scheme.into(),
+            host: host.into(),
+            port,
+        }
+    }
+
+    /// Parse an endpoint from a URL such as `https://api.example.com` or
+    /// `http://localhost:8080`.  The port defaults to that of the scheme.  The URL must not have
+    /// a path other than `/`, a query, or credentials, since they would be ignored.
+    pub fn from_url(url: &str) -> Result<PublicEndpoint> {
+        let parsed = Url::parse(url).chain_err(|| format!("could not parse url {}", url))?;
+        if parsed.path() != "/" || parsed.query().is_some() || parsed.fragment().is_some() ||
+           !parsed.username().is_empty() || parsed.password().is_some() {
+            bail!("public endpoint {} must be only a scheme, host, and port", url);
+        }
+        let host = parsed.host_str().ok_or_else(|| format!("url {} has no host", url))?;
+        let port = parsed.port()
+            .or_else(|| default_port(parsed.scheme()))
+            .ok_or_else(|| format!("url {} has no port", url))?;
+        Ok(PublicEndpoint::new(parsed.scheme(), host, port))
+    }
+
+    /// Get the endpoint's scheme.
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    /// Get the endpoint's host.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Get the endpoint's port.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Create a request to this endpoint with the given method and path (including any query).
+    pub fn request_builder<'a>(&'a self, method: &'a str, path: &'a str) -> RequestBuilder<'a> {
+        RequestBuilder::new(method, &self.host, self.port, path)
+    }
+}
+
+impl FromStr for PublicEndpoint {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<PublicEndpoint> {
+        PublicEndpoint::from_url(s)
+    }
+}
+
+impl fmt::Display for PublicEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}://{}", self.scheme, self.host)?;
+        if default_port(&self.scheme) != Some(self.port) {
+            write!(f, ":{}", self.port)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PublicEndpoint;
+
+    #[test]
+    fn from_url() {
+        let endpoint = PublicEndpoint::from_url("https://api.example.com").unwrap();
+        assert_eq!(endpoint, PublicEndpoint::new("https", "api.example.com", 443));
+        assert_eq!(endpoint.to_string(), "https://api.example.com");
+
+        let endpoint: PublicEndpoint = "http://[::1]:8080/".parse().unwrap();
+        assert_eq!(endpoint.host(), "[::1]");
+        assert_eq!(endpoint.port(), 8080);
+        assert_eq!(endpoint.to_string(), "http://[::1]:8080");
+
+        assert!(PublicEndpoint::from_url("https://api.example.com/v1").is_err());
+        assert!(PublicEndpoint::from_url("https://api.example.com/?a=b").is_err());
+        assert!(PublicEndpoint::from_url("https://me@api.example.com").is_err());
+        assert!(PublicEndpoint::from_url("gopher://api.example.com").is_err());
+        assert!(PublicEndpoint::from_url("api.example.com").is_err());
+    }
+
+    #[test]
+    fn request_builder() {
+        let endpoint = PublicEndpoint::new("https", "api.example.com", 443);
+        let request = endpoint.request_builder("POST", "/a?b=c").request();
+        assert_eq!(request.method(), "POST");
+        assert_eq!(request.host(), "api.example.com");
+        assert_eq!(request.port(), 443);
+        assert_eq!(request.path(), "/a?b=c");
+    }
+}
@@ -0,0 +1,382 @@
+//! Signed envelopes for message-queue payloads.
+//!
+//! Services which already share Hawk credentials can use them to authenticate messages sent
+//! through a queue such as AMQP, Kafka, or SQS.  The sender seals each payload into an
+//! `Envelope` carrying the credentials id, a timestamp, a nonce, the payload hash, and a MAC,
+//! and sends it alongside the payload, for example as a message attribute or header.  The
+//! receiver looks up the key for the envelope's id and validates the envelope against the
+//! payload, optionally rejecting replays with a `NonceStore`.
+//!
+//! Envelopes serialize with serde as
+//! `{"id": .., "ts": .., "nonce": .., "hash": .., "mac": ..}`, with the hash and MAC in base64.
+//! The MAC uses the normalized string prefix `hawk.1.message`, so envelopes are not
+//! interoperable with other Hawk implementations.  This module requires the `serde` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate hawk;
+//! extern crate serde_json;
+//!
+//! use hawk::{Credentials, Key, ValidationOptions, SHA256};
+//! use hawk::envelope::Envelope;
+//!
+//! fn main() {
+//!     let credentials = Credentials {
+//!         id: "producer".to_string(),
+//!         key: Key::new(vec![99u8; 32], &SHA256),
+//!     };
+//!     let payload = b"{\"order\": 1234}";
+//!
+//!     let envelope = Envelope::seal(&credentials, "application/json", payload).unwrap();
+//!     let attribute = serde_json::to_string(&envelope).unwrap();
+//!
+//!     // ..on the consumer
+//!     let envelope: Envelope = serde_json::from_str(&attribute).unwrap();
+//!     assert_eq!(envelope.id(), "producer");
+//!     assert!(envelope.validate(&credentials.key, "application/json", payload,
+//!                               &ValidationOptions::default(), None).is_ok());
+//! }
+//! ```
+
+use base64;
+use clock;
+use credentials::{Credentials, Key};
+use crypto;
+use error::*;
+use mac::{Mac, MacType};
+use nonce::{self, NonceStore};
+use payload::PayloadHasher;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, Visitor};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::error;
+use std::fmt;
+use time::Timespec;
+use validation::ValidationOptions;
+
+/// The MAC type used for envelopes.
+pub const ENVELOPE_MAC_TYPE: MacType = MacType::Custom("hawk.1.message");
+
+/// A signed envelope for a message payload.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Envelope {
+    id: String,
+    ts: Timespec,
+    nonce: String,
+    hash: Vec<u8>,
+    mac: Mac,
+}
+
+/// The ways in which envelope validation can fail.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EnvelopeError {
+    /// The envelope's hash does not match the payload.
+    HashMismatch,
+    /// The envelope's MAC is not valid for the given key.
+    MacMismatch,
+    /// The timestamp is outside the allowed skew.
+    Stale,
+    /// The nonce has been seen before for this id.
+    Replayed,
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EnvelopeError::HashMismatch => f.write_str("Envelope hash does not match payload"),
+            EnvelopeError::MacMismatch => f.write_str("Envelope MAC is not valid"),
+            EnvelopeError::Stale => f.write_str("Envelope timestamp is outside the allowed skew"),
+            EnvelopeError::Replayed => f.write_str("Envelope nonce has already been used"),
+        }
+    }
+}
+
+impl error::Error for EnvelopeError {}
+
+impl Envelope {
+    /// Seal a payload, inventing a new nonce and using the current time.
+    pub fn seal(credentials: &Credentials, content_type: &str, payload: &[u8]) -> Result<Self> {
+        Envelope::seal_full(credentials,
+                            content_type,
+                            payload,
+                            clock::now(),
+                            nonce::generate())
+    }
+
+    /// Similar to `seal`, but allowing specification of the timestamp and nonce.
+    pub fn seal_full<S>(credentials: &Credentials,
+                        content_type: &str,
+                        payload: &[u8],
+                        ts: Timespec,
+                        nonce: S)
+                        -> Result<Self>
+        where S: Into<String>
+    {
+        // only whole seconds are serialized, so discard any fractional part up front
+        let ts = Timespec::new(ts.sec, 0);
+        let nonce = nonce.into();
+        let hash = PayloadHasher::hash(content_type, credentials.key.algorithm(), payload);
+        let mac = envelope_mac(&credentials.key, &credentials.id, ts, &nonce, &hash)?;
+        Ok(Envelope {
+            id: credentials.id.clone(),
+            ts,
+            nonce,
+            hash,
+            mac,
+        })
+    }
+
+    /// Get the id of the credentials which sealed the envelope.  Use this to look up the key
+    /// with which to validate it.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Get the envelope's timestamp.
+    pub fn ts(&self) -> Timespec {
+        self.ts
+    }
+
+    /// Get the envelope's nonce.
+    pub fn nonce(&self) -> &str {
+        &self.nonce
+    }
+
+    /// Get the hash of the sealed payload.
+    pub fn hash(&self) -> &[u8] {
+        &self.hash
+    }
+
+    /// Get the envelope's MAC.
+    pub fn mac(&self) -> &Mac {
+        &self.mac
+    }
+
+    /// Validate the envelope against the given payload.
+    ///
+    /// The timestamp is checked against `options.ts_skew` using `options.clock`.  If a
+    /// NonceStore is given, the envelope's id and nonce are recorded in it once the envelope is
+    /// otherwise valid, and an envelope whose nonce has already been recorded is rejected.
+    pub fn validate(&self,
+                    key: &Key,
+                    content_type: &str,
+                    payload: &[u8],
+                    options: &ValidationOptions,
+                    nonces: Option<&dyn NonceStore>)
+                    -> ::std::result::Result<(), EnvelopeError> {
+        let calculated = envelope_mac(key, &self.id, self.ts, &self.nonce, &self.hash)
+            .map_err(|_| EnvelopeError::MacMismatch)?;
+        if calculated != self.mac {
+            return Err(EnvelopeError::MacMismatch);
+        }
+
+        let hash = PayloadHasher::hash(content_type, key.algorithm(), payload);
+        if !crypto::ct_eq(&hash, &self.hash) {
+            return Err(EnvelopeError::HashMismatch);
+        }
+
+        let now = options.clock.now();
+        if now - self.ts > options.ts_skew || self.ts - now > options.ts_skew {
+            return Err(EnvelopeError::Stale);
+        }
+
+        if let Some(nonces) = nonces {
+            if !nonces.check_and_record(&self.id, &self.nonce, self.ts) {
+                return Err(EnvelopeError::Replayed);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Calculate an envelope MAC.  The id is included in the MAC, in the position of the host, so
+/// that an envelope cannot be re-attributed to other credentials sharing the same key.
+fn envelope_mac(key: &Key, id: &str, ts: Timespec, nonce: &str, hash: &[u8]) -> Result<Mac> {
+    Mac::new(ENVELOPE_MAC_TYPE, key, ts, nonce, "", id, 0, "", Some(hash), None)
+}
+
+const FIELDS: &[&str] = &["id", "ts", "nonce", "hash", "mac"];
+
+impl Serialize for Envelope {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut state = serializer.serialize_struct("Envelope", FIELDS.len())?;
+        state.serialize_field("id", &self.id)?;
+        state.serialize_field("ts", &self.ts.sec)?;
+        state.serialize_field("nonce", &self.nonce)?;
+        state.serialize_field("hash", &base64::encode(&self.hash))?;
+        state.serialize_field("mac", &self.mac)?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Envelope {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Envelope, D::Error>
+        where D: Deserializer<'de>
+    {
+        deserializer.deserialize_struct("Envelope", FIELDS, EnvelopeVisitor)
+    }
+}
+
+struct EnvelopeVisitor;
+
+impl<'de> Visitor<'de> for EnvelopeVisitor {
+    type Value = Envelope;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a Hawk message envelope")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> ::std::result::Result<Envelope, A::Error>
+        where A: MapAccess<'de>
+    {
+        let mut id: Option<String> = None;
+        let mut ts: Option<i64> = None;
+        let mut nonce: Option<String> = None;
+        let mut hash: Option<Vec<u8>> = None;
+        let mut mac: Option<Mac> = None;
+        while let Some(field) = map.next_key::<String>()? {
+            match &field[..] {
+                "id" if id.is_none() => id = Some(map.next_value()?),
+                "ts" if ts.is_none() => ts = Some(map.next_value()?),
+                "nonce" if nonce.is_none() => nonce = Some(map.next_value()?),
+                "hash" if hash.is_none() => {
+                    let encoded: String = map.next_value()?;
+                    hash = Some(base64::decode(&encoded).map_err(de::Error::custom)?);
+                }
+                "mac" if mac.is_none() => mac = Some(map.next_value()?),
+                "id" | "ts" | "nonce" | "hash" | "mac" => {
+                    return Err(de::Error::custom(format!("duplicate field `{}`", field)));
+                }
+                _ => return Err(de::Error::unknown_field(&field, FIELDS)),
+            }
+        }
+        Ok(Envelope {
+            id: id.ok_or_else(|| de::Error::missing_field("id"))?,
+            ts: Timespec::new(ts.ok_or_else(|| de::Error::missing_field("ts"))?, 0),
+            nonce: nonce.ok_or_else(|| de::Error::missing_field("nonce"))?,
+            hash: hash.ok_or_else(|| de::Error::missing_field("hash"))?,
+            mac: mac.ok_or_else(|| de::Error::missing_field("mac"))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clock::FixedClock;
+    use nonce::MemoryNonceStore;
+    use ring::digest;
+    use serde_json;
+    use std::sync::Arc;
+    use testing::test_credentials;
+    use time::Duration;
+
+    #[test]
+    fn round_trip_json() {
+        let credentials = test_credentials();
+        let envelope = Envelope::seal(&credentials, "text/plain", b"hello").unwrap();
+        let json = serde_json::to_string(&envelope).unwrap();
+        let parsed: Envelope = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, envelope);
+        assert_eq!(parsed.validate(&credentials.key,
+                                   "text/plain",
+                                   b"hello",
+                                   &ValidationOptions::default(),
+                                   None),
+                   Ok(()));
+    }
+
+    #[test]
+    fn json_format() {
+        let envelope = Envelope::seal_full(&test_credentials(),
+                                           "text/plain",
+                                           b"hello",
+                                           Timespec::new(1000, 0),
+                                           "abc")
+            .unwrap();
+        let json: serde_json::Value = serde_json::to_value(&envelope).unwrap();
+        assert_eq!(json["id"], "me");
+        assert_eq!(json["ts"], 1000);
+        assert_eq!(json["nonce"], "abc");
+        assert_eq!(json["hash"], base64::encode(envelope.hash()));
+        assert_eq!(json["mac"], base64::encode(envelope.mac()));
+    }
+
+    #[test]
+    fn json_invalid() {
+        assert!(serde_json::from_str::<Envelope>(r#"{"id": "a"}"#).is_err());
+        assert!(serde_json::from_str::<Envelope>(r#"{"id": "a", "ts": 1, "nonce": "n",
+            "hash": "!!", "mac": "AAAA"}"#)
+            .is_err());
+        assert!(serde_json::from_str::<Envelope>(r#"{"id": "a", "ts": 1, "nonce": "n",
+            "hash": "AAAA", "mac": "AAAA", "extra": 1}"#)
+            .is_err());
+    }
+
+    #[test]
+    fn wrong_payload() {
+        let credentials = test_credentials();
+        let envelope = Envelope::seal(&credentials, "text/plain", b"hello").unwrap();
+        assert_eq!(envelope.validate(&credentials.key,
+                                     "text/plain",
+                                     b"goodbye",
+                                     &ValidationOptions::default(),
+                                     None),
+                   Err(EnvelopeError::HashMismatch));
+    }
+
+    #[test]
+    fn wrong_key_or_id() {
+        let credentials = test_credentials();
+        let envelope = Envelope::seal(&credentials, "text/plain", b"hello").unwrap();
+        let other = Key::new(vec![1u8; 32], &digest::SHA256);
+        assert_eq!(envelope.validate(&other, "text/plain", b"hello",
+                                     &ValidationOptions::default(), None),
+                   Err(EnvelopeError::MacMismatch));
+
+        let mut json: serde_json::Value = serde_json::to_value(&envelope).unwrap();
+        json["id"] = "someone-else".into();
+        let forged: Envelope = serde_json::from_value(json).unwrap();
+        assert_eq!(forged.validate(&credentials.key, "text/plain", b"hello",
+                                   &ValidationOptions::default(), None),
+                   Err(EnvelopeError::MacMismatch));
+    }
+
+    #[test]
+    fn stale() {
+        let credentials = test_credentials();
+        let envelope = Envelope::seal_full(&credentials,
+                                           "text/plain",
+                                           b"hello",
+                                           Timespec::new(1000, 0),
+                                           "abc")
+            .unwrap();
+        let at = |secs| {
+            ValidationOptions {
+                clock: Arc::new(FixedClock(Timespec::new(secs, 0))),
+                ..ValidationOptions::default()
+            }
+        };
+        assert_eq!(envelope.validate(&credentials.key, "text/plain", b"hello", &at(1030), None),
+                   Ok(()));
+        assert_eq!(envelope.validate(&credentials.key, "text/plain", b"hello", &at(1100), None),
+                   Err(EnvelopeError::Stale));
+    }
+
+    #[test]
+    fn replayed() {
+        let credentials = test_credentials();
+        let nonces = MemoryNonceStore::new(Duration::minutes(1));
+        let options = ValidationOptions::default();
+        let envelope = Envelope::seal(&credentials, "text/plain", b"hello").unwrap();
+        assert_eq!(envelope.validate(&credentials.key, "text/plain", b"hello", &options,
+                                     Some(&nonces)),
+                   Ok(()));
+        assert_eq!(envelope.validate(&credentials.key, "text/plain", b"hello", &options,
+                                     Some(&nonces)),
+                   Err(EnvelopeError::Replayed));
+    }
+}
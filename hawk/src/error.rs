@@ -3,10 +3,42 @@ error_chain! {
         HeaderParseError {
             description("Unparseable Hawk header")
         }
+
+        MultipleAuthentications {
+            description("Request has both a Hawk Authorization header and a bewit")
+        }
     }
 
     foreign_links {
         Io(::std::io::Error);
         Decode(::base64::DecodeError);
+        Bewit(::hawk_core::BewitError);
+    }
+}
+
+pub use hawk_core::Stage;
+
+/// Header syntax errors become an `ErrorKind::HeaderParseError`, caused by the
+/// `HeaderSyntaxError`; other header errors keep their description.
+impl From<::hawk_core::HeaderError> for Error {
+    fn from(e: ::hawk_core::HeaderError) -> Error {
+        match e {
+            ::hawk_core::HeaderError::Syntax(e) => {
+                Error::with_chain(e, ErrorKind::HeaderParseError)
+            }
+            e => e.to_string().into(),
+        }
+    }
+}
+
+impl Error {
+    /// Get the stage of authentication at which this error occurred.
+    pub fn stage(&self) -> Stage {
+        match *self.kind() {
+            ErrorKind::HeaderParseError | ErrorKind::Decode(_) => Stage::Parse,
+            ErrorKind::MultipleAuthentications => Stage::Policy,
+            ErrorKind::Bewit(ref e) => e.stage(),
+            _ => Stage::Other,
+        }
     }
 }
@@ -0,0 +1,375 @@
+use error::*;
+use hawk_core::Header;
+use std::fmt;
+use std::str::{self, FromStr};
+
+/// A value for a header's `ext` field, checked to be valid in a header: without `"` or control
+/// characters.
+///
+/// # Examples
+///
+/// ```
+/// use hawk::Ext;
+///
+/// let ext = Ext::new("caf\u{e9} au lait").unwrap().truncate_to(4);
+/// assert_eq!(ext.as_str(), "caf");
+/// assert!(Ext::new("a\r\nX-Injected: yes").is_err());
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Ext(String);
+
+impl Ext {
+    /// Create an `ext` value, failing if it cannot appear in a header.
+    pub fn new<S: Into<String>>(value: S) -> Result<Ext> {
+        let value = value.into();
+        Header::check_str(&value)?;
+        Ok(Ext(value))
+    }
+
+    /// Truncate the value to at most `max_bytes` bytes, without splitting a UTF-8 character.
+    /// The result is still a valid `ext` value.
+    pub fn truncate_to(mut self, max_bytes: usize) -> Ext {
+        if self.0.len() > max_bytes {
+            let mut len = max_bytes;
+            while !self.0.is_char_boundary(len) {
+                len -= 1;
+            }
+            self.0.truncate(len);
+        }
+        self
+    }
+
+    /// Get the value.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Get the value, as a `String`.
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl AsRef<str> for Ext {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Ext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Structured application data for a request's `ext` field.
+///
+/// Since the `ext` field is covered by the MAC, it can bind application-level metadata into a
+/// request's signature: a GraphQL gateway, for example, can require the client to sign the
+/// name of the operation it is performing, and check that the operation in the body is the one
+/// that was signed, even when the payload hash is not validated.
+///
+/// `ExtFields` encodes named fields as `name=value` pairs separated by `;`, percent-encoding
+/// any characters outside a conservative set of printable ASCII, so that the result is always a
+/// valid `ext` value.  Parsing rejects duplicate names, so that client and server cannot
+/// disagree about which of two values was signed.
+///
+/// # Examples
+///
+/// On the client, encode the fields and set them as the request's `ext`:
+///
+/// ```
+/// use hawk::{Credentials, ExtFields, Key, RequestBuilder, SHA256};
+///
+/// let credentials = Credentials {
+///     id: "me".to_string(),
+///     key: Key::new(vec![99u8; 32], &SHA256),
+/// };
+/// let ext = ExtFields::new()
+///     .with_field("op", "CreateUser")
+///     .with_field("tenant", "acme corp")
+///     .to_string();
+/// assert_eq!(ext, "op=CreateUser;tenant=acme%20corp");
+/// let header = RequestBuilder::new("POST", "api.example.com", 443, "/graphql")
+///     .ext(&ext[..])
+///     .request()
+///     .make_header(&credentials)
+///     .unwrap();
+/// ```
+///
+/// On the server, once the header is validated, compare the signed fields with the request:
+///
+/// ```
+/// # use hawk::{Credentials, ExtFields, Key, RequestBuilder, ValidationOptions, SHA256};
+/// # let credentials = Credentials {
+/// #     id: "me".to_string(),
+/// #     key: Key::new(vec![99u8; 32], &SHA256),
+/// # };
+/// # let ext = ExtFields::new().with_field("op", "CreateUser").to_string();
+/// # let header = RequestBuilder::new("POST", "api.example.com", 443, "/graphql")
+/// #     .ext(&ext[..]).request().make_header(&credentials).unwrap();
+/// let request = RequestBuilder::new("POST", "api.example.com", 443, "/graphql").request();
+/// let options = ValidationOptions::default();
+/// let artifacts = request.authenticate_header(&header, &credentials.key, &options).unwrap();
+/// let operation_name = "CreateUser"; // from the request body
+/// let fields = artifacts.ext_fields().unwrap();
+/// assert!(fields.require("op", operation_name).is_ok());
+/// assert!(fields.require("op", "DeleteUser").is_err());
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ExtFields {
+    fields: Vec<(String, String)>,
+}
+
+impl ExtFields {
+    /// Create an empty set of fields.
+    pub fn new() -> ExtFields {
+        ExtFields::default()
+    }
+
+    /// Set the named field, replacing any existing value.  Fields are encoded in the order they
+    /// were first set.
+    pub fn with_field<N, V>(mut self, name: N, value: V) -> Self
+        where N: Into<String>,
+              V: Into<String>
+    {
+        let (name, value) = (name.into(), value.into());
+        match self.fields.iter_mut().find(|(n, _)| *n == name) {
+            Some(field) => field.1 = value,
+            None => self.fields.push((name, value)),
+        }
+        self
+    }
+
+    /// Parse fields from an `ext` value, as encoded by `to_string`.  An empty value has no
+    /// fields.
+    pub fn parse(ext: &str) -> Result<ExtFields> {
+        let mut fields = ExtFields::new();
+        if ext.is_empty() {
+            return Ok(fields);
+        }
+        for pair in ext.split(';') {
+            let (name, value) = pair.split_once('=')
+                .ok_or_else(|| format!("ext field {:?} has no value", pair))?;
+            let (name, value) = (decode(name)?, decode(value)?);
+            if fields.get(&name).is_some() {
+                bail!("ext field {:?} appears more than once", name);
+            }
+            fields.fields.push((name, value));
+        }
+        Ok(fields)
+    }
+
+    /// Get the value of the named field, if it is present.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields.iter().find(|(n, _)| n == name).map(|(_, v)| &v[..])
+    }
+
+    /// Check that the named field is present, with the expected value.
+    pub fn require(&self, name: &str, expected: &str) -> Result<()> {
+        match self.get(name) {
+            Some(value) if value == expected => Ok(()),
+            Some(value) => bail!("ext field {:?} is {:?}, not {:?}", name, value, expected),
+            None => bail!("ext field {:?} is missing", name),
+        }
+    }
+
+    /// Iterate over the fields, as `(name, value)` pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(n, v)| (&n[..], &v[..]))
+    }
+
+    /// Encode the fields as an `ext` value; this is the same as `to_string`.
+    pub fn to_ext(&self) -> Ext {
+        Ext(self.to_string())
+    }
+
+    /// Encode the fields as an `ext` value of at most `max_bytes` bytes, which still parses.
+    ///
+    /// Fields are encoded in order until the budget is reached.  A value which does not fit
+    /// is truncated between encoded characters, and later fields are dropped.  A truncated
+    /// value is only a prefix of the original, so a server binding a field to the request
+    /// should compare it in full, which fails for a truncated value; truncation suits fields
+    /// such as descriptions or user-supplied labels.
+    pub fn to_ext_truncated(&self, max_bytes: usize) -> Ext {
+        let mut ext = String::new();
+        let mut encoded = String::new();
+        for (name, value) in &self.fields {
+            encoded.clear();
+            if !ext.is_empty() {
+                encoded.push(';');
+            }
+            let _ = encode(&mut encoded, name);
+            encoded.push('=');
+            if ext.len() + encoded.len() > max_bytes {
+                break;
+            }
+            ext.push_str(&encoded);
+            let mut buf = [0u8; 4];
+            for c in value.chars() {
+                encoded.clear();
+                let _ = encode(&mut encoded, c.encode_utf8(&mut buf));
+                if ext.len() + encoded.len() > max_bytes {
+                    return Ext(ext);
+                }
+                ext.push_str(&encoded);
+            }
+        }
+        Ext(ext)
+    }
+
+    /// Get the number of fields.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Determine whether there are no fields.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+impl FromStr for ExtFields {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<ExtFields> {
+        ExtFields::parse(s)
+    }
+}
+
+impl fmt::Display for ExtFields {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (name, value)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                f.write_str(";")?;
+            }
+            encode(f, name)?;
+            f.write_str("=")?;
+            encode(f, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Determine whether a byte is written as-is in an encoded field.
+fn is_plain(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"-._~!$'()*+,/:@".contains(&b)
+}
+
+fn encode<W: fmt::Write>(f: &mut W, s: &str) -> fmt::Result {
+    for b in s.bytes() {
+        if is_plain(b) {
+            f.write_char(b as char)?;
+        } else {
+            write!(f, "%{:02X}", b)?;
+        }
+    }
+    Ok(())
+}
+
+fn decode(s: &str) -> Result<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = tail.get(..2)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|hex| str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| format!("invalid percent-encoding in ext field {:?}", s))?;
+            bytes.push(hex);
+            rest = &tail[2..];
+        } else if is_plain(b) {
+            bytes.push(b);
+            rest = tail;
+        } else {
+            bail!("ext field {:?} contains an unencoded {:?}", s, b as char);
+        }
+    }
+    String::from_utf8(bytes).chain_err(|| format!("ext field {:?} is not UTF-8", s))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Ext, ExtFields};
+
+    #[test]
+    fn round_trip() {
+        let fields = ExtFields::new()
+            .with_field("op", "CreateUser")
+            .with_field("q", "a=b;c \"d\" \\ caf\u{e9} 100%")
+            .with_field("empty", "");
+        let encoded = fields.to_string();
+        assert_eq!(encoded,
+                   "op=CreateUser;q=a%3Db%3Bc%20%22d%22%20%5C%20caf%C3%A9%20100%25;empty=");
+        assert_eq!(ExtFields::parse(&encoded).unwrap(), fields);
+        assert_eq!(fields.get("q"), Some("a=b;c \"d\" \\ caf\u{e9} 100%"));
+        assert_eq!(fields.iter().map(|(n, _)| n).collect::<Vec<_>>(), vec!["op", "q", "empty"]);
+    }
+
+    #[test]
+    fn with_field_replaces() {
+        let fields = ExtFields::new()
+            .with_field("a", "1")
+            .with_field("b", "2")
+            .with_field("a", "3");
+        assert_eq!(fields.to_string(), "a=3;b=2");
+        assert_eq!(fields.len(), 2);
+    }
+
+    #[test]
+    fn parse() {
+        assert!(ExtFields::parse("").unwrap().is_empty());
+        assert!(ExtFields::parse("a=1;a=2").is_err());
+        assert!(ExtFields::parse("a").is_err());
+        assert!(ExtFields::parse("a=%2").is_err());
+        assert!(ExtFields::parse("a=%zz").is_err());
+        assert!(ExtFields::parse("a=%+1").is_err());
+        assert!(ExtFields::parse("a=%FF").is_err());
+        assert!(ExtFields::parse("a=b c").is_err());
+        assert!(ExtFields::parse("a=1;;b=2").is_err());
+        assert_eq!("a=x%2Fy".parse::<ExtFields>().unwrap().get("a"), Some("x/y"));
+    }
+
+    #[test]
+    fn require() {
+        let fields = ExtFields::new().with_field("op", "CreateUser");
+        assert!(fields.require("op", "CreateUser").is_ok());
+        assert_eq!(fields.require("op", "DeleteUser").unwrap_err().to_string(),
+                   "ext field \"op\" is \"CreateUser\", not \"DeleteUser\"");
+        assert!(fields.require("tenant", "acme").is_err());
+    }
+
+    #[test]
+    fn ext_truncate_to() {
+        let ext = Ext::new("caf\u{e9}").unwrap();
+        assert_eq!(ext.clone().truncate_to(10).as_str(), "caf\u{e9}");
+        assert_eq!(ext.clone().truncate_to(5).as_str(), "caf\u{e9}");
+        assert_eq!(ext.clone().truncate_to(4).as_str(), "caf");
+        assert_eq!(ext.truncate_to(0).as_str(), "");
+        assert!(Ext::new("a\"b").is_err());
+        assert!(Ext::new("a\nb").is_err());
+    }
+
+    #[test]
+    fn to_ext_truncated() {
+        let fields = ExtFields::new()
+            .with_field("op", "CreateUser")
+            .with_field("label", "caf\u{e9} 1");
+        assert_eq!(fields.to_ext(), Ext::new("op=CreateUser;label=caf%C3%A9%201").unwrap());
+        assert_eq!(fields.to_ext_truncated(100), fields.to_ext());
+
+        // every budget gives a value which parses, with a prefix of each field
+        for max_bytes in 0..fields.to_string().len() {
+            let ext = fields.to_ext_truncated(max_bytes);
+            assert!(ext.as_str().len() <= max_bytes);
+            let parsed = ExtFields::parse(ext.as_str()).unwrap();
+            for (name, value) in parsed.iter() {
+                assert!(fields.get(name).unwrap().starts_with(value));
+            }
+        }
+        assert_eq!(fields.to_ext_truncated(28).as_str(), "op=CreateUser;label=caf");
+        assert_eq!(fields.to_ext_truncated(29).as_str(), "op=CreateUser;label=caf%C3%A9");
+        assert_eq!(fields.to_ext_truncated(18).as_str(), "op=CreateUser");
+        assert_eq!(fields.to_ext_truncated(2).as_str(), "");
+    }
+}
@@ -0,0 +1,320 @@
+//! Extracting Hawk headers from an `http::HeaderMap`.
+//!
+//! Every integration with an HTTP library must find the `Authorization` (or, on the client,
+//! `Server-Authorization`) header, check that it uses the Hawk scheme, and parse the remainder.
+//! The functions in this module do so, distinguishing the ways this can fail so that a server
+//! can, for example, fall through to another authentication scheme for `NotHawk` while
+//! rejecting `Multiple` outright.  This module requires the `http` feature.
+//!
+//! A request may carry several credentials, either as separate header lines or folded by a
+//! proxy into one comma-separated value, such as `Basic dXNlcjpwYXNz, Hawk id="me", ...`.  By
+//! default these are rejected as `Multiple`; a `MultipleHeaderPolicy` can instead select the
+//! one Hawk credential among them.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate http;
+//! extern crate hawk;
+//!
+//! use hawk::extract::{self, ExtractError};
+//! use http::HeaderMap;
+//! use http::header::AUTHORIZATION;
+//!
+//! fn main() {
+//!     let mut headers = HeaderMap::new();
+//!     assert_eq!(extract::authorization(&headers).unwrap_err(), ExtractError::Missing);
+//!
+//!     headers.insert(AUTHORIZATION,
+//!                    "Hawk id=\"dh37fgj492je\", ts=\"1353832234\", nonce=\"j4h3g2\", \
+//!                     mac=\"6R4rV5iE+NPoym+WwjeHzjAGXUtLNIxmo1vpMofpLAE=\""
+//!                        .parse()
+//!                        .unwrap());
+//!     let header = extract::authorization(&headers).unwrap();
+//!     assert_eq!(header.id(), Some("dh37fgj492je"));
+//! }
+//! ```
+
+use auth_source;
+use error;
+use hawk_core::Header;
+use http::header::{HeaderMap, AUTHORIZATION};
+use std::error::Error as StdError;
+use std::fmt;
+use std::str::FromStr;
+
+/// The name of the header carrying a server's response authentication.
+pub const SERVER_AUTHORIZATION: &str = "server-authorization";
+
+/// The ways extracting a Hawk header from a `HeaderMap` can fail.
+#[derive(Debug)]
+pub enum ExtractError {
+    /// The header is not present.
+    Missing,
+    /// The header is present more than once, or one header contains several credentials.
+    /// Which value a server acts on must not depend on how a proxy folded or reordered them, so
+    /// such requests should be rejected.
+    Multiple,
+    /// The header's value is not visible ASCII, so cannot be a Hawk header.
+    NotAscii,
+    /// The header uses a scheme other than Hawk.
+    NotHawk,
+    /// The header uses the Hawk scheme, but could not be parsed.
+    Invalid(error::Error),
+}
+
+impl PartialEq for ExtractError {
+    /// Errors are equal if they are the same variant; the causes of `Invalid` errors are not
+    /// compared.
+    fn eq(&self, other: &ExtractError) -> bool {
+        matches!((self, other),
+                 (&ExtractError::Missing, &ExtractError::Missing) |
+                 (&ExtractError::Multiple, &ExtractError::Multiple) |
+                 (&ExtractError::NotAscii, &ExtractError::NotAscii) |
+                 (&ExtractError::NotHawk, &ExtractError::NotHawk) |
+                 (&ExtractError::Invalid(_), &ExtractError::Invalid(_)))
+    }
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExtractError::Missing => f.write_str("Header is missing"),
+            ExtractError::Multiple => f.write_str("Header is present more than once"),
+            ExtractError::NotAscii => f.write_str("Header is not visible ASCII"),
+            ExtractError::NotHawk => f.write_str("Header does not use the Hawk scheme"),
+            ExtractError::Invalid(ref e) => write!(f, "Invalid Hawk header: {}", e),
+        }
+    }
+}
+
+impl StdError for ExtractError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            ExtractError::Invalid(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// How to treat a header present more than once, or containing several credentials.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MultipleHeaderPolicy {
+    /// Reject the request with `ExtractError::Multiple`.  This is the default.
+    #[default]
+    Reject,
+    /// Use the only credentials with the Hawk scheme, ignoring those with other schemes, for a
+    /// service which also accepts, say, Basic authentication behind a proxy which folds
+    /// headers.  Requests with more than one set of Hawk credentials are still rejected with
+    /// `ExtractError::Multiple`, and those with none with `ExtractError::NotHawk`.
+    SelectHawk,
+}
+
+/// Extract and parse the Hawk `Authorization` header of a request.
+pub fn authorization(headers: &HeaderMap) -> ::std::result::Result<Header, ExtractError> {
+    hawk_header(headers, AUTHORIZATION.as_str())
+}
+
+/// Extract and parse the Hawk `Authorization` header of a request, treating multiple
+/// credentials according to the given policy.
+pub fn authorization_with_policy(headers: &HeaderMap,
+                                 policy: MultipleHeaderPolicy)
+                                 -> ::std::result::Result<Header, ExtractError> {
+    hawk_header_with_policy(headers, AUTHORIZATION.as_str(), policy)
+}
+
+/// Extract and parse the Hawk `Server-Authorization` header of a response.
+pub fn server_authorization(headers: &HeaderMap) -> ::std::result::Result<Header, ExtractError> {
+    hawk_header(headers, SERVER_AUTHORIZATION)
+}
+
+/// Extract and parse the Hawk header with the given name.
+pub fn hawk_header(headers: &HeaderMap,
+                   name: &str)
+                   -> ::std::result::Result<Header, ExtractError> {
+    hawk_header_with_policy(headers, name, MultipleHeaderPolicy::Reject)
+}
+
+/// Extract and parse the Hawk header with the given name, treating multiple credentials
+/// according to the given policy.
+pub fn hawk_header_with_policy(headers: &HeaderMap,
+                               name: &str,
+                               policy: MultipleHeaderPolicy)
+                               -> ::std::result::Result<Header, ExtractError> {
+    let mut credentials = vec![];
+    for value in headers.get_all(name) {
+        let value = value.to_str().map_err(|_| ExtractError::NotAscii)?;
+        credentials.extend(split_credentials(value));
+    }
+    let value = match policy {
+        MultipleHeaderPolicy::Reject => {
+            match credentials[..] {
+                [] => return Err(ExtractError::Missing),
+                [value] => auth_source::hawk_header_value(value).ok_or(ExtractError::NotHawk)?,
+                _ => return Err(ExtractError::Multiple),
+            }
+        }
+        MultipleHeaderPolicy::SelectHawk => {
+            if credentials.is_empty() {
+                return Err(ExtractError::Missing);
+            }
+            let mut hawk = credentials.into_iter().filter_map(auth_source::hawk_header_value);
+            match (hawk.next(), hawk.next()) {
+                (None, _) => return Err(ExtractError::NotHawk),
+                (Some(value), None) => value,
+                (Some(_), Some(_)) => return Err(ExtractError::Multiple),
+            }
+        }
+    };
+    Header::from_str(value).map_err(|e| ExtractError::Invalid(e.into()))
+}
+
+/// Split an Authorization header value into the credentials it contains.  Proxies may fold
+/// several header lines into one, as in `Basic dXNlcjpwYXNz, Hawk id="me", ...`; a new
+/// credential begins after a comma (outside a quoted string) which is followed by a scheme,
+/// that is, a token which is not the name of an `=` parameter.
+fn split_credentials(value: &str) -> Vec<&str> {
+    let mut credentials = vec![];
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        if in_quotes {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_quotes = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_quotes = true;
+        } else if c == ',' && starts_with_scheme(&value[i + 1..]) {
+            credentials.push(value[start..i].trim());
+            start = i + 1;
+        }
+    }
+    credentials.push(value[start..].trim());
+    credentials
+}
+
+/// Determine whether the given text, following a comma, begins with an authentication scheme
+/// rather than a parameter.
+fn starts_with_scheme(rest: &str) -> bool {
+    let rest = rest.trim_start();
+    let is_tchar = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    let token_len = rest.find(|c: char| !is_tchar(c)).unwrap_or(rest.len());
+    if token_len == 0 {
+        return false;
+    }
+    let after = rest[token_len..].trim_start();
+    match after.chars().next() {
+        // a scheme alone, as in `Negotiate`
+        None | Some(',') => true,
+        Some('=') => false,
+        // a scheme must be separated from its parameters by whitespace
+        Some(_) => after.len() < rest.len() - token_len,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http::header::{HeaderValue, WWW_AUTHENTICATE};
+
+    const VALUE: &str = "Hawk id=\"me\", ts=\"1353832234\", nonce=\"abc\", mac=\"AAAA\"";
+
+    fn headers(name: &'static str, values: &[&[u8]]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(name, HeaderValue::from_bytes(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn present() {
+        let header = authorization(&headers("authorization", &[VALUE.as_bytes()])).unwrap();
+        assert_eq!(header.id(), Some("me"));
+        assert_eq!(header.nonce(), Some("abc"));
+
+        let headers = headers("Server-Authorization", &[b"hawk mac=\"AAAA\""]);
+        assert!(server_authorization(&headers).unwrap().mac().is_some());
+        assert_eq!(authorization(&headers).unwrap_err(), ExtractError::Missing);
+    }
+
+    #[test]
+    fn failures() {
+        assert_eq!(authorization(&HeaderMap::new()).unwrap_err(), ExtractError::Missing);
+        assert_eq!(authorization(&headers("authorization", &[VALUE.as_bytes(),
+                                                            VALUE.as_bytes()]))
+                       .unwrap_err(),
+                   ExtractError::Multiple);
+        assert_eq!(authorization(&headers("authorization", &[b"Hawk id=\"caf\xe9\""]))
+                       .unwrap_err(),
+                   ExtractError::NotAscii);
+        assert_eq!(authorization(&headers("authorization", &[b"Bearer abc"])).unwrap_err(),
+                   ExtractError::NotHawk);
+        let err = authorization(&headers("authorization", &[b"Hawk id=\"me"])).unwrap_err();
+        assert_eq!(err, ExtractError::Invalid(error::ErrorKind::HeaderParseError.into()));
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn split() {
+        let hawk = "Hawk id=\"me\", ts=\"1\", nonce=\"a,b\", mac=\"AAAA\"";
+        assert_eq!(split_credentials(hawk), vec![hawk]);
+        assert_eq!(split_credentials(&format!("{}, {}", hawk, hawk)), vec![hawk, hawk]);
+        assert_eq!(split_credentials(&format!("Basic dXNlcjpwYXNz==, {}", hawk)),
+                   vec!["Basic dXNlcjpwYXNz==", hawk]);
+        assert_eq!(split_credentials(&format!("{},Negotiate", hawk)), vec![hawk, "Negotiate"]);
+        // commas and schemes within quoted strings do not split
+        assert_eq!(split_credentials("Hawk ext=\"a, Hawk b\\\", Hawk c\", id=\"me\""),
+                   vec!["Hawk ext=\"a, Hawk b\\\", Hawk c\", id=\"me\""]);
+        // nor do parameters, with or without whitespace around `=`
+        assert_eq!(split_credentials("Hawk id=\"me\", ts = \"1\""),
+                   vec!["Hawk id=\"me\", ts = \"1\""]);
+        assert_eq!(split_credentials(""), vec![""]);
+    }
+
+    #[test]
+    fn folded() {
+        let folded = format!("{}, {}", VALUE, VALUE);
+        assert_eq!(authorization(&headers("authorization", &[folded.as_bytes()])).unwrap_err(),
+                   ExtractError::Multiple);
+        let basic = format!("Basic dXNlcjpwYXNz, {}", VALUE);
+        assert_eq!(authorization(&headers("authorization", &[basic.as_bytes()])).unwrap_err(),
+                   ExtractError::Multiple);
+        assert_eq!(authorization(&headers("authorization", &[b"Basic dXNlcjpwYXNz",
+                                                            VALUE.as_bytes()]))
+                       .unwrap_err(),
+                   ExtractError::Multiple);
+    }
+
+    #[test]
+    fn select_hawk() {
+        let select = |values: &[&[u8]]| {
+            authorization_with_policy(&headers("authorization", values),
+                                      MultipleHeaderPolicy::SelectHawk)
+        };
+        let basic = format!("Basic dXNlcjpwYXNz, {}", VALUE);
+        assert_eq!(select(&[basic.as_bytes()]).unwrap().id(), Some("me"));
+        assert_eq!(select(&[b"Bearer abc", VALUE.as_bytes()]).unwrap().id(), Some("me"));
+        assert_eq!(select(&[VALUE.as_bytes()]).unwrap().id(), Some("me"));
+
+        let folded = format!("{}, {}", VALUE, VALUE);
+        assert_eq!(select(&[folded.as_bytes()]).unwrap_err(), ExtractError::Multiple);
+        assert_eq!(select(&[VALUE.as_bytes(), VALUE.as_bytes()]).unwrap_err(),
+                   ExtractError::Multiple);
+        assert_eq!(select(&[b"Bearer abc", b"Basic dXNlcjpwYXNz"]).unwrap_err(),
+                   ExtractError::NotHawk);
+        assert_eq!(select(&[]).unwrap_err(), ExtractError::Missing);
+    }
+
+    #[test]
+    fn other_header() {
+        let headers = headers("x-hawk", &[VALUE.as_bytes()]);
+        assert!(hawk_header(&headers, "x-hawk").is_ok());
+        assert_eq!(hawk_header(&headers, WWW_AUTHENTICATE.as_str()).unwrap_err(),
+                   ExtractError::Missing);
+    }
+}
@@ -0,0 +1,124 @@
+use credentials::Credentials;
+use error::*;
+use hawk_core::Header;
+use mac::{Mac, MacType};
+use nonce;
+use clock;
+use time;
+
+/// A RequestFingerprint holds the parts of a request's normalized string which do not change
+/// from one header to the next: the method, host, port, path, hash, and ext.
+///
+/// Clients which sign the same request repeatedly can create a fingerprint once, using
+/// `Request::fingerprint`, and then make headers from it; each header then only requires
+/// formatting the timestamp and nonce.  Headers made this way are identical to those made by
+/// the corresponding `Request` methods.
+///
+/// # Examples
+///
+/// ```
+/// use hawk::{RequestBuilder, Credentials, Key, SHA256};
+///
+/// let credentials = Credentials {
+///     id: "me".to_string(),
+///     key: Key::new(vec![99u8; 32], &SHA256),
+/// };
+/// let fingerprint = RequestBuilder::new("GET", "example.com", 443, "/v1/status")
+///     .request()
+///     .fingerprint()
+///     .unwrap();
+///
+/// for _ in 0..3 {
+///     let header = fingerprint.make_header(&credentials).unwrap();
+///     // ..send a request with this header
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RequestFingerprint {
+    suffix: Vec<u8>,
+    ext: Option<String>,
+    hash: Option<Vec<u8>>,
+    app: Option<String>,
+    dlg: Option<String>,
+}
+
+impl RequestFingerprint {
+    pub(crate) fn new(suffix: Vec<u8>,
+                      ext: Option<String>,
+                      hash: Option<Vec<u8>>,
+                      app: Option<String>,
+                      dlg: Option<String>)
+                      -> Self {
+        RequestFingerprint {
+            suffix,
+            ext,
+            hash,
+            app,
+            dlg,
+        }
+    }
+
+    /// Create a new Header for the fingerprinted request, inventing a new nonce and setting the
+    /// timestamp to the current time.
+    pub fn make_header(&self, credentials: &Credentials) -> Result<Header> {
+        let nonce = nonce::generate();
+        self.make_header_full(credentials, clock::now(), nonce)
+    }
+
+    /// Similar to `make_header`, but allowing specification of the timestamp and nonce.
+    pub fn make_header_full<S>(&self,
+                               credentials: &Credentials,
+                               ts: time::Timespec,
+                               nonce: S)
+                               -> Result<Header>
+        where S: Into<String>
+    {
+        let nonce = nonce.into();
+        let mac = Mac::with_suffix(MacType::Header, &credentials.key, ts, &nonce, &self.suffix)?;
+        let header = Header::new(Some(credentials.id.clone()),
+                                 Some(ts),
+                                 Some(nonce),
+                                 Some(mac.into()),
+                                 self.ext.clone(),
+                                 self.hash.clone(),
+                                 self.app.clone(),
+                                 self.dlg.clone())?;
+        debug_assert!(header.canonicalize().is_ok(), "header does not round-trip: {}", header);
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use request::RequestBuilder;
+    use testing::test_credentials;
+    use time::Timespec;
+
+    #[test]
+    fn same_as_request() {
+        let credentials = test_credentials();
+        let hash = [1u8, 2, 3, 4];
+        let req = RequestBuilder::new("POST", "example.com", 443, "/v1/api")
+            .hash(&hash[..])
+            .ext("ext-data")
+            .app("my-app")
+            .dlg("my-dlg")
+            .request();
+        let fingerprint = req.fingerprint().unwrap();
+
+        let ts = Timespec::new(1353832234, 0);
+        assert_eq!(fingerprint.make_header_full(&credentials, ts, "nonny").unwrap(),
+                   req.make_header_full(&credentials, ts, "nonny").unwrap());
+    }
+
+    #[test]
+    fn validates() {
+        let credentials = test_credentials();
+        let req = RequestBuilder::new("GET", "example.com", 443, "/v1/status").request();
+        let fingerprint = req.fingerprint().unwrap();
+        for _ in 0..3 {
+            let header = fingerprint.make_header(&credentials).unwrap();
+            assert!(req.validate_header(&header, &credentials.key, ::time::Duration::minutes(1)));
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Randomized tests that the parsers never panic on attacker-controlled input.
+//!
+//! Each parser is fed a deterministic stream of random strings, built from the characters that
+//! matter to Hawk's syntax, and of mutations of valid inputs.  A panic fails the test; the
+//! parsers' results are otherwise ignored.  Set `HAWK_FUZZ_ITERATIONS` to run more cases.
+
+use auth_source::AuthSource;
+use base64;
+use hawk_core::{Bewit, Header, HeaderRef, ParseMode};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use request::RequestBuilder;
+use std::env;
+use std::str::FromStr;
+use testing::test_credentials;
+use time::{Duration, Timespec};
+use validation::ValidationOptions;
+
+/// Fragments from which random inputs are assembled.
+const FRAGMENTS: &[&str] = &["id", "ts", "nonce", "mac", "ext", "hash", "app", "dlg", "=", "\"",
+                             ",", " ", "\t", "\\", "Hawk", "bewit", "?", "&", "#", "/", "0",
+                             "-1", "99999999999999999999", "9223372036854775807",
+                             "-9223372036854775808", "abc", "+/=", "\u{e9}", "\u{20ac}",
+                             "\u{1f600}", "\n", "\0"];
+
+fn iterations() -> usize {
+    env::var("HAWK_FUZZ_ITERATIONS").ok().and_then(|n| n.parse().ok()).unwrap_or(2000)
+}
+
+fn rng() -> XorShiftRng {
+    XorShiftRng::from_seed([0x6861_776b, 0x6675_7a7a, 1, 2])
+}
+
+/// Assemble a random string from `FRAGMENTS`.
+fn random_input(rng: &mut XorShiftRng) -> String {
+    let len = rng.gen_range(0, 24);
+    (0..len).map(|_| *rng.choose(FRAGMENTS).unwrap()).collect()
+}
+
+/// Mutate a valid input by truncating it, or by inserting or deleting a character, at random
+/// character boundaries.
+fn mutate(rng: &mut XorShiftRng, valid: &str) -> String {
+    let boundaries: Vec<usize> =
+        valid.char_indices().map(|(i, _)| i).chain(Some(valid.len())).collect();
+    let at = *rng.choose(&boundaries).unwrap();
+    let mut mutated = valid.to_string();
+    match rng.gen_range(0, 3) {
+        0 => mutated.truncate(at),
+        1 => mutated.insert_str(at, rng.choose(FRAGMENTS).unwrap()),
+        _ => {
+            if at < valid.len() {
+                mutated.remove(at);
+            }
+        }
+    }
+    mutated
+}
+
+#[test]
+fn header_parsing_never_panics() {
+    let request = RequestBuilder::new("GET", "example.com", 443, "/").request();
+    let valid = request.make_header_full(&test_credentials(), Timespec::new(1353832234, 0), "nonny")
+        .unwrap()
+        .with_ext(Some("a=b"))
+        .unwrap()
+        .to_string();
+    let options = ValidationOptions::default();
+    let mut rng = rng();
+    for i in 0..iterations() {
+        let input = if i % 2 == 0 {
+            random_input(&mut rng)
+        } else {
+            mutate(&mut rng, &valid)
+        };
+        let _ = HeaderRef::parse_with_mode(&input, ParseMode::Strict);
+        let _ = HeaderRef::parse_with_mode(&input, ParseMode::Tolerant);
+        if let Ok(header) = Header::from_str(&input) {
+            let _ = header.canonicalize();
+            let _ = header.size();
+            let _ = request.check_header(&header, &test_credentials().key, &options);
+        }
+    }
+}
+
+#[test]
+fn bewit_parsing_never_panics() {
+    let request = RequestBuilder::new("GET", "example.com", 443, "/").request();
+    let credentials = test_credentials();
+    let valid = request.make_bewit(&credentials, Duration::minutes(1)).unwrap();
+    let decoded = String::from_utf8(base64::decode_config(&valid.to_str(), base64::URL_SAFE_NO_PAD)
+            .unwrap())
+        .unwrap();
+    let options = ValidationOptions { allow_scoped_bewits: true, ..ValidationOptions::default() };
+    let mut rng = rng();
+    for i in 0..iterations() {
+        // mostly exercise the parser after base64 decoding, where the interesting structure is
+        let input = match i % 3 {
+            0 => random_input(&mut rng),
+            1 => base64::encode_config(&random_input(&mut rng), base64::URL_SAFE_NO_PAD),
+            _ => base64::encode_config(&mutate(&mut rng, &decoded), base64::URL_SAFE_NO_PAD),
+        };
+        if let Ok(bewit) = Bewit::from_str(&input) {
+            let _ = bewit.to_str();
+            let _ = request.verify_bewit(&bewit, &credentials.key, &options);
+        }
+    }
+}
+
+#[test]
+fn auth_source_never_panics() {
+    let mut rng = rng();
+    for _ in 0..iterations() {
+        let authorization = random_input(&mut rng);
+        let path = random_input(&mut rng);
+        let _ = AuthSource::from_request(Some(&authorization), &path);
+    }
+}
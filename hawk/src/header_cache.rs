@@ -0,0 +1,295 @@
+use artifacts::Artifacts;
+use clock;
+use credentials::Validity;
+use error::*;
+use hawk_core::Header;
+use rejected::RejectionReason;
+use request::Request;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use time::{Duration, Timespec};
+
+/// A small cache of parsed headers and their validation verdicts, keyed by the raw header
+/// string, for use with `Authenticator::check_cached`.
+///
+/// Clients retrying an idempotent request often resend the identical `Authorization` header
+/// within seconds.  With this cache, the retries are parsed once, and the verdict of validating
+/// the header's MAC and payload hash for the request is reused rather than recalculated.
+///
+/// # Safety of cached verdicts
+///
+/// A verdict is reused only for a request with the same method, host, port, path, and payload
+/// hash as the one it was reached for.  Checks which depend on the time or on shared state are
+/// not cached, and are repeated for every request:
+///
+/// * the timestamp is checked against the allowed skew, so a cached header still becomes stale;
+/// * the matching key's validity period is checked, so cached credentials still expire; and
+/// * the nonce is checked against the `Authenticator`'s nonce store, so a retry which reuses
+///   the header is rejected as a replay, exactly as it would be without the cache.
+///
+/// Verdicts for unknown ids and not-yet-valid or expired credentials are never cached.
+/// However, the credentials provider is not consulted for a header with a cached verdict, so
+/// a key which is revoked, or added, is not noticed for that header until its entry expires.
+/// The time-to-live should therefore be short, no longer than the allowed timestamp skew, after
+/// which the header would be stale anyway.
+///
+/// A cache must only be used with one `Authenticator`, since verdicts also depend on the
+/// authenticator's credentials and validation options.
+pub struct HeaderCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Arc<CachedHeader>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl HeaderCache {
+    /// Create a cache holding at most `capacity` headers, each for at most `ttl`.
+    pub fn new(capacity: usize, ttl: Duration) -> HeaderCache {
+        HeaderCache {
+            capacity,
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the parsed header for the given raw header value (without the `Hawk` scheme),
+    /// parsing and caching it if it is not already cached.  Headers which cannot be parsed are
+    /// not cached.
+    pub fn get(&self, raw: &str) -> Result<Arc<CachedHeader>> {
+        let now = clock::now();
+        {
+            let entries = self.entries.lock().unwrap();
+            if let Some(cached) = entries.get(raw) {
+                if now < cached.inserted + self.ttl {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(cached.clone());
+                }
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let cached = Arc::new(CachedHeader {
+            header: Header::from_str(raw)?,
+            inserted: now,
+            verdict: Mutex::new(None),
+        });
+        if self.capacity > 0 {
+            let mut entries = self.entries.lock().unwrap();
+            if entries.len() >= self.capacity && !entries.contains_key(raw) {
+                let ttl = self.ttl;
+                entries.retain(|_, cached| now < cached.inserted + ttl);
+                if entries.len() >= self.capacity {
+                    let oldest = entries.iter()
+                        .min_by_key(|(_, cached)| cached.inserted)
+                        .map(|(raw, _)| raw.clone());
+                    if let Some(oldest) = oldest {
+                        entries.remove(&oldest);
+                    }
+                }
+            }
+            entries.insert(raw.to_string(), cached.clone());
+        }
+        Ok(cached)
+    }
+
+    /// Get the number of cached headers, including any which have expired but not yet been
+    /// removed.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Determine whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove all cached headers, for example after revoking a key.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Get the number of calls to `get` which found the header in the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of calls to `get` which parsed the header.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+/// A header from a `HeaderCache`, with the verdict of its last validation.
+#[derive(Debug)]
+pub struct CachedHeader {
+    header: Header,
+    inserted: Timespec,
+    verdict: Mutex<Option<Verdict>>,
+}
+
+impl CachedHeader {
+    /// Get the parsed header.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Get the cached verdict for the given request, if any.
+    pub(crate) fn verdict(&self, request: &Request) -> Option<CachedVerdict> {
+        let verdict = self.verdict.lock().unwrap();
+        verdict.as_ref().filter(|v| v.matches(request)).map(|v| v.verdict.clone())
+    }
+
+    /// Record the verdict of validating this header for the given request, if it can be reused.
+    pub(crate) fn record(&self,
+                         request: &Request,
+                         verdict: ::std::result::Result<(&Artifacts, Validity), RejectionReason>) {
+        let verdict = match verdict {
+            Ok((artifacts, validity)) => {
+                CachedVerdict::Valid {
+                    without_query: artifacts.is_signed_without_query(),
+                    app_principal: artifacts.is_app_principal(),
+                    validity,
+                    normalized_hash: artifacts.normalized_hash().map(|h| h.to_string()),
+                }
+            }
+            Err(reason) if is_cacheable(reason) => CachedVerdict::Rejected(reason),
+            Err(_) => return,
+        };
+        *self.verdict.lock().unwrap() = Some(Verdict {
+            method: request.method().to_string(),
+            host: request.host().to_string(),
+            port: request.port(),
+            path: request.path().to_string(),
+            hash: request.hash().map(|hash| hash.to_vec()),
+            verdict,
+        });
+    }
+}
+
+/// Determine whether a rejection depends only on the header, request, keys, and options, and
+/// not on the time or other state.
+fn is_cacheable(reason: RejectionReason) -> bool {
+    match reason {
+        RejectionReason::MissingField(_) |
+        RejectionReason::WrongLength(_) |
+        RejectionReason::ExtNotPermitted |
+        RejectionReason::NonceNotPermitted |
+        RejectionReason::MacMismatch |
+        RejectionReason::MissingHash |
+        RejectionReason::HashMismatch => true,
+        RejectionReason::Stale |
+        RejectionReason::UnknownId |
+        RejectionReason::CredentialsNotYetValid |
+        RejectionReason::CredentialsExpired |
+        RejectionReason::Replayed |
+        RejectionReason::NonceStoreUnavailable => false,
+    }
+}
+
+/// The outcome of validating a header's MAC and payload hash.
+#[derive(Clone, Debug)]
+pub(crate) enum CachedVerdict {
+    /// The header is valid for the request, with a key having the given validity.
+    Valid {
+        without_query: bool,
+        app_principal: bool,
+        validity: Validity,
+        normalized_hash: Option<String>,
+    },
+    /// The header was rejected, for a reason which does not depend on the time.
+    Rejected(RejectionReason),
+}
+
+/// A cached verdict, with the request it applies to.
+#[derive(Debug)]
+struct Verdict {
+    method: String,
+    host: String,
+    port: u16,
+    path: String,
+    hash: Option<Vec<u8>>,
+    verdict: CachedVerdict,
+}
+
+impl Verdict {
+    fn matches(&self, request: &Request) -> bool {
+        self.method == request.method() && self.host == request.host() &&
+        self.port == request.port() && self.path == request.path() &&
+        self.hash.as_deref() == request.hash()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use credentials::Validity;
+    use request::RequestBuilder;
+    use testing::Deterministic;
+
+    const RAW: &str = "id=\"me\", ts=\"1353832234\", nonce=\"j4h3g2\", mac=\"AAAA\"";
+
+    #[test]
+    fn get() {
+        let _deterministic = Deterministic::at(Timespec::new(1353832234, 0));
+        let cache = HeaderCache::new(2, Duration::seconds(10));
+        let first = cache.get(RAW).unwrap();
+        assert_eq!(first.header().nonce(), Some("j4h3g2"));
+        let second = cache.get(RAW).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        assert!(cache.get("id=\"me").is_err());
+        assert_eq!(cache.len(), 1);
+
+        cache.get("id=\"a\"").unwrap();
+        cache.get("id=\"b\"").unwrap();
+        assert_eq!(cache.len(), 2);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn expiry() {
+        let cache = HeaderCache::new(10, Duration::seconds(10));
+        let first = {
+            let _deterministic = Deterministic::at(Timespec::new(1353832234, 0));
+            cache.get(RAW).unwrap()
+        };
+        let _deterministic = Deterministic::at(Timespec::new(1353832244, 0));
+        assert!(!Arc::ptr_eq(&first, &cache.get(RAW).unwrap()));
+        assert_eq!(cache.hits(), 0);
+    }
+
+    #[test]
+    fn verdicts() {
+        let cache = HeaderCache::new(10, Duration::seconds(10));
+        let cached = cache.get(RAW).unwrap();
+        let req = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let other = RequestBuilder::new("GET", "example.com", 443, "/x").request();
+        assert!(cached.verdict(&req).is_none());
+
+        cached.record(&req, Err(RejectionReason::Stale));
+        assert!(cached.verdict(&req).is_none());
+
+        cached.record(&req, Err(RejectionReason::MacMismatch));
+        assert!(matches!(cached.verdict(&req),
+                         Some(CachedVerdict::Rejected(RejectionReason::MacMismatch))));
+        assert!(cached.verdict(&other).is_none());
+
+        let artifacts = Artifacts::new("GET",
+                                       "example.com",
+                                       443,
+                                       "/x",
+                                       cached.header(),
+                                       Timespec::new(0, 0),
+                                       "n");
+        cached.record(&other, Ok((&artifacts, Validity::default())));
+        assert!(matches!(cached.verdict(&other), Some(CachedVerdict::Valid { .. })));
+        assert!(cached.verdict(&req).is_none());
+    }
+}
@@ -0,0 +1,98 @@
+use clock::{Clock, DefaultClock};
+use nonce::NonceGenerator;
+use ring::digest;
+use std::sync::Arc;
+use time::Duration;
+
+/// Options for making a request header with `Request::make_header_with`.
+///
+/// This parallels `ValidationOptions` on the server.  The default options make the same header
+/// as `Request::make_header`.  As for `ValidationOptions`, further options may be added in minor
+/// releases, so outside this crate the options must be built with the `with_*` setters.
+///
+/// # Examples
+///
+/// ```
+/// use hawk::{Credentials, HeaderOptions, Key, RequestBuilder, SHA256};
+/// use hawk::nonce::SequentialNonceGenerator;
+/// use std::sync::Arc;
+///
+/// let credentials = Credentials {
+///     id: "me".to_string(),
+///     key: Key::new(vec![99u8; 32], &SHA256),
+/// };
+/// let options = HeaderOptions::default()
+///     .with_nonce_generator(Some(Arc::new(SequentialNonceGenerator::new("n"))))
+///     .with_digest(Some(&SHA256));
+/// let request = RequestBuilder::new("GET", "example.com", 443, "/v1/status").request();
+/// let header = request.make_header_with(&credentials, &options).unwrap();
+/// assert_eq!(header.nonce(), Some("n0"));
+/// ```
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct HeaderOptions {
+    /// The source of the header's timestamp.
+    pub clock: Arc<dyn Clock>,
+
+    /// An offset added to the clock's time, such as the skew between this client's clock and
+    /// the server's.
+    pub ts_offset: Duration,
+
+    /// The source of the header's nonce.  If not given, the default nonce generator is used;
+    /// see `nonce::set_default_nonce_generator`.
+    pub nonce_generator: Option<Arc<dyn NonceGenerator>>,
+
+    /// Whether the MAC covers the request path's query, as the Hawk protocol requires.  Some
+    /// legacy servers sign only the path; see `ValidationOptions::accept_mac_without_query`.
+    pub include_query: bool,
+
+    /// The digest algorithm the credentials' key must use.  If given, making a header with a key
+    /// for a different algorithm fails, rather than producing a header the server will reject.
+    pub digest: Option<&'static digest::Algorithm>,
+}
+
+impl Default for HeaderOptions {
+    fn default() -> Self {
+        HeaderOptions {
+            clock: Arc::new(DefaultClock),
+            ts_offset: Duration::zero(),
+            nonce_generator: None,
+            include_query: true,
+            digest: None,
+        }
+    }
+}
+
+impl HeaderOptions {
+    /// Set the source of the header's timestamp; see the `clock` field.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Set the offset added to the clock's time; see the `ts_offset` field.
+    pub fn with_ts_offset(mut self, ts_offset: Duration) -> Self {
+        self.ts_offset = ts_offset;
+        self
+    }
+
+    /// Set the source of the header's nonce; see the `nonce_generator` field.
+    pub fn with_nonce_generator(mut self,
+                                nonce_generator: Option<Arc<dyn NonceGenerator>>)
+                                -> Self {
+        self.nonce_generator = nonce_generator;
+        self
+    }
+
+    /// Set whether the MAC covers the query; see the `include_query` field.
+    pub fn with_include_query(mut self, include_query: bool) -> Self {
+        self.include_query = include_query;
+        self
+    }
+
+    /// Set the digest algorithm the key must use; see the `digest` field.
+    pub fn with_digest(mut self, digest: Option<&'static digest::Algorithm>) -> Self {
+        self.digest = digest;
+        self
+    }
+}
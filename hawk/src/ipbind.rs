@@ -0,0 +1,226 @@
+//! Binding bewits and headers to the client's IP address.
+//!
+//! A bewit in a download link can be used by anyone who obtains the link.  To limit this, the
+//! issuer can embed the intended client's IP address in the `ext` value, where it is covered by
+//! the MAC, and the server can check it against the address the request actually came from.
+//!
+//! The address is embedded either in the clear, as `hawk-ip:<address>`, or hashed, as
+//! `hawk-iphash:<mac>`, where the MAC is calculated over the address with the credentials' key,
+//! so that a bewit in a URL does not reveal the client's address.  This is not part of the Hawk
+//! protocol, and the claim occupies the whole `ext` value.
+//!
+//! Behind proxies, the observed peer is the nearest proxy; `client_ip` finds the client's
+//! address in `X-Forwarded-For` given the number of trusted proxies.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate time;
+//! extern crate hawk;
+//!
+//! use hawk::{Credentials, Key, RequestBuilder, SHA256};
+//! use hawk::ipbind::{self, IpBinding};
+//!
+//! fn main() {
+//!     let credentials = Credentials {
+//!         id: "me".to_string(),
+//!         key: Key::new(vec![99u8; 32], &SHA256),
+//!     };
+//!     let client = "203.0.113.7".parse().unwrap();
+//!
+//!     // issuer
+//!     let ext = ipbind::make_ext(client, IpBinding::Hashed, &credentials.key);
+//!     let request = RequestBuilder::new("GET", "example.com", 443, "/download")
+//!         .ext(&ext[..])
+//!         .request();
+//!     let bewit = request.make_bewit(&credentials, time::Duration::minutes(5)).unwrap();
+//!
+//!     // server, behind one load balancer, after validating the bewit
+//!     let peer = "10.0.0.2".parse().unwrap();
+//!     let observed = ipbind::client_ip(peer, Some("203.0.113.7"), 1).unwrap();
+//!     assert!(ipbind::verify_ext(bewit.ext(), observed, &credentials.key).is_ok());
+//! }
+//! ```
+
+use base64;
+use credentials::Key;
+use crypto;
+use std::error;
+use std::fmt;
+use std::net::IpAddr;
+
+/// The prefix of an `ext` value carrying a plain address.
+const PLAIN_PREFIX: &str = "hawk-ip:";
+
+/// The prefix of an `ext` value carrying a hashed address.
+const HASHED_PREFIX: &str = "hawk-iphash:";
+
+/// The prefix of the data signed to hash an address.
+const HASH_CONTEXT: &str = "hawk.1.ip\n";
+
+/// How to embed an IP address in an `ext` value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IpBinding {
+    /// Embed the address in the clear.
+    Plain,
+    /// Embed a MAC of the address, calculated with the credentials' key.
+    Hashed,
+}
+
+/// The ways an IP binding check can fail.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IpBindingError {
+    /// The `ext` value does not carry an IP binding claim.
+    Missing,
+    /// The `ext` value carries an IP binding claim that cannot be parsed.
+    Malformed,
+    /// The claimed address does not match the client's address.
+    Mismatch,
+}
+
+impl fmt::Display for IpBindingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IpBindingError::Missing => f.write_str("No IP binding in ext"),
+            IpBindingError::Malformed => f.write_str("Malformed IP binding in ext"),
+            IpBindingError::Mismatch => f.write_str("Client IP does not match IP binding"),
+        }
+    }
+}
+
+impl error::Error for IpBindingError {}
+
+/// Make an `ext` value binding a bewit or header to the given client address.
+pub fn make_ext(ip: IpAddr, binding: IpBinding, key: &Key) -> String {
+    match binding {
+        IpBinding::Plain => format!("{}{}", PLAIN_PREFIX, ip),
+        IpBinding::Hashed => {
+            format!("{}{}",
+                    HASHED_PREFIX,
+                    base64::encode_config(&hash_ip(ip, key), base64::URL_SAFE_NO_PAD))
+        }
+    }
+}
+
+/// Verify that the given `ext` value, taken from a validated bewit or header, binds it to the
+/// given client address.  Use `client_ip` to determine the client's address.
+pub fn verify_ext(ext: Option<&str>,
+                  client_ip: IpAddr,
+                  key: &Key)
+                  -> ::std::result::Result<(), IpBindingError> {
+    let ext = ext.ok_or(IpBindingError::Missing)?;
+    let matches = if let Some(ip) = ext.strip_prefix(PLAIN_PREFIX) {
+        let ip: IpAddr = ip.parse().map_err(|_| IpBindingError::Malformed)?;
+        normalize(ip) == normalize(client_ip)
+    } else if let Some(hash) = ext.strip_prefix(HASHED_PREFIX) {
+        let hash = base64::decode_config(hash, base64::URL_SAFE_NO_PAD)
+            .map_err(|_| IpBindingError::Malformed)?;
+        crypto::ct_eq(&hash, &hash_ip(client_ip, key))
+    } else {
+        return Err(IpBindingError::Missing);
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(IpBindingError::Mismatch)
+    }
+}
+
+/// Determine the client's address from the peer address of the connection and the value of the
+/// `X-Forwarded-For` header, if any, given the number of trusted proxies in front of the server.
+///
+/// Each trusted proxy appends the address of its own peer to `X-Forwarded-For`, so with
+/// `trusted_hops` proxies the client's address is the `trusted_hops`th entry from the end;
+/// entries before that were supplied by the client and cannot be trusted.  With no trusted
+/// proxies, the peer address is the client's address.  Returns `None` if the header has too
+/// few entries, or the relevant entry is not an IP address.
+pub fn client_ip(peer: IpAddr, forwarded_for: Option<&str>, trusted_hops: usize) -> Option<IpAddr> {
+    if trusted_hops == 0 {
+        return Some(peer);
+    }
+    forwarded_for?
+        .rsplit(',')
+        .nth(trusted_hops - 1)
+        .and_then(|entry| entry.trim().parse().ok())
+}
+
+/// Map IPv4-mapped IPv6 addresses to IPv4, so that the same client matches however its
+/// address is presented.
+fn normalize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => ip,
+        },
+        ip => ip,
+    }
+}
+
+fn hash_ip(ip: IpAddr, key: &Key) -> Vec<u8> {
+    let ip = normalize(ip).to_string();
+    key.sign_parts(&[HASH_CONTEXT.as_bytes(), ip.as_bytes()])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ring::digest;
+    use testing::test_credentials;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn plain() {
+        let key = test_credentials().key;
+        let ext = make_ext(ip("203.0.113.7"), IpBinding::Plain, &key);
+        assert_eq!(ext, "hawk-ip:203.0.113.7");
+        assert_eq!(verify_ext(Some(&ext), ip("203.0.113.7"), &key), Ok(()));
+        assert_eq!(verify_ext(Some(&ext), ip("::ffff:203.0.113.7"), &key), Ok(()));
+        assert_eq!(verify_ext(Some(&ext), ip("203.0.113.8"), &key),
+                   Err(IpBindingError::Mismatch));
+    }
+
+    #[test]
+    fn hashed() {
+        let key = test_credentials().key;
+        let ext = make_ext(ip("2001:db8::1"), IpBinding::Hashed, &key);
+        assert!(ext.starts_with("hawk-iphash:"));
+        assert!(!ext.contains("2001"));
+        assert_eq!(verify_ext(Some(&ext), ip("2001:db8::1"), &key), Ok(()));
+        assert_eq!(verify_ext(Some(&ext), ip("2001:db8::2"), &key),
+                   Err(IpBindingError::Mismatch));
+
+        // the hash depends on the key
+        let other = Key::new(vec![1u8; 32], &digest::SHA256);
+        assert_eq!(verify_ext(Some(&ext), ip("2001:db8::1"), &other),
+                   Err(IpBindingError::Mismatch));
+    }
+
+    #[test]
+    fn missing_or_malformed() {
+        let key = test_credentials().key;
+        assert_eq!(verify_ext(None, ip("127.0.0.1"), &key),
+                   Err(IpBindingError::Missing));
+        assert_eq!(verify_ext(Some("other"), ip("127.0.0.1"), &key),
+                   Err(IpBindingError::Missing));
+        assert_eq!(verify_ext(Some("hawk-ip:nope"), ip("127.0.0.1"), &key),
+                   Err(IpBindingError::Malformed));
+        assert_eq!(verify_ext(Some("hawk-iphash:!!"), ip("127.0.0.1"), &key),
+                   Err(IpBindingError::Malformed));
+    }
+
+    #[test]
+    fn client_ip_hops() {
+        let peer = ip("10.0.0.2");
+        let xff = Some("198.51.100.1, 203.0.113.7 , 10.0.0.1");
+        assert_eq!(client_ip(peer, xff, 0), Some(peer));
+        assert_eq!(client_ip(peer, xff, 1), Some(ip("10.0.0.1")));
+        assert_eq!(client_ip(peer, xff, 2), Some(ip("203.0.113.7")));
+        assert_eq!(client_ip(peer, xff, 3), Some(ip("198.51.100.1")));
+        assert_eq!(client_ip(peer, xff, 4), None);
+        assert_eq!(client_ip(peer, None, 1), None);
+        assert_eq!(client_ip(peer, Some("unknown"), 1), None);
+    }
+}
@@ -0,0 +1,184 @@
+use hawk_core::Bewit;
+use credentials::Credentials;
+use error::*;
+use path_encoding::may_traverse;
+use request::RequestBuilder;
+use time::Duration;
+
+/// A BewitIssuer mints bewits according to a fixed policy.
+///
+/// Services which hand out bewits can configure a single issuer with the credentials, host, and
+/// port to use, the maximum lifetime of a bewit, and the paths for which bewits may be issued.
+/// Call sites then use `issue`, which refuses to mint bewits outside that policy.
+///
+/// # Examples
+///
+/// ```
+/// extern crate time;
+/// extern crate hawk;
+///
+/// use hawk::{BewitIssuer, Credentials, Key, SHA256};
+///
+/// fn main() {
+///     let credentials = Credentials {
+///         id: "me".to_string(),
+///         key: Key::new(vec![99u8; 32], &SHA256),
+///     };
+///     let issuer = BewitIssuer::new(credentials, "example.com", 443, time::Duration::hours(1))
+///         .allow_path_prefix("/downloads/");
+///
+///     assert!(issuer.issue("/downloads/file.zip", time::Duration::minutes(10)).is_ok());
+///     assert!(issuer.issue("/admin", time::Duration::minutes(10)).is_err());
+///     assert!(issuer.issue("/downloads/file.zip", time::Duration::days(10)).is_err());
+/// }
+/// ```
+pub struct BewitIssuer {
+    credentials: Credentials,
+    host: String,
+    port: u16,
+    max_ttl: Duration,
+    path_prefixes: Vec<String>,
+    ext: Option<String>,
+}
+
+impl BewitIssuer {
+    /// Create a new issuer for the given credentials, host, and port, which will issue bewits
+    /// valid for at most `max_ttl`.
+    ///
+    /// The issuer initially allows no paths; add allowed paths with `allow_path_prefix`.
+    pub fn new<S>(credentials: Credentials, host: S, port: u16, max_ttl: Duration) -> Self
+        where S: Into<String>
+    {
+        BewitIssuer {
+            credentials,
+            host: host.into(),
+            port,
+            max_ttl,
+            path_prefixes: vec![],
+            ext: None,
+        }
+    }
+
+    /// Allow bewits to be issued for paths beginning with the given prefix.  To allow a
+    /// directory, include the trailing `/`, as `/downloads` also matches `/downloads-private`.
+    pub fn allow_path_prefix<S>(mut self, prefix: S) -> Self
+        where S: Into<String>
+    {
+        self.path_prefixes.push(prefix.into());
+        self
+    }
+
+    /// Set a template for the `ext` property of issued bewits.  Any occurrence of `{path}` in
+    /// the template is replaced with the path for which the bewit is issued.
+    pub fn ext<S>(mut self, template: S) -> Self
+        where S: Into<String>
+    {
+        self.ext = Some(template.into());
+        self
+    }
+
+    /// Issue a bewit for the given path, valid for `ttl` from now.
+    ///
+    /// This fails if the path is not allowed, if it may resolve outside the allowed prefix (with
+    /// `.` or `..` segments, even percent-encoded, or encoded separators), or if `ttl` exceeds
+    /// the issuer's maximum.
+    pub fn issue(&self, path: &str, ttl: Duration) -> Result<Bewit<'static>> {
+        if ttl > self.max_ttl {
+            bail!("bewit ttl {} exceeds maximum of {}", ttl, self.max_ttl);
+        }
+        if may_traverse(path.split_once('?').map_or(path, |(path, _)| path)) {
+            bail!("bewit path {} may resolve outside its prefix", path);
+        }
+        if !self.path_prefixes.iter().any(|prefix| path.starts_with(&prefix[..])) {
+            bail!("bewit path {} is not allowed", path);
+        }
+
+        let ext = self.ext.as_ref().map(|template| template.replace("{path}", path));
+        let request = RequestBuilder::new("GET", &self.host, self.port, path)
+            .ext(ext.as_ref().map(|ext| &ext[..]))
+            .request();
+        let bewit = request.make_bewit(&self.credentials, ttl)?;
+        Ok(bewit.into_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BewitIssuer;
+    use credentials::{Credentials, Key};
+    use request::RequestBuilder;
+    use ring::digest;
+    use time::Duration;
+
+    fn issuer() -> BewitIssuer {
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        BewitIssuer::new(credentials, "example.com", 443, Duration::hours(1))
+            .allow_path_prefix("/downloads/")
+            .allow_path_prefix("/public/")
+    }
+
+    #[test]
+    fn issue_valid() {
+        let issuer = issuer();
+        let bewit = issuer.issue("/downloads/file.zip", Duration::minutes(10)).unwrap();
+        assert_eq!(bewit.id(), "me");
+        assert_eq!(bewit.ext(), None);
+
+        let req = RequestBuilder::new("GET", "example.com", 443, "/downloads/file.zip")
+            .request();
+        assert!(req.validate_bewit(&bewit, &issuer.credentials.key));
+    }
+
+    #[test]
+    fn issue_second_prefix() {
+        assert!(issuer().issue("/public/index.html", Duration::minutes(10)).is_ok());
+    }
+
+    #[test]
+    fn issue_path_not_allowed() {
+        assert!(issuer().issue("/private/file.zip", Duration::minutes(10)).is_err());
+        assert!(issuer().issue("/downloads", Duration::minutes(10)).is_err());
+    }
+
+    #[test]
+    fn issue_path_dotdot() {
+        for path in &["/downloads/../private/file.zip",
+                      "/downloads/%2e%2e/secret",
+                      "/downloads/..%2fsecret",
+                      "/downloads/..%5Csecret",
+                      "/downloads/..\\secret"] {
+            assert!(issuer().issue(path, Duration::minutes(10)).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn issue_no_prefixes() {
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        let issuer = BewitIssuer::new(credentials, "example.com", 443, Duration::hours(1));
+        assert!(issuer.issue("/downloads/file.zip", Duration::minutes(10)).is_err());
+    }
+
+    #[test]
+    fn issue_ttl_too_long() {
+        assert!(issuer().issue("/downloads/file.zip", Duration::hours(2)).is_err());
+        assert!(issuer().issue("/downloads/file.zip", Duration::hours(1)).is_ok());
+    }
+
+    #[test]
+    fn issue_ext_template() {
+        let issuer = issuer().ext("issued-for:{path}");
+        let bewit = issuer.issue("/downloads/file.zip", Duration::minutes(10)).unwrap();
+        assert_eq!(bewit.ext(), Some("issued-for:/downloads/file.zip"));
+
+        let req = RequestBuilder::new("GET", "example.com", 443, "/downloads/file.zip")
+            .ext("issued-for:/downloads/file.zip")
+            .request();
+        assert!(req.validate_bewit(&bewit, &issuer.credentials.key));
+    }
+}
@@ -0,0 +1,521 @@
+//! Distributing Hawk keys as a JSON document.
+//!
+//! A key set is a JSON document listing Hawk credentials, much as a JWKS document lists JSON
+//! Web Keys.  A service can publish its key set at an internal endpoint, and servers can fetch
+//! it periodically with a `RemoteCredentialsProvider`, picking up new and rotated keys without
+//! restarting.  This module requires the `json` feature.
+//!
+//! The document has the form:
+//!
+//! ```json
+//! {
+//!   "keys": [
+//!     {
+//!       "id": "svc-a",
+//!       "key": "Y2NjY2NjY2NjY2NjY2NjY2NjY2NjY2NjY2NjY2NjY2M=",
+//!       "algorithm": "sha256",
+//!       "not_before": 1500000000,
+//!       "expires": 1600000000
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! The `key` is standard base64, and the `algorithm` is a name accepted by `digest_algorithm`.
+//! `not_before` and `expires` are optional Unix timestamps, in seconds; a key is in effect from
+//! `not_before` (inclusive) until `expires` (exclusive).  Unknown fields are ignored.  An id may
+//! appear more than once, for example while its key is being rotated.
+//!
+//! # Examples
+//!
+//! ```
+//! use hawk::keyset::KeySet;
+//! use hawk::CredentialsProvider;
+//!
+//! let keys = KeySet::from_json(r#"{"keys": [
+//!     {"id": "svc-a", "key": "Y2NjY2NjY2NjY2NjY2NjY2NjY2NjY2NjY2NjY2NjY2M=",
+//!      "algorithm": "sha256"}
+//! ]}"#).unwrap();
+//! assert_eq!(keys.keys("svc-a").len(), 1);
+//! assert_eq!(keys.keys("svc-b").len(), 0);
+//! ```
+//!
+//! # Encrypted key sets
+//!
+//! So that key sets can be checked in to configuration management, a key set document can be
+//! encrypted with a passphrase using `KeySet::encrypt_json`, and loaded at startup with
+//! `KeySet::load_encrypted`.  The encrypted form is a single line of standard base64 (whitespace
+//! is ignored when decoding) encoding:
+//!
+//! * the 8 bytes `HAWKKS\0\x01`, identifying the format and its version;
+//! * the number of PBKDF2 iterations, as a 4-byte big-endian integer;
+//! * a 16-byte random salt;
+//! * a 12-byte random nonce; and
+//! * the document, encrypted with AES-256-GCM, followed by the 16-byte tag.
+//!
+//! The AES key is derived from the passphrase and salt with PBKDF2-HMAC-SHA256, and the first
+//! 40 bytes, before the ciphertext, are authenticated as additional data.  Encrypted key sets
+//! use 100,000 iterations, and those with fewer, or more than 1,000,000, are rejected.
+
+use algorithm::digest_algorithm;
+use base64;
+use clock;
+use credentials::{CredentialsProvider, Key, Validity};
+use error::*;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{aead, pbkdf2};
+use serde_json::{self, Value};
+use std::fs;
+use std::path::Path;
+use std::sync::{RwLock, RwLockReadGuard};
+use time::{Duration, Timespec};
+
+/// A key in a key set, with its validity period.
+#[derive(Clone)]
+pub struct KeySetEntry {
+    id: String,
+    key: Key,
+    validity: Validity,
+}
+
+impl KeySetEntry {
+    /// Get the id this key belongs to.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Get the key.
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+
+    /// Get the time from which this key is in effect, if any.
+    pub fn not_before(&self) -> Option<Timespec> {
+        self.validity.not_before
+    }
+
+    /// Get the time at which this key stops being in effect, if any.
+    pub fn expires(&self) -> Option<Timespec> {
+        self.validity.expires
+    }
+
+    /// Get the period during which this key is in effect.
+    pub fn validity(&self) -> Validity {
+        self.validity
+    }
+
+    /// Determine whether this key is in effect at the given time.
+    pub fn is_valid_at(&self, now: Timespec) -> bool {
+        self.validity.is_valid_at(now)
+    }
+}
+
+/// A set of keys parsed from a key set document.
+///
+/// As a `CredentialsProvider`, a KeySet returns only the keys in effect at the current time
+/// from `keys`, and all keys with their validity periods from `keys_with_validity`, so that
+/// requests signed with expired keys are rejected as such.
+#[derive(Clone, Default)]
+pub struct KeySet {
+    entries: Vec<KeySetEntry>,
+}
+
+impl KeySet {
+    /// Parse a key set document.  The whole document is rejected if any entry is invalid.
+    pub fn from_json(json: &str) -> Result<KeySet> {
+        let doc: Value = serde_json::from_str(json).chain_err(|| "Invalid key set JSON")?;
+        let keys = match doc.get("keys").and_then(Value::as_array) {
+            Some(keys) => keys,
+            None => bail!("Key set has no `keys` array"),
+        };
+        let entries = keys.iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                parse_entry(entry).chain_err(|| format!("Invalid key set entry {}", i))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(KeySet { entries })
+    }
+
+    /// Encrypt a key set document with the given passphrase, in the format described in the
+    /// module documentation.  The document is checked to be a valid key set first.
+    pub fn encrypt_json(json: &str, passphrase: &str) -> Result<String> {
+        KeySet::from_json(json)?;
+        let mut encrypted = Vec::with_capacity(HEADER_LEN + json.len() + TAG_LEN);
+        encrypted.extend_from_slice(MAGIC);
+        encrypted.extend_from_slice(&ITERATIONS.to_be_bytes());
+        encrypted.resize(HEADER_LEN, 0);
+        SystemRandom::new()
+            .fill(&mut encrypted[MAGIC.len() + 4..])
+            .map_err(|_| "Could not generate a random salt and nonce")?;
+        encrypted.extend_from_slice(json.as_bytes());
+        encrypted.resize(HEADER_LEN + json.len() + TAG_LEN, 0);
+
+        let (header, in_out) = encrypted.split_at_mut(HEADER_LEN);
+        let key = aead::SealingKey::new(&aead::AES_256_GCM,
+                                        &derive_key(passphrase, ITERATIONS, salt(header)))
+            .map_err(|_| "Could not create an encryption key")?;
+        aead::seal_in_place(&key, nonce(header), header, in_out, TAG_LEN)
+            .map_err(|_| "Could not encrypt the key set")?;
+        Ok(base64::encode(&encrypted))
+    }
+
+    /// Decrypt and parse a key set encrypted with `encrypt_json`.  This fails if the passphrase
+    /// is wrong or the encrypted key set has been modified.
+    pub fn decrypt(encrypted: &str, passphrase: &str) -> Result<KeySet> {
+        let encoded: String = encrypted.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut encrypted = base64::decode(&encoded)
+            .chain_err(|| "Encrypted key set is not valid base64")?;
+        if encrypted.len() < HEADER_LEN + TAG_LEN || !encrypted.starts_with(MAGIC) {
+            bail!("Not an encrypted key set");
+        }
+        let (header, in_out) = encrypted.split_at_mut(HEADER_LEN);
+        let mut iterations = [0u8; 4];
+        iterations.copy_from_slice(&header[MAGIC.len()..MAGIC.len() + 4]);
+        let iterations = u32::from_be_bytes(iterations);
+        // the count is not authenticated until decryption succeeds, so bound it to avoid
+        // hanging on a corrupt file, or silently accepting a weak key derivation
+        if !(ITERATIONS..=MAX_ITERATIONS).contains(&iterations) {
+            bail!("Encrypted key set has {} PBKDF2 iterations, outside {}..={}",
+                  iterations,
+                  ITERATIONS,
+                  MAX_ITERATIONS);
+        }
+        let key = aead::OpeningKey::new(&aead::AES_256_GCM,
+                                        &derive_key(passphrase, iterations, salt(header)))
+            .map_err(|_| "Could not create a decryption key")?;
+        let json = aead::open_in_place(&key, nonce(header), header, 0, in_out)
+            .map_err(|_| "Could not decrypt key set: wrong passphrase, or modified")?;
+        let json = ::std::str::from_utf8(json).chain_err(|| "Decrypted key set is not UTF-8")?;
+        KeySet::from_json(json)
+    }
+
+    /// Read and decrypt a key set encrypted with `encrypt_json` from the given file.
+    pub fn load_encrypted<P>(path: P, passphrase: &str) -> Result<KeySet>
+        where P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        let encrypted = fs::read_to_string(path)
+            .chain_err(|| format!("Could not read key set {}", path.display()))?;
+        KeySet::decrypt(&encrypted, passphrase)
+            .chain_err(|| format!("Could not load key set {}", path.display()))
+    }
+
+    /// Get all entries in the key set, whether or not they are in effect.
+    pub fn entries(&self) -> &[KeySetEntry] {
+        &self.entries
+    }
+
+    /// Get the keys for the given id which are in effect at the given time.
+    pub fn keys_at(&self, id: &str, now: Timespec) -> Vec<Key> {
+        self.entries
+            .iter()
+            .filter(|e| e.id == id && e.is_valid_at(now))
+            .map(|e| e.key.clone())
+            .collect()
+    }
+}
+
+impl CredentialsProvider for KeySet {
+    fn keys(&self, id: &str) -> Vec<Key> {
+        self.keys_at(id, clock::now())
+    }
+
+    fn keys_with_validity(&self, id: &str) -> Vec<(Key, Validity)> {
+        self.entries
+            .iter()
+            .filter(|e| e.id == id)
+            .map(|e| (e.key.clone(), e.validity))
+            .collect()
+    }
+}
+
+const MAGIC: &[u8] = b"HAWKKS\0\x01";
+const ITERATIONS: u32 = 100_000;
+const MAX_ITERATIONS: u32 = 10 * ITERATIONS;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = 8 + 4 + SALT_LEN + NONCE_LEN;
+const TAG_LEN: usize = 16;
+
+fn salt(header: &[u8]) -> &[u8] {
+    &header[MAGIC.len() + 4..MAGIC.len() + 4 + SALT_LEN]
+}
+
+fn nonce(header: &[u8]) -> &[u8] {
+    &header[HEADER_LEN - NONCE_LEN..HEADER_LEN]
+}
+
+fn derive_key(passphrase: &str, iterations: u32, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::derive(&pbkdf2::HMAC_SHA256, iterations, salt, passphrase.as_bytes(), &mut key);
+    key
+}
+
+fn parse_entry(entry: &Value) -> Result<KeySetEntry> {
+    let string = |name: &str| -> Result<&str> {
+        match entry.get(name).and_then(Value::as_str) {
+            Some(s) => Ok(s),
+            None => bail!("`{}` must be a string", name),
+        }
+    };
+    let timestamp = |name: &str| -> Result<Option<Timespec>> {
+        match entry.get(name) {
+            None | Some(&Value::Null) => Ok(None),
+            Some(v) => match v.as_i64() {
+                Some(secs) => Ok(Some(Timespec::new(secs, 0))),
+                None => bail!("`{}` must be an integer", name),
+            },
+        }
+    };
+
+    let algorithm = digest_algorithm(string("algorithm")?)?;
+    let key = base64::decode(string("key")?).chain_err(|| "`key` is not valid base64")?;
+    Ok(KeySetEntry {
+        id: string("id")?.to_string(),
+        key: Key::new(key, algorithm),
+        validity: Validity {
+            not_before: timestamp("not_before")?,
+            expires: timestamp("expires")?,
+        },
+    })
+}
+
+/// A CredentialsProvider which periodically refreshes a key set from a remote source.
+///
+/// This crate does not include an HTTP client, so the provider is given a function which
+/// fetches the key set document, for example from an internal endpoint.  The document is
+/// fetched when the provider is created, and again on the first lookup after each
+/// `refresh_interval`.  If a refresh fails, the provider keeps using the keys it has, and tries
+/// again on the next lookup.
+pub struct RemoteCredentialsProvider<F> {
+    fetch: F,
+    refresh_interval: Duration,
+    state: RwLock<RemoteState>,
+}
+
+struct RemoteState {
+    keys: KeySet,
+    fetched_at: Timespec,
+}
+
+impl<F> RemoteCredentialsProvider<F>
+    where F: Fn() -> Result<String>
+{
+    /// Create a new provider, fetching the initial key set with `fetch`.  Fails if the initial
+    /// key set cannot be fetched or parsed.
+    pub fn new(fetch: F, refresh_interval: Duration) -> Result<Self> {
+        let keys = KeySet::from_json(&fetch()?)?;
+        Ok(RemoteCredentialsProvider {
+            fetch,
+            refresh_interval,
+            state: RwLock::new(RemoteState {
+                keys,
+                fetched_at: clock::now(),
+            }),
+        })
+    }
+
+    /// Fetch the key set now, replacing the current keys if successful.
+    pub fn refresh(&self) -> Result<()> {
+        let keys = KeySet::from_json(&(self.fetch)()?)?;
+        let mut state = self.state.write().unwrap_or_else(|e| e.into_inner());
+        state.keys = keys;
+        state.fetched_at = clock::now();
+        Ok(())
+    }
+
+    /// Get a copy of the current key set.
+    pub fn key_set(&self) -> KeySet {
+        self.state.read().unwrap_or_else(|e| e.into_inner()).keys.clone()
+    }
+
+    /// Refresh the key set if `refresh_interval` has passed since it was last fetched, and
+    /// return it.
+    fn current(&self) -> RwLockReadGuard<'_, RemoteState> {
+        let now = clock::now();
+        let stale = {
+            let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+            now - state.fetched_at >= self.refresh_interval
+        };
+        if stale && self.refresh().is_err() {
+            // keep the old keys, but wait a full interval before trying again
+            self.state.write().unwrap_or_else(|e| e.into_inner()).fetched_at = now;
+        }
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<F> CredentialsProvider for RemoteCredentialsProvider<F>
+    where F: Fn() -> Result<String>
+{
+    fn keys(&self, id: &str) -> Vec<Key> {
+        self.current().keys.keys(id)
+    }
+
+    fn keys_with_validity(&self, id: &str) -> Vec<(Key, Validity)> {
+        self.current().keys.keys_with_validity(id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+
+    const KEY_A: &str = "Y2NjY2NjY2NjY2NjY2NjY2NjY2NjY2NjY2NjY2NjY2M=";
+
+    #[test]
+    fn parse() {
+        let set = KeySet::from_json(&format!(r#"{{"keys": [
+            {{"id": "a", "key": "{}", "algorithm": "sha256"}},
+            {{"id": "a", "key": "{}", "algorithm": "SHA-512", "not_before": 100,
+              "expires": 200, "comment": "ignored"}}
+        ]}}"#,
+                                             KEY_A,
+                                             KEY_A))
+            .unwrap();
+        assert_eq!(set.entries().len(), 2);
+        assert_eq!(set.entries()[0].id(), "a");
+        assert_eq!(set.entries()[0].not_before(), None);
+        assert_eq!(set.entries()[1].key().algorithm().output_len, 64);
+        assert_eq!(set.entries()[1].not_before(), Some(Timespec::new(100, 0)));
+        assert_eq!(set.entries()[1].expires(), Some(Timespec::new(200, 0)));
+        assert_eq!(set.entries()[0].key().sign(b"data"),
+                   Key::new(vec![99u8; 32], &::ring::digest::SHA256).sign(b"data"));
+    }
+
+    #[test]
+    fn encrypted() {
+        let json = format!(r#"{{"keys": [{{"id": "a", "key": "{}", "algorithm": "sha256"}}]}}"#,
+                           KEY_A);
+        let encrypted = KeySet::encrypt_json(&json, "correct horse").unwrap();
+        assert_ne!(encrypted, KeySet::encrypt_json(&json, "correct horse").unwrap());
+        assert!(!encrypted.contains(KEY_A));
+
+        let set = KeySet::decrypt(&encrypted, "correct horse").unwrap();
+        assert_eq!(set.keys("a").len(), 1);
+        assert!(KeySet::decrypt(&encrypted, "battery staple").is_err());
+
+        // any modification, including to the iteration count, is detected
+        let mut tampered = base64::decode(&encrypted).unwrap();
+        tampered[11] ^= 1;
+        assert!(KeySet::decrypt(&base64::encode(&tampered), "correct horse").is_err());
+        assert!(KeySet::decrypt(&encrypted[..encrypted.len() - 4], "correct horse").is_err());
+        assert!(KeySet::decrypt("aGF3aw==", "correct horse").is_err());
+
+        // iteration counts out of range are rejected before deriving a key
+        let with_iterations = |iterations: u32| {
+            let mut encrypted = base64::decode(&encrypted).unwrap();
+            encrypted[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&iterations.to_be_bytes());
+            KeySet::decrypt(&base64::encode(&encrypted), "correct horse")
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        for iterations in [0, 1, ITERATIONS - 1, MAX_ITERATIONS + 1, u32::MAX] {
+            assert!(with_iterations(iterations).contains("PBKDF2 iterations"));
+        }
+        assert!(with_iterations(MAX_ITERATIONS).contains("wrong passphrase"));
+
+        assert!(KeySet::encrypt_json(r#"{"keys": 1}"#, "correct horse").is_err());
+
+        let path = ::std::env::temp_dir().join("hawk-keyset-encrypted-test");
+        let wrapped: Vec<String> = encrypted.as_bytes()
+            .chunks(64)
+            .map(|line| String::from_utf8(line.to_vec()).unwrap())
+            .collect();
+        fs::write(&path, wrapped.join("\n") + "\n").unwrap();
+        let set = KeySet::load_encrypted(&path, "correct horse").unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(set.keys("a").len(), 1);
+        assert!(KeySet::load_encrypted(&path, "correct horse").is_err());
+    }
+
+    #[test]
+    fn validity_period() {
+        let set = KeySet::from_json(&format!(r#"{{"keys": [
+            {{"id": "a", "key": "{}", "algorithm": "sha256", "not_before": 100, "expires": 200}}
+        ]}}"#,
+                                             KEY_A))
+            .unwrap();
+        assert_eq!(set.keys_at("a", Timespec::new(99, 0)).len(), 0);
+        assert_eq!(set.keys_at("a", Timespec::new(100, 0)).len(), 1);
+        assert_eq!(set.keys_at("a", Timespec::new(199, 0)).len(), 1);
+        assert_eq!(set.keys_at("a", Timespec::new(200, 0)).len(), 0);
+        assert_eq!(set.keys_at("b", Timespec::new(150, 0)).len(), 0);
+
+        let with_validity = set.keys_with_validity("a");
+        assert_eq!(with_validity.len(), 1);
+        assert_eq!(with_validity[0].1.expires, Some(Timespec::new(200, 0)));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(KeySet::from_json("[]").is_err());
+        assert!(KeySet::from_json(r#"{"keys": [{"id": "a", "algorithm": "sha256"}]}"#).is_err());
+        assert!(KeySet::from_json(r#"{"keys": [{"id": "a", "key": "!!",
+                                                "algorithm": "sha256"}]}"#)
+            .is_err());
+        assert!(KeySet::from_json(&format!(r#"{{"keys": [{{"id": "a", "key": "{}",
+                                                          "algorithm": "md5"}}]}}"#,
+                                           KEY_A))
+            .is_err());
+        assert!(KeySet::from_json(&format!(r#"{{"keys": [{{"id": "a", "key": "{}",
+                                                          "algorithm": "sha256",
+                                                          "expires": "soon"}}]}}"#,
+                                           KEY_A))
+            .is_err());
+    }
+
+    #[test]
+    fn remote_refresh() {
+        let fetches = Cell::new(0);
+        let provider = RemoteCredentialsProvider::new(|| {
+                fetches.set(fetches.get() + 1);
+                if fetches.get() > 2 {
+                    bail!("endpoint unavailable");
+                }
+                let id = if fetches.get() == 1 { "a" } else { "b" };
+                Ok(format!(r#"{{"keys": [{{"id": "{}", "key": "{}", "algorithm": "sha256"}}]}}"#,
+                           id,
+                           KEY_A))
+            },
+                                                      Duration::hours(1))
+            .unwrap();
+        assert_eq!(fetches.get(), 1);
+
+        // not yet due for a refresh
+        assert_eq!(provider.keys("a").len(), 1);
+        assert_eq!(fetches.get(), 1);
+
+        provider.refresh().unwrap();
+        assert_eq!(provider.keys("a").len(), 0);
+        assert_eq!(provider.keys("b").len(), 1);
+
+        // a failed refresh keeps the old keys
+        assert!(provider.refresh().is_err());
+        assert_eq!(provider.keys("b").len(), 1);
+    }
+
+    #[test]
+    fn remote_refreshes_when_stale() {
+        let fetches = Cell::new(0);
+        let provider = RemoteCredentialsProvider::new(|| {
+                fetches.set(fetches.get() + 1);
+                Ok(format!(r#"{{"keys": [{{"id": "a", "key": "{}", "algorithm": "sha256"}}]}}"#,
+                           KEY_A))
+            },
+                                                      Duration::zero())
+            .unwrap();
+        provider.keys("a");
+        provider.keys("a");
+        assert_eq!(fetches.get(), 3);
+    }
+
+    #[test]
+    fn remote_initial_fetch_fails() {
+        assert!(RemoteCredentialsProvider::new(|| bail!("unavailable"), Duration::hours(1))
+            .is_err());
+    }
+}
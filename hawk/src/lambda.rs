@@ -0,0 +1,453 @@
+//! Validating Hawk headers in an AWS API Gateway Lambda authorizer.
+//!
+//! API Gateway calls a `REQUEST` authorizer with a JSON event describing the request, and
+//! expects an IAM policy document allowing or denying it.  `AuthorizerEvent` parses the event,
+//! in either the REST API (payload format 1.0) or HTTP API (2.0) form, and `authorize`
+//! validates its Hawk `Authorization` header, producing an `AuthorizerResponse` which allows the
+//! request for the header's credentials id.  This module requires the `lambda` feature.
+//!
+//! Format 1.0 events give only the decoded query parameters, not the query string the client
+//! signed, so events with a query string must use format 2.0.
+//!
+//! A denied request gets a `403 Forbidden`.  For a `401 Unauthorized`, which invites the client
+//! to retry with credentials, the function should instead fail with the message
+//! `Unauthorized`.
+//!
+//! # Examples
+//!
+//! ```
+//! use hawk::lambda::{AuthorizerEvent, AuthorizerResponse};
+//! use hawk::keyset::KeySet;
+//! use hawk::ValidationOptions;
+//!
+//! fn handle(event: &str, keys: &KeySet) -> String {
+//!     let event = AuthorizerEvent::from_json(event).unwrap();
+//!     let response = match event.authorize(keys, &ValidationOptions::default()) {
+//!         Ok(response) => response,
+//!         Err(_) => AuthorizerResponse::deny("anonymous", event.resource()),
+//!     };
+//!     response.to_json()
+//! }
+//!
+//! let keys = KeySet::from_json(r#"{"keys": []}"#).unwrap();
+//! let event = r#"{
+//!     "version": "2.0",
+//!     "type": "REQUEST",
+//!     "routeArn": "arn:aws:execute-api:us-east-1:123456789012:abcdef123/prod/GET/items",
+//!     "rawPath": "/items",
+//!     "rawQueryString": "",
+//!     "headers": {"host": "api.example.com"},
+//!     "requestContext": {"http": {"method": "GET", "sourceIp": "203.0.113.7"}}
+//! }"#;
+//! assert!(handle(event, &keys).contains("\"Effect\":\"Deny\""));
+//! ```
+
+use auth_source;
+use credentials::CredentialsProvider;
+use endpoint::PublicEndpoint;
+use error::*;
+use hawk_core::Header;
+use request::RequestBuilder;
+use serde_json::{self, Map, Value};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+use validation::ValidationOptions;
+use verify::VerifyError;
+
+/// The request described by an API Gateway `REQUEST` authorizer event.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AuthorizerEvent {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    source_ip: Option<IpAddr>,
+    resource: String,
+}
+
+impl AuthorizerEvent {
+    /// Parse an authorizer event, in payload format 1.0 or 2.0.
+    pub fn from_json(json: &str) -> Result<AuthorizerEvent> {
+        let doc: Value = serde_json::from_str(json).chain_err(|| "Invalid authorizer event JSON")?;
+        let string = |pointer: &str| doc.pointer(pointer).and_then(Value::as_str);
+        let required = |pointer: &str| -> Result<String> {
+            match string(pointer) {
+                Some(s) => Ok(s.to_string()),
+                None => bail!("Authorizer event has no `{}`", pointer),
+            }
+        };
+
+        let event = if string("/version") == Some("2.0") {
+            let mut path = required("/rawPath")?;
+            match string("/rawQueryString") {
+                Some("") | None => {}
+                Some(query) => {
+                    path.push('?');
+                    path.push_str(query);
+                }
+            }
+            AuthorizerEvent {
+                method: required("/requestContext/http/method")?,
+                path,
+                // format 2.0 joins repeated headers with commas
+                headers: headers(doc.get("headers"), |v| v.as_str().into_iter().collect()),
+                source_ip: string("/requestContext/http/sourceIp").and_then(|ip| ip.parse().ok()),
+                resource: required("/routeArn")?,
+            }
+        } else {
+            let has_query = doc.get("queryStringParameters")
+                .and_then(Value::as_object)
+                .is_some_and(|query| !query.is_empty());
+            if has_query {
+                bail!("Payload format 1.0 does not preserve the query string; use format 2.0");
+            }
+            let headers = match doc.get("multiValueHeaders") {
+                multi @ Some(&Value::Object(_)) => {
+                    headers(multi, |v| {
+                        v.as_array().into_iter().flatten().filter_map(Value::as_str).collect()
+                    })
+                }
+                _ => headers(doc.get("headers"), |v| v.as_str().into_iter().collect()),
+            };
+            AuthorizerEvent {
+                method: required("/httpMethod")?,
+                path: required("/path")?,
+                headers,
+                source_ip: string("/requestContext/identity/sourceIp")
+                    .and_then(|ip| ip.parse().ok()),
+                resource: required("/methodArn")?,
+            }
+        };
+        Ok(event)
+    }
+
+    /// Get the request method.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Get the request path, including any query.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Get the values of the request headers with the given name, which is case-insensitive.
+    pub fn headers<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers.iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| &v[..])
+    }
+
+    /// Get the address of the client, if API Gateway reported one.
+    pub fn source_ip(&self) -> Option<IpAddr> {
+        self.source_ip
+    }
+
+    /// Get the ARN of the method or route being authorized, for the policy document.
+    pub fn resource(&self) -> &str {
+        &self.resource
+    }
+
+    /// Validate the request's Hawk `Authorization` header against the keys of the given
+    /// provider, returning a response allowing the request for the header's credentials id.
+    ///
+    /// The host is that of the `Host` header, and the scheme that of `X-Forwarded-Proto`, or
+    /// `https`.  The response's context carries the credentials id as `hawkId`, and the
+    /// header's `ext` data, if any, as `hawkExt`, for the integration to read.
+    pub fn authorize<P>(&self,
+                        provider: &P,
+                        options: &ValidationOptions)
+                        -> ::std::result::Result<AuthorizerResponse, VerifyError>
+        where P: CredentialsProvider + ?Sized
+    {
+        self.authorize_for(None, provider, options)
+    }
+
+    /// Like `authorize`, but validating the request as made to the given public endpoint,
+    /// ignoring the `Host` and `X-Forwarded-Proto` headers.  Use this when clients reach the
+    /// API through a custom domain, whose host and port they sign.
+    pub fn authorize_at<P>(&self,
+                           endpoint: &PublicEndpoint,
+                           provider: &P,
+                           options: &ValidationOptions)
+                           -> ::std::result::Result<AuthorizerResponse, VerifyError>
+        where P: CredentialsProvider + ?Sized
+    {
+        self.authorize_for(Some(endpoint), provider, options)
+    }
+
+    fn authorize_for<P>(&self,
+                        endpoint: Option<&PublicEndpoint>,
+                        provider: &P,
+                        options: &ValidationOptions)
+                        -> ::std::result::Result<AuthorizerResponse, VerifyError>
+        where P: CredentialsProvider + ?Sized
+    {
+        let mut authorizations = self.headers("authorization");
+        let authorization = authorizations.next().ok_or(VerifyError::NotHawk)?;
+        if authorizations.next().is_some() {
+            return Err(VerifyError::Malformed);
+        }
+        let value = auth_source::hawk_header_value(authorization).ok_or(VerifyError::NotHawk)?;
+        let header = Header::from_str(value).map_err(|_| VerifyError::Malformed)?;
+
+        let request = match endpoint {
+            Some(endpoint) => endpoint.request_builder(&self.method, &self.path).request(),
+            None => {
+                let host = self.headers("host").next().ok_or(VerifyError::Malformed)?;
+                let scheme = self.headers("x-forwarded-proto").next().unwrap_or("https");
+                RequestBuilder::from_host_header(&self.method, scheme, host, &self.path)
+                    .map_err(|_| VerifyError::Malformed)?
+                    .request()
+            }
+        };
+        let artifacts = request.check_with_provider(&header, provider, options)
+            .map_err(VerifyError::Rejected)?;
+
+        // check_with_provider fails for headers without an id
+        let id = header.id().unwrap_or("");
+        let mut response = AuthorizerResponse::allow(id, &self.resource).with_context("hawkId", id);
+        if let Some(ext) = artifacts.ext() {
+            response = response.with_context("hawkExt", ext);
+        }
+        Ok(response)
+    }
+}
+
+/// Collect the headers in a JSON object, lowercasing their names.
+fn headers<F>(object: Option<&Value>, values: F) -> Vec<(String, String)>
+    where F: Fn(&Value) -> Vec<&str>
+{
+    let empty = Map::new();
+    let object = object.and_then(Value::as_object).unwrap_or(&empty);
+    object.iter()
+        .flat_map(|(name, value)| {
+            values(value).into_iter().map(move |v| (name.to_ascii_lowercase(), v.to_string()))
+        })
+        .collect()
+}
+
+/// Whether a policy allows or denies the request.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+/// The response of a Lambda authorizer: a policy document allowing or denying the invocation
+/// of a resource, for a principal, with context values passed on to the integration.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AuthorizerResponse {
+    principal_id: String,
+    effect: Effect,
+    resource: String,
+    context: BTreeMap<String, String>,
+}
+
+impl AuthorizerResponse {
+    /// Create a response allowing the principal to invoke the resource.
+    pub fn allow(principal_id: &str, resource: &str) -> AuthorizerResponse {
+        AuthorizerResponse::new(principal_id, Effect::Allow, resource)
+    }
+
+    /// Create a response denying the principal the resource.
+    pub fn deny(principal_id: &str, resource: &str) -> AuthorizerResponse {
+        AuthorizerResponse::new(principal_id, Effect::Deny, resource)
+    }
+
+    fn new(principal_id: &str, effect: Effect, resource: &str) -> AuthorizerResponse {
+        AuthorizerResponse {
+            principal_id: principal_id.to_string(),
+            effect,
+            resource: resource.to_string(),
+            context: BTreeMap::new(),
+        }
+    }
+
+    /// Add a context value.
+    pub fn with_context(mut self, key: &str, value: &str) -> AuthorizerResponse {
+        self.context.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Get the principal id.
+    pub fn principal_id(&self) -> &str {
+        &self.principal_id
+    }
+
+    /// Get the policy's effect.
+    pub fn effect(&self) -> Effect {
+        self.effect
+    }
+
+    /// Get a context value.
+    pub fn context(&self, key: &str) -> Option<&str> {
+        self.context.get(key).map(|v| &v[..])
+    }
+
+    /// Format the response as the JSON document API Gateway expects.
+    pub fn to_json(&self) -> String {
+        let effect = match self.effect {
+            Effect::Allow => "Allow",
+            Effect::Deny => "Deny",
+        };
+        let doc = json!({
+            "principalId": self.principal_id,
+            "policyDocument": {
+                "Version": "2012-10-17",
+                "Statement": [{
+                    "Action": "execute-api:Invoke",
+                    "Effect": effect,
+                    "Resource": self.resource,
+                }],
+            },
+            "context": self.context,
+        });
+        doc.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use credentials::{Credentials, Key};
+    use keyset::KeySet;
+    use rejected::RejectionReason;
+    use ring::digest;
+
+    const KEYS: &str = r#"{"keys": [{"id": "me", "algorithm": "sha256",
+                                     "key": "Y2NjY2NjY2NjY2NjY2NjY2NjY2NjY2NjY2NjY2NjY2M="}]}"#;
+    const ARN: &str = "arn:aws:execute-api:us-east-1:123456789012:abcdef123/prod/GET/items";
+
+    fn authorization(method: &str, path: &str) -> String {
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        RequestBuilder::new(method, "api.example.com", 443, path)
+            .ext("tenant-a")
+            .request()
+            .sign_to_string(&credentials)
+            .unwrap()
+    }
+
+    fn v2_event(method: &str, query: &str, authorization: &str) -> String {
+        json!({
+            "version": "2.0",
+            "type": "REQUEST",
+            "routeArn": ARN,
+            "rawPath": "/items",
+            "rawQueryString": query,
+            "headers": {"host": "api.example.com", "authorization": authorization},
+            "requestContext": {"http": {"method": method, "sourceIp": "203.0.113.7"}},
+        })
+        .to_string()
+    }
+
+    fn v1_event(authorization: &[&str]) -> String {
+        json!({
+            "type": "REQUEST",
+            "methodArn": ARN,
+            "path": "/items",
+            "httpMethod": "GET",
+            "headers": {"Host": "api.example.com"},
+            "multiValueHeaders": {"Host": ["api.example.com"], "Authorization": authorization},
+            "queryStringParameters": null,
+            "requestContext": {"identity": {"sourceIp": "203.0.113.7"}},
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn parse_v2() {
+        let event = AuthorizerEvent::from_json(&v2_event("POST", "a=1&b=2", "Hawk id=\"me\""))
+            .unwrap();
+        assert_eq!(event.method(), "POST");
+        assert_eq!(event.path(), "/items?a=1&b=2");
+        assert_eq!(event.headers("Authorization").collect::<Vec<_>>(), vec!["Hawk id=\"me\""]);
+        assert_eq!(event.source_ip(), Some("203.0.113.7".parse().unwrap()));
+        assert_eq!(event.resource(), ARN);
+
+        let event = AuthorizerEvent::from_json(&v2_event("GET", "", "")).unwrap();
+        assert_eq!(event.path(), "/items");
+    }
+
+    #[test]
+    fn parse_v1() {
+        let event = AuthorizerEvent::from_json(&v1_event(&["a", "b"])).unwrap();
+        assert_eq!(event.method(), "GET");
+        assert_eq!(event.path(), "/items");
+        assert_eq!(event.headers("authorization").count(), 2);
+        assert_eq!(event.headers("host").next(), Some("api.example.com"));
+
+        let with_query = v1_event(&[]).replace("\"queryStringParameters\":null",
+                                              "\"queryStringParameters\":{\"a\":\"1\"}");
+        assert!(AuthorizerEvent::from_json(&with_query).is_err());
+        assert!(AuthorizerEvent::from_json("{}").is_err());
+        assert!(AuthorizerEvent::from_json("not json").is_err());
+    }
+
+    #[test]
+    fn authorize() {
+        let keys = KeySet::from_json(KEYS).unwrap();
+        let options = ValidationOptions::default();
+
+        let event = v2_event("GET", "a=1", &authorization("GET", "/items?a=1"));
+        let response = AuthorizerEvent::from_json(&event).unwrap().authorize(&keys, &options)
+            .unwrap();
+        assert_eq!(response.effect(), Effect::Allow);
+        assert_eq!(response.principal_id(), "me");
+        assert_eq!(response.context("hawkId"), Some("me"));
+        assert_eq!(response.context("hawkExt"), Some("tenant-a"));
+
+        let event = v2_event("GET", "a=2", &authorization("GET", "/items?a=1"));
+        assert_eq!(AuthorizerEvent::from_json(&event).unwrap().authorize(&keys, &options),
+                   Err(VerifyError::Rejected(RejectionReason::MacMismatch)));
+
+        let authorization = authorization("GET", "/items");
+        let event = AuthorizerEvent::from_json(&v1_event(&[&authorization])).unwrap();
+        assert!(event.authorize(&keys, &options).is_ok());
+        let event = AuthorizerEvent::from_json(&v1_event(&[])).unwrap();
+        assert_eq!(event.authorize(&keys, &options), Err(VerifyError::NotHawk));
+        let event = AuthorizerEvent::from_json(&v1_event(&[&authorization, &authorization]))
+            .unwrap();
+        assert_eq!(event.authorize(&keys, &options), Err(VerifyError::Malformed));
+    }
+
+    #[test]
+    fn authorize_at() {
+        let keys = KeySet::from_json(KEYS).unwrap();
+        let options = ValidationOptions::default();
+
+        // API Gateway reports its own hostname, not the custom domain the client signed
+        let event = v2_event("GET", "", &authorization("GET", "/items"))
+            .replace("\"host\":\"api.example.com\"", "\"host\":\"abc.execute-api.aws\"");
+        let event = AuthorizerEvent::from_json(&event).unwrap();
+        assert_eq!(event.authorize(&keys, &options),
+                   Err(VerifyError::Rejected(RejectionReason::MacMismatch)));
+        let endpoint = PublicEndpoint::new("https", "api.example.com", 443);
+        assert!(event.authorize_at(&endpoint, &keys, &options).is_ok());
+        let endpoint = PublicEndpoint::new("https", "api.example.com", 8443);
+        assert!(event.authorize_at(&endpoint, &keys, &options).is_err());
+    }
+
+    #[test]
+    fn response_json() {
+        let response = AuthorizerResponse::allow("me", ARN).with_context("hawkId", "me");
+        let doc: Value = serde_json::from_str(&response.to_json()).unwrap();
+        assert_eq!(doc,
+                   json!({
+                       "principalId": "me",
+                       "policyDocument": {
+                           "Version": "2012-10-17",
+                           "Statement": [{
+                               "Action": "execute-api:Invoke",
+                               "Effect": "Allow",
+                               "Resource": ARN,
+                           }],
+                       },
+                       "context": {"hawkId": "me"},
+                   }));
+        assert!(AuthorizerResponse::deny("me", ARN).to_json().contains("\"Effect\":\"Deny\""));
+    }
+}
@@ -2,6 +2,8 @@
 //! authentictation. It is a low-level crate, used by higher-level crates to integrate with various
 //! Rust HTTP libraries.  For example `hyper-hawk` integrates Hawk with Hyper.
 //!
+//! The commonly used types can be imported together with `use hawk::prelude::*`.
+//!
 //! # Examples
 //!
 //! ## Hawk Client
@@ -35,9 +37,9 @@
 //!     let header = request.make_header(&credentials).unwrap();
 //!
 //!     // the header would the be attached to the request
-//!     assert_eq!(header.id.unwrap(), "test-client");
-//!     assert_eq!(header.mac.unwrap().len(), 32);
-//!     assert_eq!(header.hash.unwrap().len(), 32);
+//!     assert_eq!(header.id().unwrap(), "test-client");
+//!     assert_eq!(header.mac().unwrap().len(), 32);
+//!     assert_eq!(header.hash().unwrap().len(), 32);
 //! }
 //! ```
 //!
@@ -50,13 +52,12 @@
 //! extern crate time;
 //! extern crate hawk;
 //!
-//! use hawk::{RequestBuilder, Header, Key, SHA256};
-//! use hawk::mac::Mac;
+//! use hawk::{RequestBuilder, Header, Key, MacBytes, SHA256};
 //!
 //! fn main() {
-//!    let mac = Mac::from(vec![7, 22, 226, 240, 84, 78, 49, 75, 115, 144, 70,
-//!                             106, 102, 134, 144, 128, 225, 239, 95, 132, 202,
-//!                             154, 213, 118, 19, 63, 183, 108, 215, 134, 118, 115]);
+//!    let mac = MacBytes::from(vec![63, 15, 165, 52, 0, 201, 44, 146, 214, 59, 253,
+//!                                  223, 208, 186, 204, 244, 199, 64, 57, 149, 248,
+//!                                  155, 5, 131, 65, 84, 221, 3, 45, 167, 193, 250]);
 //!    // get the header (usually from the received request; constructed directly here)
 //!    let hdr = Header::new(Some("dh37fgj492je"),
 //!                          Some(time::Timespec::new(1353832234, 0)),
@@ -83,6 +84,15 @@ extern crate time;
 extern crate ring;
 extern crate url;
 extern crate rand;
+extern crate smallvec;
+extern crate hawk_core;
+#[cfg(feature = "http")]
+extern crate http;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(any(feature = "json", test))]
+#[cfg_attr(feature = "lambda", macro_use)]
+extern crate serde_json;
 
 #[cfg(test)]
 #[macro_use]
@@ -91,28 +101,131 @@ extern crate pretty_assertions;
 #[macro_use]
 extern crate error_chain;
 
-mod header;
-pub use header::Header;
+pub use hawk_core::{Field, Fields, Header, HeaderError, HeaderField, HeaderRef, HeaderSyntaxError,
+                    MacBytes, ParseMode};
 
 mod credentials;
-pub use credentials::{Credentials, Key};
+pub use credentials::{AppCredentials, Credentials, CredentialsProvider, Key, SharedCredentials,
+                      Validity};
 
 mod request;
-pub use request::{Request, RequestBuilder};
+pub use request::{default_port, Request, RequestBuilder};
+
+mod endpoint;
+pub use endpoint::PublicEndpoint;
+
+mod path_encoding;
+pub use path_encoding::PathEncoding;
+
+mod conformance;
+pub use conformance::Conformance;
+
+mod method_override;
+pub use method_override::{MethodOverride, METHOD_OVERRIDE_HEADER};
+
+mod header_options;
+pub use header_options::HeaderOptions;
+
+mod ext;
+pub use ext::{Ext, ExtFields};
+
+mod header_cache;
+pub use header_cache::{CachedHeader, HeaderCache};
+
+mod fingerprint;
+pub use fingerprint::RequestFingerprint;
+
+mod signer;
+pub use signer::{Scratch, Signer};
+
+mod artifacts;
+pub use artifacts::{Artifacts, Principal};
+
+mod rejected;
+pub use rejected::{RejectedRequest, RejectionReason};
+
+pub use hawk_core::{RedactedBewit, RedactedHeader};
 
 mod response;
 pub use response::{Response, ResponseBuilder};
 
+mod challenge;
+pub use challenge::Challenge;
+
+mod clock;
+pub use clock::{reset_default_clock, set_default_clock, Clock, ClockCorrector, ClockUnsynchronized,
+                DefaultClock, FixedClock, SkewCorrectedClock, SystemClock};
+
+mod validation;
+pub use validation::{ExtCharset, ExtPolicy, NonceCharset, NonceOutagePolicy, NoncePolicy,
+                     PayloadPolicy, ValidationOptions};
+
 mod error;
 pub use error::*;
 
 mod payload;
-pub use payload::PayloadHasher;
+pub use payload::{verify_payload, PayloadError, PayloadHasher};
+
+mod multipart;
+pub use multipart::MultipartHasher;
+
+pub use hawk_core::{Bewit, BewitError};
+
+mod issuer;
+pub use issuer::BewitIssuer;
+
+mod auth_source;
+pub use auth_source::AuthSource;
 
-mod bewit;
-pub use bewit::Bewit;
+mod verify;
+pub use verify::{verify_request, VerifyError};
+
+mod authenticator;
+pub use authenticator::{Authenticator, ComponentStatus, SelfTestReport};
+
+pub mod prelude;
 
 pub mod mac;
 
+mod signed_writer;
+pub use signed_writer::SignedWriter;
+
+pub mod crypto;
+
+#[cfg(feature = "http")]
+pub mod extract;
+
+pub mod ipbind;
+
+pub mod nonce;
+
+#[cfg(feature = "serde")]
+pub mod envelope;
+
+#[cfg(feature = "json")]
+pub mod keyset;
+
+#[cfg(feature = "lambda")]
+pub mod lambda;
+
+pub mod refresh;
+
+pub mod subkey;
+
+pub mod testing;
+
+pub mod webhook;
+
+pub mod websocket;
+
 // convenience imports
-pub use ring::digest::{SHA256, SHA384, SHA512};
+pub use ring::digest::{Algorithm as DigestAlgorithm, SHA256, SHA384, SHA512};
+
+mod algorithm;
+pub use algorithm::{digest_algorithm, digest_algorithm_name, digest_output_len};
+
+#[cfg(test)]
+mod compat;
+
+#[cfg(test)]
+mod fuzz;
@@ -1,16 +1,57 @@
 use credentials::Key;
 use base64;
-use ring::constant_time;
+use crypto;
+use std::fmt;
 use std::io::Write;
 use std::ops::Deref;
+use std::str::FromStr;
 use error::*;
+use hawk_core::MacBytes;
 use time;
 
 /// The kind of MAC calcuation (corresponding to the first line of the message)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MacType {
     Header,
     Response,
     Bewit,
+    /// A caller-supplied first line, such as `hawk.1.webhook`, for protocols which reuse Hawk's
+    /// framing.  MACs calculated with a custom type are not interoperable with other Hawk
+    /// implementations.  The prefix must not contain a newline.
+    Custom(&'static str),
+}
+
+impl MacType {
+    /// Get the first line of the normalized string for this type.
+    pub fn prefix(&self) -> &'static str {
+        match *self {
+            MacType::Header => "hawk.1.header",
+            MacType::Response => "hawk.1.response",
+            MacType::Bewit => "hawk.1.bewit",
+            MacType::Custom(prefix) => prefix,
+        }
+    }
+}
+
+/// MacTypes display as their normalized-string prefix, such as `hawk.1.header`.
+impl fmt::Display for MacType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.prefix())
+    }
+}
+
+/// MacTypes parse from either their normalized-string prefix (`hawk.1.header`) or the short name
+/// (`header`, `response`, or `bewit`).  Custom types cannot be parsed.
+impl FromStr for MacType {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<MacType> {
+        match s {
+            "header" | "hawk.1.header" => Ok(MacType::Header),
+            "response" | "hawk.1.response" => Ok(MacType::Response),
+            "bewit" | "hawk.1.bewit" => Ok(MacType::Bewit),
+            _ => bail!("Unknown MAC type {}", s),
+        }
+    }
 }
 
 /// Mac represents a message authentication code, the signature in a Hawk transaction.
@@ -32,36 +73,70 @@ impl Mac {
                hash: Option<&[u8]>,
                ext: Option<&str>)
                -> Result<Mac> {
-        let mut buffer: Vec<u8> = vec![];
-
-        write!(buffer,
-               "{}\n",
-               match mac_type {
-                   MacType::Header => "hawk.1.header",
-                   MacType::Response => "hawk.1.response",
-                   MacType::Bewit => "hawk.1.bewit",
-               })?;
-        write!(buffer, "{}\n", ts.sec)?;
-        write!(buffer, "{}\n", nonce)?;
-        write!(buffer, "{}\n", method)?;
-        write!(buffer, "{}\n", path)?;
-        write!(buffer, "{}\n", host)?;
-        write!(buffer, "{}\n", port)?;
-
-        if let Some(h) = hash {
-            write!(buffer, "{}\n", base64::encode(h))?;
-        } else {
-            write!(buffer, "\n")?;
-        }
+        let suffix = normalized_suffix(method, host, port, path, hash, ext)?;
+        Mac::with_suffix(mac_type, key, ts, nonce, &suffix)
+    }
 
-        match ext {
-            Some(e) => write!(buffer, "{}\n", e)?,
-            None => write!(buffer, "\n")?,
-        };
+    /// Calculate a MAC from the type, timestamp, and nonce, and a suffix previously produced by
+    /// `normalized_suffix`.
+    pub(crate) fn with_suffix(mac_type: MacType,
+                              key: &Key,
+                              ts: time::Timespec,
+                              nonce: &str,
+                              suffix: &[u8])
+                              -> Result<Mac> {
+        let mut buffer: Vec<u8> = Vec::with_capacity(mac_type.prefix().len() + nonce.len() + 24);
+        write_normalized_prefix(&mut buffer, mac_type, ts, nonce)?;
+
+        Ok(Mac(key.sign_parts(&[&buffer, suffix])))
+    }
 
-        println!("{:?}", String::from_utf8(buffer.clone()));
+    /// Replace this MAC with the HMAC of the given data, calculated with the given key.
+    ///
+    /// The result is written into this MAC's existing buffer, so a `Mac` reused to sign many
+    /// messages allocates only when it is first used with a longer digest.
+    pub fn sign(&mut self, key: &Key, data: &[u8]) {
+        self.replace(key.sign_raw(&[data]).as_ref());
+    }
 
-        Ok(Mac(key.sign(buffer.as_ref())))
+    /// Replace the contents of this MAC, reusing its buffer.
+    pub(crate) fn replace(&mut self, mac: &[u8]) {
+        self.0.clear();
+        self.0.extend_from_slice(mac);
+    }
+
+    /// Encode this MAC as a lower-case hexadecimal string.
+    pub fn to_hex(&self) -> String {
+        format!("{:x}", self)
+    }
+
+    /// Decode a MAC from a hexadecimal string, in either case.
+    pub fn from_hex(hex: &str) -> Result<Mac> {
+        let digit = |c: u8| -> Result<u8> {
+            match c {
+                b'0'..=b'9' => Ok(c - b'0'),
+                b'a'..=b'f' => Ok(c - b'a' + 10),
+                b'A'..=b'F' => Ok(c - b'A' + 10),
+                _ => bail!("Invalid character in hex-encoded MAC"),
+            }
+        };
+        let mut mac = Vec::with_capacity(hex.len() / 2);
+        for pair in hex.as_bytes().chunks(2) {
+            if pair.len() != 2 {
+                bail!("Hex-encoded MAC has odd length");
+            }
+            mac.push(digit(pair[0])? << 4 | digit(pair[1])?);
+        }
+        Ok(Mac(mac))
+    }
+}
+
+impl fmt::LowerHex for Mac {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
     }
 }
 
@@ -77,6 +152,12 @@ impl From<Vec<u8>> for Mac {
     }
 }
 
+impl From<Mac> for MacBytes {
+    fn from(mac: Mac) -> Self {
+        MacBytes::from(&mac.0[..])
+    }
+}
+
 impl Deref for Mac {
     type Target = Vec<u8>;
 
@@ -85,19 +166,134 @@ impl Deref for Mac {
     }
 }
 
+/// Write the first part of the normalized string, up to and including the nonce, to the given
+/// buffer.
+pub(crate) fn write_normalized_prefix(buffer: &mut Vec<u8>,
+                                      mac_type: MacType,
+                                      ts: time::Timespec,
+                                      nonce: &str)
+                                      -> Result<()> {
+    let prefix = mac_type.prefix();
+    if prefix.contains('\n') {
+        bail!("Custom MAC type prefix cannot contain a newline");
+    }
+
+    write!(buffer, "{}\n", prefix)?;
+    write!(buffer, "{}\n", ts.sec)?;
+    write!(buffer, "{}\n", nonce)?;
+    Ok(())
+}
+
+/// Write the part of the normalized string following the timestamp and nonce to the given
+/// buffer.
+pub(crate) fn write_normalized_suffix(buffer: &mut Vec<u8>,
+                                      method: &str,
+                                      host: &str,
+                                      port: u16,
+                                      path: &str,
+                                      hash: Option<&[u8]>,
+                                      ext: Option<&str>)
+                                      -> Result<()> {
+    write!(buffer, "{}\n", method)?;
+    write!(buffer, "{}\n", path)?;
+    write!(buffer, "{}\n", host)?;
+    write!(buffer, "{}\n", port)?;
+
+    if let Some(h) = hash {
+        write!(buffer, "{}\n", base64::encode(h))?;
+    } else {
+        write!(buffer, "\n")?;
+    }
+
+    match ext {
+        Some(e) => write!(buffer, "{}\n", e)?,
+        None => write!(buffer, "\n")?,
+    };
+
+    Ok(())
+}
+
+/// Write the `app` and `dlg` lines of the normalized string to the given buffer.  As in the
+/// Node implementation, these follow the `ext` line, and are included only if `app` is given.
+pub(crate) fn write_normalized_app(buffer: &mut Vec<u8>,
+                                   app: Option<&str>,
+                                   dlg: Option<&str>)
+                                   -> Result<()> {
+    if let Some(app) = app {
+        write!(buffer, "{}\n{}\n", app, dlg.unwrap_or(""))?;
+    }
+    Ok(())
+}
+
+/// Format the part of the normalized string following the timestamp and nonce.  This part
+/// depends only on the request, so it can be calculated once and reused for several MACs.
+pub(crate) fn normalized_suffix(method: &str,
+                                host: &str,
+                                port: u16,
+                                path: &str,
+                                hash: Option<&[u8]>,
+                                ext: Option<&str>)
+                                -> Result<Vec<u8>> {
+    let mut buffer: Vec<u8> = vec![];
+    write_normalized_suffix(&mut buffer, method, host, port, path, hash, ext)?;
+    Ok(buffer)
+}
+
 impl PartialEq for Mac {
     fn eq(&self, other: &Mac) -> bool {
-        match constant_time::verify_slices_are_equal(&self.0[..], &other.0[..]) {
-            Ok(_) => true,
-            Err(_) => false,
-        }
+        crypto::ct_eq(&self.0[..], &other.0[..])
+    }
+}
+
+/// Macs are serialized as base64 strings, as in Hawk headers.  To serialize as hex instead, use
+/// `#[serde(with = "hawk::mac::serde_hex")]`.  This requires the `serde` feature.
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Mac {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+        where S: ::serde::Serializer
+    {
+        serializer.serialize_str(&base64::encode(&self.0))
     }
 }
 
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for Mac {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Mac, D::Error>
+        where D: ::serde::Deserializer<'de>
+    {
+        let encoded = <String as ::serde::Deserialize>::deserialize(deserializer)?;
+        base64::decode(&encoded)
+            .map(Mac)
+            .map_err(::serde::de::Error::custom)
+    }
+}
+
+/// Serialize and deserialize Macs as hex strings, for use with `#[serde(with = "..")]`.  This
+/// requires the `serde` feature.
+#[cfg(feature = "serde")]
+pub mod serde_hex {
+    use super::Mac;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
+
+    pub fn serialize<S>(mac: &Mac, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.serialize_str(&mac.to_hex())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Mac, D::Error>
+        where D: Deserializer<'de>
+    {
+        let encoded = String::deserialize(deserializer)?;
+        Mac::from_hex(&encoded).map_err(|e| D::Error::custom(e.to_string()))
+    }
+}
 
 #[cfg(test)]
 mod test {
     use super::{Mac, MacType};
+    use std::str::FromStr;
     use time::Timespec;
     use credentials::Key;
     use ring::digest;
@@ -108,6 +304,22 @@ mod test {
                  &digest::SHA256)
     }
 
+    #[test]
+    fn test_sign_reuses_buffer() {
+        let sha256 = key();
+        let sha512 = Key::new(vec![1u8; 64], &digest::SHA512);
+        let mut mac = Mac::from(Vec::new());
+        mac.sign(&sha512, b"warm up");
+        let (ptr, capacity) = (mac.as_ptr(), mac.capacity());
+
+        for (i, key) in [&sha256, &sha512, &sha256].iter().enumerate() {
+            let data = format!("message {}", i);
+            mac.sign(key, data.as_bytes());
+            assert_eq!(&mac[..], &key.sign(data.as_bytes())[..]);
+            assert_eq!((mac.as_ptr(), mac.capacity()), (ptr, capacity));
+        }
+    }
+
     #[test]
     fn test_make_mac() {
         let key = key();
@@ -169,4 +381,92 @@ mod test {
                 vec![187, 104, 238, 100, 168, 112, 37, 68, 187, 141, 168, 155, 177, 193, 113, 0,
                      50, 105, 127, 36, 24, 117, 200, 251, 138, 199, 108, 14, 105, 123, 234, 119]);
     }
+
+    fn custom_mac(mac_type: MacType) -> ::error::Result<Mac> {
+        Mac::new(mac_type,
+                 &key(),
+                 Timespec::new(1000, 100),
+                 "nonny",
+                 "POST",
+                 "mysite.com",
+                 443,
+                 "/v1/api",
+                 None,
+                 None)
+    }
+
+    #[test]
+    fn test_make_mac_custom() {
+        // a custom type with the standard prefix is equivalent to the standard type
+        assert_eq!(custom_mac(MacType::Custom("hawk.1.header")).unwrap(),
+                   custom_mac(MacType::Header).unwrap());
+        assert!(custom_mac(MacType::Custom("hawk.1.webhook")).unwrap() !=
+                custom_mac(MacType::Header).unwrap());
+    }
+
+    #[test]
+    fn test_make_mac_custom_newline() {
+        assert!(custom_mac(MacType::Custom("hawk.1.header\nsneaky")).is_err());
+    }
+
+    #[test]
+    fn test_mac_type_strings() {
+        for mac_type in &[MacType::Header, MacType::Response, MacType::Bewit] {
+            assert_eq!(&MacType::from_str(&mac_type.to_string()).unwrap(), mac_type);
+        }
+        assert_eq!(MacType::from_str("header").unwrap(), MacType::Header);
+        assert_eq!(MacType::from_str("response").unwrap(), MacType::Response);
+        assert_eq!(MacType::from_str("bewit").unwrap(), MacType::Bewit);
+        assert_eq!(MacType::Custom("hawk.1.webhook").to_string(), "hawk.1.webhook");
+        assert!(MacType::from_str("hawk.1.webhook").is_err());
+    }
+
+    #[test]
+    fn test_hex() {
+        let mac = Mac::from(vec![0, 1, 15, 16, 171, 255]);
+        assert_eq!(mac.to_hex(), "00010f10abff");
+        assert_eq!(format!("{:x}", mac), "00010f10abff");
+        assert_eq!(Mac::from_hex("00010f10abff").unwrap(), mac);
+        assert_eq!(Mac::from_hex("00010F10ABFF").unwrap(), mac);
+        assert_eq!(Mac::from_hex("").unwrap(), Mac::from(vec![]));
+    }
+
+    #[test]
+    fn test_eq() {
+        let mac = Mac::from(vec![1, 2, 3]);
+        assert_eq!(mac, Mac::from(vec![1, 2, 3]));
+        assert_ne!(mac, Mac::from(vec![1, 2, 4]));
+        assert_ne!(mac, Mac::from(vec![1, 2]));
+        assert_eq!(Mac::from(vec![]), Mac::from(vec![]));
+    }
+
+    #[test]
+    fn test_from_hex_invalid() {
+        assert!(Mac::from_hex("abc").is_err());
+        assert!(Mac::from_hex("zz").is_err());
+        assert!(Mac::from_hex("é0").is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        use serde_json;
+        let mac = Mac::from(vec![1, 2, 3, 4]);
+        let json = serde_json::to_string(&mac).unwrap();
+        assert_eq!(json, "\"AQIDBA==\"");
+        assert_eq!(serde_json::from_str::<Mac>(&json).unwrap(), mac);
+        assert!(serde_json::from_str::<Mac>("\"!!\"").is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_hex() {
+        use serde_json;
+        let mac = Mac::from(vec![1, 2, 3, 171]);
+        let mut ser = serde_json::Serializer::new(vec![]);
+        super::serde_hex::serialize(&mac, &mut ser).unwrap();
+        assert_eq!(ser.into_inner(), b"\"010203ab\"".to_vec());
+        let mut de = serde_json::Deserializer::from_str("\"010203ab\"");
+        assert_eq!(super::serde_hex::deserialize(&mut de).unwrap(), mac);
+    }
 }
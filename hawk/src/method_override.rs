@@ -0,0 +1,53 @@
+use std::borrow::Cow;
+
+/// The header in which clients behind restrictive proxies give the intended method of a `POST`
+/// request.
+pub const METHOD_OVERRIDE_HEADER: &str = "x-http-method-override";
+
+/// Which method is signed and validated for a `POST` request carrying a method override, such as
+/// `X-HTTP-Method-Override: DELETE`.
+///
+/// Hawk signs the request method, so client and server must agree on whether that is the method
+/// transmitted or the one it stands for.  Set the override with
+/// `RequestBuilder::method_override` on both sides, and use the same policy.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MethodOverride {
+    /// Sign the method as transmitted (`POST`), ignoring any override.  This is the default,
+    /// and matches other Hawk implementations, which know nothing of overrides.
+    #[default]
+    Transmitted,
+    /// Sign the overriding method (`DELETE`).  The override only applies to `POST` requests;
+    /// for other methods it is ignored, as servers honoring overrides do.
+    Overridden,
+}
+
+impl MethodOverride {
+    /// Get the method to sign, given the transmitted method and the override, if any.
+    pub fn method<'a>(&self,
+                      method: Cow<'a, str>,
+                      method_override: Option<&'a str>)
+                      -> Cow<'a, str> {
+        match (*self, method_override) {
+            (MethodOverride::Overridden, Some(method_override))
+                if method.eq_ignore_ascii_case("POST") => Cow::Borrowed(method_override.trim()),
+            _ => method,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MethodOverride;
+    use std::borrow::Cow;
+
+    #[test]
+    fn method() {
+        let post = || Cow::Borrowed("POST");
+        assert_eq!(MethodOverride::Transmitted.method(post(), Some("DELETE")), "POST");
+        assert_eq!(MethodOverride::Overridden.method(post(), Some("DELETE")), "DELETE");
+        assert_eq!(MethodOverride::Overridden.method(post(), Some(" PUT ")), "PUT");
+        assert_eq!(MethodOverride::Overridden.method(post(), None), "POST");
+        assert_eq!(MethodOverride::Overridden.method(Cow::Borrowed("GET"), Some("DELETE")),
+                   "GET");
+    }
+}
@@ -0,0 +1,128 @@
+use ring::digest;
+use payload::PayloadHasher;
+
+/// A utility for building and hashing multipart bodies together.
+///
+/// Hawk payload hashes cover the raw body exactly as it is sent, so a multipart body must be
+/// hashed after it has been serialized.  This type serializes parts as they are added, feeding
+/// exactly the same bytes to a `PayloadHasher`, so the body and its hash cannot disagree.  If
+/// the body has already been serialized by some other means, simply hash it with
+/// `PayloadHasher::hash` using the multipart content type (without parameters).
+///
+/// # Examples
+///
+/// ```
+/// use hawk::{MultipartHasher, SHA256};
+///
+/// let mut hasher = MultipartHasher::new("multipart/form-data", "XyZzY", &SHA256);
+/// hasher.part(&[("Content-Disposition", "form-data; name=\"field\"")], "value");
+/// let content_type = hasher.content_type();
+/// let (body, hash) = hasher.finish();
+/// assert_eq!(content_type, "multipart/form-data; boundary=XyZzY");
+/// ```
+pub struct MultipartHasher {
+    content_type: String,
+    boundary: String,
+    body: Vec<u8>,
+    hasher: PayloadHasher,
+}
+
+impl MultipartHasher {
+    /// Create a new MultipartHasher.  The `content_type` should be the lower-case multipart
+    /// type, such as `multipart/form-data`, without parameters.  The boundary must not appear
+    /// in any of the parts.
+    pub fn new<S1, S2>(content_type: S1,
+                       boundary: S2,
+                       algorithm: &'static digest::Algorithm)
+                       -> Self
+        where S1: Into<String>,
+              S2: Into<String>
+    {
+        let content_type = content_type.into();
+        let hasher = PayloadHasher::new(&content_type, algorithm);
+        MultipartHasher {
+            content_type,
+            boundary: boundary.into(),
+            body: vec![],
+            hasher,
+        }
+    }
+
+    /// Get the full `Content-Type` header value for the body, including the boundary.
+    pub fn content_type(&self) -> String {
+        format!("{}; boundary={}", self.content_type, self.boundary)
+    }
+
+    /// Add a part with the given headers and content.  Parts appear in the body in the order
+    /// they are added.
+    pub fn part<B>(&mut self, headers: &[(&str, &str)], content: B)
+        where B: AsRef<[u8]>
+    {
+        let boundary = format!("--{}\r\n", self.boundary);
+        self.write(boundary.as_bytes());
+        for &(name, value) in headers {
+            self.write(name.as_bytes());
+            self.write(b": ");
+            self.write(value.as_bytes());
+            self.write(b"\r\n");
+        }
+        self.write(b"\r\n");
+        self.write(content.as_ref());
+        self.write(b"\r\n");
+    }
+
+    /// Finish the body, returning the serialized body and its payload hash.
+    pub fn finish(mut self) -> (Vec<u8>, Vec<u8>) {
+        let boundary = format!("--{}--\r\n", self.boundary);
+        self.write(boundary.as_bytes());
+        (self.body, self.hasher.finish())
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        self.body.extend_from_slice(data);
+        self.hasher.update(data);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MultipartHasher;
+    use payload::PayloadHasher;
+    use ring::digest::SHA256;
+
+    #[test]
+    fn serializes_parts() {
+        let mut hasher = MultipartHasher::new("multipart/form-data", "XyZzY", &SHA256);
+        hasher.part(&[("Content-Disposition", "form-data; name=\"a\"")], "first");
+        hasher.part(&[("Content-Disposition", "form-data; name=\"b\"; filename=\"b.bin\""),
+                      ("Content-Type", "application/octet-stream")],
+                    vec![0u8, 1, 2]);
+        let (body, _) = hasher.finish();
+        assert_eq!(body,
+                   b"--XyZzY\r\n\
+                     Content-Disposition: form-data; name=\"a\"\r\n\
+                     \r\n\
+                     first\r\n\
+                     --XyZzY\r\n\
+                     Content-Disposition: form-data; name=\"b\"; filename=\"b.bin\"\r\n\
+                     Content-Type: application/octet-stream\r\n\
+                     \r\n\
+                     \x00\x01\x02\r\n\
+                     --XyZzY--\r\n"
+                       .to_vec());
+    }
+
+    #[test]
+    fn hash_matches_body() {
+        let mut hasher = MultipartHasher::new("multipart/form-data", "XyZzY", &SHA256);
+        hasher.part(&[("Content-Disposition", "form-data; name=\"a\"")], "first");
+        let (body, hash) = hasher.finish();
+        assert_eq!(hash, PayloadHasher::hash("multipart/form-data", &SHA256, &body));
+    }
+
+    #[test]
+    fn content_type() {
+        let hasher = MultipartHasher::new("multipart/mixed", "abc", &SHA256);
+        assert_eq!(hasher.content_type(), "multipart/mixed; boundary=abc");
+    }
+}
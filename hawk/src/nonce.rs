@@ -0,0 +1,952 @@
+use clock;
+use error::*;
+use rand;
+use rand::Rng;
+use request::random_string;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use time::{Duration, Timespec};
+
+/// A record of nonces seen recently, used to detect replayed messages.
+///
+/// Implementations must be safe to share between the threads handling requests.
+pub trait NonceStore {
+    /// Record the given nonce for the given id and timestamp.  Returns false if the same
+    /// id and nonce have already been recorded, indicating a replay.
+    fn check_and_record(&self, id: &str, nonce: &str, ts: Timespec) -> bool;
+
+    /// Record the given nonce, as for `check_and_record`, but distinguish a store which could
+    /// not be consulted, so that the caller can apply a `NonceOutagePolicy`.
+    ///
+    /// Stores backed by an external service should implement this, and have
+    /// `check_and_record` return false when the service is unavailable.  The default
+    /// implementation calls `check_and_record`, and never fails.
+    fn try_check_and_record(&self,
+                            id: &str,
+                            nonce: &str,
+                            ts: Timespec)
+                            -> ::std::result::Result<bool, NonceStoreUnavailable> {
+        Ok(self.check_and_record(id, nonce, ts))
+    }
+
+    /// Record the given nonce, as for `check_and_record`, but describe any replay in a
+    /// `ReplayReport`, for logging or alerting.
+    ///
+    /// The default implementation calls `check_and_record`, and so cannot say when the nonce
+    /// was first seen or how often it has been presented.
+    fn check_and_report(&self,
+                        id: &str,
+                        nonce: &str,
+                        ts: Timespec)
+                        -> ::std::result::Result<(), ReplayReport> {
+        if self.check_and_record(id, nonce, ts) {
+            Ok(())
+        } else {
+            Err(ReplayReport {
+                id: id.to_string(),
+                nonce: nonce.to_string(),
+                ts,
+                first_seen: None,
+                attempts: None,
+            })
+        }
+    }
+}
+
+/// A description of a replayed nonce, from `NonceStore::check_and_report`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ReplayReport {
+    id: String,
+    nonce: String,
+    ts: Timespec,
+    first_seen: Option<Timespec>,
+    attempts: Option<u32>,
+}
+
+impl ReplayReport {
+    /// Get the credentials id the nonce was presented with.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Get the replayed nonce.
+    pub fn nonce(&self) -> &str {
+        &self.nonce
+    }
+
+    /// Get the timestamp of the replayed message.
+    pub fn ts(&self) -> Timespec {
+        self.ts
+    }
+
+    /// Get the time, by the store's clock, at which the nonce was first recorded, if the store
+    /// tracks it.
+    pub fn first_seen(&self) -> Option<Timespec> {
+        self.first_seen
+    }
+
+    /// Get the number of times the nonce has been presented, including the first, if the store
+    /// tracks it.  A count well above two suggests a deliberate attack rather than a client
+    /// retrying a request.
+    pub fn attempts(&self) -> Option<u32> {
+        self.attempts
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hawk nonce replayed for id {} (ts {})", self.id, self.ts.sec)?;
+        if let Some(first_seen) = self.first_seen {
+            write!(f, ", first seen at {}", first_seen.sec)?;
+        }
+        if let Some(attempts) = self.attempts {
+            write!(f, ", {} attempts", attempts)?;
+        }
+        Ok(())
+    }
+}
+
+/// The error returned by `NonceStore::try_check_and_record` when the store could not be
+/// consulted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct NonceStoreUnavailable {
+    reason: String,
+}
+
+impl NonceStoreUnavailable {
+    /// Create an error with the given reason, for logging.
+    pub fn new<S: Into<String>>(reason: S) -> NonceStoreUnavailable {
+        NonceStoreUnavailable { reason: reason.into() }
+    }
+
+    /// Get the reason the store could not be consulted.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for NonceStoreUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "nonce store unavailable: {}", self.reason)
+    }
+}
+
+impl error::Error for NonceStoreUnavailable {}
+
+/// A NonceStore which stops consulting a failing store for a while, so that an outage does not
+/// add the store's timeout to every request.
+///
+/// After `failure_threshold` consecutive failures of the inner store's
+/// `try_check_and_record`, the breaker opens: for the next `cooldown`, it fails immediately
+/// without consulting the inner store.  After that, one request is let through as a trial; if
+/// it succeeds the breaker closes, and otherwise it stays open for another `cooldown`.
+pub struct CircuitBreakerNonceStore<S> {
+    inner: S,
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Timespec>,
+}
+
+impl<S: NonceStore> CircuitBreakerNonceStore<S> {
+    /// Wrap the given store in a circuit breaker.
+    pub fn new(inner: S, failure_threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreakerNonceStore {
+            inner,
+            failure_threshold: cmp::max(failure_threshold, 1),
+            cooldown,
+            state: Mutex::new(BreakerState {
+                failures: 0,
+                open_until: None,
+            }),
+        }
+    }
+
+    /// Get the inner store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Determine whether the breaker is open, so that the inner store is not being consulted.
+    pub fn is_open(&self) -> bool {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.open_until.is_some_and(|until| clock::now() < until)
+    }
+}
+
+impl<S: NonceStore> NonceStore for CircuitBreakerNonceStore<S> {
+    fn check_and_record(&self, id: &str, nonce: &str, ts: Timespec) -> bool {
+        self.try_check_and_record(id, nonce, ts).unwrap_or(false)
+    }
+
+    fn try_check_and_record(&self,
+                            id: &str,
+                            nonce: &str,
+                            ts: Timespec)
+                            -> ::std::result::Result<bool, NonceStoreUnavailable> {
+        {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(until) = state.open_until {
+                let now = clock::now();
+                if now < until {
+                    return Err(NonceStoreUnavailable::new("circuit breaker open"));
+                }
+                // let this request through as a trial, holding off others until it completes
+                state.open_until = Some(now + self.cooldown);
+            }
+        }
+
+        let result = self.inner.try_check_and_record(id, nonce, ts);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match result {
+            Ok(_) => {
+                state.failures = 0;
+                state.open_until = None;
+            }
+            Err(_) => {
+                state.failures = state.failures.saturating_add(1);
+                if state.failures >= self.failure_threshold {
+                    state.open_until = Some(clock::now() + self.cooldown);
+                }
+            }
+        }
+        result
+    }
+}
+
+/// The nonces remembered by a store, for carrying replay protection across a restart.
+///
+/// A snapshot is taken with `MemoryNonceStore::snapshot` or `BucketedNonceStore::snapshot`,
+/// and loaded into a new store with its `restore` method.  With the `serde` feature, snapshots
+/// serialize as a sequence of `[id, nonce, ts]` triples, so they can be written to a file on
+/// shutdown and read on startup.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct NonceSnapshot {
+    entries: Vec<(String, String, i64)>,
+}
+
+impl NonceSnapshot {
+    /// Get the number of nonces in the snapshot.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Determine whether the snapshot contains no nonces.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over the id, nonce, and timestamp of the nonces in the snapshot.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, Timespec)> {
+        self.entries.iter().map(|(id, nonce, ts)| (&id[..], &nonce[..], Timespec::new(*ts, 0)))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for NonceSnapshot {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+        where S: ::serde::Serializer
+    {
+        self.entries.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for NonceSnapshot {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<NonceSnapshot, D::Error>
+        where D: ::serde::Deserializer<'de>
+    {
+        Ok(NonceSnapshot { entries: ::serde::Deserialize::deserialize(deserializer)? })
+    }
+}
+
+/// An in-memory NonceStore.
+///
+/// Nonces are remembered for the given window after their timestamp, which should be at least
+/// the timestamp skew allowed during validation; after that, a replayed message is rejected for
+/// its timestamp anyway.  Expired nonces are discarded at most once per window.
+///
+/// The store counts the times each nonce is presented, for its `ReplayReport`s, and can call a
+/// function registered with `on_replay` for every replay, for example to raise an alert.
+pub struct MemoryNonceStore {
+    window: Duration,
+    state: Mutex<MemoryState>,
+    on_replay: Option<Box<ReplayCallback>>,
+}
+
+type ReplayCallback = dyn Fn(&ReplayReport) + Send + Sync;
+
+struct MemoryState {
+    seen: HashMap<(String, String), Seen>,
+    last_pruned: Timespec,
+}
+
+struct Seen {
+    ts: Timespec,
+    first_seen: Timespec,
+    attempts: u32,
+}
+
+impl MemoryNonceStore {
+    /// Create a new, empty store remembering nonces for `window`.
+    pub fn new(window: Duration) -> Self {
+        MemoryNonceStore {
+            window,
+            state: Mutex::new(MemoryState {
+                seen: HashMap::new(),
+                last_pruned: clock::now(),
+            }),
+            on_replay: None,
+        }
+    }
+
+    /// Call the given function with the report of every replay detected, whether by
+    /// `check_and_record` or `check_and_report`.  The function is called after the store's lock
+    /// is released, on the thread which detected the replay.
+    pub fn on_replay<F>(mut self, on_replay: F) -> Self
+        where F: Fn(&ReplayReport) + Send + Sync + 'static
+    {
+        self.on_replay = Some(Box::new(on_replay));
+        self
+    }
+
+    /// Take a snapshot of the nonces remembered, omitting those which have expired.
+    pub fn snapshot(&self) -> NonceSnapshot {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = clock::now();
+        let entries = state.seen
+            .iter()
+            .filter(|&(_, seen)| seen.ts + self.window >= now)
+            .map(|((id, nonce), seen)| (id.clone(), nonce.clone(), seen.ts.sec))
+            .collect();
+        NonceSnapshot { entries }
+    }
+
+    /// Remember the nonces in the given snapshot, in addition to those already remembered.
+    /// Nonces which have since expired are skipped.
+    pub fn restore(&self, snapshot: &NonceSnapshot) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = clock::now();
+        for (id, nonce, ts) in snapshot.iter() {
+            if ts + self.window >= now {
+                state.seen.entry((id.to_string(), nonce.to_string())).or_insert(Seen {
+                    ts,
+                    first_seen: now,
+                    attempts: 1,
+                });
+            }
+        }
+    }
+
+    /// Get the number of nonces currently remembered.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).seen.len()
+    }
+
+    /// Determine whether the store currently remembers no nonces.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl NonceStore for MemoryNonceStore {
+    fn check_and_record(&self, id: &str, nonce: &str, ts: Timespec) -> bool {
+        self.check_and_report(id, nonce, ts).is_ok()
+    }
+
+    fn check_and_report(&self,
+                        id: &str,
+                        nonce: &str,
+                        ts: Timespec)
+                        -> ::std::result::Result<(), ReplayReport> {
+        let report = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+            let now = clock::now();
+            if now - state.last_pruned > self.window {
+                let window = self.window;
+                state.seen.retain(|_, seen| seen.ts + window >= now);
+                state.last_pruned = now;
+            }
+
+            let key = (id.to_string(), nonce.to_string());
+            match state.seen.get_mut(&key) {
+                Some(seen) => {
+                    seen.attempts = seen.attempts.saturating_add(1);
+                    ReplayReport {
+                        id: key.0,
+                        nonce: key.1,
+                        ts,
+                        first_seen: Some(seen.first_seen),
+                        attempts: Some(seen.attempts),
+                    }
+                }
+                None => {
+                    state.seen.insert(key,
+                                      Seen {
+                                          ts,
+                                          first_seen: now,
+                                          attempts: 1,
+                                      });
+                    return Ok(());
+                }
+            }
+        };
+        if let Some(ref on_replay) = self.on_replay {
+            on_replay(&report);
+        }
+        Err(report)
+    }
+}
+
+/// A NonceStore organized as time buckets, with a hard limit on the nonces it remembers.
+///
+/// Nonces are grouped by their timestamp into buckets `window` wide, and a bucket is dropped
+/// whole once every timestamp in it is more than `window` old, so expiry costs nothing per
+/// nonce.  The `window` should be at least the timestamp skew allowed during validation.
+///
+/// If recording a nonce would exceed `max_entries`, the oldest buckets are evicted until it
+/// fits.  This weakens replay protection for the evicted timestamps, so evictions are counted
+/// in the `BucketedNonceStoreStats`, and should be rare with `max_entries` sized for the peak
+/// request rate over twice the window.  If the newest bucket alone is full, the nonce is not
+/// recorded and `check_and_record` returns false, rejecting the request rather than accepting
+/// it unprotected.
+pub struct BucketedNonceStore {
+    width: i64,
+    max_entries: usize,
+    state: Mutex<BucketedState>,
+}
+
+struct BucketedState {
+    buckets: BTreeMap<i64, HashSet<(String, String)>>,
+    stats: BucketedNonceStoreStats,
+}
+
+/// Counters describing a `BucketedNonceStore`, from `BucketedNonceStore::stats`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct BucketedNonceStoreStats {
+    /// The number of nonces currently remembered.
+    pub entries: usize,
+    /// The number of buckets currently held.
+    pub buckets: usize,
+    /// The number of buckets dropped because all of their timestamps expired.
+    pub expired_buckets: u64,
+    /// The number of buckets evicted early to stay within `max_entries`.
+    pub evicted_buckets: u64,
+    /// The number of nonces in buckets evicted early.
+    pub evicted_entries: u64,
+    /// The number of nonces refused because the newest bucket was full.
+    pub refused: u64,
+}
+
+impl BucketedNonceStore {
+    /// Create a new, empty store remembering nonces for `window`, and at most `max_entries`
+    /// nonces in all.
+    pub fn new(window: Duration, max_entries: usize) -> Self {
+        BucketedNonceStore {
+            width: cmp::max(window.num_seconds(), 1),
+            max_entries,
+            state: Mutex::new(BucketedState {
+                buckets: BTreeMap::new(),
+                stats: BucketedNonceStoreStats::default(),
+            }),
+        }
+    }
+
+    /// Get the store's counters.
+    pub fn stats(&self) -> BucketedNonceStoreStats {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).stats
+    }
+
+    /// Take a snapshot of the nonces remembered.  Since nonces are remembered by bucket, this
+    /// may include some which are up to one bucket past expiry.
+    pub fn snapshot(&self) -> NonceSnapshot {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entries = vec![];
+        for (&index, bucket) in &state.buckets {
+            entries.extend(bucket.iter()
+                .map(|(id, nonce)| (id.clone(), nonce.clone(), index * self.width)));
+        }
+        NonceSnapshot { entries }
+    }
+
+    /// Remember the nonces in the given snapshot, in addition to those already remembered,
+    /// subject to the store's `max_entries`.
+    pub fn restore(&self, snapshot: &NonceSnapshot) {
+        for (id, nonce, ts) in snapshot.iter() {
+            self.check_and_record(id, nonce, ts);
+        }
+    }
+}
+
+impl NonceStore for BucketedNonceStore {
+    fn check_and_record(&self, id: &str, nonce: &str, ts: Timespec) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let state = &mut *state;
+
+        // a bucket expires once its last second, plus the window, has passed
+        let now = clock::now().sec;
+        while let Some((&start, _)) = state.buckets.iter().next() {
+            if start * self.width + 2 * self.width > now {
+                break;
+            }
+            let bucket = state.buckets.remove(&start).unwrap_or_default();
+            state.stats.entries -= bucket.len();
+            state.stats.expired_buckets += 1;
+        }
+
+        let key = (id.to_string(), nonce.to_string());
+        if state.buckets.values().any(|bucket| bucket.contains(&key)) {
+            state.stats.buckets = state.buckets.len();
+            return false;
+        }
+
+        let index = ts.sec.div_euclid(self.width);
+        while state.stats.entries >= self.max_entries {
+            let oldest = match state.buckets.keys().next() {
+                Some(&oldest) if oldest < index => oldest,
+                _ => {
+                    state.stats.refused += 1;
+                    state.stats.buckets = state.buckets.len();
+                    return false;
+                }
+            };
+            let bucket = state.buckets.remove(&oldest).unwrap_or_default();
+            state.stats.entries -= bucket.len();
+            state.stats.evicted_buckets += 1;
+            state.stats.evicted_entries += bucket.len() as u64;
+        }
+
+        state.buckets.entry(index).or_default().insert(key);
+        state.stats.entries += 1;
+        state.stats.buckets = state.buckets.len();
+        true
+    }
+}
+
+/// A source of nonces for new headers and signatures.
+pub trait NonceGenerator: fmt::Debug + Send + Sync {
+    /// Generate a new nonce.  Nonces cannot contain `"`.
+    fn generate(&self) -> String;
+}
+
+/// A NonceGenerator producing random nonces: the base64 encoding of ten random bytes.  This is
+/// the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomNonceGenerator;
+
+impl NonceGenerator for RandomNonceGenerator {
+    fn generate(&self) -> String {
+        random_string(10)
+    }
+}
+
+/// The alphabet of nonces generated by the Node Hawk client: the URL-safe base64 alphabet.
+pub const NODE_NONCE_ALPHABET: &str =
+    "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// A NonceGenerator producing random nonces of a fixed length, drawn from a given alphabet.
+/// This is useful for peers which reject long or unusual nonces.  The default generates six
+/// characters from `NODE_NONCE_ALPHABET`, as the Node Hawk client does.
+#[derive(Clone, Debug)]
+pub struct AlphabetNonceGenerator {
+    len: usize,
+    alphabet: Vec<char>,
+}
+
+impl AlphabetNonceGenerator {
+    /// Create a generator producing nonces of `len` characters from `alphabet`.  The alphabet
+    /// must be non-empty printable ASCII, without `"` or `\`.
+    pub fn new(len: usize, alphabet: &str) -> Result<AlphabetNonceGenerator> {
+        if len == 0 {
+            bail!("nonce length must be positive");
+        }
+        if alphabet.is_empty() {
+            bail!("nonce alphabet is empty");
+        }
+        if let Some(c) = alphabet.chars()
+            .find(|&c| !matches!(c, ' '..='~') || c == '"' || c == '\\') {
+            bail!("nonce alphabet contains {:?}", c);
+        }
+        Ok(AlphabetNonceGenerator {
+            len,
+            alphabet: alphabet.chars().collect(),
+        })
+    }
+}
+
+impl Default for AlphabetNonceGenerator {
+    fn default() -> AlphabetNonceGenerator {
+        AlphabetNonceGenerator::new(6, NODE_NONCE_ALPHABET).unwrap()
+    }
+}
+
+impl NonceGenerator for AlphabetNonceGenerator {
+    fn generate(&self) -> String {
+        let mut rng = rand::thread_rng();
+        (0..self.len).map(|_| self.alphabet[rng.gen_range(0, self.alphabet.len())]).collect()
+    }
+}
+
+/// A NonceGenerator producing a predictable sequence of nonces, `<prefix>0`, `<prefix>1`, and so
+/// on.  This is only suitable for tests.
+#[derive(Debug)]
+pub struct SequentialNonceGenerator {
+    prefix: String,
+    next: AtomicU64,
+}
+
+impl SequentialNonceGenerator {
+    /// Create a new generator with the given prefix, starting from 0.
+    pub fn new<S>(prefix: S) -> SequentialNonceGenerator
+        where S: Into<String>
+    {
+        SequentialNonceGenerator {
+            prefix: prefix.into(),
+            next: AtomicU64::new(0),
+        }
+    }
+}
+
+impl NonceGenerator for SequentialNonceGenerator {
+    fn generate(&self) -> String {
+        format!("{}{}", self.prefix, self.next.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// The generator registered with `set_default_nonce_generator`, if any.
+static DEFAULT_GENERATOR: RwLock<Option<Arc<dyn NonceGenerator>>> = RwLock::new(None);
+
+thread_local! {
+    /// A generator overriding the default on this thread; see `testing::deterministic_for_tests`.
+    static THREAD_GENERATOR: RefCell<Option<Arc<dyn NonceGenerator>>> =
+        const { RefCell::new(None) };
+}
+
+/// Register the nonce generator used wherever the crate invents a nonce, such as in
+/// `make_header`, in place of `RandomNonceGenerator`.
+pub fn set_default_nonce_generator(generator: Arc<dyn NonceGenerator>) {
+    *DEFAULT_GENERATOR.write().unwrap_or_else(|e| e.into_inner()) = Some(generator);
+}
+
+/// Remove any generator registered with `set_default_nonce_generator`, reverting to random
+/// nonces.
+pub fn reset_default_nonce_generator() {
+    *DEFAULT_GENERATOR.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Replace the generator overriding the default on this thread, returning the previous one.
+pub(crate) fn replace_thread_generator(generator: Option<Arc<dyn NonceGenerator>>)
+                                       -> Option<Arc<dyn NonceGenerator>> {
+    THREAD_GENERATOR.with(|g| g.replace(generator))
+}
+
+/// Generate a nonce with the default generator.
+pub(crate) fn generate() -> String {
+    if let Some(nonce) = THREAD_GENERATOR.with(|g| g.borrow().as_ref().map(|g| g.generate())) {
+        return nonce;
+    }
+    let registered = DEFAULT_GENERATOR.read().unwrap_or_else(|e| e.into_inner());
+    match *registered {
+        Some(ref generator) => generator.generate(),
+        None => random_string(10),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BucketedNonceStore, BucketedNonceStoreStats, CircuitBreakerNonceStore,
+                MemoryNonceStore, NonceGenerator, NonceStore, NonceStoreUnavailable,
+                RandomNonceGenerator, ReplayReport, SequentialNonceGenerator};
+    use super::{AlphabetNonceGenerator, NODE_NONCE_ALPHABET};
+    use std::cell::Cell;
+    use std::sync::{Arc, Mutex};
+    use testing::Deterministic;
+    use time::{self, Duration, Timespec};
+
+    #[test]
+    fn detects_replay() {
+        let store = MemoryNonceStore::new(Duration::minutes(1));
+        let now = time::now().to_timespec();
+        assert!(store.check_and_record("me", "abc", now));
+        assert!(!store.check_and_record("me", "abc", now));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn reports_replay() {
+        let _deterministic = Deterministic::at(Timespec::new(1000, 0));
+        let reports = Arc::new(Mutex::new(vec![]));
+        let store = {
+            let reports = reports.clone();
+            MemoryNonceStore::new(Duration::minutes(1))
+                .on_replay(move |report| reports.lock().unwrap().push(report.clone()))
+        };
+        let ts = Timespec::new(990, 0);
+        assert_eq!(store.check_and_report("me", "abc", ts), Ok(()));
+        assert!(!store.check_and_record("me", "abc", ts));
+        let report = store.check_and_report("me", "abc", ts).unwrap_err();
+        assert_eq!(report.id(), "me");
+        assert_eq!(report.nonce(), "abc");
+        assert_eq!(report.ts(), ts);
+        assert_eq!(report.first_seen(), Some(Timespec::new(1000, 0)));
+        assert_eq!(report.attempts(), Some(3));
+        assert_eq!(report.to_string(),
+                   "Hawk nonce replayed for id me (ts 990), first seen at 1000, 3 attempts");
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].attempts(), Some(2));
+        assert_eq!(reports[1], report);
+    }
+
+    #[test]
+    fn default_report() {
+        struct Replaying;
+        impl NonceStore for Replaying {
+            fn check_and_record(&self, _: &str, _: &str, _: Timespec) -> bool {
+                false
+            }
+        }
+        let report: ReplayReport = Replaying.check_and_report("me", "abc", Timespec::new(5, 0))
+            .unwrap_err();
+        assert_eq!(report.first_seen(), None);
+        assert_eq!(report.attempts(), None);
+        assert_eq!(report.to_string(), "Hawk nonce replayed for id me (ts 5)");
+    }
+
+    #[test]
+    fn separates_ids() {
+        let store = MemoryNonceStore::new(Duration::minutes(1));
+        let now = time::now().to_timespec();
+        assert!(store.check_and_record("me", "abc", now));
+        assert!(store.check_and_record("you", "abc", now));
+        assert!(store.check_and_record("me", "def", now));
+        assert_eq!(store.len(), 3);
+    }
+
+    #[test]
+    fn prunes_expired() {
+        let store = MemoryNonceStore::new(Duration::seconds(0));
+        let old = time::now().to_timespec() - Duration::minutes(10);
+        assert!(store.check_and_record("me", "abc", old));
+        ::std::thread::sleep(::std::time::Duration::from_millis(10));
+        assert!(store.check_and_record("me", "def", time::now().to_timespec()));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn bucketed_detects_replay() {
+        let _deterministic = Deterministic::at(Timespec::new(1000, 0));
+        let store = BucketedNonceStore::new(Duration::minutes(1), 100);
+        let ts = Timespec::new(1000, 0);
+        assert!(store.check_and_record("me", "abc", ts));
+        assert!(!store.check_and_record("me", "abc", ts));
+        assert!(!store.check_and_record("me", "abc", Timespec::new(950, 0)));
+        assert!(store.check_and_record("you", "abc", ts));
+        assert!(store.check_and_record("me", "def", Timespec::new(950, 0)));
+        assert_eq!(store.stats(),
+                   BucketedNonceStoreStats {
+                       entries: 3,
+                       buckets: 2,
+                       ..BucketedNonceStoreStats::default()
+                   });
+    }
+
+    #[test]
+    fn bucketed_expires_buckets() {
+        let store = BucketedNonceStore::new(Duration::seconds(10), 100);
+        {
+            let _deterministic = Deterministic::at(Timespec::new(1000, 0));
+            assert!(store.check_and_record("me", "a", Timespec::new(995, 0)));
+            assert!(store.check_and_record("me", "b", Timespec::new(1005, 0)));
+        }
+        {
+            // [990, 1000) is still within the window of 1005
+            let _deterministic = Deterministic::at(Timespec::new(1005, 0));
+            assert!(!store.check_and_record("me", "a", Timespec::new(995, 0)));
+        }
+        let _deterministic = Deterministic::at(Timespec::new(1010, 0));
+        assert!(store.check_and_record("me", "c", Timespec::new(1010, 0)));
+        let stats = store.stats();
+        assert_eq!((stats.entries, stats.buckets, stats.expired_buckets), (2, 2, 1));
+    }
+
+    #[test]
+    fn bucketed_cap() {
+        let _deterministic = Deterministic::at(Timespec::new(1000, 0));
+        let store = BucketedNonceStore::new(Duration::seconds(10), 2);
+        assert!(store.check_and_record("me", "a", Timespec::new(990, 0)));
+        assert!(store.check_and_record("me", "b", Timespec::new(1000, 0)));
+        // evicts the oldest bucket
+        assert!(store.check_and_record("me", "c", Timespec::new(1000, 0)));
+        // the newest bucket is full
+        assert!(!store.check_and_record("me", "d", Timespec::new(1000, 0)));
+        assert_eq!(store.stats(),
+                   BucketedNonceStoreStats {
+                       entries: 2,
+                       buckets: 1,
+                       expired_buckets: 0,
+                       evicted_buckets: 1,
+                       evicted_entries: 1,
+                       refused: 1,
+                   });
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let _deterministic = Deterministic::at(Timespec::new(1000, 0));
+        let store = MemoryNonceStore::new(Duration::seconds(10));
+        assert!(store.check_and_record("me", "old", Timespec::new(900, 0)));
+        assert!(store.check_and_record("me", "abc", Timespec::new(995, 0)));
+        let snapshot = store.snapshot();
+        assert_eq!(snapshot.iter().collect::<Vec<_>>(),
+                   vec![("me", "abc", Timespec::new(995, 0))]);
+
+        let restarted = MemoryNonceStore::new(Duration::seconds(10));
+        restarted.restore(&snapshot);
+        assert!(!restarted.check_and_record("me", "abc", Timespec::new(995, 0)));
+        assert_eq!(restarted.len(), 1);
+
+        // expired nonces are skipped on restore
+        let _later = Deterministic::at(Timespec::new(1010, 0));
+        let late = MemoryNonceStore::new(Duration::seconds(10));
+        late.restore(&snapshot);
+        assert!(late.is_empty());
+    }
+
+    #[test]
+    fn bucketed_snapshot_restore() {
+        let _deterministic = Deterministic::at(Timespec::new(1000, 0));
+        let store = BucketedNonceStore::new(Duration::seconds(10), 100);
+        assert!(store.check_and_record("me", "abc", Timespec::new(995, 0)));
+        let snapshot = store.snapshot();
+        assert_eq!(snapshot.len(), 1);
+
+        let restarted = BucketedNonceStore::new(Duration::seconds(10), 100);
+        restarted.restore(&snapshot);
+        assert!(!restarted.check_and_record("me", "abc", Timespec::new(995, 0)));
+
+        // snapshots are interchangeable between stores
+        let memory = MemoryNonceStore::new(Duration::seconds(10));
+        memory.restore(&snapshot);
+        assert!(!memory.check_and_record("me", "abc", Timespec::new(995, 0)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshot_serde() {
+        use super::NonceSnapshot;
+        let _deterministic = Deterministic::at(Timespec::new(1000, 0));
+        let store = MemoryNonceStore::new(Duration::seconds(10));
+        assert!(store.check_and_record("me", "abc", Timespec::new(995, 0)));
+        let json = ::serde_json::to_string(&store.snapshot()).unwrap();
+        assert_eq!(json, r#"[["me","abc",995]]"#);
+        let snapshot: NonceSnapshot = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(snapshot, store.snapshot());
+        assert!(::serde_json::from_str::<NonceSnapshot>(r#"[["me", 995]]"#).is_err());
+    }
+
+    /// A store which fails while `down` is set, counting the calls it receives.
+    struct FlakyStore {
+        down: Cell<bool>,
+        calls: Cell<u32>,
+    }
+
+    impl NonceStore for FlakyStore {
+        fn check_and_record(&self, id: &str, nonce: &str, ts: Timespec) -> bool {
+            self.try_check_and_record(id, nonce, ts).unwrap_or(false)
+        }
+
+        fn try_check_and_record(&self,
+                                _: &str,
+                                _: &str,
+                                _: Timespec)
+                                -> Result<bool, NonceStoreUnavailable> {
+            self.calls.set(self.calls.get() + 1);
+            if self.down.get() {
+                Err(NonceStoreUnavailable::new("connection refused"))
+            } else {
+                Ok(true)
+            }
+        }
+    }
+
+    #[test]
+    fn circuit_breaker() {
+        let ts = Timespec::new(1000, 0);
+        let store = CircuitBreakerNonceStore::new(FlakyStore {
+                                                      down: Cell::new(true),
+                                                      calls: Cell::new(0),
+                                                  },
+                                                  2,
+                                                  Duration::seconds(30));
+        let _deterministic = Deterministic::at(ts);
+        let err = store.try_check_and_record("me", "a", ts).unwrap_err();
+        assert_eq!(err.to_string(), "nonce store unavailable: connection refused");
+        assert!(!store.is_open());
+        assert!(!store.check_and_record("me", "b", ts));
+        assert!(store.is_open());
+
+        // while open, the inner store is not consulted
+        assert_eq!(store.try_check_and_record("me", "c", ts).unwrap_err().reason(),
+                   "circuit breaker open");
+        assert_eq!(store.inner().calls.get(), 2);
+
+        // after the cooldown, a failed trial keeps the breaker open
+        {
+            let _later = Deterministic::at(Timespec::new(1030, 0));
+            assert!(store.try_check_and_record("me", "d", ts).is_err());
+            assert_eq!(store.inner().calls.get(), 3);
+            assert!(store.is_open());
+        }
+
+        // and a successful trial closes it
+        store.inner().down.set(false);
+        let _later = Deterministic::at(Timespec::new(1060, 0));
+        assert_eq!(store.try_check_and_record("me", "e", ts), Ok(true));
+        assert!(!store.is_open());
+        assert_eq!(store.try_check_and_record("me", "f", ts), Ok(true));
+        assert_eq!(store.inner().calls.get(), 5);
+    }
+
+    #[test]
+    fn random_nonces() {
+        let nonce = RandomNonceGenerator.generate();
+        assert_eq!(nonce.len(), 16);
+        assert_ne!(nonce, RandomNonceGenerator.generate());
+    }
+
+    #[test]
+    fn alphabet_nonces() {
+        let nonce = AlphabetNonceGenerator::default().generate();
+        assert_eq!(nonce.len(), 6);
+        assert!(nonce.chars().all(|c| NODE_NONCE_ALPHABET.contains(c)));
+
+        let generator = AlphabetNonceGenerator::new(20, "ab").unwrap();
+        let nonce = generator.generate();
+        assert_eq!(nonce.len(), 20);
+        assert!(nonce.chars().all(|c| c == 'a' || c == 'b'));
+
+        assert!(AlphabetNonceGenerator::new(0, "ab").is_err());
+        assert!(AlphabetNonceGenerator::new(6, "").is_err());
+        assert!(AlphabetNonceGenerator::new(6, "ab\"").is_err());
+        assert!(AlphabetNonceGenerator::new(6, "ab\u{e9}").is_err());
+    }
+
+    #[test]
+    fn sequential_nonces() {
+        let generator = SequentialNonceGenerator::new("n");
+        assert_eq!(generator.generate(), "n0");
+        assert_eq!(generator.generate(), "n1");
+    }
+}
@@ -0,0 +1,197 @@
+use std::borrow::Cow;
+
+/// The treatment of percent-encoding in a request path before signing.
+///
+/// Both client and server must use the same policy for a request to validate.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PathEncoding {
+    /// Sign the path exactly as given.  This is the default, and matches the Node
+    /// implementation, which signs the request target exactly as it appears on the wire.
+    #[default]
+    AsIs,
+    /// Sign a canonical percent-encoded form of the path, following RFC 3986: characters
+    /// outside the URI character set (such as spaces and non-ASCII characters) are encoded,
+    /// encoded unreserved characters are decoded, and escapes use upper-case hex digits.
+    /// Reserved characters, encoded or not, are unchanged, as are the path's query and
+    /// separators.
+    ///
+    /// Sending the canonical path on the wire keeps requests interoperable with peers which
+    /// sign the path as given.
+    Canonical,
+}
+
+impl PathEncoding {
+    /// Apply this policy to the given path.
+    pub fn apply<'a>(&self, path: Cow<'a, str>) -> Cow<'a, str> {
+        match *self {
+            PathEncoding::AsIs => path,
+            PathEncoding::Canonical => {
+                if is_canonical(&path) {
+                    path
+                } else {
+                    Cow::Owned(canonicalize(&path))
+                }
+            }
+        }
+    }
+}
+
+fn is_unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~')
+}
+
+fn is_reserved(b: u8) -> bool {
+    matches!(b,
+             b':' | b'/' | b'?' | b'#' | b'[' | b']' | b'@' | b'!' | b'$' | b'&' | b'\'' |
+             b'(' | b')' | b'*' | b'+' | b',' | b';' | b'=')
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decode the percent-escape at the start of `bytes`, if there is one.
+fn escape_at(bytes: &[u8]) -> Option<u8> {
+    if bytes.len() >= 3 && bytes[0] == b'%' {
+        Some(hex_value(bytes[1])? << 4 | hex_value(bytes[2])?)
+    } else {
+        None
+    }
+}
+
+fn is_canonical(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b == b'%' {
+            match escape_at(&bytes[i..]) {
+                Some(decoded) if !is_unreserved(decoded) &&
+                                 !bytes[i + 1..i + 3].iter().any(u8::is_ascii_lowercase) => {
+                    i += 3;
+                }
+                _ => return false,
+            }
+        } else if is_unreserved(b) || is_reserved(b) {
+            i += 1;
+        } else {
+            return false;
+        }
+    }
+    true
+}
+
+fn canonicalize(path: &str) -> String {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    let bytes = path.as_bytes();
+    let mut result = String::with_capacity(bytes.len() + 8);
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        let (byte, escaped) = match escape_at(&bytes[i..]) {
+            Some(decoded) => {
+                i += 3;
+                (decoded, true)
+            }
+            None => {
+                i += 1;
+                (b, false)
+            }
+        };
+        if is_unreserved(byte) || (!escaped && is_reserved(byte)) {
+            result.push(byte as char);
+        } else {
+            result.push('%');
+            result.push(HEX[(byte >> 4) as usize] as char);
+            result.push(HEX[(byte & 0xf) as usize] as char);
+        }
+    }
+    result
+}
+
+/// Determine whether the given path (without its query) might resolve outside the prefix it
+/// appears to lie under, once a server decodes or normalizes it: that is, whether it has `.`
+/// or `..` segments, even percent-encoded, or contains a backslash or an encoded `/` or `\`,
+/// which some servers treat as separators.
+pub(crate) fn may_traverse(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    if path.contains('\\') || path.contains("%2f") || path.contains("%5c") {
+        return true;
+    }
+    path.split('/').any(|segment| {
+        let segment = segment.replace("%2e", ".");
+        segment == "." || segment == ".."
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{may_traverse, PathEncoding};
+    use std::borrow::Cow;
+
+    #[test]
+    fn traversal() {
+        assert!(!may_traverse("/downloads/a.b/c..d/.e"));
+        assert!(!may_traverse("/downloads/a%20b"));
+        for path in &["/downloads/../secret",
+                      "/downloads/./secret",
+                      "/downloads/%2E%2e/secret",
+                      "/downloads/.%2E",
+                      "/downloads/..%2fsecret",
+                      "/downloads/..%2Fsecret",
+                      "/downloads/..%5csecret",
+                      "/downloads/..%5Csecret",
+                      "/downloads/..\\secret"] {
+            assert!(may_traverse(path), "{}", path);
+        }
+    }
+
+    fn canonical(path: &str) -> String {
+        PathEncoding::Canonical.apply(Cow::Borrowed(path)).into_owned()
+    }
+
+    #[test]
+    fn as_is() {
+        assert_eq!(PathEncoding::AsIs.apply(Cow::Borrowed("/a b/%7e")), "/a b/%7e");
+    }
+
+    #[test]
+    fn canonical_unchanged() {
+        for path in &["/", "/a/b/c", "/a%2Fb", "/x?y=1&z=%20", "/~user/a-b_c.d", "/a+b;c=d@e:f"] {
+            assert_eq!(&canonical(path), path);
+            assert!(matches!(PathEncoding::Canonical.apply(Cow::Borrowed(path)), Cow::Borrowed(_)));
+        }
+    }
+
+    #[test]
+    fn canonical_encodes() {
+        assert_eq!(canonical("/with space"), "/with%20space");
+        assert_eq!(canonical("/caf\u{e9}"), "/caf%C3%A9");
+        assert_eq!(canonical("/a\"b<c>"), "/a%22b%3Cc%3E");
+        assert_eq!(canonical("/100%"), "/100%25");
+        assert_eq!(canonical("/%zz"), "/%25zz");
+    }
+
+    #[test]
+    fn canonical_escapes() {
+        // unreserved characters are decoded
+        assert_eq!(canonical("/%7Euser/%41"), "/~user/A");
+        // reserved characters stay encoded, with upper-case hex
+        assert_eq!(canonical("/a%2fb"), "/a%2Fb");
+        assert_eq!(canonical("/a%2Fb"), "/a%2Fb");
+        assert_eq!(canonical("/%c3%a9"), "/%C3%A9");
+    }
+
+    #[test]
+    fn canonical_idempotent() {
+        for path in &["/with space", "/caf\u{e9}", "/%7e%2f%zz%", "/a b?c=d e"] {
+            let once = canonical(path);
+            assert_eq!(canonical(&once), once);
+        }
+    }
+}
@@ -1,7 +1,22 @@
 use ring::digest;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, ErrorKind, IoSlice, Read, Write};
+use std::mem;
+use std::path::Path;
+use error::*;
+use hawk_core::Header;
+use crypto;
+
+/// Size of the chunks in which files are read by `PayloadHasher::hash_file`.
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
 
 /// A utility for hashing payloads. Feed your entity body to this, then pass the `finish`
 /// result to a request or response.
+///
+/// A PayloadHasher is `Send`, so servers hashing many payloads can keep hashers in a pool and
+/// reuse them with `reset` or `finish_reset`.
 pub struct PayloadHasher {
     context: digest::Context,
     algorithm: &'static digest::Algorithm,
@@ -18,12 +33,19 @@ impl PayloadHasher {
             context: digest::Context::new(algorithm),
             algorithm: algorithm,
         };
-        hasher.update(b"hawk.1.payload\n");
-        hasher.update(content_type.as_ref());
-        hasher.update(b"\n");
+        hasher.update_prefix(content_type);
         hasher
     }
 
+    /// Reset this PayloadHasher to hash a new payload with the given content type, discarding
+    /// any data hashed so far.  The digest algorithm is unchanged.
+    pub fn reset<B>(&mut self, content_type: B)
+        where B: AsRef<[u8]>
+    {
+        self.context = digest::Context::new(self.algorithm);
+        self.update_prefix(content_type);
+    }
+
     /// Hash a single value and return it
     pub fn hash<B1, B2>(content_type: B1,
                         algorithm: &'static digest::Algorithm,
@@ -37,6 +59,31 @@ impl PayloadHasher {
         hasher.finish()
     }
 
+    /// Hash the contents of a file and return the result.
+    ///
+    /// The file is read in large chunks, so it is never held in memory all at once.  This is
+    /// useful for hashing large uploads.
+    pub fn hash_file<B, P>(content_type: B,
+                           algorithm: &'static digest::Algorithm,
+                           path: P)
+                           -> Result<Vec<u8>>
+        where B: AsRef<[u8]>,
+              P: AsRef<Path>
+    {
+        let mut file = File::open(path)?;
+        let mut hasher = PayloadHasher::new(content_type, algorithm);
+        let mut buf = vec![0u8; FILE_CHUNK_SIZE];
+        loop {
+            match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => hasher.update(&buf[..n]),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(hasher.finish())
+    }
+
     /// Update the hash with new data.
     pub fn update<B>(&mut self, data: B)
         where B: AsRef<[u8]>
@@ -44,22 +91,118 @@ impl PayloadHasher {
         self.context.update(data.as_ref());
     }
 
+    /// Update the hash with several slices of data, in order, as if they had been concatenated.
+    /// This avoids copying a body which is already split into chunks into a single buffer.
+    pub fn update_vectored(&mut self, slices: &[IoSlice<'_>]) {
+        for slice in slices {
+            self.context.update(slice);
+        }
+    }
+
     /// Finish hashing and return the result
     ///
     /// Note that this appends a newline to the payload, as does the JS Hawk implementaiton.
     pub fn finish(mut self) -> Vec<u8> {
+        self.finish_digest()
+    }
+
+    /// Finish hashing and return the result, then reset this PayloadHasher to hash a new
+    /// payload with the given content type.
+    pub fn finish_reset<B>(&mut self, content_type: B) -> Vec<u8>
+        where B: AsRef<[u8]>
+    {
+        let rv = self.finish_digest();
+        self.update_prefix(content_type);
+        rv
+    }
+
+    fn update_prefix<B>(&mut self, content_type: B)
+        where B: AsRef<[u8]>
+    {
+        self.update(b"hawk.1.payload\n");
+        self.update(content_type.as_ref());
         self.update(b"\n");
-        let digest = self.context.finish();
+    }
+
+    /// Finish the current digest, leaving a fresh context in its place.
+    fn finish_digest(&mut self) -> Vec<u8> {
+        self.update(b"\n");
+        let context = mem::replace(&mut self.context, digest::Context::new(self.algorithm));
+        let digest = context.finish();
         let mut rv = vec![0; self.algorithm.output_len];
         rv.clone_from_slice(digest.as_ref());
         rv
     }
 }
 
+/// Writing to a PayloadHasher updates the hash, so a body can be hashed with `io::copy`.
+/// Writes never fail.
+impl Write for PayloadHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.update_vectored(bufs);
+        Ok(bufs.iter().map(|buf| buf.len()).sum())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The ways in which payload verification can fail.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PayloadError {
+    /// The header does not contain a `hash` field.
+    MissingHash,
+    /// The header's `hash` does not match the payload.
+    HashMismatch,
+}
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PayloadError::MissingHash => f.write_str("Hawk header has no payload hash"),
+            PayloadError::HashMismatch => f.write_str("Hawk payload hash does not match payload"),
+        }
+    }
+}
+
+impl error::Error for PayloadError {}
+
+/// Verify that the `hash` in the given header matches the given payload.
+///
+/// The hash is calculated from the body with the given content type and algorithm, and compared
+/// in constant time.  A header without a hash results in `PayloadError::MissingHash`, allowing
+/// callers to decide whether to require hashes on a particular route.
+pub fn verify_payload<B1, B2>(header: &Header,
+                              content_type: B1,
+                              algorithm: &'static digest::Algorithm,
+                              body: B2)
+                              -> ::std::result::Result<(), PayloadError>
+    where B1: AsRef<[u8]>,
+          B2: AsRef<[u8]>
+{
+    let header_hash = header.hash().ok_or(PayloadError::MissingHash)?;
+    let hash = PayloadHasher::hash(content_type, algorithm, body);
+    if crypto::ct_eq(&hash, header_hash) {
+        Ok(())
+    } else {
+        Err(PayloadError::HashMismatch)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::PayloadHasher;
+    use super::{verify_payload, PayloadError, PayloadHasher, FILE_CHUNK_SIZE};
+    use hawk_core::Header;
     use ring::digest::SHA256;
+    use std::env;
+    use std::fs;
+    use std::io::{self, IoSlice, Write};
 
     #[test]
     fn hash_consistency() {
@@ -85,4 +228,95 @@ mod tests {
         assert_eq!(hash3, hash1);
         assert_eq!(hash4, hash1);
     }
+
+    #[test]
+    fn vectored() {
+        let expected = PayloadHasher::hash("text/plain", &SHA256, "pàyload");
+
+        let mut hasher = PayloadHasher::new("text/plain", &SHA256);
+        hasher.update_vectored(&[IoSlice::new("pà".as_bytes()), IoSlice::new(b"yload")]);
+        assert_eq!(hasher.finish(), expected);
+
+        let mut hasher = PayloadHasher::new("text/plain", &SHA256);
+        let slices = [IoSlice::new(b"p"), IoSlice::new("àyl".as_bytes()), IoSlice::new(b"oad")];
+        assert_eq!(hasher.write_vectored(&slices).unwrap(), 8);
+        assert_eq!(hasher.finish(), expected);
+
+        let mut hasher = PayloadHasher::new("text/plain", &SHA256);
+        io::copy(&mut "pàyload".as_bytes(), &mut hasher).unwrap();
+        assert_eq!(hasher.finish(), expected);
+    }
+
+    #[test]
+    fn reset() {
+        let mut hasher = PayloadHasher::new("application/json", &SHA256);
+        hasher.update("discarded");
+        hasher.reset("text/plain");
+        hasher.update("pàyload");
+        assert_eq!(hasher.finish(),
+                   PayloadHasher::hash("text/plain", &SHA256, "pàyload"));
+    }
+
+    #[test]
+    fn finish_reset() {
+        let mut hasher = PayloadHasher::new("text/plain", &SHA256);
+        hasher.update("pàyload");
+        assert_eq!(hasher.finish_reset("application/json"),
+                   PayloadHasher::hash("text/plain", &SHA256, "pàyload"));
+        hasher.update("{}");
+        assert_eq!(hasher.finish_reset("text/plain"),
+                   PayloadHasher::hash("application/json", &SHA256, "{}"));
+    }
+
+    #[test]
+    fn hash_file() {
+        // use a payload spanning several chunks
+        let payload: Vec<u8> = (0..FILE_CHUNK_SIZE * 3 + 17).map(|i| i as u8).collect();
+        let path = env::temp_dir().join("hawk-payload-hash-file-test");
+        fs::write(&path, &payload).unwrap();
+        let hash = PayloadHasher::hash_file("application/octet-stream", &SHA256, &path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(hash.unwrap(),
+                   PayloadHasher::hash("application/octet-stream", &SHA256, &payload));
+    }
+
+    #[test]
+    fn hash_file_missing() {
+        let path = env::temp_dir().join("hawk-payload-hash-file-does-not-exist");
+        assert!(PayloadHasher::hash_file("text/plain", &SHA256, &path).is_err());
+    }
+
+    fn header_with_hash(hash: Option<Vec<u8>>) -> Header {
+        Header::new::<String>(None, None, None, None, None, hash, None, None).unwrap()
+    }
+
+    #[test]
+    fn verify_payload_matches() {
+        let hash = PayloadHasher::hash("text/plain", &SHA256, "pàyload");
+        let header = header_with_hash(Some(hash));
+        assert_eq!(verify_payload(&header, "text/plain", &SHA256, "pàyload"), Ok(()));
+    }
+
+    #[test]
+    fn verify_payload_mismatch() {
+        let hash = PayloadHasher::hash("text/plain", &SHA256, "pàyload");
+        let header = header_with_hash(Some(hash));
+        assert_eq!(verify_payload(&header, "text/plain", &SHA256, "other"),
+                   Err(PayloadError::HashMismatch));
+        assert_eq!(verify_payload(&header, "application/json", &SHA256, "pàyload"),
+                   Err(PayloadError::HashMismatch));
+    }
+
+    #[test]
+    fn verify_payload_missing_hash() {
+        let header = header_with_hash(None);
+        assert_eq!(verify_payload(&header, "text/plain", &SHA256, "pàyload"),
+                   Err(PayloadError::MissingHash));
+    }
+
+    #[test]
+    fn is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<PayloadHasher>();
+    }
 }
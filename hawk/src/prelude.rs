@@ -0,0 +1,37 @@
+//! The most commonly used types, for glob import.
+//!
+//! ```
+//! use hawk::prelude::*;
+//!
+//! let credentials = Credentials {
+//!     id: "me".to_string(),
+//!     key: Key::new(vec![99u8; 32], &SHA256),
+//! };
+//! let request = RequestBuilder::new("GET", "example.com", 443, "/").request();
+//! let header = request.make_header(&credentials).unwrap();
+//! assert!(request.authenticate_header(&header, &credentials.key, &ValidationOptions::default())
+//!     .is_some());
+//! ```
+//!
+//! # Stability
+//!
+//! Everything exported here is part of the crate's stable API: it will not be removed or
+//! renamed, nor will its meaning change, without a major version bump.  New items may be added
+//! in minor releases, so to avoid conflicts with a glob import they are given names specific to
+//! Hawk; for the same reason, the crate's `Error` and `Result` are exported as `HawkError` and
+//! `HawkResult`.  Items outside the prelude, particularly in the `nonce`, `refresh`, `testing`,
+//! and feature-gated modules, are still evolving.
+
+pub use artifacts::Artifacts;
+pub use authenticator::Authenticator;
+pub use hawk_core::Bewit;
+pub use credentials::{Credentials, CredentialsProvider, Key};
+pub use error::{Error as HawkError, Result as HawkResult};
+pub use hawk_core::Header;
+pub use header_options::HeaderOptions;
+pub use payload::PayloadHasher;
+pub use rejected::RejectionReason;
+pub use request::{Request, RequestBuilder};
+pub use response::{Response, ResponseBuilder};
+pub use validation::{PayloadPolicy, ValidationOptions};
+pub use ring::digest::{SHA256, SHA384, SHA512};
@@ -0,0 +1,214 @@
+//! Refreshing bewits for long-lived streams.
+//!
+//! Clients which cannot set headers, such as browser `EventSource` clients of a Server-Sent
+//! Events stream, authenticate with bewits.  A long-lived stream outlives its bewit, so before
+//! the bewit expires the server mints a "refresh" bewit for the same path and sends it down the
+//! stream; the client uses it when it next reconnects.
+//!
+//! A refresh bewit's `ext` carries a continuation token chosen by the server (for example, a
+//! stream id or the last event id) and the MAC of the bewit it replaces, tying the two together.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate time;
+//! extern crate hawk;
+//!
+//! use hawk::{Credentials, Key, RequestBuilder, ValidationOptions, SHA256};
+//! use hawk::refresh;
+//!
+//! fn main() {
+//!     let credentials = Credentials {
+//!         id: "me".to_string(),
+//!         key: Key::new(vec![99u8; 32], &SHA256),
+//!     };
+//!     let request = RequestBuilder::new("GET", "example.com", 443, "/events").request();
+//!     let options = ValidationOptions::default();
+//!     let bewit = request.make_bewit(&credentials, time::Duration::minutes(5)).unwrap();
+//!
+//!     // while streaming, mint a refresh bewit and send it to the client
+//!     let next = refresh::make_refresh_bewit(&request, &bewit, &credentials,
+//!                                            time::Duration::minutes(5), "event-42",
+//!                                            &options).unwrap();
+//!
+//!     // on reconnect, validate it and recover the continuation token
+//!     let continuation = refresh::validate_refresh_bewit(&request, &next, &credentials.key,
+//!                                                        &options).unwrap();
+//!     assert_eq!(continuation.token(), "event-42");
+//!     assert!(continuation.follows(&bewit));
+//! }
+//! ```
+
+use base64;
+use hawk_core::{Bewit, BewitError};
+use credentials::{Credentials, Key};
+use crypto;
+use error::*;
+use mac::Mac;
+use request::Request;
+use time::Duration;
+use validation::ValidationOptions;
+
+/// The prefix of the `ext` value of a refresh bewit.
+const REFRESH_EXT_PREFIX: &str = "hawk-refresh:";
+
+/// The continuation information carried by a refresh bewit.
+#[derive(Clone, Debug)]
+pub struct Continuation {
+    token: String,
+    previous_mac: Mac,
+}
+
+impl Continuation {
+    /// Get the continuation token given when the bewit was minted.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Get the MAC of the bewit which this bewit refreshed.
+    pub fn previous_mac(&self) -> &Mac {
+        &self.previous_mac
+    }
+
+    /// Determine whether this bewit was minted to refresh the given bewit.
+    pub fn follows(&self, previous: &Bewit) -> bool {
+        crypto::ct_eq(previous.mac(), &self.previous_mac)
+    }
+}
+
+/// Mint a refresh bewit for the given request, replacing `previous`, valid for `ttl` from now.
+///
+/// The previous bewit must itself be valid for the request, according to `options`.  The token
+/// must not contain `\` or `:`.
+pub fn make_refresh_bewit(request: &Request,
+                          previous: &Bewit,
+                          credentials: &Credentials,
+                          ttl: Duration,
+                          token: &str,
+                          options: &ValidationOptions)
+                          -> Result<Bewit<'static>> {
+    if token.contains(['\\', ':']) {
+        bail!("Refresh token cannot contain `\\` or `:`");
+    }
+    request.verify_bewit(previous, &credentials.key, options)
+        .chain_err(|| "Cannot refresh an invalid bewit")?;
+
+    let ext = format!("{}{}:{}",
+                      REFRESH_EXT_PREFIX,
+                      token,
+                      base64::encode_config(previous.mac(), base64::URL_SAFE_NO_PAD));
+    let request = request.with_ext(Some(&ext));
+    Ok(request.make_bewit(credentials, ttl)?.into_owned())
+}
+
+/// Validate a refresh bewit for the given request, returning its continuation information.
+///
+/// A bewit which is otherwise valid, but was not minted by `make_refresh_bewit`, fails with
+/// `BewitError::BadExt`.
+pub fn validate_refresh_bewit(request: &Request,
+                              bewit: &Bewit,
+                              key: &Key,
+                              options: &ValidationOptions)
+                              -> ::std::result::Result<Continuation, BewitError> {
+    request.verify_bewit(bewit, key, options)?;
+
+    let ext = bewit.ext().ok_or(BewitError::BadExt)?;
+    if !ext.starts_with(REFRESH_EXT_PREFIX) {
+        return Err(BewitError::BadExt);
+    }
+    let (token, previous_mac) = ext[REFRESH_EXT_PREFIX.len()..]
+        .split_once(':')
+        .ok_or(BewitError::BadExt)?;
+    let previous_mac = base64::decode_config(previous_mac, base64::URL_SAFE_NO_PAD)
+        .map_err(|_| BewitError::BadExt)?;
+    Ok(Continuation {
+        token: token.to_string(),
+        previous_mac: Mac::from(previous_mac),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use request::RequestBuilder;
+    use std::str::FromStr;
+    use testing::test_credentials;
+
+    #[test]
+    fn round_trip() {
+        let credentials = test_credentials();
+        let request = RequestBuilder::new("GET", "example.com", 443, "/events").request();
+        let options = ValidationOptions::default();
+        let first = request.make_bewit(&credentials, Duration::minutes(5)).unwrap();
+
+        let second = make_refresh_bewit(&request, &first, &credentials, Duration::minutes(5),
+                                        "stream-1", &options)
+            .unwrap();
+        // it survives encoding in a URL
+        let second = Bewit::from_str(&second.to_str()).unwrap();
+        let continuation = validate_refresh_bewit(&request, &second, &credentials.key, &options)
+            .unwrap();
+        assert_eq!(continuation.token(), "stream-1");
+        assert!(continuation.follows(&first));
+
+        // and can itself be refreshed
+        let third = make_refresh_bewit(&request, &second, &credentials, Duration::minutes(5),
+                                       "stream-1", &options)
+            .unwrap();
+        let continuation = validate_refresh_bewit(&request, &third, &credentials.key, &options)
+            .unwrap();
+        assert!(continuation.follows(&second));
+        assert!(!continuation.follows(&first));
+    }
+
+    #[test]
+    fn other_path() {
+        let credentials = test_credentials();
+        let request = RequestBuilder::new("GET", "example.com", 443, "/events").request();
+        let other = RequestBuilder::new("GET", "example.com", 443, "/other").request();
+        let options = ValidationOptions::default();
+        let first = request.make_bewit(&credentials, Duration::minutes(5)).unwrap();
+        let second = make_refresh_bewit(&request, &first, &credentials, Duration::minutes(5),
+                                        "stream-1", &options)
+            .unwrap();
+        assert_eq!(validate_refresh_bewit(&other, &second, &credentials.key, &options)
+                       .unwrap_err(),
+                   BewitError::MacMismatch);
+        assert!(make_refresh_bewit(&other, &first, &credentials, Duration::minutes(5),
+                                   "stream-1", &options)
+            .is_err());
+    }
+
+    #[test]
+    fn expired_previous() {
+        let credentials = test_credentials();
+        let request = RequestBuilder::new("GET", "example.com", 443, "/events").request();
+        let options = ValidationOptions::default();
+        let first = request.make_bewit(&credentials, Duration::minutes(-5)).unwrap();
+        assert!(make_refresh_bewit(&request, &first, &credentials, Duration::minutes(5),
+                                   "stream-1", &options)
+            .is_err());
+    }
+
+    #[test]
+    fn not_a_refresh() {
+        let credentials = test_credentials();
+        let request = RequestBuilder::new("GET", "example.com", 443, "/events").request();
+        let options = ValidationOptions::default();
+        let bewit = request.make_bewit(&credentials, Duration::minutes(5)).unwrap();
+        assert_eq!(validate_refresh_bewit(&request, &bewit, &credentials.key, &options)
+                       .unwrap_err(),
+                   BewitError::BadExt);
+    }
+
+    #[test]
+    fn bad_token() {
+        let credentials = test_credentials();
+        let request = RequestBuilder::new("GET", "example.com", 443, "/events").request();
+        let options = ValidationOptions::default();
+        let bewit = request.make_bewit(&credentials, Duration::minutes(5)).unwrap();
+        assert!(make_refresh_bewit(&request, &bewit, &credentials, Duration::minutes(5),
+                                   "a:b", &options)
+            .is_err());
+    }
+}
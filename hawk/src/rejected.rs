@@ -0,0 +1,294 @@
+use base64;
+use crypto;
+use error::Stage;
+use hawk_core::{Header, HeaderField};
+use std::error;
+use std::fmt;
+use std::fmt::Write;
+
+/// The reasons for which a request header can be rejected.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RejectionReason {
+    /// A field required to authenticate the request is missing from the header.
+    MissingField(HeaderField),
+    /// The header's `mac` is not the length of a digest made with the key's algorithm, or its
+    /// `hash` is not the length of the payload hash given for the request, so it cannot match.
+    WrongLength(HeaderField),
+    /// The header's `ext` is not permitted by the `ExtPolicy`.
+    ExtNotPermitted,
+    /// The header's `nonce` is not permitted by the `NoncePolicy`.
+    NonceNotPermitted,
+    /// The header's MAC does not match the request.
+    MacMismatch,
+    /// A payload hash is required, but the header has none.
+    MissingHash,
+    /// The header's payload hash does not match the one given for the request.
+    HashMismatch,
+    /// The header's timestamp is outside the allowed skew.
+    Stale,
+    /// The credentials provider has no keys for the header's `id`.
+    UnknownId,
+    /// The header was signed with a key whose validity period has not yet begun.
+    CredentialsNotYetValid,
+    /// The header was signed with a key whose validity period has ended.
+    CredentialsExpired,
+    /// The header's nonce has been seen before.
+    Replayed,
+    /// The nonce store could not be consulted, and the `NonceOutagePolicy` is to fail closed.
+    NonceStoreUnavailable,
+}
+
+impl RejectionReason {
+    /// Get the stage of authentication at which the request was rejected.
+    pub fn stage(&self) -> Stage {
+        match *self {
+            RejectionReason::MissingField(_) | RejectionReason::WrongLength(_) => Stage::Parse,
+            RejectionReason::UnknownId |
+            RejectionReason::CredentialsNotYetValid |
+            RejectionReason::CredentialsExpired => Stage::Lookup,
+            RejectionReason::MacMismatch | RejectionReason::HashMismatch => Stage::Crypto,
+            RejectionReason::ExtNotPermitted |
+            RejectionReason::NonceNotPermitted |
+            RejectionReason::MissingHash |
+            RejectionReason::Replayed => Stage::Policy,
+            RejectionReason::Stale => Stage::Clock,
+            RejectionReason::NonceStoreUnavailable => Stage::Other,
+        }
+    }
+}
+
+impl fmt::Display for RejectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RejectionReason::MissingField(field) => write!(f, "missing `{}` field", field),
+            RejectionReason::WrongLength(field) => {
+                write!(f, "`{}` field has the wrong length", field)
+            }
+            RejectionReason::ExtNotPermitted => f.write_str("ext not permitted"),
+            RejectionReason::NonceNotPermitted => f.write_str("nonce not permitted"),
+            RejectionReason::MacMismatch => f.write_str("MAC mismatch"),
+            RejectionReason::MissingHash => f.write_str("missing payload hash"),
+            RejectionReason::HashMismatch => f.write_str("payload hash mismatch"),
+            RejectionReason::Stale => f.write_str("timestamp outside allowed skew"),
+            RejectionReason::UnknownId => f.write_str("unknown id"),
+            RejectionReason::CredentialsNotYetValid => f.write_str("credentials not yet valid"),
+            RejectionReason::CredentialsExpired => f.write_str("credentials expired"),
+            RejectionReason::Replayed => f.write_str("replayed nonce"),
+            RejectionReason::NonceStoreUnavailable => f.write_str("nonce store unavailable"),
+        }
+    }
+}
+
+/// A description of a rejected request header, safe to log.
+///
+/// This is produced by `Request::check_header`, and includes the reason for the rejection, the
+/// header with its `mac` and `hash` redacted to short prefixes, and the SHA-256 hash of the
+/// normalized string the server calculated, if it got that far.  Comparing that hash to one
+/// calculated by the client reveals whether the two disagreed about the request itself (method,
+/// host, port, path, and so on) or about the key.
+///
+/// The `Display` implementation formats all of this on one line.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RejectedRequest {
+    reason: RejectionReason,
+    header: String,
+    normalized_hash: Option<String>,
+}
+
+impl RejectedRequest {
+    pub(crate) fn new(reason: RejectionReason,
+                      header: &Header,
+                      normalized: Option<&[u8]>)
+                      -> RejectedRequest {
+        RejectedRequest {
+            reason,
+            header: redact(header),
+            normalized_hash: normalized.map(crypto::sha256_hex),
+        }
+    }
+
+    /// Get the reason the header was rejected.
+    pub fn reason(&self) -> RejectionReason {
+        self.reason
+    }
+
+    /// Get the header, formatted as for `Header::fmt_header`, but with the `mac` and `hash`
+    /// truncated.
+    pub fn redacted_header(&self) -> &str {
+        &self.header
+    }
+
+    /// Get the lower-case hex SHA-256 hash of the normalized string calculated for the request,
+    /// if validation got as far as calculating it.
+    pub fn normalized_hash(&self) -> Option<&str> {
+        self.normalized_hash.as_ref().map(|h| &h[..])
+    }
+}
+
+impl fmt::Display for RejectedRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hawk request rejected ({}): {}", self.reason, self.header)?;
+        if let Some(ref hash) = self.normalized_hash {
+            write!(f, "; normalized string sha256={}", hash)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for RejectedRequest {}
+
+/// The number of base64 characters of a MAC or hash retained when redacting, as for
+/// `RedactedHeader`.
+const REDACTED_PREFIX_LEN: usize = 6;
+
+/// Encode the given bytes as base64, truncated to a short prefix.
+fn prefix(bytes: &[u8]) -> String {
+    let mut encoded = base64::encode(bytes);
+    if encoded.len() > REDACTED_PREFIX_LEN {
+        encoded.truncate(REDACTED_PREFIX_LEN);
+        encoded.push_str("...");
+    }
+    encoded
+}
+
+/// Format a header with its MAC and hash truncated to short prefixes.
+fn redact(header: &Header) -> String {
+    let fields = [("id", header.id().map(|v| v.to_string())),
+                  ("ts", header.ts().map(|ts| ts.sec.to_string())),
+                  ("nonce", header.nonce().map(|v| v.to_string())),
+                  ("mac", header.mac().map(|mac| prefix(mac))),
+                  ("ext", header.ext().map(|v| v.to_string())),
+                  ("hash", header.hash().map(prefix)),
+                  ("app", header.app().map(|v| v.to_string())),
+                  ("dlg", header.dlg().map(|v| v.to_string()))];
+    let mut out = String::new();
+    for (name, value) in fields.iter() {
+        if let Some(ref value) = *value {
+            if !out.is_empty() {
+                out.push_str(", ");
+            }
+            let _ = write!(out, "{}=\"{}\"", name, value);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use credentials::Key;
+    use ring::digest;
+    use request::RequestBuilder;
+    use testing::test_credentials;
+    use time::Timespec;
+    use validation::ValidationOptions;
+
+    #[test]
+    fn stages() {
+        use auth_source::AuthSource;
+        use hawk_core::BewitError;
+        use std::str::FromStr;
+
+        assert_eq!(RejectionReason::MissingField(HeaderField::Ts).stage(), Stage::Parse);
+        assert_eq!(RejectionReason::WrongLength(HeaderField::Mac).stage(), Stage::Parse);
+        assert_eq!(RejectionReason::UnknownId.stage(), Stage::Lookup);
+        assert_eq!(RejectionReason::MacMismatch.stage(), Stage::Crypto);
+        assert_eq!(RejectionReason::Replayed.stage(), Stage::Policy);
+        assert_eq!(RejectionReason::NonceNotPermitted.stage(), Stage::Policy);
+        assert_eq!(RejectionReason::Stale.stage(), Stage::Clock);
+        assert_eq!(RejectionReason::NonceStoreUnavailable.stage(), Stage::Other);
+        assert_eq!(Stage::Clock.to_string(), "clock");
+
+        assert_eq!(Header::from_str("id=\"me").unwrap_err().stage(), Stage::Parse);
+        assert_eq!(AuthSource::from_request(Some("Hawk id=\"me\""), "/?bewit=x")
+                       .unwrap_err()
+                       .stage(),
+                   Stage::Policy);
+        let expired: ::error::Error = BewitError::Expired { at: Timespec::new(0, 0) }.into();
+        assert_eq!(expired.stage(), Stage::Clock);
+        let msg: ::error::Error = "something else".into();
+        assert_eq!(msg.stage(), Stage::Other);
+    }
+
+    #[test]
+    fn mac_mismatch() {
+        let hash = [1u8; 32];
+        let client = RequestBuilder::new("POST", "example.com", 443, "/a")
+            .hash(&hash[..])
+            .ext("my-ext")
+            .request();
+        let header = client.make_header(&test_credentials()).unwrap();
+
+        let server = RequestBuilder::new("POST", "example.com", 443, "/b")
+            .hash(&hash[..])
+            .request();
+        let rejected = server.check_header(&header, &test_credentials().key,
+                                           &ValidationOptions::default())
+            .unwrap_err();
+        assert_eq!(rejected.reason(), RejectionReason::MacMismatch);
+        assert_eq!(rejected.normalized_hash().unwrap().len(), 64);
+
+        // the mac and hash are redacted, but other fields are intact
+        let mac = base64::encode(header.mac().unwrap());
+        let redacted = rejected.redacted_header();
+        assert!(!redacted.contains(&mac[..]));
+        assert!(redacted.contains(&format!("mac=\"{}...\"", &mac[..6])));
+        assert!(redacted.contains("hash=\"AQEBAQ...\""));
+        assert!(redacted.contains("ext=\"my-ext\""));
+        assert!(redacted.starts_with("id=\"me\", ts=\""));
+
+        let logged = rejected.to_string();
+        assert!(logged.starts_with("Hawk request rejected (MAC mismatch): id=\"me\""));
+        assert!(logged.contains("normalized string sha256="));
+    }
+
+    #[test]
+    fn normalized_hash_identifies_disagreement() {
+        let client = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let header = client.make_header(&test_credentials()).unwrap();
+        let wrong_key = Key::new(vec![1u8; 32], &digest::SHA256);
+
+        // with the wrong key, the normalized strings agree
+        let rejected = client.check_header(&header, &wrong_key, &ValidationOptions::default())
+            .unwrap_err();
+        let mut normalized = vec![];
+        let mut out = String::new();
+        client.write_header(&mut out,
+                            &mut normalized,
+                            &test_credentials(),
+                            header.ts().unwrap(),
+                            header.nonce().unwrap())
+            .unwrap();
+        let expected =
+            RejectedRequest::new(RejectionReason::MacMismatch, &header, Some(&normalized));
+        assert_eq!(rejected.normalized_hash(), expected.normalized_hash());
+    }
+
+    #[test]
+    fn missing_field() {
+        let credentials = test_credentials();
+        let req = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let header = req.make_header_full(&credentials, Timespec::new(1000, 0), "n")
+            .unwrap()
+            .with_ts(None);
+        let rejected = req.check_header(&header, &credentials.key, &ValidationOptions::default())
+            .unwrap_err();
+        assert_eq!(rejected.reason(), RejectionReason::MissingField(HeaderField::Ts));
+        assert_eq!(rejected.normalized_hash(), None);
+        assert_eq!(rejected.to_string(),
+                   format!("Hawk request rejected (missing `ts` field): id=\"me\", nonce=\"n\", \
+                            mac=\"{}...\"",
+                           &base64::encode(header.mac().unwrap())[..6]));
+    }
+
+    #[test]
+    fn stale() {
+        let credentials = test_credentials();
+        let req = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let header = req.make_header_full(&credentials, Timespec::new(1000, 0), "n").unwrap();
+        let rejected = req.check_header(&header, &credentials.key, &ValidationOptions::default())
+            .unwrap_err();
+        assert_eq!(rejected.reason(), RejectionReason::Stale);
+        assert!(rejected.normalized_hash().is_some());
+    }
+}
@@ -1,16 +1,42 @@
 use base64;
 use time;
 use url::Url;
-use mac::{Mac, MacType};
-use header::Header;
+#[cfg(feature = "http")]
+use http::Uri;
+use mac::{normalized_suffix, write_normalized_app, write_normalized_prefix, write_normalized_suffix,
+          Mac, MacType};
+use crypto;
+use algorithm::digest_output_len;
+use std::fmt::Write;
+use fingerprint::RequestFingerprint;
+use hawk_core::{Bewit, BewitError, Header, HeaderField};
+use rejected::{RejectedRequest, RejectionReason};
 use response::ResponseBuilder;
-use bewit::Bewit;
-use credentials::{Credentials, Key};
+use credentials::{AppCredentials, Credentials, CredentialsProvider, Key, Validity};
+use validation::{ExtPolicy, PayloadPolicy, ValidationOptions};
+use path_encoding::{may_traverse, PathEncoding};
+use conformance::Conformance;
+use method_override::MethodOverride;
+use header_options::HeaderOptions;
+use artifacts::Artifacts;
 use rand;
 use rand::Rng;
 use error::*;
-use time::{now, Duration};
+use time::Duration;
 use std::str;
+use std::borrow::Cow;
+use std::mem;
+use std::ptr;
+use std::time::{self as std_time, SystemTime};
+use clock::{self, duration_from_std, timespec_from_system_time};
+use nonce;
+#[cfg(feature = "json")]
+use serde::Serialize;
+#[cfg(feature = "json")]
+use serde_json;
+#[cfg(feature = "json")]
+use payload::PayloadHasher;
+use ring::digest;
 
 /// Request represents a single HTTP request.
 ///
@@ -34,22 +60,109 @@ use std::str;
 /// See the documentation in the crate root for examples of creating and validating headers.
 #[derive(Debug, Clone)]
 pub struct Request<'a> {
-    method: &'a str,
-    host: &'a str,
+    method: Cow<'a, str>,
+    host: Cow<'a, str>,
     port: u16,
-    path: &'a str,
-    hash: Option<&'a [u8]>,
+    path: Cow<'a, str>,
+    path_encoding: PathEncoding,
+    conformance: Conformance,
+    method_override: Option<&'a str>,
+    method_override_policy: MethodOverride,
+    hash: Option<Cow<'a, [u8]>>,
     ext: Option<&'a str>,
     app: Option<&'a str>,
     dlg: Option<&'a str>,
 }
 
 impl<'a> Request<'a> {
+    /// Get the request method.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Get the request host.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Get the request port.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Get the request path, after applying the request's `PathEncoding`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Get a copy of this request with the given path, applying the request's `PathEncoding`.
+    ///
+    /// This is intended for clients which make requests to many endpoints with otherwise fixed
+    /// parameters: build one request for the host and port, then specialize it for each call.
+    /// The path need only outlive the copy, not the original request, and the original's path
+    /// is not copied.  The payload hash, `ext`, `app`, and `dlg` are kept.
+    ///
+    /// ```
+    /// use hawk::RequestBuilder;
+    /// let base = RequestBuilder::new("GET", "mysite.com", 443, "/").request();
+    /// for id in 1..3 {
+    ///     let path = format!("/api/user/{}", id);
+    ///     let request = base.with_path(&path);
+    ///     assert_eq!(request.path(), path);
+    /// }
+    /// ```
+    pub fn with_path<'b>(&self, path: &'b str) -> Request<'b>
+        where 'a: 'b
+    {
+        Request {
+            method: self.method.clone(),
+            host: self.host.clone(),
+            port: self.port,
+            path: self.path_encoding.apply(Cow::Borrowed(path)),
+            path_encoding: self.path_encoding,
+            conformance: self.conformance,
+            method_override: self.method_override,
+            method_override_policy: self.method_override_policy,
+            hash: self.hash.clone(),
+            ext: self.ext,
+            app: self.app,
+            dlg: self.dlg,
+        }
+    }
+
+    /// Get a copy of this request with the given method, applying the request's
+    /// `MethodOverride` policy and `Conformance`.  Like `with_path`, the method need only
+    /// outlive the copy.
+    pub fn with_method<'b>(&self, method: &'b str) -> Request<'b>
+        where 'a: 'b
+    {
+        let mut request: Request<'b> = self.clone();
+        let method = self.method_override_policy
+            .method(Cow::Borrowed(method), self.method_override);
+        request.method = self.conformance.method(method);
+        request
+    }
+
+    /// Get a copy of this request with the given `ext`.
+    pub(crate) fn with_ext<'b>(&self, ext: Option<&'b str>) -> Request<'b>
+        where 'a: 'b
+    {
+        let mut request: Request<'b> = self.clone();
+        request.ext = ext;
+        request
+    }
+
     /// Create a new Header for this request, inventing a new nonce and setting the
     /// timestamp to the current time.
     pub fn make_header(&self, credentials: &Credentials) -> Result<Header> {
-        let nonce = random_string(10);
-        self.make_header_full(credentials, time::now().to_timespec(), nonce)
+        let nonce = nonce::generate();
+        self.make_header_full(credentials, clock::now(), nonce)
+    }
+
+    /// Similar to `make_header`, but with the timestamp given as a `SystemTime`.
+    pub fn make_header_at(&self, credentials: &Credentials, at: SystemTime) -> Result<Header> {
+        let nonce = nonce::generate();
+        self.make_header_full(credentials, timespec_from_system_time(at), nonce)
     }
 
     /// Similar to `make_header`, but allowing specification of the timestamp
@@ -62,43 +175,174 @@ impl<'a> Request<'a> {
         where S: Into<String>
     {
         let nonce = nonce.into();
-        let mac = Mac::new(MacType::Header,
-                           &credentials.key,
-                           ts,
-                           &nonce,
-                           self.method,
-                           self.host,
-                           self.port,
-                           self.path,
-                           self.hash,
-                           self.ext)?;
-        Header::new(Some(credentials.id.clone()),
-                    Some(ts),
-                    Some(nonce),
-                    Some(mac),
-                    match self.ext {
-                        None => None,
-                        Some(v) => Some(v.to_string()),
-                    },
-                    match self.hash {
-                        None => None,
-                        Some(v) => Some(v.to_vec()),
-                    },
-                    match self.app {
-                        None => None,
-                        Some(v) => Some(v.to_string()),
-                    },
-                    match self.dlg {
-                        None => None,
-                        Some(v) => Some(v.to_string()),
-                    })
+        let mut normalized = vec![];
+        self.write_normalized(&mut normalized, MacType::Header, ts, &nonce, self.hash(), self.ext)?;
+        write_normalized_app(&mut normalized, self.app, self.dlg)?;
+        let mac = Mac::from(credentials.key.sign(&normalized));
+        let header = Header::new(Some(credentials.id.clone()),
+                                 Some(ts),
+                                 Some(nonce),
+                                 Some(mac.into()),
+                                 match self.ext {
+                                     None => None,
+                                     Some(v) => Some(v.to_string()),
+                                 },
+                                 match self.hash {
+                                     None => None,
+                                     Some(ref v) => Some(v.to_vec()),
+                                 },
+                                 match self.app {
+                                     None => None,
+                                     Some(v) => Some(v.to_string()),
+                                 },
+                                 match self.dlg {
+                                     None => None,
+                                     Some(v) => Some(v.to_string()),
+                                 })?;
+        debug_assert!(header.canonicalize().is_ok(), "header does not round-trip: {}", header);
+        Ok(header)
+    }
+
+    /// Create a new Header for this request with the given options.
+    ///
+    /// With the default options, this is equivalent to `make_header`.
+    pub fn make_header_with(&self,
+                            credentials: &Credentials,
+                            options: &HeaderOptions)
+                            -> Result<Header> {
+        if let Some(digest) = options.digest {
+            if !ptr::eq(credentials.key.algorithm(), digest) {
+                bail!("Credentials' key does not use the required digest algorithm");
+            }
+        }
+        let ts = options.clock.now() + options.ts_offset;
+        let nonce = match options.nonce_generator {
+            Some(ref generator) => generator.generate(),
+            None => nonce::generate(),
+        };
+
+        let mut request = self.clone();
+        if !options.include_query {
+            if let Some((path, _)) = self.path.split_once('?') {
+                request.path = Cow::Owned(path.to_string());
+            }
+        }
+        request.make_header_full(credentials, ts, nonce)
+    }
+
+    /// Make a complete `Authorization` header value for this request, including the `Hawk`
+    /// scheme, inventing a new nonce and setting the timestamp to the current time.
+    ///
+    /// This is equivalent to `format!("Hawk {}", request.make_header(credentials)?)`, but
+    /// formats the header directly, without building an intermediate `Header`.  Use a `Signer`
+    /// to also reuse buffers between requests.
+    pub fn sign_to_string(&self, credentials: &Credentials) -> Result<String> {
+        let mut out = String::new();
+        self.write_header(&mut out, &mut vec![], credentials, clock::now(), &nonce::generate())?;
+        out.insert_str(0, "Hawk ");
+        Ok(out)
+    }
+
+    /// Write the header value for this request to `out`, replacing its contents, and using
+    /// `normalized` to hold the normalized string.  This is the allocation-free equivalent of
+    /// `make_header_full(..).to_string()`, used by `Signer`.
+    pub(crate) fn write_header(&self,
+                               out: &mut String,
+                               normalized: &mut Vec<u8>,
+                               credentials: &Credentials,
+                               ts: time::Timespec,
+                               nonce: &str)
+                               -> Result<()> {
+        for component in [Some(&credentials.id[..]), Some(nonce), self.ext, self.app, self.dlg]
+            .iter()
+            .flatten() {
+            Header::check_str(component)?;
+        }
+
+        normalized.clear();
+        self.write_normalized(normalized, MacType::Header, ts, nonce, self.hash(), self.ext)?;
+        write_normalized_app(normalized, self.app, self.dlg)?;
+        let mac = credentials.key.sign_raw(&[normalized]);
+
+        out.clear();
+        write!(out, "id=\"{}\", ts=\"{}\", nonce=\"{}\", mac=\"", credentials.id, ts.sec, nonce)
+            .chain_err(|| "Error formatting header")?;
+        base64::encode_config_buf(mac.as_ref(), base64::STANDARD, out);
+        out.push('"');
+        if let Some(ext) = self.ext {
+            write!(out, ", ext=\"{}\"", ext).chain_err(|| "Error formatting header")?;
+        }
+        if let Some(hash) = self.hash() {
+            out.push_str(", hash=\"");
+            base64::encode_config_buf(hash, base64::STANDARD, out);
+            out.push('"');
+        }
+        if let Some(app) = self.app {
+            write!(out, ", app=\"{}\"", app).chain_err(|| "Error formatting header")?;
+        }
+        if let Some(dlg) = self.dlg {
+            write!(out, ", dlg=\"{}\"", dlg).chain_err(|| "Error formatting header")?;
+        }
+        Ok(())
+    }
+
+    /// Write the normalized string for this request to `buffer`, with the given hash and ext.
+    fn write_normalized(&self,
+                        buffer: &mut Vec<u8>,
+                        mac_type: MacType,
+                        ts: time::Timespec,
+                        nonce: &str,
+                        hash: Option<&[u8]>,
+                        ext: Option<&str>)
+                        -> Result<()> {
+        write_normalized_prefix(buffer, mac_type, ts, nonce)?;
+        write_normalized_suffix(buffer, &self.method, &self.host, self.port, &self.path, hash, ext)
+    }
+
+    /// Precompute the parts of this request's normalized string which are the same for every
+    /// header, for clients which sign the same request repeatedly.  See `RequestFingerprint`.
+    pub fn fingerprint(&self) -> Result<RequestFingerprint> {
+        let mut suffix = normalized_suffix(&self.method,
+                                           &self.host,
+                                           self.port,
+                                           &self.path,
+                                           self.hash(),
+                                           self.ext)?;
+        write_normalized_app(&mut suffix, self.app, self.dlg)?;
+        Ok(RequestFingerprint::new(suffix,
+                                   self.ext.map(|v| v.to_string()),
+                                   self.hash().map(|v| v.to_vec()),
+                                   self.app.map(|v| v.to_string()),
+                                   self.dlg.map(|v| v.to_string())))
+    }
+
+    /// Create a new Header for this request, authenticating as an application rather than a
+    /// user.  The header's `id` and `app` fields are both set to the application id, and any
+    /// `app` or `dlg` given to the builder are ignored.
+    pub fn make_app_header(&self, credentials: &AppCredentials) -> Result<Header> {
+        let nonce = nonce::generate();
+        self.make_app_header_full(credentials, clock::now(), nonce)
+    }
+
+    /// Similar to `make_app_header`, but allowing specification of the timestamp and nonce.
+    pub fn make_app_header_full<S>(&self,
+                                   credentials: &AppCredentials,
+                                   ts: time::Timespec,
+                                   nonce: S)
+                                   -> Result<Header>
+        where S: Into<String>
+    {
+        let mut request = self.clone();
+        request.app = Some(credentials.app());
+        request.dlg = None;
+        request.make_header_full(credentials.credentials(), ts, nonce)
     }
 
     /// Make a "bewit" that can be attached to a URL to authenticate GET access.
     ///
     /// The ttl gives the time for which this bewit is valid, starting now.
     pub fn make_bewit(&self, credentials: &'a Credentials, ttl: Duration) -> Result<Bewit<'a>> {
-        let exp = time::now().to_timespec() + ttl;
+        let exp = clock::now() + ttl;
         // note that this includes `method` and `hash` even though they must always be GET and None
         // for bewits.  If they aren't, then the bewit just won't validate -- no need to catch
         // that now
@@ -106,16 +350,37 @@ impl<'a> Request<'a> {
                            &credentials.key,
                            exp,
                            "",
-                           self.method,
-                           self.host,
+                           &self.method,
+                           &self.host,
                            self.port,
-                           self.path,
-                           self.hash,
+                           &self.path,
+                           self.hash(),
                            self.ext)?;
-        let bewit = Bewit::new(&credentials.id, exp, mac, self.ext);
+        let bewit = Bewit::new(&credentials.id, exp, mac.into(), self.ext);
         Ok(bewit)
     }
 
+    /// Make a scoped bewit, granting `GET` access to every resource under this request's path.
+    ///
+    /// This is not part of the Hawk protocol, and scoped bewits are only accepted by validators
+    /// with `ValidationOptions::allow_scoped_bewits` set.  The path must end with `/`, and is
+    /// carried in the bewit's `ext` as `hawk-scope:<path>`, replacing any `ext` of the request.
+    /// The bewit's MAC covers this path, rather than the path of the resource being accessed.
+    pub fn make_scoped_bewit(&self,
+                             credentials: &Credentials,
+                             ttl: Duration)
+                             -> Result<Bewit<'static>> {
+        if !self.path.ends_with('/') {
+            bail!("Scoped bewit path must end with `/`");
+        }
+        if self.path.contains(['\\', '?']) {
+            bail!("Scoped bewit path cannot contain `\\` or `?`");
+        }
+        let ext = format!("{}{}", SCOPE_EXT_PREFIX, self.path);
+        let request = self.with_ext(Some(&ext));
+        Ok(request.make_bewit(credentials, ttl)?.into_owned())
+    }
+
     /// Validate the given header.  This validates that the `mac` field matches that calculated
     /// using the other header fields and the given request information.
     ///
@@ -129,74 +394,259 @@ impl<'a> Request<'a> {
     /// If a hash has been supplied, then the header must contain a matching hash. Note that this
     /// hash must be calculated based on the request body, not copied from the request header!
     pub fn validate_header(&self, header: &Header, key: &Key, ts_skew: Duration) -> bool {
+        let options = ValidationOptions { ts_skew, ..ValidationOptions::default() };
+        self.validate_header_with_options(header, key, &options)
+    }
+
+    /// Validate the given header, as for `validate_header`, but taking the allowed skew as a
+    /// `std::time::Duration`.
+    pub fn validate_header_with_max_age(&self,
+                                        header: &Header,
+                                        key: &Key,
+                                        max_age: std_time::Duration)
+                                        -> bool {
+        self.validate_header(header, key, duration_from_std(max_age))
+    }
+
+    /// Validate the given header, as for `validate_header`, but with the given options.
+    ///
+    /// In addition to the checks performed by `validate_header`, this requires that the header
+    /// contain a hash if the options' `require_payload_hash` policy calls for one.
+    pub fn validate_header_with_options(&self,
+                                        header: &Header,
+                                        key: &Key,
+                                        options: &ValidationOptions)
+                                        -> bool {
+        self.authenticate_header(header, key, options).is_some()
+    }
+
+    /// Validate the given header, as for `validate_header_with_options`, returning the
+    /// `Artifacts` of the authenticated request on success.
+    ///
+    /// The artifacts can be used to build a response to the request.
+    pub fn authenticate_header<'b>(&'b self,
+                                   header: &'b Header,
+                                   key: &Key,
+                                   options: &ValidationOptions)
+                                   -> Option<Artifacts<'b>> {
+        self.authenticate_header_in(header, key, options, &mut Vec::new())
+    }
+
+    /// Validate the given header, as for `authenticate_header`, but describing the reason for
+    /// any failure in a `RejectedRequest`, which is safe to log.
+    pub fn check_header<'b>(&'b self,
+                            header: &'b Header,
+                            key: &Key,
+                            options: &ValidationOptions)
+                            -> ::std::result::Result<Artifacts<'b>, RejectedRequest> {
+        let mut normalized = Vec::new();
+        self.check_header_in(header, key, options, &mut normalized)
+            .map_err(|reason| {
+                let normalized = if normalized.is_empty() {
+                    None
+                } else {
+                    Some(&normalized[..])
+                };
+                RejectedRequest::new(reason, header, normalized)
+            })
+    }
+
+    /// Validate the given header, as for `authenticate_header`, using the given buffer to hold
+    /// the normalized string.
+    pub(crate) fn authenticate_header_in<'b>(&'b self,
+                                             header: &'b Header,
+                                             key: &Key,
+                                             options: &ValidationOptions,
+                                             normalized: &mut Vec<u8>)
+                                             -> Option<Artifacts<'b>> {
+        self.check_header_in(header, key, options, normalized).ok()
+    }
+
+    /// Validate the given header, returning the reason for any failure.  The normalized string
+    /// is left in `normalized`, if validation got as far as calculating it.
+    fn check_header_in<'b>(&'b self,
+                           header: &'b Header,
+                           key: &Key,
+                           options: &ValidationOptions,
+                           normalized: &mut Vec<u8>)
+                           -> ::std::result::Result<Artifacts<'b>, RejectionReason> {
+        normalized.clear();
+
         // extract required fields, returning early if they are not present
-        let ts = match header.ts {
-            Some(ts) => ts,
-            None => {
-                return false;
-            }
+        let ts = header.ts().ok_or(RejectionReason::MissingField(HeaderField::Ts))?;
+        let nonce = header.nonce().ok_or(RejectionReason::MissingField(HeaderField::Nonce))?;
+        let header_mac = header.mac().ok_or(RejectionReason::MissingField(HeaderField::Mac))?;
+        let header_hash = header.hash();
+        let header_ext = header.ext();
+        // a MAC or hash of the wrong length cannot match; the payload hash may have been made
+        // with a different algorithm than the key's, so it is compared with the request's
+        let not_permitted = if header_mac.len() != digest_output_len(key.algorithm()) {
+            Some(RejectionReason::WrongLength(HeaderField::Mac))
+        } else if header_hash.zip(self.hash()).is_some_and(|(h, l)| h.len() != l.len()) {
+            Some(RejectionReason::WrongLength(HeaderField::Hash))
+        } else if header_ext.is_some_and(|ext| !options.ext_policy.permits(ext)) {
+            Some(RejectionReason::ExtNotPermitted)
+        } else if !options.nonce_policy.permits(nonce) {
+            Some(RejectionReason::NonceNotPermitted)
+        } else {
+            None
         };
-        let nonce = match header.nonce {
-            Some(ref nonce) => nonce,
-            None => {
-                return false;
-            }
-        };
-        let header_mac = match header.mac {
-            Some(ref mac) => mac,
-            None => {
-                return false;
+        if let Some(reason) = not_permitted {
+            if options.equalize_timing &&
+               self.write_normalized(normalized,
+                                     MacType::Header,
+                                     ts,
+                                     nonce,
+                                     header_hash,
+                                     header_ext)
+                   .is_ok() {
+                key.sign_raw(&[normalized]);
             }
-        };
-        let header_hash = match header.hash {
-            Some(ref hash) => Some(&hash[..]),
-            None => None,
-        };
-        let header_ext = match header.ext {
-            Some(ref ext) => Some(&ext[..]),
-            None => None,
-        };
+            return Err(reason);
+        }
 
         // first verify the MAC
-        match Mac::new(MacType::Header,
-                       key,
-                       ts,
-                       nonce,
-                       self.method,
-                       self.host,
-                       self.port,
-                       self.path,
-                       header_hash,
-                       header_ext) {
-            Ok(calculated_mac) => {
-                if &calculated_mac != header_mac {
-                    return false;
-                }
-            }
-            Err(_) => {
-                return false;
+        if self.write_normalized(normalized, MacType::Header, ts, nonce, header_hash, header_ext)
+               .and_then(|_| write_normalized_app(normalized, header.app(), header.dlg()))
+               .is_err() {
+            return Err(RejectionReason::MacMismatch);
+        }
+        let mut path = &self.path[..];
+        let mut retried = None;
+        if !crypto::ct_eq(key.sign_raw(&[normalized]).as_ref(), header_mac) {
+            // try again without the query, if permitted; `normalized` is left holding the
+            // normalized string for the full path, for diagnostics
+            let without_query = match self.path.split_once('?') {
+                Some((without_query, _)) if options.accept_mac_without_query => without_query,
+                _ => return Err(RejectionReason::MacMismatch),
+            };
+            let mut retry = Vec::with_capacity(normalized.len());
+            let matches = write_normalized_prefix(&mut retry, MacType::Header, ts, nonce)
+                .and_then(|_| {
+                    write_normalized_suffix(&mut retry,
+                                            &self.method,
+                                            &self.host,
+                                            self.port,
+                                            without_query,
+                                            header_hash,
+                                            header_ext)
+                })
+                .and_then(|_| write_normalized_app(&mut retry, header.app(), header.dlg()))
+                .is_ok() && crypto::ct_eq(key.sign_raw(&[&retry]).as_ref(), header_mac);
+            if !matches {
+                return Err(RejectionReason::MacMismatch);
             }
-        };
+            path = without_query;
+            retried = Some(retry);
+        }
 
         // ..then the hashes
-        if let Some(local_hash) = self.hash {
+        if header_hash.is_none() && options.require_payload_hash.requires_hash(&self.method) {
+            return Err(RejectionReason::MissingHash);
+        }
+        if let Some(local_hash) = self.hash() {
             if let Some(server_hash) = header_hash {
-                if local_hash != server_hash {
-                    return false;
+                if !crypto::ct_eq(local_hash, server_hash) {
+                    return Err(RejectionReason::HashMismatch);
                 }
-            } else {
-                return false;
+            } else if options.require_payload_hash != PayloadPolicy::IfPresent {
+                return Err(RejectionReason::MissingHash);
             }
         }
 
         // ..then the timestamp
-        let now = now().to_timespec();
+        let now = options.clock.now();
         let skew = if now > ts { now - ts } else { ts - now };
-        if skew > ts_skew {
-            return false;
+        if skew > options.ts_skew {
+            return Err(RejectionReason::Stale);
+        }
+
+        let mut artifacts =
+            Artifacts::new(&self.method, &self.host, self.port, path, header, ts, nonce)
+                .with_empty_ext_as_absent(options.empty_ext_as_absent);
+        if options.record_normalized_hash {
+            let authenticated = retried.as_ref().unwrap_or(normalized);
+            artifacts = artifacts.with_normalized_hash(Some(crypto::sha256_hex(authenticated)));
+        }
+        if path.len() < self.path.len() {
+            Ok(artifacts.signed_without_query())
+        } else {
+            Ok(artifacts)
         }
+    }
+
+    /// Validate the given header, as for `authenticate_header`, looking up the keys for the
+    /// header's `id` from the given provider.
+    ///
+    /// The header is valid if it validates with any of the keys for its id.  A header without an
+    /// `id`, or with an id for which the provider has no keys, is not valid.  Keys outside their
+    /// validity period, as given by `CredentialsProvider::keys_with_validity`, are not accepted.
+    pub fn authenticate_with_provider<'b, P>(&'b self,
+                                             header: &'b Header,
+                                             provider: &P,
+                                             options: &ValidationOptions)
+                                             -> Option<Artifacts<'b>>
+        where P: CredentialsProvider + ?Sized
+    {
+        self.check_with_provider(header, provider, options).ok()
+    }
+
+    /// Validate the given header, as for `authenticate_with_provider`, but returning the reason
+    /// for any failure.
+    ///
+    /// A header which validates only with keys outside their validity period (according to
+    /// `options.clock`) is rejected with `RejectionReason::CredentialsExpired` or
+    /// `CredentialsNotYetValid`.  Since the MAC is checked first, these reasons are only given
+    /// for headers genuinely signed with such a key.  Otherwise, the reason is that for the
+    /// first key.
+    pub fn check_with_provider<'b, P>(&'b self,
+                                      header: &'b Header,
+                                      provider: &P,
+                                      options: &ValidationOptions)
+                                      -> ::std::result::Result<Artifacts<'b>, RejectionReason>
+        where P: CredentialsProvider + ?Sized
+    {
+        self.check_with_provider_validity(header, provider, options).map(|(artifacts, _)| artifacts)
+    }
 
-        true
+    /// Validate the given header, as for `check_with_provider`, also returning the validity of
+    /// the key which matched.
+    pub(crate) fn check_with_provider_validity<'b, P>
+        (&'b self,
+         header: &'b Header,
+         provider: &P,
+         options: &ValidationOptions)
+         -> ::std::result::Result<(Artifacts<'b>, Validity), RejectionReason>
+        where P: CredentialsProvider + ?Sized
+    {
+        let id = header.id().ok_or(RejectionReason::MissingField(HeaderField::Id))?;
+        let now = options.clock.now();
+        let mut normalized = Vec::new();
+        let mut rejection = None;
+        let keys = provider.keys_with_validity(id);
+        if keys.is_empty() && options.equalize_timing {
+            let throwaway = Key::new(vec![0u8; digest::SHA256.output_len], &digest::SHA256);
+            let _ = self.check_header_in(header, &throwaway, options, &mut normalized);
+        }
+        for (key, validity) in keys {
+            let reason = match self.check_header_in(header, &key, options, &mut normalized) {
+                Ok(_) if validity.not_before.is_some_and(|nbf| now < nbf) => {
+                    RejectionReason::CredentialsNotYetValid
+                }
+                Ok(_) if !validity.is_valid_at(now) => RejectionReason::CredentialsExpired,
+                Ok(artifacts) => {
+                    return Ok((artifacts.with_app_principal(provider.is_app(id)), validity))
+                }
+                Err(reason) => reason,
+            };
+            let validity_failure = matches!(reason,
+                                            RejectionReason::CredentialsNotYetValid |
+                                            RejectionReason::CredentialsExpired);
+            if rejection.is_none() || validity_failure {
+                rejection = Some(reason);
+            }
+        }
+        Err(rejection.unwrap_or(RejectionReason::UnknownId))
     }
 
     /// Validate the given bewit matches this request.
@@ -206,49 +656,115 @@ impl<'a> Request<'a> {
     ///
     /// Nonces and hashes do not apply when using bewits.
     pub fn validate_bewit(&self, bewit: &Bewit, key: &Key) -> bool {
+        self.validate_bewit_with_options(bewit, key, &ValidationOptions::default())
+    }
+
+    /// Validate the given bewit matches this request, using the given options.
+    ///
+    /// If `options.allow_head_bewit` is set and this is a `HEAD` request, a bewit issued for the
+    /// same resource with `GET` is also accepted.
+    pub fn validate_bewit_with_options(&self,
+                                       bewit: &Bewit,
+                                       key: &Key,
+                                       options: &ValidationOptions)
+                                       -> bool {
+        self.verify_bewit(bewit, key, options).is_ok()
+    }
+
+    /// Validate the given bewit matches this request, as for `validate_bewit_with_options`, but
+    /// returning the reason for any failure.
+    ///
+    /// The MAC is checked before the expiration time, so `BewitError::Expired` is only returned
+    /// for genuine bewits, and reveals nothing about forgeries.  A genuine bewit with an `ext`
+    /// not permitted by `options.ext_policy` fails with `BewitError::BadExt`.  The bewit is
+    /// accepted until `options.bewit_grace` after its expiration time, according to
+    /// `options.clock`.  A genuine scoped bewit (see `make_scoped_bewit`) used outside its scope
+    /// fails with `BewitError::OutOfScope`.
+    pub fn verify_bewit(&self,
+                        bewit: &Bewit,
+                        key: &Key,
+                        options: &ValidationOptions)
+                        -> ::std::result::Result<(), BewitError> {
+        // a scoped bewit's MAC covers the scope, rather than the requested path
+        let scope = bewit.ext()
+            .filter(|_| options.allow_scoped_bewits)
+            .and_then(|ext| ext.strip_prefix(SCOPE_EXT_PREFIX));
+        let path = scope.unwrap_or(&self.path);
+        let mac_matches = self.bewit_mac_matches(&self.method, path, bewit, key) ||
+                          (options.allow_head_bewit && self.method == "HEAD" &&
+                           self.bewit_mac_matches("GET", path, bewit, key));
+        if !mac_matches {
+            return Err(BewitError::MacMismatch);
+        }
+        if let Some(scope) = scope {
+            if !path_in_scope(&self.path, scope) {
+                return Err(BewitError::OutOfScope);
+            }
+        }
+        if bewit.ext().is_some_and(|ext| !options.ext_policy.permits(ext)) {
+            return Err(BewitError::BadExt);
+        }
+
+        let now = options.clock.now();
+        if bewit.exp() + options.bewit_grace < now {
+            return Err(BewitError::Expired { at: bewit.exp() });
+        }
+
+        Ok(())
+    }
+
+    /// Determine whether the bewit's MAC matches this request, using the given method and path.
+    fn bewit_mac_matches(&self, method: &str, path: &str, bewit: &Bewit, key: &Key) -> bool {
         let calculated_mac = Mac::new(MacType::Bewit,
                                       key,
                                       bewit.exp(),
                                       "",
-                                      self.method,
-                                      self.host,
+                                      method,
+                                      &self.host,
                                       self.port,
-                                      self.path,
-                                      self.hash,
-                                      match bewit.ext() {
-                                          Some(e) => Some(e),
-                                          None => None,
-                                      });
-        let calculated_mac = match calculated_mac {
-            Ok(m) => m,
-            Err(_) => {
-                return false;
-            }
-        };
-
-        if bewit.mac() != &calculated_mac {
-            return false;
+                                      path,
+                                      self.hash(),
+                                      bewit.ext());
+        match calculated_mac {
+            Ok(mac) => crypto::ct_eq(bewit.mac(), &mac),
+            Err(_) => false,
         }
+    }
 
-        let now = time::now().to_timespec();
-        if bewit.exp() < now {
-            return false;
+    /// Get the content hash for this request, if any.
+    pub(crate) fn hash(&self) -> Option<&[u8]> {
+        match self.hash {
+            Some(ref hash) => Some(&hash[..]),
+            None => None,
         }
-
-        true
     }
 
     /// Get a Response instance for a response to this request.  This is a convenience
     /// wrapper around `Response::from_request_header`.
-    pub fn make_response_builder(&self, req_header: &'a Header) -> ResponseBuilder<'a> {
+    pub fn make_response_builder<'b>(&'b self, req_header: &'b Header) -> ResponseBuilder<'b> {
         ResponseBuilder::from_request_header(req_header,
-                                             self.method,
-                                             self.host,
+                                             &self.method,
+                                             &self.host,
                                              self.port,
-                                             self.path)
+                                             &self.path)
     }
 }
 
+/// The prefix of the `ext` value of a scoped bewit.
+const SCOPE_EXT_PREFIX: &str = "hawk-scope:";
+
+/// Determine whether the given request path (which may include a query) lies under the given
+/// scope, which ends with `/`.  Paths which may resolve to resources outside the scope, with
+/// `.` or `..` segments or encoded separators after it, are not in scope.
+fn path_in_scope(path: &str, scope: &str) -> bool {
+    let rest = match path.strip_prefix(scope) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let rest = rest.split_once('?').map_or(rest, |(rest, _)| rest);
+    !may_traverse(rest)
+}
+
 #[derive(Debug, Clone)]
 pub struct RequestBuilder<'a>(Request<'a>);
 
@@ -256,10 +772,14 @@ impl<'a> RequestBuilder<'a> {
     /// Create a new request with the given method, host, port, and path.
     pub fn new(method: &'a str, host: &'a str, port: u16, path: &'a str) -> Self {
         RequestBuilder(Request {
-            method: method,
-            host: host,
+            method: Cow::Borrowed(method),
+            host: Cow::Borrowed(host),
             port: port,
-            path: path,
+            path: Cow::Borrowed(path),
+            path_encoding: PathEncoding::AsIs,
+            conformance: Conformance::Strict,
+            method_override: None,
+            method_override_policy: MethodOverride::Transmitted,
             hash: None,
             ext: None,
             app: None,
@@ -270,11 +790,60 @@ impl<'a> RequestBuilder<'a> {
     /// Create a new request with the host, port, and path determined from the URL.
     pub fn from_url(method: &'a str, url: &'a Url) -> Result<Self> {
         let (host, port, path) = RequestBuilder::parse_url(url)?;
+        Ok(RequestBuilder::new(method, host, port, path))
+    }
+
+    /// Create a new request with the host, port, and path determined from a string URL.
+    ///
+    /// Unlike `from_url`, the URL is parsed internally and the resulting host and path are owned
+    /// by the request, so the caller need not keep a `Url` alive alongside the builder.
+    pub fn from_str_url(method: &'a str, url: &str) -> Result<Self> {
+        let url = Url::parse(url).chain_err(|| format!("could not parse url {}", url))?;
+        let (host, port, path) = RequestBuilder::parse_url(&url)?;
+        let (host, path) = (host.to_string(), path.to_string());
         Ok(RequestBuilder(Request {
-            method: method,
-            host: host,
+            method: Cow::Borrowed(method),
+            host: Cow::Owned(host),
             port: port,
-            path: path,
+            path: Cow::Owned(path),
+            path_encoding: PathEncoding::AsIs,
+            conformance: Conformance::Strict,
+            method_override: None,
+            method_override_policy: MethodOverride::Transmitted,
+            hash: None,
+            ext: None,
+            app: None,
+            dlg: None,
+        }))
+    }
+
+    /// Create a new request from an absolute-form request target, as received by a forward
+    /// proxy (`GET http://example.com/path?query HTTP/1.1`).
+    ///
+    /// The target is decomposed as the origin server would see it: the host and port (defaulting
+    /// to the scheme's port) from the authority, and the path including any query, as it would
+    /// appear in an origin-form request target.  Any fragment is discarded.  This allows a proxy
+    /// to validate requests which the client signed for the origin server.
+    pub fn from_absolute_form(method: &'a str, target: &str) -> Result<Self> {
+        let url = Url::parse(target)
+            .chain_err(|| format!("{} is not an absolute-form request target", target))?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            bail!("request target {} does not have an http or https scheme", target);
+        }
+        let (host, port, path) = RequestBuilder::parse_url(&url)?;
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path.to_string(),
+        };
+        Ok(RequestBuilder(Request {
+            method: Cow::Borrowed(method),
+            host: Cow::Owned(host.to_string()),
+            port,
+            path: Cow::Owned(path),
+            path_encoding: PathEncoding::AsIs,
+            conformance: Conformance::Strict,
+            method_override: None,
+            method_override_policy: MethodOverride::Transmitted,
             hash: None,
             ext: None,
             app: None,
@@ -282,21 +851,67 @@ impl<'a> RequestBuilder<'a> {
         }))
     }
 
+    /// Create a new request from the value of a `Host` header, as received by a server, along
+    /// with the scheme on which the request arrived and the path (including any query) from the
+    /// request target.
+    ///
+    /// If the `Host` header has no port, the default port for the scheme is used.  Bracketed
+    /// IPv6 addresses such as `[::1]:8080` are supported; the brackets are retained in the host.
+    pub fn from_host_header(method: &'a str,
+                            scheme: &str,
+                            host: &'a str,
+                            path: &'a str)
+                            -> Result<Self> {
+        // a colon after any closing bracket separates the host from the port
+        let port_sep = host.rfind(':').filter(|&i| host.rfind(']').is_none_or(|b| b < i));
+        let (host, port) = match port_sep {
+            Some(i) => {
+                let port = host[i + 1..].parse::<u16>()
+                    .chain_err(|| format!("invalid port in Host header {}", host))?;
+                (&host[..i], port)
+            }
+            None => {
+                let port = default_port(scheme)
+                    .ok_or_else(|| format!("no default port for scheme {}", scheme))?;
+                (host, port)
+            }
+        };
+        Ok(RequestBuilder::new(method, host, port, path))
+    }
+
+    /// Create a new request with the host, port, and path determined from an `http::Uri`.
+    ///
+    /// If the URI has no explicit port, the default port for its scheme is used. Any query is
+    /// included in the path.  This requires the `http` feature.
+    #[cfg(feature = "http")]
+    pub fn from_uri(method: &'a str, uri: &'a Uri) -> Result<Self> {
+        let host = uri.host()
+            .ok_or_else(|| format!("uri {} has no host", uri))?;
+        let port = uri.port_u16()
+            .or_else(|| uri.scheme_str().and_then(default_port))
+            .ok_or_else(|| format!("uri {} has no port", uri))?;
+        let path = match uri.path_and_query() {
+            Some(path_and_query) => path_and_query.as_str(),
+            None => "/",
+        };
+        Ok(RequestBuilder::new(method, host, port, path))
+    }
+
     /// Set the request method. This should be a capitalized string.
     pub fn method(mut self, method: &'a str) -> Self {
-        self.0.method = method;
+        self.0.method = Cow::Borrowed(method);
         self
     }
 
     /// Set the URL path for the request.
     pub fn path(mut self, path: &'a str) -> Self {
-        self.0.path = path;
+        self.0.path = Cow::Borrowed(path);
         self
     }
 
     /// Set the URL hostname for the request
     pub fn host(mut self, host: &'a str) -> Self {
-        self.0.host = host;
+        self.0.host = Cow::Borrowed(host);
         self
     }
 
@@ -314,10 +929,36 @@ impl<'a> RequestBuilder<'a> {
 
     /// Set the content hash for the request
     pub fn hash<H: Into<Option<&'a [u8]>>>(mut self, hash: H) -> Self {
-        self.0.hash = hash.into();
+        self.0.hash = hash.into().map(Cow::Borrowed);
+        self
+    }
+
+    /// Set the content hash for the request, moving an owned hash into the request.
+    ///
+    /// This is useful when the hash is calculated in the same scope that builds the request, so
+    /// there is no need to keep the hash alive separately.
+    pub fn hash_owned<H: Into<Option<Vec<u8>>>>(mut self, hash: H) -> Self {
+        self.0.hash = hash.into().map(Cow::Owned);
         self
     }
 
+    /// Serialize the given value as a JSON request body, setting the content hash for the
+    /// request to the hash of that body with content type `application/json`.
+    ///
+    /// This returns the updated builder along with the serialized body, which must be sent
+    /// exactly as returned.  This requires the `json` feature.
+    #[cfg(feature = "json")]
+    pub fn json_payload<T>(self,
+                           value: &T,
+                           algorithm: &'static digest::Algorithm)
+                           -> Result<(Self, Vec<u8>)>
+        where T: Serialize
+    {
+        let body = serde_json::to_vec(value).chain_err(|| "could not serialize JSON payload")?;
+        let hash = PayloadHasher::hash("application/json", algorithm, &body);
+        Ok((self.hash_owned(hash), body))
+    }
+
     /// Set the `ext` Hawk property for the request
     pub fn ext<S: Into<Option<&'a str>>>(mut self, ext: S) -> Self {
         self.0.ext = ext.into();
@@ -336,24 +977,123 @@ impl<'a> RequestBuilder<'a> {
         self
     }
 
+    /// Set the percent-encoding policy for the request path.  The policy is applied when the
+    /// request is built, so it affects the path however it was set.
+    pub fn path_encoding(mut self, path_encoding: PathEncoding) -> Self {
+        self.0.path_encoding = path_encoding;
+        self
+    }
+
+    /// Set whether to replicate the quirks of the Node Hawk implementation.  Like the path
+    /// encoding, this is applied when the request is built.
+    pub fn conformance(mut self, conformance: Conformance) -> Self {
+        self.0.conformance = conformance;
+        self
+    }
+
+    /// Set the value of the request's method override header (`X-HTTP-Method-Override`), if
+    /// any.  Whether the override is signed in place of the method depends on the policy set
+    /// with `method_override_policy`; by default, it is not.
+    pub fn method_override<M: Into<Option<&'a str>>>(mut self, method_override: M) -> Self {
+        self.0.method_override = method_override.into();
+        self
+    }
+
+    /// Set which method is signed for a request with a method override.  Like the path
+    /// encoding, this is applied when the request is built, and both client and server should
+    /// use the same policy.
+    pub fn method_override_policy(mut self, policy: MethodOverride) -> Self {
+        self.0.method_override_policy = policy;
+        self
+    }
+
     /// Get the request from this builder
-    pub fn request(self) -> Request<'a> {
+    pub fn request(mut self) -> Request<'a> {
+        let path = mem::replace(&mut self.0.path, Cow::Borrowed(""));
+        self.0.path = self.0.path_encoding.apply(path);
+        let method = mem::replace(&mut self.0.method, Cow::Borrowed(""));
+        let method = self.0.method_override_policy.method(method, self.0.method_override);
+        self.0.method = self.0.conformance.method(method);
+        self.0.ext = self.0.conformance.ext(self.0.ext);
         self.0
     }
 
-    fn parse_url(url: &'a Url) -> Result<(&'a str, u16, &'a str)> {
+    /// Get the request from this builder, first checking that its components are usable.
+    ///
+    /// Unlike `request`, this fails if the method or host is empty, the port is 0, the path does
+    /// not begin with `/` (or is not `*`), or any component contains a newline, which would
+    /// corrupt the normalized string.  Such requests would otherwise only fail later, as a MAC
+    /// mismatch.
+    pub fn build(self) -> Result<Request<'a>> {
+        let req = self.request();
+        if req.method.is_empty() {
+            bail!("request method is empty");
+        }
+        if req.method.contains(char::is_whitespace) {
+            bail!("request method {:?} contains whitespace", req.method);
+        }
+        if req.host.is_empty() {
+            bail!("request host is empty");
+        }
+        if req.host.contains(|c: char| c == '/' || char::is_whitespace(c)) {
+            bail!("request host {:?} is not a bare hostname", req.host);
+        }
+        if req.port == 0 {
+            bail!("request port is 0");
+        }
+        if !req.path.starts_with('/') && req.path != "*" {
+            bail!("request path {:?} does not begin with `/`", req.path);
+        }
+        if req.path.contains('\n') {
+            bail!("request path {:?} contains a newline", req.path);
+        }
+        if req.ext.is_some_and(|ext| ext.contains('\n')) {
+            bail!("request ext contains a newline");
+        }
+        Ok(req)
+    }
+
+    /// Get the request from this builder, as for `build`, additionally checking that its `ext`
+    /// is permitted by the given policy.  Use this to catch oversized or unsafe `ext` values
+    /// before sending them, rather than when a proxy or the server rejects them.
+    pub fn build_with_ext_policy(self, policy: &ExtPolicy) -> Result<Request<'a>> {
+        let req = self.build()?;
+        if let Some(ext) = req.ext {
+            policy.check(ext)?;
+        }
+        Ok(req)
+    }
+
+    fn parse_url(url: &Url) -> Result<(&str, u16, &str)> {
         let host = url.host_str()
             .ok_or_else(|| format!("url {} has no host", url))?;
-        let port = url.port_or_known_default()
+        let port = url.port()
+            .or_else(|| default_port(url.scheme()))
             .ok_or_else(|| format!("url {} has no port", url))?;
         let path = url.path();
         Ok((host, port, path))
     }
 }
 
+/// Get the default port for the given URL scheme: 80 for `http` and `ws`, and 443 for `https`
+/// and `wss`.  Schemes are case-insensitive.
+///
+/// This is used wherever the crate infers a port that was not given explicitly, so the same
+/// defaults apply whether a request is built from a URL, a `Host` header, or a proxy's request
+/// target.
+pub fn default_port(scheme: &str) -> Option<u16> {
+    if scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("ws") {
+        Some(80)
+    } else if scheme.eq_ignore_ascii_case("https") || scheme.eq_ignore_ascii_case("wss") {
+        Some(443)
+    } else {
+        None
+    }
+}
+
 /// Create a random string with `bytes` bytes of entropy.  The string
 /// is base64-encoded. so it will be longer than bytes characters.
-fn random_string(bytes: usize) -> String {
+pub(crate) fn random_string(bytes: usize) -> String {
     let mut rng = rand::thread_rng();
     let mut bytes = vec![0u8; bytes];
     rng.fill_bytes(&mut bytes);
@@ -363,10 +1103,13 @@ fn random_string(bytes: usize) -> String {
 #[cfg(test)]
 mod test {
     use super::*;
-    use time::Timespec;
+    use time::{now, Timespec};
+    use validation::{ExtCharset, NonceCharset, NoncePolicy};
     use credentials::{Credentials, Key};
-    use header::Header;
+    use hawk_core::{Header, MacBytes};
     use url::Url;
+    use clock::FixedClock;
+    use std::sync::Arc;
     use ring::digest;
     use std::str::FromStr;
 
@@ -402,12 +1145,44 @@ mod test {
         assert_eq!(req.path, "/foo");
         assert_eq!(req.host, "example.com");
         assert_eq!(req.port, 443);
-        assert_eq!(req.hash, Some(&hash[..]));
+        assert_eq!(req.hash, Some(Cow::Borrowed(&hash[..])));
         assert_eq!(req.ext, Some("ext"));
         assert_eq!(req.app, Some("app"));
         assert_eq!(req.dlg, Some("dlg"));
     }
 
+    #[test]
+    fn test_builder_hash_owned() {
+        let req = RequestBuilder::new("GET", "example.com", 443, "/foo")
+            .hash_owned(vec![1, 2, 3, 4])
+            .request();
+        assert_eq!(req.hash, Some(Cow::Owned(vec![1, 2, 3, 4])));
+
+        let req = RequestBuilder::new("GET", "example.com", 443, "/foo")
+            .hash_owned(vec![1, 2, 3, 4])
+            .hash_owned(None)
+            .request();
+        assert_eq!(req.hash, None);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_builder_json_payload() {
+        use payload::PayloadHasher;
+        let mut value = ::std::collections::BTreeMap::new();
+        value.insert("user", "me");
+        let (bldr, body) = RequestBuilder::new("POST", "example.com", 443, "/foo")
+            .json_payload(&value, &digest::SHA256)
+            .unwrap();
+        let req = bldr.request();
+
+        assert_eq!(body, b"{\"user\":\"me\"}".to_vec());
+        assert_eq!(req.hash,
+                   Some(Cow::Owned(PayloadHasher::hash("application/json",
+                                                       &digest::SHA256,
+                                                       &body))));
+    }
+
     #[test]
     fn test_builder_clone() {
         let rb = RequestBuilder::new("GET", "site", 443, "/foo");
@@ -430,6 +1205,50 @@ mod test {
         assert_eq!(req.port, 443); // default for https
     }
 
+    #[test]
+    fn test_str_url_builder() {
+        let req = RequestBuilder::from_str_url("GET", "http://example.com:8080/foo?x=y")
+            .unwrap()
+            .request();
+
+        assert_eq!(req.path, "/foo");
+        assert_eq!(req.host, "example.com");
+        assert_eq!(req.port, 8080);
+    }
+
+    #[test]
+    fn test_str_url_builder_invalid() {
+        assert!(RequestBuilder::from_str_url("GET", "not a url").is_err());
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_uri_builder() {
+        let uri = "https://example.com/foo?bar=baz".parse::<::http::Uri>().unwrap();
+        let req = RequestBuilder::from_uri("GET", &uri).unwrap().request();
+
+        assert_eq!(req.path, "/foo?bar=baz");
+        assert_eq!(req.host, "example.com");
+        assert_eq!(req.port, 443); // default for https
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_uri_builder_explicit_port() {
+        let uri = "http://example.com:8080/".parse::<::http::Uri>().unwrap();
+        let req = RequestBuilder::from_uri("GET", &uri).unwrap().request();
+
+        assert_eq!(req.path, "/");
+        assert_eq!(req.port, 8080);
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_uri_builder_no_host() {
+        let uri = "/foo".parse::<::http::Uri>().unwrap();
+        assert!(RequestBuilder::from_uri("GET", &uri).is_err());
+    }
+
     #[test]
     fn test_make_header_full() {
         let req = RequestBuilder::new("GET", "example.com", 443, "/foo").request();
@@ -440,18 +1259,18 @@ mod test {
         let header = req.make_header_full(&credentials, Timespec::new(1000, 100), "nonny")
             .unwrap();
         assert_eq!(header,
-                   Header {
-                       id: Some("me".to_string()),
-                       ts: Some(Timespec::new(1000, 100)),
-                       nonce: Some("nonny".to_string()),
-                       mac: Some(Mac::from(vec![122, 47, 2, 53, 195, 247, 185, 107, 133, 250,
-                                                61, 134, 200, 35, 118, 94, 48, 175, 237, 108,
-                                                60, 71, 4, 2, 244, 66, 41, 172, 91, 7, 233, 140])),
-                       ext: None,
-                       hash: None,
-                       app: None,
-                       dlg: None,
-                   });
+                   Header::new(Some("me"),
+                               Some(Timespec::new(1000, 100)),
+                               Some("nonny"),
+                               Some(MacBytes::from(vec![122, 47, 2, 53, 195, 247, 185, 107, 133,
+                                                        250, 61, 134, 200, 35, 118, 94, 48, 175,
+                                                        237, 108, 60, 71, 4, 2, 244, 66, 41, 172,
+                                                        91, 7, 233, 140])),
+                               None,
+                               None,
+                               None,
+                               None)
+                       .unwrap());
     }
 
     #[test]
@@ -470,36 +1289,134 @@ mod test {
         let header = req.make_header_full(&credentials, Timespec::new(1000, 100), "nonny")
             .unwrap();
         assert_eq!(header,
-                   Header {
-                       id: Some("me".to_string()),
-                       ts: Some(Timespec::new(1000, 100)),
-                       nonce: Some("nonny".to_string()),
-                       mac: Some(Mac::from(vec![72, 123, 243, 214, 145, 81, 129, 54, 183, 90,
-                                                22, 136, 192, 146, 208, 53, 216, 138, 145, 94,
-                                                175, 204, 217, 8, 77, 16, 202, 50, 10, 144, 133,
-                                                162])),
-                       ext: Some("ext".to_string()),
-                       hash: Some(hash.clone()),
-                       app: Some("app".to_string()),
-                       dlg: Some("dlg".to_string()),
-                   });
+                   Header::new(Some("me"),
+                               Some(Timespec::new(1000, 100)),
+                               Some("nonny"),
+                               // the MAC covers app and dlg, as in the Node implementation
+                               Some(MacBytes::from(vec![93, 12, 77, 2, 71, 247, 66, 197, 252, 184,
+                                                        121, 57, 90, 176, 150, 87, 9, 129, 224, 108,
+                                                        48, 84, 89, 168, 94, 18, 150, 156, 145, 142,
+                                                        108, 165])),
+                               Some("ext"),
+                               Some(hash.clone()),
+                               Some("app"),
+                               Some("dlg"))
+                       .unwrap());
     }
 
     #[test]
-    fn test_validate_matches_generated() {
-        let req = RequestBuilder::new("GET", "example.com", 443, "/foo").request();
+    fn test_sign_to_string() {
+        let hash = [0u8];
+        let req = RequestBuilder::new("GET", "example.com", 443, "/foo")
+            .hash(Some(&hash[..]))
+            .ext("ext")
+            .app("app")
+            .request();
         let credentials = Credentials {
             id: "me".to_string(),
             key: Key::new(vec![99u8; 32], &digest::SHA256),
         };
-        let header = req.make_header_full(&credentials, now().to_timespec(), "nonny")
-            .unwrap();
-        assert!(req.validate_header(&header, &credentials.key, Duration::minutes(1)));
+        let signed = {
+            let _deterministic = ::testing::deterministic_for_tests();
+            req.sign_to_string(&credentials).unwrap()
+        };
+        let header = {
+            let _deterministic = ::testing::deterministic_for_tests();
+            req.make_header(&credentials).unwrap()
+        };
+        assert_eq!(signed, format!("Hawk {}", header));
     }
 
     #[test]
-    fn test_validate_real_request() {
-        let header = Header::from_str(REAL_HEADER).unwrap();
+    fn test_conformance_node_hawk() {
+        // the header example from the Hawk specification, signed with a lower-case method
+        let credentials = Credentials {
+            id: "dh37fgj492je".to_string(),
+            key: Key::new(&b"werxhqb98rpaxn39848xrunpaw3489ruxnpa98w4rxn"[..], &digest::SHA256),
+        };
+        let ts = Timespec::new(1353832234, 0);
+        let make = |method, conformance| {
+            RequestBuilder::new(method, "example.com", 8000, "/resource/1?b=1&a=2")
+                .ext("some-app-ext-data")
+                .conformance(conformance)
+                .request()
+                .make_header_full(&credentials, ts, "j4h3g2")
+                .unwrap()
+        };
+        let expected = base64::decode("6R4rV5iE+NPoym+WwjeHzjAGXUtLNIxmo1vpMofpLAE=").unwrap();
+        assert_eq!(&make("GET", Conformance::Strict).mac().unwrap()[..], &expected[..]);
+        assert_eq!(&make("get", Conformance::NodeHawk).mac().unwrap()[..], &expected[..]);
+        assert_ne!(&make("get", Conformance::Strict).mac().unwrap()[..], &expected[..]);
+
+        // the server applies the same normalization
+        let server = RequestBuilder::new("get", "example.com", 8000, "/resource/1?b=1&a=2")
+            .conformance(Conformance::NodeHawk)
+            .request();
+        assert_eq!(server.method(), "GET");
+    }
+
+    #[test]
+    fn test_conformance_empty_ext() {
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        let make = |conformance| {
+            RequestBuilder::new("GET", "example.com", 443, "/")
+                .ext("")
+                .conformance(conformance)
+                .request()
+                .make_header_full(&credentials, Timespec::new(1000, 0), "nonny")
+                .unwrap()
+        };
+        let (strict, node) = (make(Conformance::Strict), make(Conformance::NodeHawk));
+        assert!(strict.to_string().contains("ext=\"\""));
+        assert!(!node.to_string().contains("ext="));
+        // an empty ext and no ext produce the same MAC
+        assert_eq!(strict.mac(), node.mac());
+    }
+
+    #[test]
+    fn test_empty_ext_as_absent() {
+        let credentials = bewit_credentials();
+        let req = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let ts = now().to_timespec();
+        let without = req.make_header_full(&credentials, ts, "nonny").unwrap();
+        let with_empty = RequestBuilder::new("GET", "example.com", 443, "/")
+            .ext("")
+            .request()
+            .make_header_full(&credentials, ts, "nonny")
+            .unwrap();
+        assert_eq!(without.mac(), with_empty.mac());
+
+        // a parsed `ext=""` validates either way, and is only reported as absent if configured
+        let parsed = Header::from_str(&with_empty.to_string()).unwrap();
+        assert_eq!(parsed.ext(), Some(""));
+        let options = ValidationOptions::default();
+        let artifacts = req.authenticate_header(&parsed, &credentials.key, &options).unwrap();
+        assert_eq!(artifacts.ext(), Some(""));
+        let options = options.with_empty_ext_as_absent(true);
+        let artifacts = req.authenticate_header(&parsed, &credentials.key, &options).unwrap();
+        assert_eq!(artifacts.ext(), None);
+        let artifacts = req.authenticate_header(&without, &credentials.key, &options).unwrap();
+        assert_eq!(artifacts.ext(), None);
+    }
+
+    #[test]
+    fn test_validate_matches_generated() {
+        let req = RequestBuilder::new("GET", "example.com", 443, "/foo").request();
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        let header = req.make_header_full(&credentials, now().to_timespec(), "nonny")
+            .unwrap();
+        assert!(req.validate_header(&header, &credentials.key, Duration::minutes(1)));
+    }
+
+    #[test]
+    fn test_validate_real_request() {
+        let header = Header::from_str(REAL_HEADER).unwrap();
         let credentials = Credentials {
             id: "me".to_string(),
             key: Key::new("tok", &digest::SHA256),
@@ -538,9 +1455,9 @@ mod test {
         Header::new(Some("dh37fgj492je"),
                     Some(Timespec::new(1353832234, 0)),
                     Some("j4h3g2"),
-                    Some(Mac::from(vec![161, 105, 122, 110, 248, 62, 129, 193, 148, 206, 239,
-                                        193, 219, 46, 137, 221, 51, 170, 135, 114, 81, 68, 145,
-                                        182, 15, 165, 145, 168, 114, 237, 52, 35])),
+                    Some(MacBytes::from(vec![161, 105, 122, 110, 248, 62, 129, 193, 148, 206, 239,
+                                             193, 219, 46, 137, 221, 51, 170, 135, 114, 81, 68, 145,
+                                             182, 15, 165, 145, 168, 114, 237, 52, 35])),
                     None,
                     None,
                     None,
@@ -552,9 +1469,9 @@ mod test {
         Header::new(Some("dh37fgj492je"),
                     Some(Timespec::new(1353832234, 0)),
                     Some("j4h3g2"),
-                    Some(Mac::from(vec![189, 53, 155, 244, 203, 150, 255, 238, 135, 144, 186,
-                                        93, 6, 189, 184, 21, 150, 210, 226, 61, 93, 154, 17,
-                                        218, 142, 250, 254, 193, 123, 132, 131, 195])),
+                    Some(MacBytes::from(vec![189, 53, 155, 244, 203, 150, 255, 238, 135, 144, 186,
+                                             93, 6, 189, 184, 21, 150, 210, 226, 61, 93, 154, 17,
+                                             218, 142, 250, 254, 193, 123, 132, 131, 195])),
                     None,
                     Some(vec![1, 2, 3, 4]),
                     None,
@@ -592,6 +1509,17 @@ mod test {
                                      Duration::weeks(52000)));
     }
 
+    #[test]
+    fn test_validate_hash_owned_validated() {
+        let header = make_header_with_hash();
+        let req = RequestBuilder::new("", "", 0, "")
+            .hash_owned(vec![1, 2, 3, 4])
+            .request();
+        assert!(req.validate_header(&header,
+                                    &Key::new("tok", &digest::SHA256),
+                                    Duration::weeks(52000)));
+    }
+
     #[test]
     fn test_validate_hash_validated() {
         let header = make_header_with_hash();
@@ -613,11 +1541,221 @@ mod test {
                                      Duration::weeks(52000)));
     }
 
-    fn round_trip_bewit(req: Request, duration: Duration, expected: bool) {
+    #[test]
+    fn test_validate_payload_policy() {
         let credentials = Credentials {
             id: "me".to_string(),
-            key: Key::new("tok", &digest::SHA256),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        let hash = [1, 2, 3, 4];
+        for &(method, policy, with_hash, expected) in
+            &[("POST", PayloadPolicy::Never, false, true),
+              ("POST", PayloadPolicy::IfPresent, false, true),
+              ("POST", PayloadPolicy::IfPresent, true, true),
+              ("POST", PayloadPolicy::BodiedMethods, false, false),
+              ("POST", PayloadPolicy::BodiedMethods, true, true),
+              ("GET", PayloadPolicy::BodiedMethods, false, true),
+              ("GET", PayloadPolicy::Always, false, false),
+              ("GET", PayloadPolicy::Always, true, true)] {
+            let client_req = RequestBuilder::new(method, "example.com", 443, "/foo")
+                .hash(if with_hash { Some(&hash[..]) } else { None })
+                .request();
+            let header = client_req.make_header(&credentials).unwrap();
+            let server_req = RequestBuilder::new(method, "example.com", 443, "/foo").request();
+            let options = ValidationOptions {
+                require_payload_hash: policy,
+                ..ValidationOptions::default()
+            };
+            assert_eq!(server_req.validate_header_with_options(&header,
+                                                               &credentials.key,
+                                                               &options),
+                       expected,
+                       "{} {:?} {}",
+                       method,
+                       policy,
+                       with_hash);
+        }
+    }
+
+    #[test]
+    fn test_validate_hash_if_present() {
+        let options = ValidationOptions {
+            require_payload_hash: PayloadPolicy::IfPresent,
+            ts_skew: Duration::weeks(52000),
+            ..ValidationOptions::default()
+        };
+        let key = Key::new("tok", &digest::SHA256);
+        let hash = [1, 2, 3, 4];
+        let req = RequestBuilder::new("", "", 0, "")
+            .hash(Some(&hash[..]))
+            .request();
+
+        // a header without a hash is accepted even though one was supplied..
+        assert!(req.validate_header_with_options(&make_header_without_hash(), &key, &options));
+        // ..but a hash in the header must still match
+        assert!(req.validate_header_with_options(&make_header_with_hash(), &key, &options));
+        let wrong_hash = [99, 99, 99, 99];
+        let req = RequestBuilder::new("", "", 0, "")
+            .hash(Some(&wrong_hash[..]))
+            .request();
+        assert!(!req.validate_header_with_options(&make_header_with_hash(), &key, &options));
+    }
+
+    #[test]
+    fn test_make_header_with() {
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        let req = RequestBuilder::new("GET", "example.com", 443, "/foo?a=1")
+            .app("app")
+            .dlg("dlg")
+            .request();
+        let options = HeaderOptions {
+            clock: Arc::new(FixedClock(Timespec::new(1000, 0))),
+            ts_offset: Duration::seconds(5),
+            nonce_generator: Some(Arc::new(::nonce::SequentialNonceGenerator::new("n"))),
+            ..HeaderOptions::default()
+        };
+
+        // the defaults match make_header_full
+        let header = req.make_header_with(&credentials, &options).unwrap();
+        assert_eq!(header,
+                   req.make_header_full(&credentials, Timespec::new(1005, 0), "n0").unwrap());
+
+        // excluding the query signs the path alone
+        let without_query = HeaderOptions { include_query: false, ..options.clone() };
+        let header = req.make_header_with(&credentials, &without_query).unwrap();
+        let expected = RequestBuilder::new("GET", "example.com", 443, "/foo")
+            .app("app")
+            .dlg("dlg")
+            .request()
+            .make_header_full(&credentials, Timespec::new(1005, 0), "n1")
+            .unwrap();
+        assert_eq!(header, expected);
+
+        // the key must use the required digest
+        let sha512 = HeaderOptions { digest: Some(&digest::SHA512), ..options.clone() };
+        assert!(req.make_header_with(&credentials, &sha512).is_err());
+    }
+
+    #[test]
+    fn test_accept_mac_without_query() {
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        let server = RequestBuilder::new("GET", "example.com", 443, "/foo?a=1").request();
+        let legacy = RequestBuilder::new("GET", "example.com", 443, "/foo")
+            .request()
+            .make_header(&credentials)
+            .unwrap();
+        let current = server.make_header(&credentials).unwrap();
+
+        let mut options = ValidationOptions::default();
+        assert!(server.authenticate_header(&legacy, &credentials.key, &options).is_none());
+
+        options.accept_mac_without_query = true;
+        let artifacts = server.authenticate_header(&legacy, &credentials.key, &options).unwrap();
+        assert!(artifacts.is_signed_without_query());
+        assert_eq!(artifacts.path(), "/foo");
+        let artifacts = server.authenticate_header(&current, &credentials.key, &options).unwrap();
+        assert!(!artifacts.is_signed_without_query());
+        assert_eq!(artifacts.path(), "/foo?a=1");
+
+        // a header signed for another path still fails
+        let other = RequestBuilder::new("GET", "example.com", 443, "/bar")
+            .request()
+            .make_header(&credentials)
+            .unwrap();
+        assert!(server.authenticate_header(&other, &credentials.key, &options).is_none());
+    }
+
+    #[test]
+    fn test_authenticate_with_provider() {
+        use credentials::SharedCredentials;
+        let old_key = Key::new(vec![1u8; 32], &digest::SHA256);
+        let new_key = Key::new(vec![2u8; 32], &digest::SHA256);
+        let provider = SharedCredentials::new();
+        provider.insert("me", old_key.clone());
+        provider.insert("me", new_key.clone());
+
+        let req = RequestBuilder::new("GET", "example.com", 443, "/foo").request();
+        let options = ValidationOptions::default();
+        for key in &[old_key, new_key] {
+            let credentials = Credentials {
+                id: "me".to_string(),
+                key: key.clone(),
+            };
+            let header = req.make_header(&credentials).unwrap();
+            assert!(req.authenticate_with_provider(&header, &provider, &options).is_some());
+
+            // an unknown id does not validate
+            let credentials = Credentials {
+                id: "you".to_string(),
+                key: credentials.key,
+            };
+            let header = req.make_header(&credentials).unwrap();
+            assert!(req.authenticate_with_provider(&header, &provider, &options).is_none());
+        }
+
+        // a key that is not in the provider does not validate
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![3u8; 32], &digest::SHA256),
         };
+        let header = req.make_header(&credentials).unwrap();
+        assert!(req.authenticate_with_provider(&header, &provider, &options).is_none());
+    }
+
+    #[test]
+    fn test_equalize_timing() {
+        use credentials::SharedCredentials;
+        let provider = SharedCredentials::new();
+        provider.insert("me", Key::new(vec![1u8; 32], &digest::SHA256));
+        let req = RequestBuilder::new("GET", "example.com", 443, "/foo").request();
+        let options = ValidationOptions::default()
+            .with_equalize_timing(true)
+            .with_ext_policy(ExtPolicy { max_len: Some(2), ..ExtPolicy::default() });
+
+        // an unknown id is still rejected, even if signed with the throwaway key
+        let throwaway = Credentials {
+            id: "you".to_string(),
+            key: Key::new(vec![0u8; 32], &digest::SHA256),
+        };
+        let header = req.make_header(&throwaway).unwrap();
+        assert_eq!(req.check_with_provider(&header, &provider, &options).unwrap_err(),
+                   RejectionReason::UnknownId);
+
+        // a non-permitted ext is rejected after calculating the normalized string and MAC
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![1u8; 32], &digest::SHA256),
+        };
+        let header = RequestBuilder::new("GET", "example.com", 443, "/foo")
+            .ext("too long")
+            .request()
+            .make_header(&credentials)
+            .unwrap();
+        let rejected = req.check_header(&header, &credentials.key, &options).unwrap_err();
+        assert_eq!(rejected.reason(), RejectionReason::ExtNotPermitted);
+        assert!(rejected.normalized_hash().is_some());
+
+        let options = options.with_equalize_timing(false);
+        let rejected = req.check_header(&header, &credentials.key, &options).unwrap_err();
+        assert_eq!(rejected.reason(), RejectionReason::ExtNotPermitted);
+        assert!(rejected.normalized_hash().is_none());
+    }
+
+    fn bewit_credentials() -> Credentials {
+        Credentials {
+            id: "me".to_string(),
+            key: Key::new("tok", &digest::SHA256),
+        }
+    }
+
+    fn round_trip_bewit(req: Request, duration: Duration, expected: bool) {
+        let credentials = bewit_credentials();
 
         let bewit = req.make_bewit(&credentials, duration).unwrap();
 
@@ -648,4 +1786,474 @@ mod test {
         let req = RequestBuilder::new("GET", "foo.com", 443, "/x/y/z").request();
         round_trip_bewit(req, Duration::minutes(-10), false);
     }
+
+    #[test]
+    fn test_validate_bewit_head() {
+        let credentials = bewit_credentials();
+        let get_req = RequestBuilder::new("GET", "foo.com", 443, "/x/y/z").request();
+        let head_req = RequestBuilder::new("HEAD", "foo.com", 443, "/x/y/z").request();
+        let allow_head = ValidationOptions::default().with_allow_head_bewit(true);
+
+        // a bewit issued for GET is accepted for HEAD only when allowed
+        let bewit = get_req.make_bewit(&credentials, Duration::minutes(10)).unwrap();
+        assert!(!head_req.validate_bewit(&bewit, &credentials.key));
+        assert!(head_req.validate_bewit_with_options(&bewit, &credentials.key, &allow_head));
+        assert!(get_req.validate_bewit_with_options(&bewit, &credentials.key, &allow_head));
+
+        // a bewit issued for HEAD still validates for HEAD, but never for GET
+        let bewit = head_req.make_bewit(&credentials, Duration::minutes(10)).unwrap();
+        assert!(head_req.validate_bewit(&bewit, &credentials.key));
+        assert!(head_req.validate_bewit_with_options(&bewit, &credentials.key, &allow_head));
+        assert!(!get_req.validate_bewit(&bewit, &credentials.key));
+        assert!(!get_req.validate_bewit_with_options(&bewit, &credentials.key, &allow_head));
+    }
+
+    #[test]
+    fn test_validate_bewit_head_other_methods() {
+        let credentials = bewit_credentials();
+        let get_req = RequestBuilder::new("GET", "foo.com", 443, "/x/y/z").request();
+        let post_req = RequestBuilder::new("POST", "foo.com", 443, "/x/y/z").request();
+        let allow_head = ValidationOptions::default().with_allow_head_bewit(true);
+
+        let bewit = get_req.make_bewit(&credentials, Duration::minutes(10)).unwrap();
+        assert!(!post_req.validate_bewit_with_options(&bewit, &credentials.key, &allow_head));
+    }
+
+    #[test]
+    fn test_validate_bewit_head_expired() {
+        let credentials = bewit_credentials();
+        let get_req = RequestBuilder::new("GET", "foo.com", 443, "/x/y/z").request();
+        let head_req = RequestBuilder::new("HEAD", "foo.com", 443, "/x/y/z").request();
+        let allow_head = ValidationOptions::default().with_allow_head_bewit(true);
+
+        let bewit = get_req.make_bewit(&credentials, Duration::minutes(-10)).unwrap();
+        assert!(!head_req.validate_bewit_with_options(&bewit, &credentials.key, &allow_head));
+    }
+
+    #[test]
+    fn test_verify_bewit_errors() {
+        let credentials = bewit_credentials();
+        let req = RequestBuilder::new("GET", "foo.com", 443, "/x/y/z").request();
+        let options = ValidationOptions::default();
+
+        let bewit = req.make_bewit(&credentials, Duration::minutes(10)).unwrap();
+        assert_eq!(req.verify_bewit(&bewit, &credentials.key, &options), Ok(()));
+
+        let other_req = RequestBuilder::new("GET", "foo.com", 443, "/x/y").request();
+        assert_eq!(other_req.verify_bewit(&bewit, &credentials.key, &options),
+                   Err(BewitError::MacMismatch));
+
+        let expired = req.make_bewit(&credentials, Duration::minutes(-10)).unwrap();
+        assert_eq!(req.verify_bewit(&expired, &credentials.key, &options),
+                   Err(BewitError::Expired { at: expired.exp() }));
+
+        // an expired bewit for a different request is a mismatch, not expired
+        assert_eq!(other_req.verify_bewit(&expired, &credentials.key, &options),
+                   Err(BewitError::MacMismatch));
+    }
+
+    #[test]
+    fn test_verify_bewit_grace() {
+        let credentials = bewit_credentials();
+        let req = RequestBuilder::new("GET", "foo.com", 443, "/x/y/z").request();
+        let bewit = req.make_bewit(&credentials, Duration::minutes(10)).unwrap();
+        let after = |secs| {
+            ValidationOptions {
+                clock: Arc::new(FixedClock(bewit.exp() + Duration::seconds(secs))),
+                bewit_grace: Duration::seconds(5),
+                ..ValidationOptions::default()
+            }
+        };
+
+        assert_eq!(req.verify_bewit(&bewit, &credentials.key, &after(-1)), Ok(()));
+        assert_eq!(req.verify_bewit(&bewit, &credentials.key, &after(5)), Ok(()));
+        assert_eq!(req.verify_bewit(&bewit, &credentials.key, &after(6)),
+                   Err(BewitError::Expired { at: bewit.exp() }));
+    }
+
+    #[test]
+    fn test_verify_scoped_bewit() {
+        let credentials = bewit_credentials();
+        let scope = RequestBuilder::new("GET", "foo.com", 443, "/downloads/build-123/").request();
+        let bewit = scope.make_scoped_bewit(&credentials, Duration::minutes(10)).unwrap();
+        let bewit = Bewit::from_str(&bewit.to_str()).unwrap();
+        assert_eq!(bewit.ext(), Some("hawk-scope:/downloads/build-123/"));
+        let scoped = ValidationOptions::default().with_allow_scoped_bewits(true);
+        let verify = |path, options| {
+            RequestBuilder::new("GET", "foo.com", 443, path)
+                .request()
+                .verify_bewit(&bewit, &credentials.key, options)
+        };
+
+        assert_eq!(verify("/downloads/build-123/app.zip", &scoped), Ok(()));
+        assert_eq!(verify("/downloads/build-123/sub/dir?x=1", &scoped), Ok(()));
+        assert_eq!(verify("/downloads/build-123/", &scoped), Ok(()));
+        assert_eq!(verify("/downloads/build-1234/app.zip", &scoped),
+                   Err(BewitError::OutOfScope));
+        assert_eq!(verify("/downloads/build-123/../build-124/app.zip", &scoped),
+                   Err(BewitError::OutOfScope));
+        assert_eq!(verify("/downloads/build-123/%2E%2e/build-124/app.zip", &scoped),
+                   Err(BewitError::OutOfScope));
+        for path in &["/downloads/build-123/..%2f..%2fsecret",
+                      "/downloads/build-123/..%2F..%2Fsecret",
+                      "/downloads/build-123/..%5csecret",
+                      "/downloads/build-123/..%5Csecret",
+                      "/downloads/build-123/..\\secret"] {
+            assert_eq!(verify(path, &scoped), Err(BewitError::OutOfScope), "{}", path);
+        }
+
+        // without the option, the bewit is only valid for the scope path itself
+        let options = ValidationOptions::default();
+        assert_eq!(verify("/downloads/build-123/app.zip", &options),
+                   Err(BewitError::MacMismatch));
+        assert_eq!(verify("/downloads/build-123/", &options), Ok(()));
+
+        // a bewit for a single resource cannot be widened into a scoped bewit
+        let single = RequestBuilder::new("GET", "foo.com", 443, "/downloads/build-123/")
+            .ext("hawk-scope:/downloads/")
+            .request()
+            .make_bewit(&credentials, Duration::minutes(10))
+            .unwrap();
+        assert_eq!(RequestBuilder::new("GET", "foo.com", 443, "/downloads/build-124/app.zip")
+                       .request()
+                       .verify_bewit(&single, &credentials.key, &scoped),
+                   Err(BewitError::MacMismatch));
+    }
+
+    #[test]
+    fn test_make_scoped_bewit_bad_path() {
+        let credentials = bewit_credentials();
+        for path in &["/downloads/build-123", "/downloads/?x=1/"] {
+            assert!(RequestBuilder::new("GET", "foo.com", 443, path)
+                .request()
+                .make_scoped_bewit(&credentials, Duration::minutes(10))
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_validate_header_clock() {
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        let req = RequestBuilder::new("GET", "foo.com", 443, "/x").request();
+        let header = req.make_header_full(&credentials, Timespec::new(1000, 0), "nonny").unwrap();
+
+        let at = |secs| {
+            ValidationOptions {
+                clock: Arc::new(FixedClock(Timespec::new(secs, 0))),
+                ..ValidationOptions::default()
+            }
+        };
+        assert!(req.validate_header_with_options(&header, &credentials.key, &at(1030)));
+        assert!(!req.validate_header_with_options(&header, &credentials.key, &at(1100)));
+    }
+
+    #[test]
+    fn test_build() {
+        assert!(RequestBuilder::new("GET", "example.com", 443, "/").build().is_ok());
+        assert!(RequestBuilder::new("OPTIONS", "example.com", 443, "*").build().is_ok());
+        assert!(RequestBuilder::new("GET", "example.com", 443, "/x").ext("abc").build().is_ok());
+    }
+
+    #[test]
+    fn test_build_invalid() {
+        let invalid = [RequestBuilder::new("", "example.com", 443, "/"),
+                       RequestBuilder::new("GE T", "example.com", 443, "/"),
+                       RequestBuilder::new("GET", "", 443, "/"),
+                       RequestBuilder::new("GET", "example.com/x", 443, "/"),
+                       RequestBuilder::new("GET", "example.com", 0, "/"),
+                       RequestBuilder::new("GET", "example.com", 443, ""),
+                       RequestBuilder::new("GET", "example.com", 443, "x/y"),
+                       RequestBuilder::new("GET", "example.com", 443, "/x\ny"),
+                       RequestBuilder::new("GET", "example.com", 443, "/").ext("a\nb")];
+        for bldr in invalid.iter() {
+            assert!(bldr.clone().build().is_err(), "{:?} should be invalid", bldr);
+        }
+    }
+
+    #[test]
+    fn test_build_with_ext_policy() {
+        let policy = ExtPolicy { max_len: Some(8), charset: ExtCharset::PrintableAscii };
+        let build = |ext| {
+            RequestBuilder::new("GET", "example.com", 443, "/")
+                .ext(ext)
+                .build_with_ext_policy(&policy)
+        };
+        assert!(build(None).is_ok());
+        assert!(build(Some("a=1")).is_ok());
+        assert!(build(Some("a=123456789")).is_err());
+        assert!(build(Some("caf\u{e9}")).is_err());
+    }
+
+    #[test]
+    fn test_validate_ext_policy() {
+        let credentials = bewit_credentials();
+        let req = RequestBuilder::new("GET", "foo.com", 443, "/x/y/z")
+            .ext("caf\u{e9}")
+            .request();
+        let strict = ValidationOptions {
+            ext_policy: ExtPolicy { max_len: None, charset: ExtCharset::PrintableAscii },
+            ..ValidationOptions::default()
+        };
+
+        let header = req.make_header(&credentials).unwrap();
+        assert!(req.validate_header_with_options(&header,
+                                                 &credentials.key,
+                                                 &ValidationOptions::default()));
+        assert!(!req.validate_header_with_options(&header, &credentials.key, &strict));
+
+        let bewit = req.make_bewit(&credentials, Duration::minutes(10)).unwrap();
+        assert_eq!(req.verify_bewit(&bewit, &credentials.key, &ValidationOptions::default()),
+                   Ok(()));
+        assert_eq!(req.verify_bewit(&bewit, &credentials.key, &strict),
+                   Err(BewitError::BadExt));
+    }
+
+    #[test]
+    fn test_validate_nonce_policy() {
+        let credentials = bewit_credentials();
+        let req = RequestBuilder::new("GET", "foo.com", 443, "/x/y/z").request();
+        let header = req.make_header_full(&credentials, now().to_timespec(), "a+b/c=").unwrap();
+        assert!(req.validate_header_with_options(&header,
+                                                 &credentials.key,
+                                                 &ValidationOptions::default()));
+
+        let url_safe = ValidationOptions::default()
+            .with_nonce_policy(NoncePolicy { max_len: None, charset: NonceCharset::UrlSafe });
+        let rejected = req.check_header(&header, &credentials.key, &url_safe).unwrap_err();
+        assert_eq!(rejected.reason(), RejectionReason::NonceNotPermitted);
+
+        let short = ValidationOptions::default()
+            .with_nonce_policy(NoncePolicy { max_len: Some(4), ..NoncePolicy::default() });
+        assert!(!req.validate_header_with_options(&header, &credentials.key, &short));
+    }
+
+    #[test]
+    fn test_validate_wrong_length() {
+        let credentials = bewit_credentials();
+        let req = RequestBuilder::new("POST", "foo.com", 443, "/x")
+            .hash(&[1u8; 32][..])
+            .request();
+        let options = ValidationOptions::default();
+        let header = req.make_header(&credentials).unwrap();
+        assert!(req.validate_header_with_options(&header, &credentials.key, &options));
+
+        // a SHA-256 MAC checked with a SHA-512 key
+        let sha512 = Key::new("tok", &digest::SHA512);
+        let rejected = req.check_header(&header, &sha512, &options).unwrap_err();
+        assert_eq!(rejected.reason(), RejectionReason::WrongLength(HeaderField::Mac));
+        assert!(rejected.normalized_hash().is_none());
+        let equalized = options.clone().with_equalize_timing(true);
+        let rejected = req.check_header(&header, &sha512, &equalized).unwrap_err();
+        assert_eq!(rejected.reason(), RejectionReason::WrongLength(HeaderField::Mac));
+        assert!(rejected.normalized_hash().is_some());
+
+        // a header hash shorter than the request's
+        let short = RequestBuilder::new("POST", "foo.com", 443, "/x")
+            .hash(&[1u8; 20][..])
+            .request()
+            .make_header(&credentials)
+            .unwrap();
+        let rejected = req.check_header(&short, &credentials.key, &options).unwrap_err();
+        assert_eq!(rejected.reason(), RejectionReason::WrongLength(HeaderField::Hash));
+    }
+
+    #[test]
+    fn test_system_time_overloads() {
+        let credentials = bewit_credentials();
+        let req = RequestBuilder::new("GET", "foo.com", 443, "/x").request();
+        let an_hour_ago = SystemTime::now() - std_time::Duration::from_secs(3600);
+        let header = req.make_header_at(&credentials, an_hour_ago).unwrap();
+        assert!(!req.validate_header_with_max_age(&header,
+                                                  &credentials.key,
+                                                  std_time::Duration::from_secs(60)));
+        assert!(req.validate_header_with_max_age(&header,
+                                                 &credentials.key,
+                                                 std_time::Duration::from_secs(7200)));
+    }
+
+    #[test]
+    fn test_default_port() {
+        assert_eq!(default_port("http"), Some(80));
+        assert_eq!(default_port("HTTPS"), Some(443));
+        assert_eq!(default_port("ws"), Some(80));
+        assert_eq!(default_port("wss"), Some(443));
+        assert_eq!(default_port("ftp"), None);
+    }
+
+    #[test]
+    fn test_url_builder_ws() {
+        let url = Url::parse("wss://example.com/chat").unwrap();
+        let req = RequestBuilder::from_url("GET", &url).unwrap().request();
+        assert_eq!(req.port(), 443);
+    }
+
+    #[test]
+    fn test_host_header_builder() {
+        let req = RequestBuilder::from_host_header("GET", "https", "example.com", "/x?y")
+            .unwrap()
+            .request();
+        assert_eq!((req.host(), req.port(), req.path()), ("example.com", 443, "/x?y"));
+
+        let req = RequestBuilder::from_host_header("GET", "http", "example.com:8080", "/")
+            .unwrap()
+            .request();
+        assert_eq!((req.host(), req.port()), ("example.com", 8080));
+
+        let req = RequestBuilder::from_host_header("GET", "http", "[::1]", "/").unwrap().request();
+        assert_eq!((req.host(), req.port()), ("[::1]", 80));
+
+        let req = RequestBuilder::from_host_header("GET", "ws", "[::1]:9000", "/")
+            .unwrap()
+            .request();
+        assert_eq!((req.host(), req.port()), ("[::1]", 9000));
+
+        assert!(RequestBuilder::from_host_header("GET", "http", "example.com:x", "/").is_err());
+        assert!(RequestBuilder::from_host_header("GET", "gopher", "example.com", "/").is_err());
+    }
+
+    #[test]
+    fn test_record_normalized_hash() {
+        let credentials = bewit_credentials();
+        let wrong_key = Key::new(vec![1u8; 32], &digest::SHA256);
+        let req = RequestBuilder::new("GET", "example.com", 443, "/foo").request();
+        let header = req.make_header(&credentials).unwrap();
+        let options = ValidationOptions::default().with_record_normalized_hash(true);
+
+        let artifacts = req.authenticate_header(&header, &credentials.key, &options).unwrap();
+        let rejected = req.check_header(&header, &wrong_key, &options).unwrap_err();
+        assert_eq!(artifacts.normalized_hash(), rejected.normalized_hash());
+        assert!(req.authenticate_header(&header, &credentials.key, &ValidationOptions::default())
+                    .unwrap()
+                    .normalized_hash()
+                    .is_none());
+
+        // the hash is of the string that was signed, without the query
+        let with_query = RequestBuilder::new("GET", "example.com", 443, "/foo?a=b").request();
+        let artifacts = with_query.authenticate_header(&header,
+                                                       &credentials.key,
+                                                       &options.with_accept_mac_without_query(true))
+            .unwrap();
+        assert!(artifacts.is_signed_without_query());
+        assert_eq!(artifacts.normalized_hash(), rejected.normalized_hash());
+    }
+
+    #[test]
+    fn test_method_override() {
+        let credentials = bewit_credentials();
+        let make = |policy| {
+            RequestBuilder::new("POST", "example.com", 443, "/items/1")
+                .method_override("DELETE")
+                .method_override_policy(policy)
+                .request()
+        };
+        let overridden = make(MethodOverride::Overridden);
+        let transmitted = make(MethodOverride::Transmitted);
+        assert_eq!(overridden.method(), "DELETE");
+        assert_eq!(transmitted.method(), "POST");
+
+        let header = overridden.make_header(&credentials).unwrap();
+        let delete = RequestBuilder::new("DELETE", "example.com", 443, "/items/1").request();
+        assert!(overridden.validate_header(&header, &credentials.key, Duration::minutes(1)));
+        assert!(delete.validate_header(&header, &credentials.key, Duration::minutes(1)));
+        assert!(!transmitted.validate_header(&header, &credentials.key, Duration::minutes(1)));
+
+        // the override only applies to POST
+        let get = RequestBuilder::new("GET", "example.com", 443, "/items/1")
+            .method_override("DELETE")
+            .method_override_policy(MethodOverride::Overridden)
+            .request();
+        assert_eq!(get.method(), "GET");
+        assert_eq!(get.with_method("post").method(), "DELETE");
+    }
+
+    #[test]
+    fn test_with_path_and_method() {
+        let credentials = bewit_credentials();
+        let base = RequestBuilder::new("GET", "example.com", 443, "/")
+            .ext(Some("tenant"))
+            .path_encoding(PathEncoding::Canonical)
+            .conformance(Conformance::NodeHawk)
+            .request();
+        let header = {
+            let path = String::from("/a b");
+            let req = base.with_path(&path).with_method("post");
+            assert_eq!(req.path(), "/a%20b");
+            assert_eq!(req.method(), "POST");
+            req.make_header(&credentials).unwrap()
+        };
+        let expected = RequestBuilder::new("POST", "example.com", 443, "/a%20b")
+            .ext(Some("tenant"))
+            .request();
+        assert!(expected.validate_header(&header, &credentials.key, Duration::minutes(1)));
+        assert_eq!(base.path(), "/");
+        assert_eq!(base.method(), "GET");
+    }
+
+    #[test]
+    fn test_path_encoding() {
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        let client = RequestBuilder::new("GET", "example.com", 443, "/caf\u{e9}/with space")
+            .path_encoding(PathEncoding::Canonical)
+            .request();
+        let header = client.make_header(&credentials).unwrap();
+
+        // a server seeing the encoded path validates with either policy
+        let server = RequestBuilder::new("GET", "example.com", 443, "/caf%c3%a9/with%20space")
+            .path_encoding(PathEncoding::Canonical)
+            .request();
+        assert!(server.validate_header(&header, &credentials.key, Duration::minutes(1)));
+        let server = RequestBuilder::new("GET", "example.com", 443, "/caf%C3%A9/with%20space")
+            .request();
+        assert!(server.validate_header(&header, &credentials.key, Duration::minutes(1)));
+
+        // but signing as-is does not match
+        let server = RequestBuilder::new("GET", "example.com", 443, "/caf%c3%a9/with%20space")
+            .request();
+        assert!(!server.validate_header(&header, &credentials.key, Duration::minutes(1)));
+    }
+
+    #[test]
+    fn test_from_absolute_form() {
+        let req = RequestBuilder::from_absolute_form("GET", "http://Example.com/a/b?c=d#frag")
+            .unwrap()
+            .request();
+        assert_eq!(req.host, "example.com");
+        assert_eq!(req.port, 80);
+        assert_eq!(req.path, "/a/b?c=d");
+
+        let req = RequestBuilder::from_absolute_form("GET", "https://example.com:8443")
+            .unwrap()
+            .request();
+        assert_eq!(req.host, "example.com");
+        assert_eq!(req.port, 8443);
+        assert_eq!(req.path, "/");
+    }
+
+    #[test]
+    fn test_from_absolute_form_validates() {
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: Key::new(vec![99u8; 32], &digest::SHA256),
+        };
+        let origin = RequestBuilder::new("GET", "example.com", 443, "/a/b?c=d").request();
+        let header = origin.make_header(&credentials).unwrap();
+
+        let proxy = RequestBuilder::from_absolute_form("GET", "https://example.com/a/b?c=d")
+            .unwrap()
+            .request();
+        assert!(proxy.validate_header(&header, &credentials.key, Duration::minutes(1)));
+    }
+
+    #[test]
+    fn test_from_absolute_form_invalid() {
+        assert!(RequestBuilder::from_absolute_form("GET", "/a/b").is_err());
+        assert!(RequestBuilder::from_absolute_form("GET", "ftp://example.com/a").is_err());
+        assert!(RequestBuilder::from_absolute_form("CONNECT", "example.com:443").is_err());
+    }
 }
@@ -1,7 +1,9 @@
-use mac::{Mac, MacType};
-use header::Header;
+use mac::{normalized_suffix, write_normalized_app, Mac, MacType};
+use crypto;
+use hawk_core::Header;
 use credentials::Key;
 use error::*;
+use time::Timespec;
 
 /// A Response represents a response from an HTTP server.
 ///
@@ -29,86 +31,82 @@ impl<'a> Response<'a> {
     pub fn make_header(&self, key: &Key) -> Result<Header> {
         let mac;
         let ts = self.req_header
-            .ts
+            .ts()
             .ok_or("Missing `ts` atttribute in Hawk header")?;
         let nonce = self.req_header
-            .nonce
-            .as_ref()
+            .nonce()
             .ok_or("Missing `nonce` attribute in Hawk header")?;
-        mac = Mac::new(MacType::Response,
-                       key,
-                       ts,
-                       nonce,
-                       self.method,
-                       self.host,
-                       self.port,
-                       self.path,
-                       self.hash,
-                       self.ext)?;
+        mac = self.calculate_mac(key, ts, nonce, self.hash, self.ext)?;
 
         // Per JS implementation, the Server-Authorization header includes only mac, hash, and ext
-        Header::new(None,
-                    None,
-                    None,
-                    Some(mac),
-                    match self.ext {
-                        None => None,
-                        Some(v) => Some(v.to_string()),
-                    },
-                    match self.hash {
-                        None => None,
-                        Some(v) => Some(v.to_vec()),
-                    },
-                    None,
-                    None)
+        let header = Header::new(None,
+                                 None,
+                                 None,
+                                 Some(mac.into()),
+                                 match self.ext {
+                                     None => None,
+                                     Some(v) => Some(v.to_string()),
+                                 },
+                                 match self.hash {
+                                     None => None,
+                                     Some(v) => Some(v.to_vec()),
+                                 },
+                                 None,
+                                 None)?;
+        debug_assert!(header.canonicalize().is_ok(), "header does not round-trip: {}", header);
+        Ok(header)
+    }
+
+    /// Calculate the MAC for a response with the given hash and ext.  Like the Node
+    /// implementation, this covers the request's `app` and `dlg`, if any.
+    fn calculate_mac(&self,
+                     key: &Key,
+                     ts: Timespec,
+                     nonce: &str,
+                     hash: Option<&[u8]>,
+                     ext: Option<&str>)
+                     -> Result<Mac> {
+        let mut suffix =
+            normalized_suffix(self.method, self.host, self.port, self.path, hash, ext)?;
+        write_normalized_app(&mut suffix, self.req_header.app(), self.req_header.dlg())?;
+        Mac::with_suffix(MacType::Response, key, ts, nonce, &suffix)
     }
 
     /// Validate a Server-Authorization header.
     ///
     /// This checks that the MAC matches and, if a hash has been supplied locally,
     /// checks that one was provided from the server and that it, too, matches.
+    ///
+    /// The server's `ext` value, if any, is covered by the MAC, so once this method returns true
+    /// it can be trusted.  If an `ext` has been supplied locally, the server must have sent the
+    /// same value.
     pub fn validate_header(&self, response_header: &Header, key: &Key) -> bool {
         // extract required fields, returning early if they are not present
-        let ts = match self.req_header.ts {
+        let ts = match self.req_header.ts() {
             Some(ts) => ts,
             None => {
                 return false;
             }
         };
-        let nonce = match self.req_header.nonce {
-            Some(ref nonce) => nonce,
+        let nonce = match self.req_header.nonce() {
+            Some(nonce) => nonce,
             None => {
                 return false;
             }
         };
-        let header_mac = match response_header.mac {
-            Some(ref mac) => mac,
+        let header_mac = match response_header.mac() {
+            Some(mac) => mac,
             None => {
                 return false;
             }
         };
-        let header_ext = match response_header.ext {
-            Some(ref ext) => Some(&ext[..]),
-            None => None,
-        };
-        let header_hash = match response_header.hash {
-            Some(ref hash) => Some(&hash[..]),
-            None => None,
-        };
+        let header_ext = response_header.ext();
+        let header_hash = response_header.hash();
 
         // first verify the MAC
-        match Mac::new(MacType::Response,
-                       key,
-                       ts,
-                       nonce,
-                       self.method,
-                       self.host,
-                       self.port,
-                       self.path,
-                       header_hash,
-                       header_ext) {
+        match self.calculate_mac(key, ts, nonce, header_hash, header_ext) {
             Ok(calculated_mac) => {
-                if &calculated_mac != header_mac {
+                if !crypto::ct_eq(&calculated_mac, header_mac) {
                     return false;
                 }
             }
@@ -120,7 +118,7 @@ impl<'a> Response<'a> {
         // ..then the hashes
         if let Some(local_hash) = self.hash {
             if let Some(server_hash) = header_hash {
-                if local_hash != server_hash {
+                if !crypto::ct_eq(local_hash, server_hash) {
                     return false;
                 }
             } else {
@@ -128,6 +126,13 @@ impl<'a> Response<'a> {
             }
         }
 
+        // ..then the ext
+        if let Some(local_ext) = self.ext {
+            if header_ext != Some(local_ext) {
+                return false;
+            }
+        }
+
         // NOTE: the timestamp self.req_header.ts was generated locally, so
         // there is no need to verify it
 
@@ -169,7 +174,9 @@ impl<'a> ResponseBuilder<'a> {
 
     /// Set the `ext` Hawk property for the response.
     ///
-    /// This need only be set on the server; it is ignored in validating responses on the client.
+    /// On the server, this value is included in the response header and its MAC.  On the client,
+    /// this need only be set if the server is expected to send a specific `ext` value, in which
+    /// case validation fails if the server's `ext` differs.
     pub fn ext<S: Into<Option<&'a str>>>(mut self, ext: S) -> Self {
         self.0.ext = ext.into();
         self
@@ -184,7 +191,7 @@ impl<'a> ResponseBuilder<'a> {
 #[cfg(test)]
 mod test {
     use super::ResponseBuilder;
-    use header::Header;
+    use hawk_core::Header;
     use credentials::Key;
     use mac::Mac;
     use time::Timespec;
@@ -214,7 +221,7 @@ mod test {
         let server_header = Header::new(None,
                                         None,
                                         None,
-                                        Some(mac),
+                                        Some(mac.into()),
                                         Some("server-ext"),
                                         None,
                                         None,
@@ -223,6 +230,55 @@ mod test {
         assert!(resp.validate_header(&server_header, &Key::new("tok", &digest::SHA256)));
     }
 
+    #[test]
+    fn test_round_trip_ext() {
+        let req_header = make_req_header();
+        let key = Key::new("tok", &digest::SHA256);
+        let server_header =
+            ResponseBuilder::from_request_header(&req_header, "POST", "localhost", 9988, "/a/b")
+                .ext("server-ext")
+                .response()
+                .make_header(&key)
+                .unwrap();
+        assert_eq!(server_header.ext(), Some("server-ext"));
+
+        let resp =
+            ResponseBuilder::from_request_header(&req_header, "POST", "localhost", 9988, "/a/b")
+                .response();
+        assert!(resp.validate_header(&server_header, &key));
+
+        // the ext is covered by the MAC, so tampering with it causes validation to fail
+        let tampered = server_header.clone().with_ext(Some("evil-ext")).unwrap();
+        assert!(!resp.validate_header(&tampered, &key));
+        let stripped = server_header.with_ext::<String>(None).unwrap();
+        assert!(!resp.validate_header(&stripped, &key));
+    }
+
+    #[test]
+    fn test_validation_ext_expected() {
+        let req_header = make_req_header();
+        let key = Key::new("tok", &digest::SHA256);
+        let server_header =
+            ResponseBuilder::from_request_header(&req_header, "POST", "localhost", 9988, "/a/b")
+                .ext("server-ext")
+                .response()
+                .make_header(&key)
+                .unwrap();
+
+        let resp =
+            ResponseBuilder::from_request_header(&req_header, "POST", "localhost", 9988, "/a/b")
+                .ext("server-ext")
+                .response();
+        assert!(resp.validate_header(&server_header, &key));
+
+        // a validly-signed but unexpected ext fails
+        let resp =
+            ResponseBuilder::from_request_header(&req_header, "POST", "localhost", 9988, "/a/b")
+                .ext("other-ext")
+                .response();
+        assert!(!resp.validate_header(&server_header, &key));
+    }
+
     #[test]
     fn test_validation_hash_in_header() {
         // When a hash is provided in the response header, but no hash is added to the Response,
@@ -237,7 +293,7 @@ mod test {
         let server_header = Header::new(None,
                                         None,
                                         None,
-                                        Some(mac),
+                                        Some(mac.into()),
                                         Some("server-ext"),
                                         Some(vec![1, 2, 3, 4]),
                                         None,
@@ -261,7 +317,7 @@ mod test {
         let server_header = Header::new(None,
                                         None,
                                         None,
-                                        Some(mac),
+                                        Some(mac.into()),
                                         Some("server-ext"),
                                         None,
                                         None,
@@ -286,7 +342,7 @@ mod test {
         let server_header = Header::new(None,
                                         None,
                                         None,
-                                        Some(mac),
+                                        Some(mac.into()),
                                         Some("server-ext"),
                                         Some(vec![1, 2, 3, 4]),
                                         None,
@@ -0,0 +1,108 @@
+use credentials::Key;
+use mac::Mac;
+use ring::hmac;
+use std::io::{self, IoSlice, Write};
+
+/// An `io::Write` which feeds everything written to it to an HMAC calculated with a key.
+///
+/// Writing never fails, and writes of several slices (`write_vectored`) consume all of them, so
+/// data already split into pieces can be signed without first copying it together.  This is
+/// how Hawk signs normalized strings, and is useful for signing other framed data with a Hawk
+/// key, such as a stream written with `io::copy` or `write!`.
+///
+/// # Examples
+///
+/// ```
+/// use hawk::{Key, SignedWriter, SHA256};
+/// use std::io::Write;
+///
+/// let key = Key::new(vec![99u8; 32], &SHA256);
+/// let mut writer = SignedWriter::new(&key);
+/// write!(writer, "{}\n{}\n", "frame", 1).unwrap();
+/// let mac = writer.finish();
+/// assert_eq!(&mac[..], &key.sign(b"frame\n1\n")[..]);
+/// ```
+pub struct SignedWriter {
+    context: hmac::SigningContext,
+}
+
+impl SignedWriter {
+    /// Start an HMAC calculated with the given key, using the key's digest algorithm.
+    pub fn new(key: &Key) -> SignedWriter {
+        SignedWriter { context: hmac::SigningContext::with_key(key.signing_key()) }
+    }
+
+    /// Add data to the HMAC.
+    pub fn update(&mut self, data: &[u8]) {
+        self.context.update(data);
+    }
+
+    /// Finish the HMAC and return it.
+    pub fn finish(self) -> Mac {
+        let mut mac = Mac::from(vec![]);
+        self.finish_into(&mut mac);
+        mac
+    }
+
+    /// Finish the HMAC, writing it into the given MAC's existing buffer, as for `Mac::sign`.
+    pub fn finish_into(self, mac: &mut Mac) {
+        mac.replace(self.sign().as_ref());
+    }
+
+    /// Finish the HMAC and return the signature, without copying it to the heap.
+    pub(crate) fn sign(self) -> hmac::Signature {
+        self.context.sign()
+    }
+}
+
+impl Write for SignedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let mut len = 0;
+        for buf in bufs {
+            self.update(buf);
+            len += buf.len();
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SignedWriter;
+    use credentials::Key;
+    use mac::Mac;
+    use ring::digest;
+    use std::io::{IoSlice, Write};
+
+    #[test]
+    fn vectored() {
+        let key = Key::new(vec![99u8; 32], &digest::SHA256);
+        let mut writer = SignedWriter::new(&key);
+        let slices = [IoSlice::new(b"hawk."), IoSlice::new(b""), IoSlice::new(b"1\n")];
+        assert_eq!(writer.write_vectored(&slices).unwrap(), 7);
+        writer.write_all(b"more").unwrap();
+        assert_eq!(writer.sign().as_ref(), &key.sign(b"hawk.1\nmore")[..]);
+    }
+
+    #[test]
+    fn finish() {
+        let key = Key::new(vec![99u8; 32], &digest::SHA512);
+        let mut writer = SignedWriter::new(&key);
+        writer.update(b"data");
+        assert_eq!(writer.finish(), Mac::from(key.sign(b"data")));
+
+        let mut mac = Mac::from(vec![0u8; 100]);
+        let writer = SignedWriter::new(&key);
+        writer.finish_into(&mut mac);
+        assert_eq!(mac, Mac::from(key.sign(b"")));
+    }
+}
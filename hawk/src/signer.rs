@@ -0,0 +1,160 @@
+use clock;
+use credentials::{Credentials, Key};
+use error::*;
+use hawk_core::Header;
+use nonce;
+use request::Request;
+use time;
+use validation::ValidationOptions;
+
+/// Buffers reused by a `Signer` across calls.
+///
+/// A Scratch grows to fit the largest header it has handled, and is then reused without further
+/// allocation.  A gateway might keep one per connection or per worker thread.
+#[derive(Debug, Default)]
+pub struct Scratch {
+    normalized: Vec<u8>,
+    header: String,
+}
+
+impl Scratch {
+    /// Create a new, empty Scratch.
+    pub fn new() -> Self {
+        Scratch::default()
+    }
+}
+
+/// A Signer makes and validates headers using the buffers in a `Scratch`, avoiding the
+/// per-request allocations made by the corresponding `Request` methods.
+///
+/// # Examples
+///
+/// ```
+/// extern crate time;
+/// extern crate hawk;
+///
+/// use hawk::{Credentials, Key, RequestBuilder, Scratch, Signer, SHA256};
+///
+/// fn main() {
+///     let credentials = Credentials {
+///         id: "me".to_string(),
+///         key: Key::new(vec![99u8; 32], &SHA256),
+///     };
+///     let request = RequestBuilder::new("GET", "example.com", 443, "/v1/status").request();
+///
+///     let mut scratch = Scratch::new();
+///     let mut signer = Signer::with_scratch(&mut scratch);
+///     for nonce in &["nonce1", "nonce2"] {
+///         let value = signer.make_header_full(&request, &credentials,
+///                                             time::now().to_timespec(), nonce).unwrap();
+///         println!("Authorization: Hawk {}", value);
+///     }
+/// }
+/// ```
+pub struct Signer<'s> {
+    scratch: &'s mut Scratch,
+}
+
+impl<'s> Signer<'s> {
+    /// Create a Signer using the given scratch buffers.
+    pub fn with_scratch(scratch: &'s mut Scratch) -> Self {
+        Signer { scratch }
+    }
+
+    /// Make a header value for the given request, as for `Request::make_header`, inventing a
+    /// new nonce and setting the timestamp to the current time.
+    ///
+    /// The result omits the `Hawk ` prefix, as does formatting a `Header`, and remains valid
+    /// until the next call to this Signer.
+    pub fn make_header(&mut self, request: &Request, credentials: &Credentials) -> Result<&str> {
+        let nonce = nonce::generate();
+        self.make_header_full(request, credentials, clock::now(), &nonce)
+    }
+
+    /// Similar to `make_header`, but allowing specification of the timestamp and nonce.  This
+    /// makes no allocations once the scratch buffers are large enough.
+    pub fn make_header_full(&mut self,
+                            request: &Request,
+                            credentials: &Credentials,
+                            ts: time::Timespec,
+                            nonce: &str)
+                            -> Result<&str> {
+        request.write_header(&mut self.scratch.header,
+                          &mut self.scratch.normalized,
+                          credentials,
+                          ts,
+                          nonce)?;
+        Ok(&self.scratch.header)
+    }
+
+    /// Validate the given header, as for `Request::validate_header_with_options`.
+    pub fn validate_header(&mut self,
+                           request: &Request,
+                           header: &Header,
+                           key: &Key,
+                           options: &ValidationOptions)
+                           -> bool {
+        request.authenticate_header_in(header, key, options, &mut self.scratch.normalized)
+            .is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use request::RequestBuilder;
+    use std::str::FromStr;
+    use testing::test_credentials;
+    use time::Timespec;
+
+    #[test]
+    fn same_as_request() {
+        let credentials = test_credentials();
+        let hash = [1u8, 2, 3, 4];
+        let mut scratch = Scratch::new();
+        let mut signer = Signer::with_scratch(&mut scratch);
+        let ts = Timespec::new(1353832234, 0);
+
+        let requests = [RequestBuilder::new("GET", "example.com", 443, "/").request(),
+                        RequestBuilder::new("POST", "example.com", 443, "/v1/api")
+                            .hash(&hash[..])
+                            .ext("ext-data")
+                            .app("my-app")
+                            .dlg("my-dlg")
+                            .request()];
+        for request in &requests {
+            let expected = request.make_header_full(&credentials, ts, "nonny").unwrap();
+            let value = signer.make_header_full(request, &credentials, ts, "nonny").unwrap();
+            assert_eq!(value, expected.to_string());
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let credentials = test_credentials();
+        let request = RequestBuilder::new("GET", "example.com", 443, "/v1/status").request();
+        let mut scratch = Scratch::new();
+        let mut signer = Signer::with_scratch(&mut scratch);
+        let options = ValidationOptions::default();
+
+        for _ in 0..3 {
+            let header = Header::from_str(signer.make_header(&request, &credentials).unwrap())
+                .unwrap();
+            assert!(signer.validate_header(&request, &header, &credentials.key, &options));
+
+            let other = RequestBuilder::new("GET", "example.com", 443, "/v2/status").request();
+            assert!(!signer.validate_header(&other, &header, &credentials.key, &options));
+        }
+    }
+
+    #[test]
+    fn invalid_component() {
+        let credentials = test_credentials();
+        let request = RequestBuilder::new("GET", "example.com", 443, "/").ext("a\"b").request();
+        let mut scratch = Scratch::new();
+        let mut signer = Signer::with_scratch(&mut scratch);
+        assert!(signer.make_header_full(&request, &credentials, Timespec::new(1, 0), "n").is_err());
+        assert!(signer.make_header_full(&request, &credentials, Timespec::new(1, 0), "n\"")
+            .is_err());
+    }
+}
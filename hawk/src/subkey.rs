@@ -0,0 +1,168 @@
+//! Per-context subkeys derived from master keys.
+//!
+//! A deployment can give each client a subkey for the current day, or for one service, derived
+//! from the client's master key with `Key::derive_subkey`, rather than the master key itself.
+//! A subkey leaked from logs or a compromised host can then only sign requests for its own
+//! context.
+//!
+//! By convention, a request signed with a subkey carries the id `<id>.<context>`, where `<id>`
+//! is the id of the master key.  The context follows the last `.`, so it cannot itself contain
+//! a `.`, although the master id can.  A server validates such requests with a
+//! `SubkeyProvider`, which looks up the master key and derives the subkey for the context,
+//! provided the context is currently accepted.
+//!
+//! # Examples
+//!
+//! ```
+//! use hawk::subkey::{subkey_id, SubkeyProvider};
+//! use hawk::{Credentials, CredentialsProvider, Key, SharedCredentials, SHA256};
+//!
+//! let master = Key::new(vec![99u8; 32], &SHA256);
+//!
+//! // the client is issued a subkey for today
+//! let credentials = Credentials {
+//!     id: subkey_id("svc-a", "2026-10-15"),
+//!     key: master.derive_subkey(b"2026-10-15"),
+//! };
+//! assert_eq!(credentials.id, "svc-a.2026-10-15");
+//!
+//! // the server holds the master keys, and accepts only today's subkeys
+//! let masters = SharedCredentials::new();
+//! masters.insert("svc-a", master);
+//! let provider = SubkeyProvider::new(masters, |context: &str| context == "2026-10-15");
+//! assert_eq!(provider.keys("svc-a.2026-10-15").len(), 1);
+//! assert_eq!(provider.keys("svc-a.2026-10-14").len(), 0);
+//! assert_eq!(provider.keys("svc-a").len(), 0);
+//! ```
+
+use credentials::{CredentialsProvider, Key, Validity};
+
+/// Get the id for a subkey of the master key with the given id, for the given context.  The
+/// context must not contain `.`.
+pub fn subkey_id(id: &str, context: &str) -> String {
+    format!("{}.{}", id, context)
+}
+
+/// Split a subkey id, as made by `subkey_id`, into the master id and the context.  Returns
+/// `None` if the id has no `.`, or if either part is empty.
+pub fn split_subkey_id(id: &str) -> Option<(&str, &str)> {
+    id.rsplit_once('.').filter(|&(id, context)| !id.is_empty() && !context.is_empty())
+}
+
+/// A credentials provider for requests signed with subkeys, deriving each subkey from the
+/// master keys given by another provider.
+///
+/// The keys for an id `<id>.<context>` are the subkeys for `<context>` of the master keys for
+/// `<id>`, with the same validity periods, provided the context is accepted by the given
+/// predicate.  Ids without a context have no keys, so requests signed with master keys are
+/// not accepted.
+///
+/// The predicate should accept only the contexts currently in use, such as today's date
+/// (and perhaps yesterday's, near midnight), since a leaked subkey remains usable for as long
+/// as its context is accepted.
+pub struct SubkeyProvider<P, F> {
+    masters: P,
+    accept: F,
+}
+
+impl<P, F> SubkeyProvider<P, F>
+    where P: CredentialsProvider,
+          F: Fn(&str) -> bool
+{
+    /// Create a provider deriving subkeys from the master keys given by `masters`, for the
+    /// contexts accepted by `accept`.
+    pub fn new(masters: P, accept: F) -> SubkeyProvider<P, F> {
+        SubkeyProvider { masters, accept }
+    }
+
+    /// Get the provider of master keys.
+    pub fn masters(&self) -> &P {
+        &self.masters
+    }
+}
+
+impl<P, F> CredentialsProvider for SubkeyProvider<P, F>
+    where P: CredentialsProvider,
+          F: Fn(&str) -> bool
+{
+    fn keys(&self, id: &str) -> Vec<Key> {
+        self.keys_with_validity(id).into_iter().map(|(key, _)| key).collect()
+    }
+
+    fn keys_with_validity(&self, id: &str) -> Vec<(Key, Validity)> {
+        match split_subkey_id(id) {
+            Some((id, context)) if (self.accept)(context) => {
+                self.masters
+                    .keys_with_validity(id)
+                    .into_iter()
+                    .map(|(key, validity)| (key.derive_subkey(context.as_bytes()), validity))
+                    .collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    fn is_app(&self, id: &str) -> bool {
+        split_subkey_id(id).is_some_and(|(id, _)| self.masters.is_app(id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use credentials::{Credentials, SharedCredentials};
+    use request::RequestBuilder;
+    use ring::digest;
+    use validation::ValidationOptions;
+
+    #[test]
+    fn ids() {
+        assert_eq!(subkey_id("me", "2026-10-15"), "me.2026-10-15");
+        assert_eq!(split_subkey_id("me.2026-10-15"), Some(("me", "2026-10-15")));
+        assert_eq!(split_subkey_id("svc.me.payments"), Some(("svc.me", "payments")));
+        assert_eq!(split_subkey_id("me"), None);
+        assert_eq!(split_subkey_id("me."), None);
+        assert_eq!(split_subkey_id(".ctx"), None);
+    }
+
+    #[test]
+    fn validate() {
+        let master = Key::new(vec![99u8; 32], &digest::SHA256);
+        let masters = SharedCredentials::new();
+        masters.insert("me", master.clone());
+        let provider = SubkeyProvider::new(masters, |context: &str| context == "today");
+        let req = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let options = ValidationOptions::default();
+
+        let credentials = Credentials {
+            id: subkey_id("me", "today"),
+            key: master.derive_subkey(b"today"),
+        };
+        let header = req.make_header(&credentials).unwrap();
+        assert!(req.authenticate_with_provider(&header, &provider, &options).is_some());
+
+        // a subkey for another context, even under the accepted context's id
+        let credentials = Credentials {
+            id: subkey_id("me", "today"),
+            key: master.derive_subkey(b"yesterday"),
+        };
+        let header = req.make_header(&credentials).unwrap();
+        assert!(req.authenticate_with_provider(&header, &provider, &options).is_none());
+
+        // a context which is no longer accepted
+        let credentials = Credentials {
+            id: subkey_id("me", "yesterday"),
+            key: master.derive_subkey(b"yesterday"),
+        };
+        let header = req.make_header(&credentials).unwrap();
+        assert!(req.authenticate_with_provider(&header, &provider, &options).is_none());
+
+        // the master key itself
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: master,
+        };
+        let header = req.make_header(&credentials).unwrap();
+        assert!(req.authenticate_with_provider(&header, &provider, &options).is_none());
+    }
+}
@@ -0,0 +1,134 @@
+//! Support for testing code which uses Hawk.
+//!
+//! Headers normally carry the current time and a random nonce, so they differ on every run.
+//! Tests which compare outbound requests against fixtures ("snapshot tests") can make them
+//! deterministic with `deterministic_for_tests`.
+//!
+//! # Examples
+//!
+//! ```
+//! use hawk::{Credentials, Key, RequestBuilder, SHA256};
+//! use hawk::testing;
+//!
+//! let credentials = Credentials {
+//!     id: "me".to_string(),
+//!     key: Key::new(vec![99u8; 32], &SHA256),
+//! };
+//! let request = RequestBuilder::new("GET", "example.com", 443, "/v1/status").request();
+//!
+//! let header = {
+//!     let _deterministic = testing::deterministic_for_tests();
+//!     request.make_header(&credentials).unwrap()
+//! };
+//! assert_eq!(header.ts().unwrap().sec, 1353832234);
+//! assert_eq!(header.nonce(), Some("nonce-0"));
+//! ```
+
+use clock::{self, Clock, FixedClock};
+use nonce::{self, NonceGenerator, SequentialNonceGenerator};
+use std::sync::Arc;
+use time::Timespec;
+
+/// The time used by `deterministic_for_tests`, as in the examples of the Hawk specification.
+pub const DETERMINISTIC_TS: i64 = 1353832234;
+
+/// Make headers, bewits, and signatures made on the current thread deterministic, until the
+/// returned guard is dropped.
+///
+/// While the guard is live, the default clock on this thread reads `DETERMINISTIC_TS`, and
+/// nonces are `nonce-0`, `nonce-1`, and so on.  This overrides any clock or nonce generator
+/// registered with `set_default_clock` or `set_default_nonce_generator`, but only on this
+/// thread, so tests running in parallel are unaffected.  Since the clock also applies to
+/// validation with the default `ValidationOptions`, headers made this way validate on the same
+/// thread.
+///
+/// This is for tests only: predictable nonces and timestamps defeat replay protection.
+pub fn deterministic_for_tests() -> Deterministic {
+    Deterministic::at(Timespec::new(DETERMINISTIC_TS, 0))
+}
+
+/// A guard making the current thread deterministic; see `deterministic_for_tests`.
+///
+/// Guards may be nested; dropping a guard restores the state before it was created.
+#[must_use = "the thread is only deterministic while the guard is live"]
+pub struct Deterministic {
+    previous_clock: Option<Arc<dyn Clock>>,
+    previous_nonces: Option<Arc<dyn NonceGenerator>>,
+}
+
+impl Deterministic {
+    /// Make the current thread deterministic, as for `deterministic_for_tests`, but with the
+    /// clock reading the given time.
+    pub fn at(ts: Timespec) -> Deterministic {
+        Deterministic {
+            previous_clock: clock::replace_thread_clock(Some(Arc::new(FixedClock(ts)))),
+            previous_nonces: nonce::replace_thread_generator(Some(Arc::new(
+                SequentialNonceGenerator::new("nonce-")))),
+        }
+    }
+}
+
+impl Drop for Deterministic {
+    fn drop(&mut self) {
+        clock::replace_thread_clock(self.previous_clock.take());
+        nonce::replace_thread_generator(self.previous_nonces.take());
+    }
+}
+
+/// The credentials used throughout this crate's unit tests: id `me` with a 32-byte SHA-256 key.
+#[cfg(test)]
+pub(crate) fn test_credentials() -> ::credentials::Credentials {
+    ::credentials::Credentials {
+        id: "me".to_string(),
+        key: ::credentials::Key::new(vec![99u8; 32], &::ring::digest::SHA256),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{deterministic_for_tests, test_credentials, Deterministic, DETERMINISTIC_TS};
+    use request::RequestBuilder;
+    use std::thread;
+    use time::{Duration, Timespec};
+
+    #[test]
+    fn stable_headers() {
+        let request = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let make = || {
+            let _deterministic = deterministic_for_tests();
+            let first = request.make_header(&test_credentials()).unwrap();
+            let second = request.make_header(&test_credentials()).unwrap();
+            assert_eq!(second.nonce(), Some("nonce-1"));
+            first.to_string()
+        };
+        assert_eq!(make(), make());
+
+        // outside the guard, headers are random again
+        let header = request.make_header(&test_credentials()).unwrap();
+        assert!(header.ts().unwrap().sec > DETERMINISTIC_TS);
+        assert_ne!(header.nonce(), Some("nonce-0"));
+    }
+
+    #[test]
+    fn validates_on_same_thread() {
+        let _deterministic = deterministic_for_tests();
+        let request = RequestBuilder::new("GET", "example.com", 443, "/").request();
+        let header = request.make_header(&test_credentials()).unwrap();
+        assert!(request.validate_header(&header, &test_credentials().key, Duration::minutes(1)));
+    }
+
+    #[test]
+    fn nested_and_thread_local() {
+        let _outer = deterministic_for_tests();
+        {
+            let _inner = Deterministic::at(Timespec::new(1000, 0));
+            assert_eq!(::clock::now(), Timespec::new(1000, 0));
+        }
+        assert_eq!(::clock::now(), Timespec::new(DETERMINISTIC_TS, 0));
+        assert_eq!(::nonce::generate(), "nonce-0");
+
+        // other threads are unaffected
+        let other = thread::spawn(::clock::now).join().unwrap();
+        assert!(other.sec > DETERMINISTIC_TS);
+    }
+}
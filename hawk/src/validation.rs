@@ -0,0 +1,417 @@
+use clock::{Clock, DefaultClock};
+use error::*;
+use std::sync::Arc;
+use time::Duration;
+
+/// Policy for requiring a payload hash in request headers.
+///
+/// Whatever the policy, a hash in the header must match any hash supplied for the request.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PayloadPolicy {
+    /// Do not require a hash in the header, unless a hash was supplied for the request.
+    Never,
+    /// Do not require a hash in the header, even if a hash was supplied for the request: verify
+    /// the hash if the header has one, and otherwise accept the request without it.  This
+    /// suits deployments which enforce payload hashing only on some routes.
+    IfPresent,
+    /// Require a hash for methods that normally carry a body: `POST`, `PUT`, and `PATCH`.
+    BodiedMethods,
+    /// Require a hash for all requests.
+    Always,
+}
+
+impl PayloadPolicy {
+    /// Determine whether this policy requires a payload hash for the given method.
+    pub fn requires_hash(&self, method: &str) -> bool {
+        match *self {
+            PayloadPolicy::Never | PayloadPolicy::IfPresent => false,
+            PayloadPolicy::BodiedMethods => matches!(method, "POST" | "PUT" | "PATCH"),
+            PayloadPolicy::Always => true,
+        }
+    }
+}
+
+/// What to do with a request whose nonce cannot be checked, because the nonce store is
+/// unavailable.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum NonceOutagePolicy {
+    /// Reject the request, with `RejectionReason::NonceStoreUnavailable`.
+    #[default]
+    FailClosed,
+    /// Accept the request without replay protection.  `Authenticator` counts such requests,
+    /// and reports each outage to its `on_nonce_outage` function, for logging.
+    FailOpen,
+}
+
+/// The characters allowed in an `ext` value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ExtCharset {
+    /// Any characters allowed in a Hawk header.
+    #[default]
+    Any,
+    /// Only printable ASCII characters (including space).  Some middleboxes mangle non-ASCII
+    /// header content, which then fails validation as a MAC mismatch.
+    PrintableAscii,
+}
+
+/// Limits on the size and content of `ext` values.
+///
+/// The default policy imposes no limits.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ExtPolicy {
+    /// The maximum length of an `ext` value, in bytes.
+    pub max_len: Option<usize>,
+
+    /// The characters allowed in an `ext` value.
+    pub charset: ExtCharset,
+}
+
+impl ExtPolicy {
+    /// Check that the given `ext` value is permitted by this policy.
+    pub fn check(&self, ext: &str) -> Result<()> {
+        if let Some(max_len) = self.max_len {
+            if ext.len() > max_len {
+                bail!("ext is {} bytes long, exceeding the limit of {}", ext.len(), max_len);
+            }
+        }
+        if self.charset == ExtCharset::PrintableAscii &&
+           !ext.bytes().all(|b| matches!(b, b' '..=b'~')) {
+            bail!("ext contains characters other than printable ASCII");
+        }
+        Ok(())
+    }
+
+    /// Determine whether the given `ext` value is permitted by this policy.
+    pub fn permits(&self, ext: &str) -> bool {
+        self.check(ext).is_ok()
+    }
+}
+
+/// The characters allowed in a nonce.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum NonceCharset {
+    /// Any characters allowed in a Hawk header.
+    Any,
+    /// Printable ASCII characters other than `"` and `\`, as accepted by the Node Hawk server.
+    /// This is the default.
+    #[default]
+    NodeHawk,
+    /// Only the URL-safe base64 alphabet: letters, digits, `-`, and `_`.  The Node Hawk client
+    /// generates nonces from this alphabet.
+    UrlSafe,
+}
+
+/// Limits on the length and content of request nonces.
+///
+/// The default policy matches the Node Hawk server: any length, and printable ASCII characters
+/// other than `"` and `\`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct NoncePolicy {
+    /// The maximum length of a nonce, in bytes.
+    pub max_len: Option<usize>,
+
+    /// The characters allowed in a nonce.
+    pub charset: NonceCharset,
+}
+
+impl NoncePolicy {
+    /// Check that the given nonce is permitted by this policy.
+    pub fn check(&self, nonce: &str) -> Result<()> {
+        if let Some(max_len) = self.max_len {
+            if nonce.len() > max_len {
+                bail!("nonce is {} bytes long, exceeding the limit of {}", nonce.len(), max_len);
+            }
+        }
+        let permitted = match self.charset {
+            NonceCharset::Any => true,
+            NonceCharset::NodeHawk => {
+                nonce.bytes().all(|b| matches!(b, b' '..=b'~') && b != b'"' && b != b'\\')
+            }
+            NonceCharset::UrlSafe => {
+                nonce.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+            }
+        };
+        if !permitted {
+            bail!("nonce contains characters not permitted by {:?}", self.charset);
+        }
+        Ok(())
+    }
+
+    /// Determine whether the given nonce is permitted by this policy.
+    pub fn permits(&self, nonce: &str) -> bool {
+        self.check(nonce).is_ok()
+    }
+}
+
+/// Options for validating a request header.
+///
+/// The default options allow one minute of clock skew, do not require a payload hash, do not
+/// accept `GET` bewits for `HEAD` requests, allow no grace period for bewit expiration, do not
+/// accept scoped bewits, impose no limits on `ext`, and use the `DefaultClock`.
+///
+/// Further options may be added in minor releases, so outside this crate the options must be
+/// built from the default with the `with_*` setters, rather than with a struct expression.
+///
+/// ```
+/// extern crate time;
+/// extern crate hawk;
+///
+/// use hawk::{PayloadPolicy, ValidationOptions};
+///
+/// # fn main() {
+///
+/// let options = ValidationOptions::default()
+///     .with_ts_skew(time::Duration::seconds(30))
+///     .with_require_payload_hash(PayloadPolicy::BodiedMethods);
+/// assert_eq!(options.ts_skew, time::Duration::seconds(30));
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ValidationOptions {
+    /// The maximum difference allowed between the header's timestamp and the current time.
+    pub ts_skew: Duration,
+
+    /// The policy for requiring a payload hash in the header.  Note that when a hash is
+    /// required, it is still up to the caller to verify that hash against the payload, by
+    /// supplying the hash when building the request or with `verify_payload`.  By default, a
+    /// header must contain a hash if one was supplied for the request; use
+    /// `PayloadPolicy::IfPresent` to accept headers without one.
+    pub require_payload_hash: PayloadPolicy,
+
+    /// Whether a bewit issued for a `GET` request is also accepted for a `HEAD` request to the
+    /// same resource, as the Node Hawk server does.  Clients often probe a resource with `HEAD`
+    /// before fetching it.
+    pub allow_head_bewit: bool,
+
+    /// How long after its expiration time a bewit is still accepted, to tolerate clock drift
+    /// between the issuer and the validator.
+    pub bewit_grace: Duration,
+
+    /// Whether scoped bewits, made with `Request::make_scoped_bewit`, are accepted for requests
+    /// to paths within their scope.  This is not part of the Hawk protocol; when it is not set,
+    /// a scoped bewit is only valid for the exact path it was issued for.
+    pub allow_scoped_bewits: bool,
+
+    /// Whether a header whose MAC was calculated over the request path without its query is
+    /// accepted, as well as one calculated over the full path.  This is for a transition period
+    /// while clients which omit the query are upgraded; it costs a second MAC calculation for
+    /// requests which fail to validate.  `Artifacts::is_signed_without_query` reports which form
+    /// matched.
+    pub accept_mac_without_query: bool,
+
+    /// Limits on the `ext` value of headers and bewits.  Values outside these limits are
+    /// rejected.
+    pub ext_policy: ExtPolicy,
+
+    /// Limits on the nonce of headers.  Nonces outside these limits are rejected.  By default,
+    /// the nonce must be printable ASCII, as for the Node Hawk server.
+    pub nonce_policy: NoncePolicy,
+
+    /// What an `Authenticator` does when its nonce store is unavailable.  By default, such
+    /// requests are rejected.
+    pub nonce_outage: NonceOutagePolicy,
+
+    /// Whether to calculate a MAC for every header before rejecting it, so that headers with
+    /// an unknown id, a `mac` of the wrong length, or a non-permitted `ext` take about as long
+    /// to reject as those with a bad MAC or a stale timestamp, and the time taken does not
+    /// reveal which check failed.  For an unknown id, the MAC is calculated with a throwaway
+    /// SHA-256 key.
+    ///
+    /// Some timing differences remain: headers missing a required field, or containing
+    /// characters that cannot be normalized, are rejected without a MAC calculation; an id
+    /// with several keys costs one MAC per key tried; and `accept_mac_without_query` costs a
+    /// second MAC for some rejected headers.  None of these reveal anything about the keys.
+    pub equalize_timing: bool,
+
+    /// Whether to include a SHA-256 hash of the normalized string in the `Artifacts` of
+    /// accepted headers, for audit records proving exactly which bytes were authenticated.
+    /// The hash is of the normalized string only, and reveals nothing about the key.
+    pub record_normalized_hash: bool,
+
+    /// Whether a header with an empty `ext` (`ext=""`) is treated as having none, so that
+    /// `Artifacts::ext` returns `None` for both, as in the Node implementation.  The MAC is the
+    /// same either way, since the normalized string has an empty `ext` line for both; this only
+    /// affects what validation reports.
+    pub empty_ext_as_absent: bool,
+
+    /// The source of the current time.
+    pub clock: Arc<dyn Clock>,
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self {
+        ValidationOptions {
+            ts_skew: Duration::minutes(1),
+            require_payload_hash: PayloadPolicy::Never,
+            allow_head_bewit: false,
+            bewit_grace: Duration::zero(),
+            allow_scoped_bewits: false,
+            accept_mac_without_query: false,
+            ext_policy: ExtPolicy::default(),
+            nonce_policy: NoncePolicy::default(),
+            nonce_outage: NonceOutagePolicy::default(),
+            equalize_timing: false,
+            record_normalized_hash: false,
+            empty_ext_as_absent: false,
+            clock: Arc::new(DefaultClock),
+        }
+    }
+}
+
+impl ValidationOptions {
+    /// Set the maximum clock skew; see the `ts_skew` field.
+    pub fn with_ts_skew(mut self, ts_skew: Duration) -> Self {
+        self.ts_skew = ts_skew;
+        self
+    }
+
+    /// Set the payload hash policy; see the `require_payload_hash` field.
+    pub fn with_require_payload_hash(mut self, require_payload_hash: PayloadPolicy) -> Self {
+        self.require_payload_hash = require_payload_hash;
+        self
+    }
+
+    /// Set whether `GET` bewits are accepted for `HEAD` requests; see the `allow_head_bewit` field.
+    pub fn with_allow_head_bewit(mut self, allow_head_bewit: bool) -> Self {
+        self.allow_head_bewit = allow_head_bewit;
+        self
+    }
+
+    /// Set the grace period for expired bewits; see the `bewit_grace` field.
+    pub fn with_bewit_grace(mut self, bewit_grace: Duration) -> Self {
+        self.bewit_grace = bewit_grace;
+        self
+    }
+
+    /// Set whether scoped bewits are accepted; see the `allow_scoped_bewits` field.
+    pub fn with_allow_scoped_bewits(mut self, allow_scoped_bewits: bool) -> Self {
+        self.allow_scoped_bewits = allow_scoped_bewits;
+        self
+    }
+
+    /// Set whether MACs calculated without the query are accepted; see the
+    /// `accept_mac_without_query` field.
+    pub fn with_accept_mac_without_query(mut self, accept_mac_without_query: bool) -> Self {
+        self.accept_mac_without_query = accept_mac_without_query;
+        self
+    }
+
+    /// Set the limits on `ext` values; see the `ext_policy` field.
+    pub fn with_ext_policy(mut self, ext_policy: ExtPolicy) -> Self {
+        self.ext_policy = ext_policy;
+        self
+    }
+
+    /// Set the limits on nonces; see the `nonce_policy` field.
+    pub fn with_nonce_policy(mut self, nonce_policy: NoncePolicy) -> Self {
+        self.nonce_policy = nonce_policy;
+        self
+    }
+
+    /// Set the policy for nonce store outages; see the `nonce_outage` field.
+    pub fn with_nonce_outage(mut self, nonce_outage: NonceOutagePolicy) -> Self {
+        self.nonce_outage = nonce_outage;
+        self
+    }
+
+    /// Set whether rejections take equal time; see the `equalize_timing` field.
+    pub fn with_equalize_timing(mut self, equalize_timing: bool) -> Self {
+        self.equalize_timing = equalize_timing;
+        self
+    }
+
+    /// Set whether to hash the normalized string of accepted headers; see the
+    /// `record_normalized_hash` field.
+    pub fn with_record_normalized_hash(mut self, record_normalized_hash: bool) -> Self {
+        self.record_normalized_hash = record_normalized_hash;
+        self
+    }
+
+    /// Set whether an empty `ext` is treated as absent; see the `empty_ext_as_absent` field.
+    pub fn with_empty_ext_as_absent(mut self, empty_ext_as_absent: bool) -> Self {
+        self.empty_ext_as_absent = empty_ext_as_absent;
+        self
+    }
+
+    /// Set the source of the current time; see the `clock` field.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ExtCharset, ExtPolicy, NonceCharset, NoncePolicy, PayloadPolicy};
+
+    #[test]
+    fn requires_hash_never() {
+        assert!(!PayloadPolicy::Never.requires_hash("GET"));
+        assert!(!PayloadPolicy::Never.requires_hash("POST"));
+        assert!(!PayloadPolicy::IfPresent.requires_hash("POST"));
+    }
+
+    #[test]
+    fn requires_hash_bodied_methods() {
+        assert!(!PayloadPolicy::BodiedMethods.requires_hash("GET"));
+        assert!(!PayloadPolicy::BodiedMethods.requires_hash("HEAD"));
+        assert!(!PayloadPolicy::BodiedMethods.requires_hash("DELETE"));
+        assert!(PayloadPolicy::BodiedMethods.requires_hash("POST"));
+        assert!(PayloadPolicy::BodiedMethods.requires_hash("PUT"));
+        assert!(PayloadPolicy::BodiedMethods.requires_hash("PATCH"));
+    }
+
+    #[test]
+    fn requires_hash_always() {
+        assert!(PayloadPolicy::Always.requires_hash("GET"));
+        assert!(PayloadPolicy::Always.requires_hash("POST"));
+    }
+
+    #[test]
+    fn ext_policy_default() {
+        let policy = ExtPolicy::default();
+        assert!(policy.permits(""));
+        assert!(policy.permits(&"x".repeat(10000)));
+        assert!(policy.permits("caf\u{e9}"));
+    }
+
+    #[test]
+    fn ext_policy_max_len() {
+        let policy = ExtPolicy { max_len: Some(4), ..ExtPolicy::default() };
+        assert!(policy.permits("abcd"));
+        assert!(!policy.permits("abcde"));
+        // the limit is in bytes, not characters
+        assert!(!policy.permits("caf\u{e9}"));
+    }
+
+    #[test]
+    fn ext_policy_printable_ascii() {
+        let policy = ExtPolicy { charset: ExtCharset::PrintableAscii, ..ExtPolicy::default() };
+        assert!(policy.permits("a=1 b=~"));
+        assert!(!policy.permits("caf\u{e9}"));
+        assert!(!policy.permits("tab\there"));
+        assert!(!policy.permits("del\u{7f}"));
+    }
+
+    #[test]
+    fn nonce_policy() {
+        let policy = NoncePolicy::default();
+        assert!(policy.permits("j4h3g2"));
+        assert!(policy.permits("a+b/c= !~"));
+        assert!(policy.permits(&"x".repeat(10000)));
+        assert!(!policy.permits("back\\slash"));
+        assert!(!policy.permits("caf\u{e9}"));
+        assert!(!policy.permits("tab\there"));
+
+        let policy = NoncePolicy { max_len: Some(6), charset: NonceCharset::UrlSafe };
+        assert!(policy.permits("a-Z_09"));
+        assert!(!policy.permits("a-Z_09x"));
+        assert!(!policy.permits("a+b"));
+        assert_eq!(policy.check("a+b").unwrap_err().to_string(),
+                   "nonce contains characters not permitted by UrlSafe");
+
+        let policy = NoncePolicy { max_len: None, charset: NonceCharset::Any };
+        assert!(policy.permits("caf\u{e9}"));
+    }
+}
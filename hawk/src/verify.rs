@@ -0,0 +1,116 @@
+use auth_source;
+use credentials::Key;
+use error::Stage;
+use hawk_core::Header;
+use rejected::RejectionReason;
+use request::RequestBuilder;
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+use validation::ValidationOptions;
+
+/// The reasons `verify_request` can reject a request.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VerifyError {
+    /// The `Authorization` value does not use the Hawk scheme.
+    NotHawk,
+    /// The `Authorization` value uses the Hawk scheme, but cannot be parsed.
+    Malformed,
+    /// The header was parsed, but does not authenticate the request.
+    Rejected(RejectionReason),
+}
+
+impl VerifyError {
+    /// Get the stage of authentication at which the request was rejected.
+    pub fn stage(&self) -> Stage {
+        match *self {
+            VerifyError::NotHawk | VerifyError::Malformed => Stage::Parse,
+            VerifyError::Rejected(reason) => reason.stage(),
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VerifyError::NotHawk => f.write_str("not a Hawk Authorization header"),
+            VerifyError::Malformed => f.write_str("malformed Hawk Authorization header"),
+            VerifyError::Rejected(reason) => write!(f, "request rejected: {}", reason),
+        }
+    }
+}
+
+impl error::Error for VerifyError {}
+
+/// Verify a request's Hawk `Authorization` header, given everything as strings.
+///
+/// This is the whole of header validation in one call, for adapters such as external
+/// authorization services which receive the request's method, host, port, request target
+/// (path and query), and `Authorization` value, and need only an answer and a reason.  The
+/// `authorization` value includes the `Hawk` scheme.  The caller must look up the key for the
+/// header's id; for that, or to verify a payload hash, use `Header` and `Request` directly.
+///
+/// # Examples
+///
+/// ```
+/// use hawk::{verify_request, Credentials, Key, RequestBuilder, ValidationOptions, SHA256};
+///
+/// let key = Key::new(vec![99u8; 32], &SHA256);
+/// let credentials = Credentials { id: "me".to_string(), key: key.clone() };
+/// let authorization = RequestBuilder::new("GET", "example.com", 443, "/a?b=c")
+///     .request()
+///     .sign_to_string(&credentials)
+///     .unwrap();
+///
+/// let options = ValidationOptions::default();
+/// assert!(verify_request("GET", "example.com", 443, "/a?b=c", &authorization, &key, &options)
+///     .is_ok());
+/// assert!(verify_request("GET", "example.com", 443, "/a", &authorization, &key, &options)
+///     .is_err());
+/// ```
+pub fn verify_request(method: &str,
+                      host: &str,
+                      port: u16,
+                      path_and_query: &str,
+                      authorization: &str,
+                      key: &Key,
+                      options: &ValidationOptions)
+                      -> ::std::result::Result<(), VerifyError> {
+    let value = auth_source::hawk_header_value(authorization).ok_or(VerifyError::NotHawk)?;
+    let header = Header::from_str(value).map_err(|_| VerifyError::Malformed)?;
+    let request = RequestBuilder::new(method, host, port, path_and_query).request();
+    request.check_header(&header, key, options)
+        .map(|_| ())
+        .map_err(|rejected| VerifyError::Rejected(rejected.reason()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use credentials::Credentials;
+    use ring::digest;
+
+    #[test]
+    fn verify() {
+        let key = Key::new(vec![99u8; 32], &digest::SHA256);
+        let credentials = Credentials {
+            id: "me".to_string(),
+            key: key.clone(),
+        };
+        let authorization = RequestBuilder::new("POST", "example.com", 8080, "/x")
+            .request()
+            .sign_to_string(&credentials)
+            .unwrap();
+        let options = ValidationOptions::default();
+        let verify = |method, authorization| {
+            verify_request(method, "example.com", 8080, "/x", authorization, &key, &options)
+        };
+
+        assert_eq!(verify("POST", &authorization), Ok(()));
+        assert_eq!(verify("PUT", &authorization),
+                   Err(VerifyError::Rejected(RejectionReason::MacMismatch)));
+        assert_eq!(verify("POST", "Bearer abc"), Err(VerifyError::NotHawk));
+        assert_eq!(verify("POST", "Hawk id=\"me"), Err(VerifyError::Malformed));
+        assert_eq!(verify("POST", "Hawk id=\"me\"").unwrap_err().stage(), Stage::Parse);
+    }
+}
@@ -0,0 +1,278 @@
+//! Signing and validation of webhook deliveries using Hawk MACs.
+//!
+//! A webhook sender signs the body of each delivery with a shared `Key`, producing a compact
+//! signature value such as `ts=1353832234,nonce=j4h3g2,mac=6R4rV5iE+NPoym+WwjeHzjAGXUtLNIxmo1vpMofpLAE=`
+//! to be sent in a header of the sender's choosing.  The receiver validates that value against
+//! the body it received, checking the timestamp skew and, optionally, rejecting replays.
+//!
+//! The MAC covers the timestamp, nonce, and payload hash of the body, using the normalized
+//! string prefix `hawk.1.webhook`.  These signatures are not interoperable with other Hawk
+//! implementations.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate time;
+//! extern crate hawk;
+//!
+//! use hawk::{Key, SHA256};
+//! use hawk::webhook::{self, WebhookOptions};
+//! use hawk::nonce::MemoryNonceStore;
+//!
+//! fn main() {
+//!     let key = Key::new(vec![99u8; 32], &SHA256);
+//!     let body = b"{\"event\": \"push\"}";
+//!
+//!     // sender
+//!     let signature = webhook::sign(&key, "application/json", body).unwrap();
+//!
+//!     // receiver
+//!     let options = WebhookOptions::default();
+//!     let nonces = MemoryNonceStore::new(options.ts_skew);
+//!     assert!(webhook::validate(&signature, &key, "application/json", body, &options,
+//!                               Some(&nonces)).is_ok());
+//!
+//!     // a second delivery of the same signature is a replay
+//!     assert!(webhook::validate(&signature, &key, "application/json", body, &options,
+//!                               Some(&nonces)).is_err());
+//! }
+//! ```
+
+use base64;
+use clock;
+use credentials::Key;
+use crypto;
+use error::*;
+use mac::{Mac, MacType};
+use nonce::{self, NonceStore};
+use payload::PayloadHasher;
+use std::error;
+use std::fmt;
+use time::{Duration, Timespec};
+
+/// The MAC type used for webhook signatures.
+pub const WEBHOOK_MAC_TYPE: MacType = MacType::Custom("hawk.1.webhook");
+
+/// The id under which webhook nonces are recorded in a NonceStore.
+const NONCE_ID: &str = "hawk.webhook";
+
+/// Options for validating a webhook signature.
+///
+/// The default options allow five minutes of clock skew, as webhook deliveries are often queued
+/// briefly before sending.
+#[derive(Clone, Debug)]
+pub struct WebhookOptions {
+    /// The maximum difference allowed between the signature's timestamp and the current time.
+    pub ts_skew: Duration,
+}
+
+impl Default for WebhookOptions {
+    fn default() -> Self {
+        WebhookOptions { ts_skew: Duration::minutes(5) }
+    }
+}
+
+/// The ways in which webhook validation can fail.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WebhookError {
+    /// The signature value could not be parsed.
+    Malformed,
+    /// The MAC does not match the body.
+    MacMismatch,
+    /// The timestamp is outside the allowed skew.
+    Stale,
+    /// The nonce has been seen before.
+    Replayed,
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WebhookError::Malformed => f.write_str("Webhook signature is malformed"),
+            WebhookError::MacMismatch => f.write_str("Webhook MAC does not match body"),
+            WebhookError::Stale => f.write_str("Webhook timestamp is outside the allowed skew"),
+            WebhookError::Replayed => f.write_str("Webhook nonce has already been used"),
+        }
+    }
+}
+
+impl error::Error for WebhookError {}
+
+/// Sign a webhook body, inventing a new nonce and using the current time.
+pub fn sign(key: &Key, content_type: &str, body: &[u8]) -> Result<String> {
+    sign_full(key, content_type, body, clock::now(), &nonce::generate())
+}
+
+/// Similar to `sign`, but allowing specification of the timestamp and nonce.  The nonce must
+/// not contain `,`.
+pub fn sign_full(key: &Key,
+                 content_type: &str,
+                 body: &[u8],
+                 ts: Timespec,
+                 nonce: &str)
+                 -> Result<String> {
+    if nonce.contains(',') {
+        bail!("Webhook nonce cannot contain `,`");
+    }
+    let mac = webhook_mac(key, content_type, body, ts, nonce)?;
+    Ok(format!("ts={},nonce={},mac={}", ts.sec, nonce, base64::encode(&mac)))
+}
+
+/// Validate a webhook signature against the body it accompanied.
+///
+/// If a NonceStore is given, the signature's nonce is recorded in it and a signature whose nonce
+/// has already been recorded is rejected.  The nonce is only recorded once the MAC and timestamp
+/// have been validated.
+pub fn validate(value: &str,
+                key: &Key,
+                content_type: &str,
+                body: &[u8],
+                options: &WebhookOptions,
+                nonces: Option<&dyn NonceStore>)
+                -> ::std::result::Result<(), WebhookError> {
+    let (ts, nonce, mac) = parse(value).ok_or(WebhookError::Malformed)?;
+
+    let calculated = webhook_mac(key, content_type, body, ts, nonce)
+        .map_err(|_| WebhookError::Malformed)?;
+    if !crypto::ct_eq(calculated.as_ref(), &mac) {
+        return Err(WebhookError::MacMismatch);
+    }
+
+    let now = clock::now();
+    if now - ts > options.ts_skew || ts - now > options.ts_skew {
+        return Err(WebhookError::Stale);
+    }
+
+    if let Some(nonces) = nonces {
+        if !nonces.check_and_record(NONCE_ID, nonce, ts) {
+            return Err(WebhookError::Replayed);
+        }
+    }
+
+    Ok(())
+}
+
+fn webhook_mac(key: &Key,
+               content_type: &str,
+               body: &[u8],
+               ts: Timespec,
+               nonce: &str)
+               -> Result<Mac> {
+    let hash = PayloadHasher::hash(content_type, key.algorithm(), body);
+    Mac::new(WEBHOOK_MAC_TYPE, key, ts, nonce, "POST", "", 0, "", Some(&hash), None)
+}
+
+/// Parse a signature value into its timestamp, nonce, and MAC.
+fn parse(value: &str) -> Option<(Timespec, &str, Vec<u8>)> {
+    let mut ts = None;
+    let mut nonce = None;
+    let mut mac = None;
+    for attr in value.split(',') {
+        let (name, value) = attr.trim().split_once('=')?;
+        match name {
+            "ts" if ts.is_none() => ts = Some(Timespec::new(value.parse().ok()?, 0)),
+            "nonce" if nonce.is_none() => nonce = Some(value),
+            "mac" if mac.is_none() => mac = Some(base64::decode(value).ok()?),
+            _ => return None,
+        }
+    }
+    Some((ts?, nonce?, mac?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nonce::MemoryNonceStore;
+    use ring::digest;
+    use testing::test_credentials;
+    use time;
+
+    #[test]
+    fn round_trip() {
+        let key = test_credentials().key;
+        let signature = sign(&key, "text/plain", b"hello").unwrap();
+        assert_eq!(validate(&signature, &key, "text/plain", b"hello", &Default::default(), None),
+                   Ok(()));
+    }
+
+    #[test]
+    fn sign_full_format() {
+        let key = test_credentials().key;
+        let signature = sign_full(&key, "text/plain", b"hello", Timespec::new(1000, 0), "abc")
+            .unwrap();
+        assert!(signature.starts_with("ts=1000,nonce=abc,mac="));
+    }
+
+    #[test]
+    fn sign_full_bad_nonce() {
+        let key = test_credentials().key;
+        assert!(sign_full(&key, "text/plain", b"hello", Timespec::new(1000, 0), "a,b").is_err());
+    }
+
+    #[test]
+    fn wrong_body() {
+        let key = test_credentials().key;
+        let signature = sign(&key, "text/plain", b"hello").unwrap();
+        assert_eq!(validate(&signature, &key, "text/plain", b"goodbye", &Default::default(), None),
+                   Err(WebhookError::MacMismatch));
+        assert_eq!(validate(&signature, &key, "text/html", b"hello", &Default::default(), None),
+                   Err(WebhookError::MacMismatch));
+    }
+
+    #[test]
+    fn wrong_key() {
+        let key = test_credentials().key;
+        let signature = sign(&key, "text/plain", b"hello").unwrap();
+        let other = Key::new(vec![1u8; 32], &digest::SHA256);
+        assert_eq!(validate(&signature, &other, "text/plain", b"hello", &Default::default(), None),
+                   Err(WebhookError::MacMismatch));
+    }
+
+    #[test]
+    fn stale() {
+        let key = test_credentials().key;
+        let ts = time::now().to_timespec() - Duration::minutes(10);
+        let signature = sign_full(&key, "text/plain", b"hello", ts, "abc").unwrap();
+        assert_eq!(validate(&signature, &key, "text/plain", b"hello", &Default::default(), None),
+                   Err(WebhookError::Stale));
+
+        let options = WebhookOptions { ts_skew: Duration::minutes(15) };
+        assert_eq!(validate(&signature, &key, "text/plain", b"hello", &options, None), Ok(()));
+    }
+
+    #[test]
+    fn replayed() {
+        let key = test_credentials().key;
+        let options = WebhookOptions::default();
+        let nonces = MemoryNonceStore::new(options.ts_skew);
+        let signature = sign(&key, "text/plain", b"hello").unwrap();
+        assert_eq!(validate(&signature, &key, "text/plain", b"hello", &options, Some(&nonces)),
+                   Ok(()));
+        assert_eq!(validate(&signature, &key, "text/plain", b"hello", &options, Some(&nonces)),
+                   Err(WebhookError::Replayed));
+    }
+
+    #[test]
+    fn invalid_not_recorded() {
+        let key = test_credentials().key;
+        let options = WebhookOptions::default();
+        let nonces = MemoryNonceStore::new(options.ts_skew);
+        let signature = sign(&key, "text/plain", b"hello").unwrap();
+        assert!(validate(&signature, &key, "text/plain", b"bad", &options, Some(&nonces)).is_err());
+        assert!(nonces.is_empty());
+    }
+
+    #[test]
+    fn malformed() {
+        let key = test_credentials().key;
+        for value in &["",
+                       "ts=1000,nonce=abc",
+                       "ts=abc,nonce=abc,mac=AAAA",
+                       "ts=1000,nonce=abc,mac=!!!",
+                       "ts=1000,nonce=abc,mac=AAAA,extra=1",
+                       "ts=1000,ts=1000,nonce=abc,mac=AAAA"] {
+            assert_eq!(validate(value, &key, "text/plain", b"hello", &Default::default(), None),
+                       Err(WebhookError::Malformed));
+        }
+    }
+}
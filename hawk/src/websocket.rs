@@ -0,0 +1,143 @@
+//! Signing and validation of WebSocket handshakes.
+//!
+//! A WebSocket connection begins with an HTTP `GET` request asking to upgrade the connection.
+//! Clients sign that request like any other, adding the resulting header to the handshake's
+//! `Authorization` header.  Optionally, the signature can be bound to the handshake's
+//! `Sec-WebSocket-Key`, by including that key in the `ext` field, so that a captured header
+//! cannot be replayed for a different connection even within the allowed skew.
+//!
+//! These helpers are independent of any particular WebSocket library; with tungstenite, for
+//! example, the header is added to the client request, and validated in the server's
+//! handshake callback.
+//!
+//! # Examples
+//!
+//! ```
+//! use hawk::{Credentials, Key, RequestBuilder, ValidationOptions, SHA256};
+//! use hawk::websocket;
+//!
+//! let credentials = Credentials {
+//!     id: "me".to_string(),
+//!     key: Key::new(vec![99u8; 32], &SHA256),
+//! };
+//! let ws_key = "dGhlIHNhbXBsZSBub25jZQ==";
+//!
+//! // client
+//! let request = RequestBuilder::new("GET", "example.com", 443, "/chat");
+//! let header = websocket::make_header(request, &credentials, Some(ws_key)).unwrap();
+//!
+//! // server
+//! let request = RequestBuilder::new("GET", "example.com", 443, "/chat").request();
+//! assert!(websocket::validate_header(&request, &header, &credentials.key, Some(ws_key),
+//!                                    &ValidationOptions::default()));
+//! ```
+
+use credentials::{Credentials, Key};
+use error::*;
+use hawk_core::Header;
+use request::{Request, RequestBuilder};
+use validation::ValidationOptions;
+
+/// The prefix of the `ext` value binding a header to a `Sec-WebSocket-Key`.
+const KEY_EXT_PREFIX: &str = "sec-websocket-key=";
+
+/// Get the `ext` value binding a header to the given `Sec-WebSocket-Key`.
+pub fn key_ext(sec_websocket_key: &str) -> String {
+    format!("{}{}", KEY_EXT_PREFIX, sec_websocket_key)
+}
+
+/// Make a header for a WebSocket handshake request.
+///
+/// If `sec_websocket_key` is given, it is bound to the header through the `ext` field,
+/// replacing any ext set on the builder.  The request method must be `GET`.
+pub fn make_header(request: RequestBuilder,
+                   credentials: &Credentials,
+                   sec_websocket_key: Option<&str>)
+                   -> Result<Header> {
+    let ext = sec_websocket_key.map(key_ext);
+    let request = match ext {
+        Some(ref ext) => request.ext(&ext[..]),
+        None => request,
+    }.request();
+    if request.method() != "GET" {
+        bail!("WebSocket handshakes must use GET, not {}", request.method());
+    }
+    request.make_header(credentials)
+}
+
+/// Validate the header of a WebSocket handshake request.
+///
+/// If `sec_websocket_key` is given, the header must be bound to it, as by `make_header`.
+/// Otherwise, the header's `ext`, if any, is not examined.
+pub fn validate_header(request: &Request,
+                       header: &Header,
+                       key: &Key,
+                       sec_websocket_key: Option<&str>,
+                       options: &ValidationOptions)
+                       -> bool {
+    if request.method() != "GET" {
+        return false;
+    }
+    if let Some(sec_websocket_key) = sec_websocket_key {
+        match header.ext() {
+            Some(ext) if ext == key_ext(sec_websocket_key) => {}
+            _ => return false,
+        }
+    }
+    request.validate_header_with_options(header, key, options)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::test_credentials;
+
+    const WS_KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+
+    fn server_request() -> Request<'static> {
+        RequestBuilder::new("GET", "example.com", 443, "/chat").request()
+    }
+
+    #[test]
+    fn bound_to_key() {
+        let credentials = test_credentials();
+        let bldr = RequestBuilder::new("GET", "example.com", 443, "/chat");
+        let header = make_header(bldr, &credentials, Some(WS_KEY)).unwrap();
+        assert_eq!(header.ext(), Some("sec-websocket-key=dGhlIHNhbXBsZSBub25jZQ=="));
+
+        let options = ValidationOptions::default();
+        assert!(validate_header(&server_request(), &header, &credentials.key, Some(WS_KEY),
+                                &options));
+        assert!(!validate_header(&server_request(),
+                                 &header,
+                                 &credentials.key,
+                                 Some("x3JJHMbDL1EzLkh9GBhXDw=="),
+                                 &options));
+        // the binding is optional for the server
+        assert!(validate_header(&server_request(), &header, &credentials.key, None, &options));
+    }
+
+    #[test]
+    fn unbound() {
+        let credentials = test_credentials();
+        let bldr = RequestBuilder::new("GET", "example.com", 443, "/chat");
+        let header = make_header(bldr, &credentials, None).unwrap();
+        let options = ValidationOptions::default();
+        assert!(validate_header(&server_request(), &header, &credentials.key, None, &options));
+        assert!(!validate_header(&server_request(), &header, &credentials.key, Some(WS_KEY),
+                                 &options));
+    }
+
+    #[test]
+    fn requires_get() {
+        let credentials = test_credentials();
+        let bldr = RequestBuilder::new("POST", "example.com", 443, "/chat");
+        assert!(make_header(bldr, &credentials, Some(WS_KEY)).is_err());
+
+        let bldr = RequestBuilder::new("GET", "example.com", 443, "/chat");
+        let header = make_header(bldr, &credentials, None).unwrap();
+        let post = RequestBuilder::new("POST", "example.com", 443, "/chat").request();
+        assert!(!validate_header(&post, &header, &credentials.key, None,
+                                 &ValidationOptions::default()));
+    }
+}
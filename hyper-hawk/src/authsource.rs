@@ -0,0 +1,81 @@
+use hawk::{self, AuthSource, ErrorKind};
+use hyper::Request;
+use std::str;
+
+/// Determine how a Hyper request presents Hawk authentication, as for
+/// `hawk::AuthSource::from_request`.
+///
+/// Servers should call this before validating either a header or a bewit: it fails with
+/// `hawk::ErrorKind::MultipleAuthentications` if the request presents both a Hawk
+/// `Authorization` header and a `bewit` query parameter (or more than one Hawk
+/// `Authorization` header), rather than letting the server silently validate only one.
+pub fn auth_source<B>(req: &Request<B>) -> hawk::Result<Option<AuthSource<'_>>> {
+    let target = req.uri().as_ref();
+    let mut source = None;
+    if let Some(raw) = req.headers().get_raw("Authorization") {
+        for line in raw.iter() {
+            let line = str::from_utf8(line)
+                .map_err(|_| hawk::Error::from("Authorization header is not valid UTF-8"))?;
+            if let Some(found) = AuthSource::from_request(Some(line), target)? {
+                if source.is_some() {
+                    return Err(ErrorKind::MultipleAuthentications.into());
+                }
+                source = Some(found);
+            }
+        }
+    }
+    match source {
+        Some(source) => Ok(Some(source)),
+        None => AuthSource::from_request(None, target),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::auth_source;
+    use hawk::{AuthSource, ErrorKind};
+    use hyper::{Method, Request};
+
+    fn request(uri: &str, authorization: &[&str]) -> Request {
+        let mut req = Request::new(Method::Get, uri.parse().unwrap());
+        for value in authorization {
+            req.headers_mut().append_raw("Authorization", value.to_string());
+        }
+        req
+    }
+
+    fn is_multiple(req: &Request) -> bool {
+        match auth_source(req) {
+            Err(e) => matches!(*e.kind(), ErrorKind::MultipleAuthentications),
+            Ok(_) => false,
+        }
+    }
+
+    #[test]
+    fn none() {
+        assert_eq!(auth_source(&request("/resource", &[])).unwrap(), None);
+        assert_eq!(auth_source(&request("/resource", &["Basic abc"])).unwrap(), None);
+    }
+
+    #[test]
+    fn header() {
+        assert_eq!(auth_source(&request("/resource?a=1", &["Hawk id=\"me\""])).unwrap(),
+                   Some(AuthSource::Header("id=\"me\"")));
+        assert_eq!(auth_source(&request("/resource", &["Basic abc", "Hawk id=\"me\""])).unwrap(),
+                   Some(AuthSource::Header("id=\"me\"")));
+    }
+
+    #[test]
+    fn bewit() {
+        assert_eq!(auth_source(&request("/resource?bewit=abcd", &[])).unwrap(),
+                   Some(AuthSource::Bewit("abcd")));
+        assert_eq!(auth_source(&request("http://example.com/resource?bewit=abcd", &[])).unwrap(),
+                   Some(AuthSource::Bewit("abcd")));
+    }
+
+    #[test]
+    fn both() {
+        assert!(is_multiple(&request("/resource?bewit=abcd", &["Hawk id=\"me\""])));
+        assert!(is_multiple(&request("/resource", &["Hawk id=\"me\"", "Hawk id=\"you\""])));
+    }
+}
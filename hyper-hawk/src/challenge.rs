@@ -0,0 +1,68 @@
+use std::fmt;
+use std::ops::Deref;
+use hawk::Challenge;
+use hyper::Result;
+use hyper::header;
+
+/// `WWW-Authenticate` header carrying a Hawk challenge, for `401 Unauthorized` responses.
+///
+/// The `WwwAuthenticate` type dereferences to a Hawk Challenge.  Servers should send this with
+/// every 401 response to a request lacking valid Hawk authentication; for example,
+/// `WwwAuthenticate(Challenge::rejected(reason))`, or `Challenge::stale(&key)` for a request
+/// with a stale timestamp.
+#[derive(Clone, PartialEq, Debug)]
+pub struct WwwAuthenticate(pub Challenge);
+
+impl Deref for WwwAuthenticate {
+    type Target = Challenge;
+
+    fn deref(&self) -> &Challenge {
+        &self.0
+    }
+}
+
+impl header::Header for WwwAuthenticate {
+    fn header_name() -> &'static str {
+        "WWW-Authenticate"
+    }
+
+    fn parse_header(raw: &header::Raw) -> Result<WwwAuthenticate> {
+        header::parsing::from_one_raw_str(raw).map(WwwAuthenticate)
+    }
+
+    fn fmt_header(&self, f: &mut header::Formatter) -> fmt::Result {
+        f.fmt_line(self)
+    }
+}
+
+impl fmt::Display for WwwAuthenticate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::WwwAuthenticate;
+    use hawk::{Challenge, Key, RejectionReason, SHA256};
+    use hyper::header::{Header, Headers, Raw};
+
+    #[test]
+    fn format() {
+        let mut headers = Headers::new();
+        headers.set(WwwAuthenticate(Challenge::rejected(RejectionReason::MacMismatch)));
+        assert_eq!(headers.to_string(), "WWW-Authenticate: Hawk error=\"Bad mac\"\r\n");
+    }
+
+    #[test]
+    fn parse() {
+        let key = Key::new(vec![1u8; 32], &SHA256);
+        let challenge = Challenge::stale(&key);
+        let raw = Raw::from(challenge.to_string());
+        let parsed = WwwAuthenticate::parse_header(&raw).unwrap();
+        assert_eq!(parsed.0, challenge);
+        assert!(parsed.verified_ts(&key).is_some());
+
+        assert!(WwwAuthenticate::parse_header(&Raw::from("Basic realm=\"x\"")).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+use futures::{Async, Future, Poll, Stream};
+use futures::sync::oneshot;
+use hawk::{PayloadHasher, DigestAlgorithm};
+
+/// `HashingStream` wraps a body stream, passing its chunks through untouched while calculating
+/// the Hawk payload hash of the body.
+///
+/// The hash is available from the accompanying `PayloadHash` future once the stream has been
+/// consumed completely, so a body can be hashed without buffering it.
+pub struct HashingStream<S> {
+    inner: S,
+    hasher: Option<PayloadHasher>,
+    sender: Option<oneshot::Sender<Vec<u8>>>,
+}
+
+impl<S> HashingStream<S>
+    where S: Stream,
+          S::Item: AsRef<[u8]>
+{
+    /// Wrap the given stream, hashing it with the given content type and algorithm.
+    pub fn new<B>(inner: S,
+                  content_type: B,
+                  algorithm: &'static DigestAlgorithm)
+                  -> (HashingStream<S>, PayloadHash)
+        where B: AsRef<[u8]>
+    {
+        let (sender, receiver) = oneshot::channel();
+        let stream = HashingStream {
+            inner,
+            hasher: Some(PayloadHasher::new(content_type, algorithm)),
+            sender: Some(sender),
+        };
+        (stream, PayloadHash(receiver))
+    }
+}
+
+impl<S> Stream for HashingStream<S>
+    where S: Stream,
+          S::Item: AsRef<[u8]>
+{
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.inner.poll()? {
+            Async::Ready(Some(chunk)) => {
+                if let Some(ref mut hasher) = self.hasher {
+                    hasher.update(chunk.as_ref());
+                }
+                Ok(Async::Ready(Some(chunk)))
+            }
+            Async::Ready(None) => {
+                if let (Some(hasher), Some(sender)) = (self.hasher.take(), self.sender.take()) {
+                    // the receiver may have been dropped, in which case nobody wants the hash
+                    let _ = sender.send(hasher.finish());
+                }
+                Ok(Async::Ready(None))
+            }
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+/// A future resolving to the payload hash calculated by a `HashingStream`.
+///
+/// If the stream is dropped before it is complete, this future fails with `Canceled`.
+pub struct PayloadHash(oneshot::Receiver<Vec<u8>>);
+
+impl Future for PayloadHash {
+    type Item = Vec<u8>;
+    type Error = oneshot::Canceled;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.0.poll()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HashingStream;
+    use futures::{Future, Stream};
+    use futures::stream;
+    use hawk::{PayloadHasher, SHA256};
+
+    #[test]
+    fn hashes_chunks() {
+        let chunks = vec![b"p\xc3\xa0y".to_vec(), b"lo".to_vec(), b"ad".to_vec()];
+        let (body, hash) = HashingStream::new(stream::iter_ok::<_, ()>(chunks),
+                                              "text/plain",
+                                              &SHA256);
+        let body = body.concat2().wait().unwrap();
+        assert_eq!(body, "pàyload".as_bytes());
+        assert_eq!(hash.wait().unwrap(),
+                   PayloadHasher::hash("text/plain", &SHA256, "pàyload"));
+    }
+
+    #[test]
+    fn dropped_stream_cancels() {
+        let chunks = vec![b"p\xc3\xa0y".to_vec()];
+        let (body, hash) = HashingStream::new(stream::iter_ok::<_, ()>(chunks),
+                                              "text/plain",
+                                              &SHA256);
+        drop(body);
+        assert!(hash.wait().is_err());
+    }
+}
@@ -1,9 +1,12 @@
 //! Library for authenticating HTTP requests and responses with Hawk.
 //!
 //! Most functionality comes directly from the `hawk` crate; this merely adds support for the
-//! [HawkScheme] [Authorization](hyper::header::Authorization) scheme and a new (nonstandard)
-//! [ServerAuthorization] header.
+//! [HawkScheme] [Authorization](hyper::header::Authorization) scheme, a new (nonstandard)
+//! [ServerAuthorization] header, and a [WwwAuthenticate] header for challenges, as well as
+//! [HashingStream] for hashing bodies as they stream, and [auth_source] for finding (and
+//! rejecting duplicate) Hawk authentication in a request.
 
+extern crate futures;
 extern crate hyper;
 extern crate hawk;
 extern crate rustc_serialize;
@@ -13,5 +16,14 @@ extern crate url;
 mod serverauth;
 pub use serverauth::ServerAuthorization;
 
+mod challenge;
+pub use challenge::WwwAuthenticate;
+
 mod authscheme;
 pub use authscheme::HawkScheme;
+
+mod hashing;
+pub use hashing::{HashingStream, PayloadHash};
+
+mod authsource;
+pub use authsource::auth_source;
@@ -6,8 +6,8 @@ extern crate url;
 extern crate futures;
 extern crate tokio_core;
 
-use hawk::{RequestBuilder, Credentials, Key, SHA256, PayloadHasher};
-use hyper_hawk::{HawkScheme, ServerAuthorization};
+use hawk::{AuthSource, RequestBuilder, Credentials, Key, SHA256, PayloadHasher};
+use hyper_hawk::{auth_source, HawkScheme, ServerAuthorization};
 use hyper::{Client, Method, Body, Request, Response};
 use hyper::header::{ContentLength, Authorization};
 use hyper::server::{Http, Service};
@@ -48,8 +48,8 @@ impl Future for ServerValidatorFuture {
 
                 let request = req_builder.request();
 
-                assert_eq!(self.header.id, Some("test-client".to_string()));
-                assert_eq!(self.header.ext, None);
+                assert_eq!(self.header.id(), Some("test-client"));
+                assert_eq!(self.header.ext(), None);
                 let key = Key::new(vec![1u8; 32], &SHA256);
                 if !request.validate_header(&self.header, &key, time::Duration::minutes(1)) {
                     panic!("header validation failed");
@@ -90,6 +90,12 @@ impl Service for TestService {
     type Future = ServerValidatorFuture;
 
     fn call(&self, req: Request) -> Self::Future {
+        // the request must present exactly one form of Hawk authentication
+        match auth_source(&req).unwrap() {
+            Some(AuthSource::Header(_)) => {}
+            other => panic!("unexpected authentication {:?}", other),
+        }
+
         // get the Authorization header the client sent
         ServerValidatorFuture {
             header: req.headers().get::<Authorization<HawkScheme>>().unwrap().clone(),
@@ -162,12 +168,12 @@ fn run_client_server(client_send_hash: bool,
             assert_eq!(body.as_ref(), b"OK");
 
             // most fields in `Server-Authorization: Hawk` are omitted
-            assert_eq!(server_hdr.id, None);
-            assert_eq!(server_hdr.ts, None);
-            assert_eq!(server_hdr.nonce, None);
-            assert_eq!(server_hdr.ext, Some("server-ext".to_string()));
-            assert_eq!(server_hdr.app, None);
-            assert_eq!(server_hdr.dlg, None);
+            assert_eq!(server_hdr.id(), None);
+            assert_eq!(server_hdr.ts(), None);
+            assert_eq!(server_hdr.nonce(), None);
+            assert_eq!(server_hdr.ext(), Some("server-ext"));
+            assert_eq!(server_hdr.app(), None);
+            assert_eq!(server_hdr.dlg(), None);
 
             let resp_payload_hash;
             let mut resp_builder = hawk_req.make_response_builder(&req_header);
@@ -7,7 +7,7 @@ extern crate futures;
 extern crate tokio_core;
 
 use std::process::{Command, Child};
-use hawk::{RequestBuilder, Credentials, Key, SHA256, PayloadHasher};
+use hawk::{RequestBuilder, Credentials, Key, SHA256, PayloadHasher, PathEncoding};
 use std::io::Read;
 use std::net::TcpListener;
 use std::path::Path;
@@ -100,10 +100,14 @@ fn client_with_header() {
             // check we got the expected body
             assert_eq!(body.as_ref(), b"Hello Steve ext-content");
 
+            // the server's ext is covered by its signature
+            assert_eq!(server_hdr.ext(), Some("response-ext"));
+
             // validate server's signature
             let payload_hash = PayloadHasher::hash(b"text/plain", &SHA256, body.as_ref());
             let response = hawk_req.make_response_builder(&req_header)
                 .hash(&payload_hash[..])
+                .ext("response-ext")
                 .response();
             if !response.validate_header(&server_hdr, &credentials.key) {
                 panic!("authentication of response header failed");
@@ -163,3 +167,45 @@ fn client_with_bewit() {
 
     child.wait().expect("Failure waiting for child");
 }
+
+/// Paths containing spaces, UTF-8, and reserved characters validate with the Node server when
+/// signed in canonical form and sent on the wire in that same form.
+#[cfg_attr(feature = "no-interoperability", ignore)]
+#[test]
+fn client_with_bewit_canonical_paths() {
+    for path in &["/resource/with space", "/resource/caf\u{e9}", "/resource/a+b;c=d@e:f%2fg"] {
+        let (mut child, port) = start_node_server();
+
+        let credentials = make_credentials();
+        let hawk_req = RequestBuilder::new("GET", "localhost", port, path)
+            .path_encoding(PathEncoding::Canonical)
+            .ext("ext-content")
+            .request();
+        let canonical = PathEncoding::Canonical.apply((*path).into());
+
+        let bewit = hawk_req.make_bewit(&credentials, time::Duration::minutes(1))
+            .unwrap();
+        let url = format!("http://localhost:{}{}?bewit={}", port, canonical, bewit.to_str());
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let client = Client::new(&handle);
+        let work = client.get(url.parse().unwrap())
+            .and_then(|res| {
+                assert_eq!(res.status(), hyper::Ok);
+
+                res.body().concat2().map(|body| {
+                    assert_eq!(body.as_ref(), b"Hello Steve ext-content");
+                })
+            });
+
+        core.run(work).unwrap();
+
+        drop(client);
+        drop(handle);
+        drop(core);
+
+        child.wait().expect("Failure waiting for child");
+    }
+}